@@ -0,0 +1,21 @@
+-- Create reminder_policies table (one policy per collection)
+CREATE TABLE reminder_policies (
+    collection_id UUID PRIMARY KEY REFERENCES collections(id) ON DELETE CASCADE,
+    days_before INTEGER[] NOT NULL DEFAULT '{7,2}',
+    repeat_every_days INTEGER NULL DEFAULT 7,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create reminders table (log of every reminder email sent)
+CREATE TABLE reminders (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    collection_id UUID NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+    recipient TEXT NOT NULL,
+    trigger TEXT NOT NULL,
+    pending_requests INTEGER NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX reminders_collection_id_idx ON reminders (collection_id, sent_at);
@@ -0,0 +1,4 @@
+-- Optional due date per request, overdue is computed in queries
+ALTER TABLE requests ADD COLUMN due_date DATE NULL;
+
+CREATE INDEX requests_collection_id_idx ON requests (collection_id);
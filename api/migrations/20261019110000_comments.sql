@@ -0,0 +1,37 @@
+-- Collections are now accessed by clients through their access token, so it must be unique
+UPDATE collections
+SET access_token = replace(gen_random_uuid()::TEXT, '-', '')
+WHERE access_token IN (
+    SELECT access_token FROM collections GROUP BY access_token HAVING COUNT(*) > 1
+);
+
+CREATE UNIQUE INDEX collections_access_token_idx ON collections (access_token);
+
+-- Create comments table (threads on requests between firm users and clients)
+CREATE TABLE comments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    request_id UUID NOT NULL REFERENCES requests(id) ON DELETE CASCADE,
+    author_type TEXT NOT NULL,
+    user_id UUID NULL REFERENCES users(id) ON DELETE SET NULL,
+    client_id UUID NULL REFERENCES clients(id) ON DELETE SET NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX comments_request_id_idx ON comments (request_id, created_at);
+
+-- Files attached to a comment
+CREATE TABLE comment_attachments (
+    comment_id UUID NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+    file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    PRIMARY KEY (comment_id, file_id)
+);
+
+-- Read markers, one per side ('user' or 'client') of each request thread
+CREATE TABLE comment_reads (
+    request_id UUID NOT NULL REFERENCES requests(id) ON DELETE CASCADE,
+    reader_type TEXT NOT NULL,
+    last_read_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (request_id, reader_type)
+);
@@ -0,0 +1,5 @@
+-- Requests either collect files or answers to a questionnaire
+ALTER TABLE requests ADD COLUMN kind TEXT NOT NULL DEFAULT 'files';
+ALTER TABLE requests ADD COLUMN questions JSONB NULL;
+ALTER TABLE requests ADD COLUMN answers JSONB NULL;
+ALTER TABLE requests ADD COLUMN answered_at TIMESTAMPTZ NULL;
@@ -0,0 +1,9 @@
+-- Files of a previous period shown as examples on the requests of a cloned collection
+CREATE TABLE request_references (
+    request_id UUID NOT NULL REFERENCES requests(id) ON DELETE CASCADE,
+    file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (request_id, file_id)
+);
+
+CREATE INDEX idx_request_references_file_id ON request_references(file_id);
@@ -0,0 +1,14 @@
+-- SHA-256 of the content, computed while uploading (NULL for files uploaded before)
+ALTER TABLE files ADD COLUMN sha256 TEXT;
+-- Earliest file of the same client with the same content
+ALTER TABLE files ADD COLUMN duplicate_of UUID REFERENCES files(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_files_sha256 ON files(sha256);
+
+-- Per-firm settings, defaults apply when a firm has no row
+CREATE TABLE firm_settings (
+    firm_id UUID PRIMARY KEY REFERENCES firms(id) ON DELETE CASCADE,
+    reject_duplicate_uploads BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
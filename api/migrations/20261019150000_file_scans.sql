@@ -0,0 +1,8 @@
+-- Malware scan of uploaded files. New files are quarantined ('pending') until found 'clean';
+-- 'infected' files are removed from storage, files uploaded before scanning existed are 'unscanned'.
+ALTER TABLE files ADD COLUMN scan_status TEXT NOT NULL DEFAULT 'unscanned';
+ALTER TABLE files ALTER COLUMN scan_status SET DEFAULT 'pending';
+ALTER TABLE files ADD COLUMN scan_result TEXT; -- Detected signature or scan error
+ALTER TABLE files ADD COLUMN scanned_at TIMESTAMPTZ;
+
+CREATE INDEX idx_files_pending_scan ON files(created_at) WHERE scan_status = 'pending';
@@ -0,0 +1,2 @@
+-- File types a request accepts ("application/pdf", "image/*"), any type when NULL
+ALTER TABLE requests ADD COLUMN allowed_types TEXT[];
@@ -0,0 +1,5 @@
+-- Thumbnail of uploaded files, rendered in the background and stored next to the original.
+-- 'pending' until rendered, then 'ready', 'unsupported' (no preview for this type) or 'failed'.
+ALTER TABLE files ADD COLUMN preview_status TEXT NOT NULL DEFAULT 'pending';
+
+CREATE INDEX idx_files_pending_preview ON files(created_at) WHERE preview_status = 'pending';
@@ -0,0 +1,8 @@
+-- Requests can turn uploaded photos into a PDF (one multi-page PDF per upload when combined).
+-- The uploaded photos are kept, linked to the PDF they were converted into.
+ALTER TABLE requests ADD COLUMN convert_images_to_pdf BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE requests ADD COLUMN combine_images BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE files ADD COLUMN converted_into UUID REFERENCES files(id) ON DELETE CASCADE;
+
+CREATE INDEX idx_files_converted_into ON files(converted_into);
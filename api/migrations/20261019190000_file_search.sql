@@ -0,0 +1,13 @@
+-- Text of uploaded documents for full-text search, extracted in the background.
+-- 'pending' until extracted, then 'extracted', 'unsupported' (no text to read) or 'failed'.
+ALTER TABLE files ADD COLUMN text_status TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE files ADD COLUMN extracted_text TEXT;
+
+-- File names are searchable right away and rank above matches in the content
+ALTER TABLE files ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('simple', translate(file_name, '_.-', '   ')), 'A') ||
+    setweight(to_tsvector('simple', COALESCE(extracted_text, '')), 'B')
+) STORED;
+
+CREATE INDEX idx_files_search_vector ON files USING GIN (search_vector);
+CREATE INDEX idx_files_pending_text ON files(created_at) WHERE text_status = 'pending';
@@ -0,0 +1,6 @@
+-- Structured data of e-invoices (Factur-X/ZUGFeRD PDFs), parsed in the background.
+-- 'pending' until parsed, then 'parsed', 'none' (not an e-invoice) or 'failed'.
+ALTER TABLE files ADD COLUMN invoice_status TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE files ADD COLUMN invoice JSONB;
+
+CREATE INDEX idx_files_pending_invoice ON files(created_at) WHERE invoice_status = 'pending';
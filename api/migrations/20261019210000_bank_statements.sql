@@ -0,0 +1,6 @@
+-- Transactions of bank statements (CSV, OFX, CAMT.053), parsed in the background.
+-- 'pending' until parsed, then 'parsed', 'none' (not a bank statement) or 'failed'.
+ALTER TABLE files ADD COLUMN statement_status TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE files ADD COLUMN bank_statement JSONB;
+
+CREATE INDEX idx_files_pending_statement ON files(created_at) WHERE statement_status = 'pending';
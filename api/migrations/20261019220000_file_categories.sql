@@ -0,0 +1,8 @@
+-- Accounting category suggested for uploaded files, once their text and structured data are known.
+-- 'pending' until the classifier ran, then 'classified'.
+ALTER TABLE files ADD COLUMN classification_status TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE files ADD COLUMN category TEXT;
+ALTER TABLE files ADD COLUMN category_confidence REAL;
+ALTER TABLE files ADD COLUMN suggested_request_id UUID REFERENCES requests(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_files_pending_classification ON files(created_at) WHERE classification_status = 'pending';
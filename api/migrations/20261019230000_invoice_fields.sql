@@ -0,0 +1,6 @@
+-- Key fields read from the text of PDF invoices which are not e-invoices, correctable by accountants.
+-- 'pending' until extracted, then 'extracted' or 'none' (not a PDF invoice, or nothing found).
+ALTER TABLE files ADD COLUMN fields_status TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE files ADD COLUMN invoice_fields JSONB;
+
+CREATE INDEX idx_files_pending_fields ON files(created_at) WHERE fields_status = 'pending';
@@ -0,0 +1,7 @@
+-- Validation report and journal totals of FECs (Fichier des Écritures Comptables).
+-- 'pending' until parsed, then 'parsed', 'none' (not an FEC) or 'failed'.
+ALTER TABLE files ADD COLUMN fec_status TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE files ADD COLUMN fec_report JSONB;
+ALTER TABLE files ADD COLUMN fec_summary JSONB;
+
+CREATE INDEX idx_files_pending_fec ON files(created_at) WHERE fec_status = 'pending';
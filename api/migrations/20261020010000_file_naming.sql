@@ -0,0 +1,8 @@
+-- Template renaming uploads, e.g. '{client}_{request}_{date}_{seq}.{ext}'. NULL keeps the names
+-- the files were uploaded with.
+ALTER TABLE firm_settings ADD COLUMN file_name_template TEXT;
+
+-- Name of the file on the client's device, `file_name` being the one given by the template
+ALTER TABLE files ADD COLUMN original_file_name TEXT;
+UPDATE files SET original_file_name = file_name;
+ALTER TABLE files ALTER COLUMN original_file_name SET NOT NULL;
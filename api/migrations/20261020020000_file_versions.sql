@@ -0,0 +1,10 @@
+-- Versions of a file re-uploaded by the client, all linked to the first one.
+-- Only the current version is listed with the request's files, the others stay in its history.
+ALTER TABLE files ADD COLUMN version_of UUID REFERENCES files(id) ON DELETE SET NULL;
+ALTER TABLE files ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE files ADD COLUMN is_current BOOLEAN NOT NULL DEFAULT TRUE;
+-- Set when an accountant turns a version down, which keeps it in the history
+ALTER TABLE files ADD COLUMN rejection_reason TEXT;
+ALTER TABLE files ADD COLUMN rejected_at TIMESTAMPTZ;
+
+CREATE INDEX idx_files_version_of ON files(version_of);
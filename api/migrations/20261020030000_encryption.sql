@@ -0,0 +1,19 @@
+-- Envelope encryption of stored objects. Each object is encrypted with its own data key, kept
+-- here encrypted with the key of its firm, which is itself encrypted with the master key from the
+-- configuration. Rotating keys only rewrites these rows, never the objects.
+CREATE TABLE firm_keys (
+    firm_id UUID PRIMARY KEY REFERENCES firms(id) ON DELETE CASCADE,
+    wrapped_key BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    rotated_at TIMESTAMPTZ
+);
+
+-- Objects without a row were stored before encryption was enabled and are read as they are
+CREATE TABLE data_keys (
+    storage_key TEXT PRIMARY KEY,
+    firm_id UUID NOT NULL REFERENCES firm_keys(firm_id) ON DELETE CASCADE,
+    wrapped_key BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_data_keys_firm_id ON data_keys(firm_id);
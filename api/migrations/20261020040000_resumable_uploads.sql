@@ -0,0 +1,26 @@
+-- Uploads resumed over several requests with the tus protocol. The bytes of each PATCH are
+-- stored as a chunk object, assembled into a file of the request once all have arrived.
+CREATE TABLE uploads (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    request_id UUID NOT NULL REFERENCES requests(id) ON DELETE CASCADE,
+    file_name TEXT NOT NULL,
+    replaces UUID REFERENCES files(id) ON DELETE SET NULL,
+    metadata TEXT, -- Upload-Metadata as sent, returned as is by HEAD
+    upload_length BIGINT NOT NULL,
+    upload_offset BIGINT NOT NULL DEFAULT 0,
+    file_id UUID REFERENCES files(id) ON DELETE SET NULL, -- Once completed
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE upload_chunks (
+    upload_id UUID NOT NULL REFERENCES uploads(id) ON DELETE CASCADE,
+    upload_offset BIGINT NOT NULL, -- Of the chunk's first byte
+    storage_key TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    PRIMARY KEY (upload_id, upload_offset)
+);
+
+CREATE INDEX idx_uploads_request_id ON uploads(request_id);
+CREATE INDEX idx_uploads_expires_at ON uploads(expires_at) WHERE file_id IS NULL;
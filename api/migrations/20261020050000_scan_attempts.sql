@@ -0,0 +1,8 @@
+-- Failed scans are retried oldest attempt first, and give up as 'failed' (still quarantined) after
+-- a few attempts instead of holding back newer files
+ALTER TABLE files ADD COLUMN scan_attempts INT NOT NULL DEFAULT 0;
+ALTER TABLE files ADD COLUMN last_scan_attempt_at TIMESTAMPTZ;
+
+DROP INDEX idx_files_pending_scan;
+CREATE INDEX idx_files_pending_scan ON files(last_scan_attempt_at NULLS FIRST, created_at)
+    WHERE scan_status = 'pending';
@@ -0,0 +1,3 @@
+-- Last run of the background processing of a file, whose pending stages are retried oldest run
+-- first so that files failing a stage don't hold back the others
+ALTER TABLE files ADD COLUMN processed_at TIMESTAMPTZ;
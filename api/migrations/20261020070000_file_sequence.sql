@@ -0,0 +1,9 @@
+-- Last sequence number given to a file of each request by the firm's naming template, claimed
+-- with an upsert so that concurrent uploads get different numbers
+CREATE TABLE request_file_sequences (
+    request_id UUID PRIMARY KEY REFERENCES requests(id) ON DELETE CASCADE,
+    last_seq BIGINT NOT NULL
+);
+
+INSERT INTO request_file_sequences (request_id, last_seq)
+SELECT request_id, COUNT(*) FROM files WHERE converted_into IS NULL GROUP BY request_id;
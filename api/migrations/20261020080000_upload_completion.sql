@@ -0,0 +1,6 @@
+-- Uploads are assembled into their file in the background once all their bytes arrived. The
+-- assembly is claimed by setting `completing_at`, and claimed again if it stalls. An upload
+-- refused as a file keeps the reason, returned to the client resuming it.
+ALTER TABLE uploads ADD COLUMN completing_at TIMESTAMPTZ;
+ALTER TABLE uploads ADD COLUMN failure TEXT;
+ALTER TABLE uploads ADD COLUMN failure_status SMALLINT;
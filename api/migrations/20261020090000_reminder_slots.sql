@@ -0,0 +1,6 @@
+-- Scheduled reminders record the slot they were sent for. A slot is claimed by inserting its
+-- reminder before the email goes out, so it is sent once even by concurrent scheduler runs.
+ALTER TABLE reminders ADD COLUMN slot_at TIMESTAMPTZ;
+
+CREATE UNIQUE INDEX reminders_slot_idx ON reminders (collection_id, slot_at)
+    WHERE slot_at IS NOT NULL;
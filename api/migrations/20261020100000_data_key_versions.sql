@@ -0,0 +1,8 @@
+-- An object being rewritten, like a preview rendered again, keeps its previous data key until its
+-- new content is written: encrypted objects name their data key, and a storage key can have
+-- several of them for a while
+ALTER TABLE data_keys ADD COLUMN id UUID NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE data_keys DROP CONSTRAINT data_keys_pkey;
+ALTER TABLE data_keys ADD PRIMARY KEY (id);
+
+CREATE INDEX idx_data_keys_storage_key ON data_keys(storage_key);
@@ -15,6 +15,14 @@ impl AppError {
             message: message.to_string(),
         }
     }
+
+    pub fn code(&self) -> StatusCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl IntoResponse for AppError {
@@ -22,3 +30,9 @@ impl IntoResponse for AppError {
         (self.code, self.message).into_response()
     }
 }
+
+impl From<StatusCode> for AppError {
+    fn from(code: StatusCode) -> Self {
+        Self::new(code, code.canonical_reason().unwrap_or_default())
+    }
+}
@@ -1,7 +1,18 @@
 use sqlx::PgPool;
+use std::sync::Arc;
+
+use crate::classifier::Classifier;
+use crate::mailer::Mailer;
+use crate::scanner::Scanner;
+use crate::storage::Storage;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db_pool: PgPool,
     pub jwt_secret: String,
+    pub mailer: Arc<dyn Mailer>,
+    pub storage: Arc<dyn Storage>,
+    pub scanner: Arc<dyn Scanner>,
+    pub classifier: Arc<dyn Classifier>,
+    pub portal_url: String, // Base URL of the client portal, the collection's access token is appended
 }
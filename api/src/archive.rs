@@ -0,0 +1,195 @@
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipDateTime, ZipEntryBuilder};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::compat::FuturesAsyncWriteCompatExt;
+use uuid::Uuid;
+
+use crate::storage::{Storage, StorageReader};
+
+pub const MANIFEST_NAME: &str = "manifest.csv";
+
+// Status of a file in the manifest
+pub const ARCHIVED: &str = "archived";
+pub const MISSING: &str = "missing"; // Listed in the database but not found in storage
+
+// A file to put in a collection's archive
+#[derive(Debug, Clone)]
+pub struct ArchiveFile {
+    pub request_id: Uuid,
+    pub request_title: String,
+    pub file_id: Uuid,
+    pub file_name: String,
+    pub storage_key: String,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+// Makes a name safe to extract on any OS: no separators, reserved or control characters
+pub fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+    let cleaned: String = cleaned.chars().take(150).collect();
+
+    if cleaned.is_empty() {
+        "untitled".to_string()
+    } else {
+        cleaned
+    }
+}
+
+// Content-Disposition value offering to save the response as `file_name`
+pub fn attachment_disposition(file_name: &str) -> String {
+    let ascii_name: String = sanitize_file_name(file_name)
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    format!("attachment; filename=\"{}\"", ascii_name)
+}
+
+// Appends " (2)", " (3)"... before the extension until the name is unused (case-insensitively)
+pub fn unique_name(used: &mut HashSet<String>, name: String) -> String {
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot..]),
+        _ => (name.as_str(), ""),
+    };
+
+    let mut candidate = name.clone();
+    let mut counter = 2;
+    while !used.insert(candidate.to_lowercase()) {
+        candidate = format!("{} ({}){}", stem, counter, extension);
+        counter += 1;
+    }
+    candidate
+}
+
+// Assigns every file its path in the archive: one folder per request, unique names in each folder
+pub fn archive_paths(files: &[ArchiveFile]) -> Vec<String> {
+    let mut used_folders = HashSet::from([MANIFEST_NAME.to_string()]);
+    let mut folders: HashMap<Uuid, String> = HashMap::new();
+    let mut used_names: HashMap<Uuid, HashSet<String>> = HashMap::new();
+
+    files
+        .iter()
+        .map(|file| {
+            let folder = folders
+                .entry(file.request_id)
+                .or_insert_with(|| {
+                    unique_name(&mut used_folders, sanitize_file_name(&file.request_title))
+                })
+                .clone();
+            let name = unique_name(
+                used_names.entry(file.request_id).or_default(),
+                sanitize_file_name(&file.file_name),
+            );
+            format!("{}/{}", folder, name)
+        })
+        .collect()
+}
+
+// Writes the ZIP to `writer` while reading the files one by one from storage, so that
+// neither the files nor the archive are held in memory. The manifest comes last since it
+// lists the checksums computed along the way, and the files missing from storage: the
+// response has started by then, so they cannot fail the request.
+pub async fn write_archive<W>(
+    storage: &dyn Storage,
+    files: Vec<ArchiveFile>,
+    writer: W,
+) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let paths = archive_paths(&files);
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    let mut manifest = csv::Writer::from_writer(Vec::new());
+    manifest.write_record([
+        "path",
+        "request_id",
+        "request",
+        "file_id",
+        "file_name",
+        "size",
+        "sha256",
+        "uploaded_at",
+        "status",
+    ])?;
+
+    let mut buffer = vec![0; 64 * 1024];
+    for (file, path) in files.into_iter().zip(paths) {
+        let (size, sha256, status) = match storage.get(&file.storage_key).await {
+            Ok(reader) => {
+                let (size, sha256) = write_stored_entry(
+                    &mut zip,
+                    reader,
+                    path.clone(),
+                    &file.uploaded_at,
+                    &mut buffer,
+                )
+                .await?;
+                (size.to_string(), sha256, ARCHIVED)
+            }
+            Err(e) => {
+                eprintln!("File {} missing from storage: {}", file.file_id, e);
+                (String::new(), String::new(), MISSING)
+            }
+        };
+
+        manifest.write_record([
+            path,
+            file.request_id.to_string(),
+            file.request_title,
+            file.file_id.to_string(),
+            file.file_name,
+            size,
+            sha256,
+            file.uploaded_at.to_rfc3339(),
+            status.to_string(),
+        ])?;
+    }
+
+    let manifest = manifest.into_inner()?;
+    let entry = ZipEntryBuilder::new(MANIFEST_NAME.to_string().into(), Compression::Deflate)
+        .last_modification_date(ZipDateTime::from_chrono(&Utc::now()));
+    zip.write_entry_whole(entry, &manifest).await?;
+    zip.close().await?.into_inner().shutdown().await?;
+
+    Ok(())
+}
+
+// Copies a stored file into a new entry of the ZIP, returns its size and hex SHA-256
+pub async fn write_stored_entry<W>(
+    zip: &mut ZipFileWriter<W>,
+    mut reader: StorageReader,
+    path: String,
+    modified: &DateTime<Utc>,
+    buffer: &mut [u8],
+) -> anyhow::Result<(u64, String)>
+where
+    W: futures::AsyncWrite + Unpin,
+{
+    let entry = ZipEntryBuilder::new(path.into(), Compression::Deflate)
+        .last_modification_date(ZipDateTime::from_chrono(modified));
+    let mut entry_writer = zip.write_entry_stream(entry).await?.compat_write();
+    let mut hasher = Sha256::new();
+    let mut size: u64 = 0;
+    loop {
+        let read = reader.read(buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        entry_writer.write_all(&buffer[..read]).await?;
+        size += read as u64;
+    }
+    entry_writer.into_inner().close().await?;
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
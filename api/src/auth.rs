@@ -8,6 +8,7 @@ use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::app_error::AppError;
 use crate::app_state::AppState;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,3 +46,68 @@ pub async fn auth_middleware(
     Ok(next.run(request).await)
 }
 
+// Identifies an end client browsing a collection through the portal
+#[derive(Debug, Clone, Copy)]
+pub struct PortalAccess {
+    pub collection_id: Uuid,
+    pub client_id: Uuid,
+}
+
+impl PortalAccess {
+    // Clients can only reach the requests of the collection their access token belongs to
+    pub async fn ensure_request(
+        &self,
+        app_state: &AppState,
+        request_id: Uuid,
+    ) -> Result<(), AppError> {
+        sqlx::query_scalar!(
+            "SELECT id FROM requests WHERE id = $1 AND collection_id = $2",
+            request_id,
+            self.collection_id
+        )
+        .fetch_optional(&app_state.db_pool)
+        .await
+        .map_err(|_| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch request"))?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Request not found"))?;
+
+        Ok(())
+    }
+}
+
+pub async fn portal_auth_middleware(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let access_token = headers
+        .get("X-Access-Token")
+        .and_then(|header| header.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let access = portal_access(&app_state, access_token)
+        .await
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // Add the portal access to request extensions, so handlers can access it
+    request.extensions_mut().insert(access);
+
+    Ok(next.run(request).await)
+}
+
+// Resolves a collection access token, expired collections are no longer reachable
+pub async fn portal_access(app_state: &AppState, access_token: &str) -> Option<PortalAccess> {
+    sqlx::query_as!(
+        PortalAccess,
+        r#"
+        SELECT id as collection_id, client_id
+        FROM collections
+        WHERE access_token = $1 AND expires_at > now()
+        "#,
+        access_token
+    )
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .ok()
+    .flatten()
+}
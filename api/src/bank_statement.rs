@@ -0,0 +1,517 @@
+use bytes::Bytes;
+use chrono::NaiveDate;
+use roxmltree::Node;
+use uuid::Uuid;
+
+use crate::model::bank_statement::{
+    BankStatement, BankStatementSummary, BankTransaction, StatementGap, GAP_BALANCE_MISMATCH,
+    GAP_DUPLICATE_STATEMENT, GAP_MISSING_PERIOD, GAP_OVERLAPPING_PERIOD, STATEMENT_CAMT053,
+    STATEMENT_CSV, STATEMENT_OFX,
+};
+use crate::text_extraction::decode_text;
+use crate::xml::{child, children, descendant, text};
+
+// Rows searched for the header of a CSV export, banks put the account details above it
+const CSV_HEADER_SEARCH_ROWS: usize = 15;
+const CSV_DATE_FORMATS: &[&str] = &["%d/%m/%Y", "%Y-%m-%d", "%d-%m-%Y", "%d.%m.%Y", "%d/%m/%y"];
+
+// Column names of the common bank exports (French and English), accents and case removed,
+// the most specific first
+const DATE_COLUMNS: &[&str] = &[
+    "date operation",
+    "date de l'operation",
+    "date comptable",
+    "date de comptabilisation",
+    "booking date",
+    "transaction date",
+    "date",
+];
+const LABEL_COLUMNS: &[&str] = &[
+    "libelle",
+    "libelle operation",
+    "libelle de l'operation",
+    "description",
+    "label",
+    "wording",
+    "details",
+    "intitule",
+];
+const AMOUNT_COLUMNS: &[&str] = &["montant", "amount"];
+const DEBIT_COLUMNS: &[&str] = &["debit"];
+const CREDIT_COLUMNS: &[&str] = &["credit"];
+const BALANCE_COLUMNS: &[&str] = &["solde", "balance"];
+const CURRENCY_COLUMNS: &[&str] = &["devise", "currency"];
+
+// Parses the transactions of a bank statement, None when the file is not one
+pub async fn parse_statement(
+    mime_type: &str,
+    content: Bytes,
+) -> anyhow::Result<Option<BankStatement>> {
+    let mime_type = mime_type.to_string();
+    tokio::task::spawn_blocking(move || match mime_type.as_str() {
+        "application/x-ofx" | "application/vnd.intu.qfx" => {
+            Ok(Some(parse_ofx(&decode_text(&content))?))
+        }
+        "application/xml" => parse_camt053(std::str::from_utf8(&content)?),
+        "text/csv" => Ok(parse_csv(&decode_text(&content))),
+        _ => Ok(None),
+    })
+    .await?
+}
+
+// OFX 1 is SGML where elements are not closed (`<TRNAMT>-19.99`), OFX 2 is XML: values are
+// read up to the next tag, which works for both
+pub fn parse_ofx(ofx: &str) -> anyhow::Result<BankStatement> {
+    if !ofx.contains("<OFX>") {
+        anyhow::bail!("not an OFX file");
+    }
+    let currency = ofx_value(ofx, "CURDEF");
+    let transactions = ofx_blocks(ofx, "STMTTRN")
+        .into_iter()
+        .filter_map(|transaction| {
+            let name = ofx_value(transaction, "NAME");
+            let memo = ofx_value(transaction, "MEMO");
+            let label = match (name, memo) {
+                (Some(name), Some(memo)) if !name.contains(&memo) => format!("{} {}", name, memo),
+                (Some(label), _) | (None, Some(label)) => label,
+                (None, None) => String::new(),
+            };
+            Some(BankTransaction {
+                date: ofx_date(&ofx_value(transaction, "DTPOSTED")?)?,
+                amount: parse_amount(&ofx_value(transaction, "TRNAMT")?)?,
+                currency: currency.clone(),
+                label,
+                balance: None,
+            })
+        })
+        .collect();
+    // The ledger balance is the balance at the end of the statement
+    let closing_balance = ofx_blocks(ofx, "LEDGERBAL")
+        .first()
+        .and_then(|balance| ofx_value(balance, "BALAMT"))
+        .and_then(|amount| parse_amount(&amount));
+
+    Ok(statement(
+        STATEMENT_OFX,
+        ofx_value(ofx, "ACCTID"),
+        currency,
+        ofx_value(ofx, "DTSTART").and_then(|date| ofx_date(&date)),
+        ofx_value(ofx, "DTEND").and_then(|date| ofx_date(&date)),
+        None,
+        closing_balance,
+        transactions,
+    ))
+}
+
+fn ofx_value(ofx: &str, tag: &str) -> Option<String> {
+    let start = ofx.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let value = ofx[start..].split('<').next()?.trim();
+    (!value.is_empty()).then(|| {
+        value
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&")
+    })
+}
+
+// Aggregates are closed in both versions
+fn ofx_blocks<'a>(ofx: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    ofx.split(&open)
+        .skip(1)
+        .map(|block| block.split(&close).next().unwrap_or(block))
+        .collect()
+}
+
+// "20260131", possibly followed by a time and a time zone
+fn ofx_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
+
+// ISO 20022 Bank to Customer Statement, None for other XML files. Files holding several
+// statements (one per day or per account) are read as one.
+pub fn parse_camt053(xml: &str) -> anyhow::Result<Option<BankStatement>> {
+    let document = roxmltree::Document::parse(xml)?;
+    let root = document.root_element();
+    let is_camt053 = root
+        .tag_name()
+        .namespace()
+        .is_some_and(|namespace| namespace.contains("camt.053"));
+    let Some(report) = child(root, "BkToCstmrStmt").filter(|_| is_camt053) else {
+        return Ok(None);
+    };
+
+    let statements: Vec<Node> = children(report, "Stmt").collect();
+    let (Some(first), Some(last)) = (statements.first(), statements.last()) else {
+        anyhow::bail!("the statement file holds no statement");
+    };
+    let account = descendant(*first, &["Acct", "Id"])
+        .and_then(|id| text(id, &["IBAN"]).or_else(|| text(id, &["Othr", "Id"])));
+    let currency = text(*first, &["Acct", "Ccy"]);
+
+    let transactions = statements
+        .iter()
+        .flat_map(|statement| children(*statement, "Ntry"))
+        // Pending entries are not on the account yet
+        .filter(|entry| {
+            text(*entry, &["Sts"])
+                .or_else(|| text(*entry, &["Sts", "Cd"]))
+                .is_none_or(|status| status == "BOOK")
+        })
+        .filter_map(|entry| {
+            let details = descendant(entry, &["NtryDtls", "TxDtls"]);
+            let label = details
+                .and_then(|details| text(details, &["RmtInf", "Ustrd"]))
+                .or_else(|| text(entry, &["AddtlNtryInf"]))
+                .or_else(|| {
+                    details.and_then(|details| {
+                        text(details, &["RltdPties", "Cdtr", "Nm"])
+                            .or_else(|| text(details, &["RltdPties", "Dbtr", "Nm"]))
+                    })
+                })
+                .unwrap_or_default();
+            let amount_node = child(entry, "Amt")?;
+            Some(BankTransaction {
+                date: camt_date(child(entry, "BookgDt")?)?,
+                amount: camt_amount(entry)?,
+                currency: amount_node
+                    .attribute("Ccy")
+                    .map(str::to_string)
+                    .or_else(|| currency.clone()),
+                label,
+                balance: None,
+            })
+        })
+        .collect();
+
+    let balance = |statement: &Node, codes: &[&str]| {
+        children(*statement, "Bal")
+            .find(|balance| {
+                text(*balance, &["Tp", "CdOrPrtry", "Cd"])
+                    .is_some_and(|code| codes.contains(&code.as_str()))
+            })
+            .and_then(|balance| camt_amount(balance))
+    };
+    let period = |statement: &Node, bound: &str| {
+        descendant(*statement, &["FrToDt", bound])
+            .and_then(|date| date.text())
+            .and_then(|date| NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok())
+    };
+
+    Ok(Some(statement(
+        STATEMENT_CAMT053,
+        account,
+        currency,
+        period(first, "FrDtTm"),
+        period(last, "ToDtTm"),
+        // Opening booked, or the previous closing booked
+        balance(first, &["OPBD", "PRCD"]),
+        balance(last, &["CLBD"]),
+        transactions,
+    )))
+}
+
+// Amounts are positive with a credit or debit indicator
+fn camt_amount(node: Node) -> Option<f64> {
+    let amount = parse_amount(child(node, "Amt")?.text()?)?;
+    match text(node, &["CdtDbtInd"]).as_deref() {
+        Some("DBIT") => Some(-amount),
+        _ => Some(amount),
+    }
+}
+
+fn camt_date(node: Node) -> Option<NaiveDate> {
+    let date = text(node, &["Dt"]).or_else(|| text(node, &["DtTm"]))?;
+    NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok()
+}
+
+// CSV exports of online banking, recognized from their header. None when no header with a
+// date and an amount (or debit and credit) column is found.
+pub fn parse_csv(csv: &str) -> Option<BankStatement> {
+    b";,\t"
+        .iter()
+        .find_map(|delimiter| parse_csv_with(csv, *delimiter))
+}
+
+struct CsvColumns {
+    date: usize,
+    label: Option<usize>,
+    amount: Option<usize>,
+    debit: Option<usize>,
+    credit: Option<usize>,
+    balance: Option<usize>,
+    currency: Option<usize>,
+}
+
+fn parse_csv_with(csv: &str, delimiter: u8) -> Option<BankStatement> {
+    let rows: Vec<csv::StringRecord> = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(csv.as_bytes())
+        .records()
+        .filter_map(Result::ok)
+        .collect();
+
+    let (header_index, columns) = rows
+        .iter()
+        .take(CSV_HEADER_SEARCH_ROWS)
+        .enumerate()
+        .find_map(|(index, row)| {
+            let headers: Vec<String> = row.iter().map(normalize_header).collect();
+            let columns = CsvColumns {
+                date: find_column(&headers, DATE_COLUMNS)?,
+                label: find_column(&headers, LABEL_COLUMNS),
+                amount: find_column(&headers, AMOUNT_COLUMNS),
+                debit: find_column(&headers, DEBIT_COLUMNS),
+                credit: find_column(&headers, CREDIT_COLUMNS),
+                balance: find_column(&headers, BALANCE_COLUMNS),
+                currency: find_column(&headers, CURRENCY_COLUMNS),
+            };
+            let has_amount =
+                columns.amount.is_some() || (columns.debit.is_some() && columns.credit.is_some());
+            has_amount.then_some((index, columns))
+        })?;
+
+    let cell = |row: &csv::StringRecord, column: Option<usize>| {
+        column
+            .and_then(|column| row.get(column))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    // Rows without a date are totals or notes
+    let mut transactions: Vec<BankTransaction> = rows[header_index + 1..]
+        .iter()
+        .filter_map(|row| {
+            let date = CSV_DATE_FORMATS.iter().find_map(|format| {
+                NaiveDate::parse_from_str(row.get(columns.date)?.trim(), format).ok()
+            })?;
+            let amount = match columns.amount {
+                Some(_) => parse_amount(&cell(row, columns.amount)?)?,
+                // Debits are listed as positive or negative numbers depending on the bank
+                None => {
+                    let debit = cell(row, columns.debit).and_then(|value| parse_amount(&value));
+                    let credit = cell(row, columns.credit).and_then(|value| parse_amount(&value));
+                    if debit.is_none() && credit.is_none() {
+                        return None;
+                    }
+                    credit.unwrap_or(0.0).abs() - debit.unwrap_or(0.0).abs()
+                }
+            };
+            Some(BankTransaction {
+                date,
+                amount,
+                currency: cell(row, columns.currency),
+                label: cell(row, columns.label).unwrap_or_default(),
+                balance: cell(row, columns.balance).and_then(|value| parse_amount(&value)),
+            })
+        })
+        .collect();
+
+    // Most banks list the latest transactions first
+    if transactions
+        .first()
+        .zip(transactions.last())
+        .is_some_and(|(first, last)| first.date > last.date)
+    {
+        transactions.reverse();
+    }
+    transactions.sort_by_key(|transaction| transaction.date);
+
+    let opening_balance = transactions
+        .first()
+        .and_then(|first| Some(round_cents(first.balance? - first.amount)));
+    let closing_balance = transactions.last().and_then(|last| last.balance);
+    let currency = transactions
+        .iter()
+        .find_map(|transaction| transaction.currency.clone());
+    Some(statement(
+        STATEMENT_CSV,
+        None,
+        currency,
+        None,
+        None,
+        opening_balance,
+        closing_balance,
+        transactions,
+    ))
+}
+
+// Lowercase without accents, dots or units: "Libellé de l'opération" is
+// "libelle de l'operation", "Montant (EUR)" is "montant"
+fn normalize_header(header: &str) -> String {
+    let header: String = header
+        .trim()
+        .trim_start_matches('\u{feff}')
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'à' | 'â' => 'a',
+            'î' | 'ï' => 'i',
+            'ô' => 'o',
+            'ù' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            '’' => '\'',
+            _ => c,
+        })
+        .filter(|c| *c != '.')
+        .collect();
+    match header.split_once(" (") {
+        Some((name, _)) => name.trim().to_string(),
+        None => header,
+    }
+}
+
+fn find_column(headers: &[String], names: &[&str]) -> Option<usize> {
+    names.iter().find_map(|name| {
+        headers.iter().position(|header| {
+            header == name
+                || header
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with(' '))
+        })
+    })
+}
+
+// "1 234,56", "1.234,56", "1,234.56", "-19.99 €"
+pub fn parse_amount(value: &str) -> Option<f64> {
+    normalize_amount(value).parse().ok()
+}
+
+// The amount with "." as its only separator, e.g. "-1234.56"
+pub fn normalize_amount(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | ','))
+        .collect();
+    let decimal_separator = match (value.rfind('.'), value.rfind(',')) {
+        (Some(dot), Some(comma)) => {
+            if comma > dot {
+                ','
+            } else {
+                '.'
+            }
+        }
+        (None, Some(_)) => ',',
+        _ => '.',
+    };
+    value
+        .chars()
+        .filter(|c| !matches!(c, '.' | ',') || *c == decimal_separator)
+        .map(|c| if c == ',' { '.' } else { c })
+        .collect()
+}
+
+fn round_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+// Completes the period and balances from the transactions when the statement lacks them, and
+// the balance after each transaction
+#[allow(clippy::too_many_arguments)]
+fn statement(
+    format: &str,
+    account: Option<String>,
+    currency: Option<String>,
+    start_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
+    opening_balance: Option<f64>,
+    closing_balance: Option<f64>,
+    mut transactions: Vec<BankTransaction>,
+) -> BankStatement {
+    transactions.sort_by_key(|transaction| transaction.date);
+    let total: f64 = transactions
+        .iter()
+        .map(|transaction| transaction.amount)
+        .sum();
+    let opening_balance =
+        opening_balance.or_else(|| closing_balance.map(|closing| round_cents(closing - total)));
+    let closing_balance =
+        closing_balance.or_else(|| opening_balance.map(|opening| round_cents(opening + total)));
+
+    if let Some(mut balance) = opening_balance {
+        for transaction in &mut transactions {
+            balance = round_cents(balance + transaction.amount);
+            transaction.balance.get_or_insert(balance);
+        }
+    }
+
+    BankStatement {
+        summary: BankStatementSummary {
+            format: format.to_string(),
+            account,
+            currency,
+            start_date: start_date.or_else(|| transactions.first().map(|t| t.date)),
+            end_date: end_date.or_else(|| transactions.last().map(|t| t.date)),
+            opening_balance,
+            closing_balance,
+            transaction_count: transactions.len(),
+        },
+        transactions,
+    }
+}
+
+// Gaps between the consecutive statements of each account, statements given with their file.
+// Statements chain when the next one starts the day after the previous one ends, with the
+// previous closing balance as opening balance. CSV exports have no declared period, only the
+// dates of their first and last transactions: days between or shared by two of them are only
+// a gap or an overlap when the balances don't chain.
+pub fn find_gaps(mut statements: Vec<(Uuid, BankStatementSummary)>) -> Vec<StatementGap> {
+    statements
+        .retain(|(_, statement)| statement.start_date.is_some() && statement.end_date.is_some());
+    statements.sort_by(|(_, a), (_, b)| {
+        (&a.account, a.start_date, a.end_date).cmp(&(&b.account, b.start_date, b.end_date))
+    });
+
+    let mut gaps = Vec::new();
+    for pair in statements.windows(2) {
+        let [(previous_id, previous), (next_id, next)] = pair else {
+            continue;
+        };
+        if previous.account != next.account {
+            continue;
+        }
+        let (Some(previous_start), Some(previous_end), Some(next_start), Some(next_end)) = (
+            previous.start_date,
+            previous.end_date,
+            next.start_date,
+            next.end_date,
+        ) else {
+            continue;
+        };
+        let balances = previous.closing_balance.zip(next.opening_balance);
+        let balances_chain =
+            balances.is_some_and(|(closing, opening)| (closing - opening).abs() < 0.005);
+        let declared_periods = previous.format != STATEMENT_CSV && next.format != STATEMENT_CSV;
+
+        let gap = |kind: &str,
+                   missing: Option<(NaiveDate, NaiveDate)>,
+                   overlap: Option<(NaiveDate, NaiveDate)>| StatementGap {
+            kind: kind.to_string(),
+            account: next.account.clone(),
+            previous_file_id: *previous_id,
+            next_file_id: *next_id,
+            missing_from: missing.map(|(from, _)| from),
+            missing_to: missing.map(|(_, to)| to),
+            overlap_from: overlap.map(|(from, _)| from),
+            overlap_to: overlap.map(|(_, to)| to),
+            previous_closing_balance: previous.closing_balance,
+            next_opening_balance: next.opening_balance,
+        };
+        let day_after = previous_end.succ_opt().unwrap_or(previous_end);
+        let overlap = (next_start, next_end.min(previous_end));
+        if (previous_start, previous_end) == (next_start, next_end) {
+            gaps.push(gap(GAP_DUPLICATE_STATEMENT, None, Some(overlap)));
+        } else if next_start > day_after && (declared_periods || !balances_chain) {
+            let day_before = next_start.pred_opt().unwrap_or(next_start);
+            gaps.push(gap(GAP_MISSING_PERIOD, Some((day_after, day_before)), None));
+        } else if next_start <= previous_end && (declared_periods || !balances_chain) {
+            gaps.push(gap(GAP_OVERLAPPING_PERIOD, None, Some(overlap)));
+        } else if next_start <= day_after && balances.is_some() && !balances_chain {
+            gaps.push(gap(GAP_BALANCE_MISMATCH, None, None));
+        }
+    }
+    gaps
+}
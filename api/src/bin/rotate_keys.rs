@@ -0,0 +1,24 @@
+use anyhow::Context;
+use trombone::db;
+use trombone::encryption::{Keyring, MasterKey};
+
+// Rotates the keys encrypting stored files without rewriting them: every firm gets a new key,
+// which its files' data keys are re-wrapped with. To replace the master key, run it with the new
+// one in MASTER_KEY and the one it replaces in PREVIOUS_MASTER_KEY.
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let master_key = MasterKey::from_env("MASTER_KEY")?.context("MASTER_KEY must be set")?;
+    let previous_master_key = MasterKey::from_env("PREVIOUS_MASTER_KEY")?;
+    let db_pool = db::setup_database_pool().await;
+
+    let rotation = Keyring::new(db_pool, master_key)
+        .rotate(previous_master_key.as_ref())
+        .await?;
+    println!(
+        "Rotated the keys of {} firms, re-wrapping {} data keys.",
+        rotation.firms, rotation.data_keys
+    );
+    Ok(())
+}
@@ -0,0 +1,492 @@
+use async_trait::async_trait;
+use chrono::{Datelike, NaiveDate};
+use uuid::Uuid;
+
+use crate::model::bank_statement::BankStatementSummary;
+use crate::model::classification::{
+    Classification, CATEGORY_BANK_STATEMENT, CATEGORY_CONTRACT, CATEGORY_OTHER, CATEGORY_PAYSLIP,
+    CATEGORY_PURCHASE_INVOICE, CATEGORY_RECEIPT, CATEGORY_SALES_INVOICE, CATEGORY_TAX_NOTICE,
+};
+use crate::model::invoice::{Invoice, InvoiceParty};
+
+// What is known of an uploaded file once its text and structured data were extracted
+pub struct Document<'a> {
+    pub file_name: &'a str,
+    pub mime_type: &'a str,
+    pub text: Option<&'a str>,
+    pub invoice: Option<&'a Invoice>,
+    pub bank_statement: Option<&'a BankStatementSummary>,
+    pub client_name: &'a str, // Company of the client, to tell its sales from its purchases
+}
+
+// A pending request of the file's collection
+pub struct RequestCandidate {
+    pub id: Uuid,
+    pub title: String,
+    pub description: Option<String>,
+}
+
+#[async_trait]
+pub trait Classifier: Send + Sync {
+    async fn classify(&self, document: &Document<'_>) -> Classification;
+
+    // The request the file most likely answers, if any stands out
+    async fn suggest_request(
+        &self,
+        document: &Document<'_>,
+        classification: &Classification,
+        requests: &[RequestCandidate],
+    ) -> Option<Uuid>;
+}
+
+// Classifies with keyword rules over the file name and text, trusting the structured formats
+// (e-invoices, bank statements) when they were recognized
+pub struct RuleClassifier;
+
+// Only the beginning of long documents is read
+const MAX_CHARS: usize = 20_000;
+
+// Below this score, nothing hints at a known category
+const MIN_SCORE: f32 = 2.0;
+
+// Invoices are told apart into sales and purchases after scoring
+const INVOICE: &str = "invoice";
+
+// Phrases hinting at a category in the normalized file name and text, with their weight
+const RULES: &[(&str, &[(&str, f32)])] = &[
+    (
+        INVOICE,
+        &[
+            ("facture", 2.0),
+            ("invoice", 2.0),
+            ("credit note", 2.0),
+            ("total ht", 2.0),
+            ("montant ht", 1.5),
+            ("date d'echeance", 1.0),
+            ("conditions de paiement", 1.0),
+            ("tva intracommunautaire", 1.5),
+            ("due date", 1.0),
+            ("subtotal", 1.0),
+            ("vat number", 1.0),
+        ],
+    ),
+    (
+        CATEGORY_RECEIPT,
+        &[
+            ("ticket", 2.0),
+            ("recu", 1.5),
+            ("receipt", 2.0),
+            ("merci de votre visite", 3.0),
+            ("a bientot", 1.0),
+            ("caisse", 1.5),
+            ("paiement cb", 1.5),
+            ("carte bancaire", 1.0),
+            ("rendu monnaie", 2.0),
+            ("thank you for", 1.0),
+        ],
+    ),
+    (
+        CATEGORY_BANK_STATEMENT,
+        &[
+            ("releve de compte", 3.0),
+            ("releve bancaire", 3.0),
+            ("extrait de compte", 3.0),
+            ("bank statement", 3.0),
+            ("solde precedent", 2.0),
+            ("ancien solde", 2.0),
+            ("nouveau solde", 2.0),
+            ("solde crediteur", 1.5),
+            ("solde debiteur", 1.5),
+            ("opening balance", 2.0),
+            ("closing balance", 2.0),
+            ("iban", 1.0),
+        ],
+    ),
+    (
+        CATEGORY_PAYSLIP,
+        &[
+            ("bulletin de paie", 4.0),
+            ("bulletin de salaire", 4.0),
+            ("fiche de paie", 4.0),
+            ("payslip", 4.0),
+            ("salaire de base", 2.0),
+            ("salaire brut", 2.0),
+            ("net imposable", 2.0),
+            ("cotisations salariales", 1.5),
+            ("conges payes", 1.0),
+            ("convention collective", 1.0),
+            ("gross pay", 2.0),
+            ("net pay", 2.0),
+        ],
+    ),
+    (
+        CATEGORY_TAX_NOTICE,
+        &[
+            ("avis d'imposition", 4.0),
+            ("avis d'impot", 4.0),
+            ("direction generale des finances publiques", 3.0),
+            ("finances publiques", 2.0),
+            ("dgfip", 2.0),
+            ("impots.gouv", 2.0),
+            ("taxe fonciere", 2.0),
+            ("cotisation fonciere des entreprises", 3.0),
+            ("impot sur le revenu", 2.0),
+            ("impot sur les societes", 2.0),
+            ("revenu fiscal de reference", 2.0),
+            ("tax notice", 3.0),
+            ("tax assessment", 3.0),
+        ],
+    ),
+    (
+        CATEGORY_CONTRACT,
+        &[
+            ("contrat", 2.0),
+            ("contract", 2.0),
+            ("entre les soussignes", 3.0),
+            ("il a ete convenu", 2.0),
+            ("ci-apres denomme", 2.0),
+            ("fait en deux exemplaires", 2.0),
+            ("lu et approuve", 2.0),
+            ("bail", 2.0),
+            ("agreement", 2.0),
+            ("hereinafter", 2.0),
+            ("the parties", 1.5),
+        ],
+    ),
+];
+
+// Photos are mostly of till receipts
+const IMAGE_RECEIPT_SCORE: f32 = 1.5;
+
+// Where the client's own name shows up relative to these, tells who issued an invoice
+const BILL_TO: &[&str] = &[
+    "facture a",
+    "facturer a",
+    "adresse de facturation",
+    "destinataire",
+    "bill to",
+    "billed to",
+    "invoice to",
+];
+
+const SALES_FILE_NAMES: &[&str] = &["vente", "sales", "emise"];
+const PURCHASE_FILE_NAMES: &[&str] = &["achat", "purchase", "fournisseur", "supplier", "recue"];
+
+// Words of request titles and descriptions asking for each category
+const REQUEST_HINTS: &[(&str, &[&str])] = &[
+    (
+        CATEGORY_SALES_INVOICE,
+        &[
+            "vente",
+            "sales",
+            "client",
+            "chiffre d'affaires",
+            "revenue",
+            "emise",
+            "issued",
+        ],
+    ),
+    (
+        CATEGORY_PURCHASE_INVOICE,
+        &[
+            "achat",
+            "purchase",
+            "fournisseur",
+            "supplier",
+            "frais",
+            "depense",
+            "expense",
+        ],
+    ),
+    (
+        CATEGORY_RECEIPT,
+        &[
+            "ticket",
+            "recu",
+            "receipt",
+            "note de frais",
+            "notes de frais",
+            "frais",
+            "depense",
+            "expense",
+        ],
+    ),
+    (
+        CATEGORY_BANK_STATEMENT,
+        &[
+            "releve",
+            "bancaire",
+            "banque",
+            "bank",
+            "statement",
+            "compte",
+        ],
+    ),
+    (
+        CATEGORY_PAYSLIP,
+        &[
+            "paie", "salaire", "salarie", "payslip", "payroll", "bulletin",
+        ],
+    ),
+    (
+        CATEGORY_TAX_NOTICE,
+        &["impot", "imposition", "fiscal", "tax", "taxe", "cfe"],
+    ),
+    (
+        CATEGORY_CONTRACT,
+        &[
+            "contrat",
+            "contract",
+            "bail",
+            "lease",
+            "agreement",
+            "juridique",
+            "legal",
+        ],
+    ),
+];
+
+// Requests for invoices in general fit both sales and purchases, with less weight
+const INVOICE_REQUEST_HINTS: &[&str] = &["facture", "invoice"];
+
+const MONTHS: [[&str; 2]; 12] = [
+    ["janvier", "january"],
+    ["fevrier", "february"],
+    ["mars", "march"],
+    ["avril", "april"],
+    ["mai", "may"],
+    ["juin", "june"],
+    ["juillet", "july"],
+    ["aout", "august"],
+    ["septembre", "september"],
+    ["octobre", "october"],
+    ["novembre", "november"],
+    ["decembre", "december"],
+];
+
+#[async_trait]
+impl Classifier for RuleClassifier {
+    async fn classify(&self, document: &Document<'_>) -> Classification {
+        if document.bank_statement.is_some() {
+            return classification(CATEGORY_BANK_STATEMENT, 0.99);
+        }
+        if let Some(invoice) = document.invoice {
+            return classify_invoice(invoice, document.client_name);
+        }
+
+        let file_name = normalize(document.file_name);
+        let text = document.text.map(normalize).unwrap_or_default();
+        let content = format!("{} {}", file_name, text);
+
+        let mut scores: Vec<(&str, f32)> = RULES
+            .iter()
+            .map(|(category, phrases)| {
+                let score = phrases
+                    .iter()
+                    .filter(|(phrase, _)| mentions(&content, phrase))
+                    .map(|(_, weight)| weight)
+                    .sum();
+                (*category, score)
+            })
+            .collect();
+        if document.mime_type.starts_with("image/") {
+            for (category, score) in scores.iter_mut() {
+                if *category == CATEGORY_RECEIPT {
+                    *score += IMAGE_RECEIPT_SCORE;
+                }
+            }
+        }
+        // Stable, the order of RULES breaks ties
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let (category, best) = scores[0];
+        if best < MIN_SCORE {
+            return classification(CATEGORY_OTHER, 0.5);
+        }
+        let confidence = (best / (best + scores[1].1 + 2.0)).min(0.9);
+        if category != INVOICE {
+            return classification(category, confidence);
+        }
+
+        let normalized_client = normalize(document.client_name);
+        let category = if SALES_FILE_NAMES
+            .iter()
+            .any(|hint| mentions(&file_name, hint))
+        {
+            CATEGORY_SALES_INVOICE
+        } else if PURCHASE_FILE_NAMES
+            .iter()
+            .any(|hint| mentions(&file_name, hint))
+        {
+            CATEGORY_PURCHASE_INVOICE
+        } else if issued_by(&text, &normalized_client) {
+            CATEGORY_SALES_INVOICE
+        } else {
+            CATEGORY_PURCHASE_INVOICE
+        };
+        classification(category, confidence * 0.9)
+    }
+
+    async fn suggest_request(
+        &self,
+        document: &Document<'_>,
+        classification: &Classification,
+        requests: &[RequestCandidate],
+    ) -> Option<Uuid> {
+        let hints = REQUEST_HINTS
+            .iter()
+            .find(|(category, _)| *category == classification.category)
+            .map(|(_, hints)| *hints)?;
+        let invoice_hints = match classification.category.as_str() {
+            CATEGORY_SALES_INVOICE | CATEGORY_PURCHASE_INVOICE => INVOICE_REQUEST_HINTS,
+            _ => &[],
+        };
+        let month = document_date(document).map(|date| MONTHS[date.month0() as usize]);
+
+        let mut best: Option<(Uuid, f32)> = None;
+        for request in requests {
+            let title = normalize(&request.title);
+            let description = request
+                .description
+                .as_deref()
+                .map(normalize)
+                .unwrap_or_default();
+
+            // Mentions in the title count double
+            let mut score = 0.0;
+            for (hint, weight) in hints
+                .iter()
+                .map(|hint| (hint, 2.0))
+                .chain(invoice_hints.iter().map(|hint| (hint, 1.0)))
+            {
+                if mentions(&title, hint) {
+                    score += weight * 2.0;
+                } else if mentions(&description, hint) {
+                    score += weight;
+                }
+            }
+            if score == 0.0 {
+                continue;
+            }
+            // The month of the document breaks ties between requests of several periods
+            if month.is_some_and(|names| {
+                names
+                    .iter()
+                    .any(|name| mentions(&title, name) || mentions(&description, name))
+            }) {
+                score += 1.0;
+            }
+
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((request.id, score));
+            }
+        }
+
+        best.map(|(id, _)| id)
+    }
+}
+
+fn classification(category: &str, confidence: f32) -> Classification {
+    Classification {
+        category: category.to_string(),
+        confidence: (confidence * 100.0).round() / 100.0,
+    }
+}
+
+// A structured invoice is a sale when the client is its seller
+fn classify_invoice(invoice: &Invoice, client_name: &str) -> Classification {
+    let client_name = normalize(client_name);
+    if is_party(&invoice.seller, &client_name) {
+        classification(CATEGORY_SALES_INVOICE, 0.95)
+    } else if is_party(&invoice.buyer, &client_name) {
+        classification(CATEGORY_PURCHASE_INVOICE, 0.95)
+    } else {
+        // Clients mostly upload the bills of their suppliers
+        classification(CATEGORY_PURCHASE_INVOICE, 0.7)
+    }
+}
+
+fn is_party(party: &InvoiceParty, client_name: &str) -> bool {
+    let Some(name) = party.name.as_deref().map(normalize) else {
+        return false;
+    };
+    client_name.len() >= 3
+        && name.len() >= 3
+        && (name.contains(client_name) || client_name.contains(&name))
+}
+
+// Whether the client's name opens the invoice as its letterhead, before any "bill to" block
+fn issued_by(text: &str, client_name: &str) -> bool {
+    if client_name.len() < 3 {
+        return false;
+    }
+    let Some(position) = find_word(text, client_name) else {
+        return false;
+    };
+    match BILL_TO
+        .iter()
+        .filter_map(|marker| find_word(text, marker))
+        .min()
+    {
+        Some(bill_to) => position < bill_to,
+        None => position < 300,
+    }
+}
+
+fn document_date(document: &Document) -> Option<NaiveDate> {
+    document
+        .invoice
+        .and_then(|invoice| invoice.issue_date)
+        .or_else(|| {
+            document
+                .bank_statement
+                .and_then(|statement| statement.start_date)
+        })
+}
+
+// Lowercase text without accents, with single spaces between words
+pub fn normalize(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len().min(MAX_CHARS));
+    let mut space = false;
+    for c in text.chars().take(MAX_CHARS) {
+        if c.is_whitespace() || c == '_' {
+            if !space {
+                normalized.push(' ');
+            }
+            space = true;
+            continue;
+        }
+        space = false;
+        for c in c.to_lowercase() {
+            match c {
+                'à' | 'â' | 'ä' | 'á' => normalized.push('a'),
+                'é' | 'è' | 'ê' | 'ë' => normalized.push('e'),
+                'î' | 'ï' | 'í' => normalized.push('i'),
+                'ô' | 'ö' | 'ó' => normalized.push('o'),
+                'ù' | 'û' | 'ü' | 'ú' => normalized.push('u'),
+                'ç' => normalized.push('c'),
+                'œ' => normalized.push_str("oe"),
+                '’' | '`' => normalized.push('\''),
+                c => normalized.push(c),
+            }
+        }
+    }
+    normalized
+}
+
+fn mentions(text: &str, phrase: &str) -> bool {
+    find_word(text, phrase).is_some()
+}
+
+// Where the phrase starts a word, e.g. "facture" in "factures". Phrases of up to three letters
+// must be whole words.
+fn find_word(text: &str, phrase: &str) -> Option<usize> {
+    text.match_indices(phrase)
+        .map(|(start, _)| start)
+        .find(|&start| {
+            let before = text[..start].chars().next_back();
+            let after = text[start + phrase.len()..].chars().next();
+            !before.is_some_and(char::is_alphanumeric)
+                && (phrase.len() > 3 || !after.is_some_and(char::is_alphanumeric))
+        })
+}
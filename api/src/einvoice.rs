@@ -0,0 +1,642 @@
+use bytes::Bytes;
+use chrono::NaiveDate;
+use lopdf::{Dictionary, Document, Object};
+use roxmltree::Node;
+use rust_decimal::Decimal;
+
+use crate::model::invoice::{
+    Invoice, InvoiceLine, InvoiceParty, INVOICE_CII, INVOICE_FACTURX, INVOICE_UBL,
+};
+use crate::xml::{child, descendant, node_text, text, LocalName};
+
+// Names of the XML attached to Factur-X, ZUGFeRD 1/2 and XRechnung PDFs
+const EMBEDDED_NAMES: &[&str] = &[
+    "factur-x.xml",
+    "zugferd-invoice.xml",
+    "zugferd_invoice.xml",
+    "xrechnung.xml",
+];
+
+const CII_NAMESPACE: &str = "urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100";
+const UBL_INVOICE_NAMESPACE: &str = "urn:oasis:names:specification:ubl:schema:xsd:Invoice-2";
+const UBL_CREDIT_NOTE_NAMESPACE: &str = "urn:oasis:names:specification:ubl:schema:xsd:CreditNote-2";
+
+// Parses the invoice of a Factur-X/ZUGFeRD PDF or of a UBL/CII XML file, None when the
+// file is not an e-invoice
+pub async fn parse_invoice(mime_type: &str, content: Bytes) -> anyhow::Result<Option<Invoice>> {
+    let mime_type = mime_type.to_string();
+    tokio::task::spawn_blocking(move || match mime_type.as_str() {
+        "application/pdf" => {
+            let Some(xml) = embedded_xml(&content)? else {
+                return Ok(None);
+            };
+            let xml = String::from_utf8(xml)?;
+            Ok(Some(parse_cii(&xml, INVOICE_FACTURX)?))
+        }
+        "application/xml" => parse_xml(std::str::from_utf8(&content)?),
+        _ => Ok(None),
+    })
+    .await?
+}
+
+// Recognizes standalone e-invoices from their root element, other XML files are ignored
+fn parse_xml(xml: &str) -> anyhow::Result<Option<Invoice>> {
+    let document = roxmltree::Document::parse(xml)?;
+    let root = document.root_element();
+    let invoice = match root.tag_name().name() {
+        "CrossIndustryInvoice" => cii_invoice(root, INVOICE_CII),
+        "Invoice" | "CreditNote" if root.tag_name().namespace().is_some_and(is_ubl) => {
+            ubl_invoice(root)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(invoice))
+}
+
+fn is_ubl(namespace: &str) -> bool {
+    namespace == UBL_INVOICE_NAMESPACE || namespace == UBL_CREDIT_NOTE_NAMESPACE
+}
+
+// Finds the invoice XML among the files attached to the PDF
+fn embedded_xml(content: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    let document = Document::load_mem(content)?;
+    let catalog = document.catalog()?;
+    let Some(tree) = catalog
+        .get(b"Names")
+        .and_then(|names| resolve_dict(&document, names))
+        .and_then(|names| names.get(b"EmbeddedFiles"))
+        .and_then(|tree| resolve_dict(&document, tree))
+        .ok()
+    else {
+        return Ok(None);
+    };
+
+    let mut file_specs = Vec::new();
+    collect_name_tree(&document, tree, &mut file_specs, 0);
+    for file_spec in file_specs {
+        let name = ["UF", "F"]
+            .iter()
+            .find_map(|key| {
+                file_spec
+                    .get(key.as_bytes())
+                    .and_then(Object::as_string)
+                    .ok()
+            })
+            .unwrap_or_default()
+            .to_lowercase();
+        if !EMBEDDED_NAMES.contains(&name.as_str()) {
+            continue;
+        }
+        let stream = file_spec
+            .get(b"EF")
+            .and_then(|files| resolve_dict(&document, files))
+            .and_then(|files| files.get(b"F"))
+            .and_then(|file| document.dereference(file))
+            .and_then(|(_, file)| file.as_stream())?;
+        let xml = match stream.filters() {
+            Ok(filters) if !filters.is_empty() => stream.decompressed_content()?,
+            _ => stream.content.clone(),
+        };
+        return Ok(Some(xml));
+    }
+    Ok(None)
+}
+
+fn resolve_dict<'a>(document: &'a Document, object: &'a Object) -> lopdf::Result<&'a Dictionary> {
+    document.dereference(object)?.1.as_dict()
+}
+
+// Name trees list their values in `Names` ([key, value, ...]) or split them in `Kids`
+fn collect_name_tree<'a>(
+    document: &'a Document,
+    node: &'a Dictionary,
+    values: &mut Vec<&'a Dictionary>,
+    depth: usize,
+) {
+    // Malformed PDFs can have cycles
+    if depth > 16 {
+        return;
+    }
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for value in names.iter().skip(1).step_by(2) {
+            if let Ok(value) = resolve_dict(document, value) {
+                values.push(value);
+            }
+        }
+    }
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            if let Ok(kid) = resolve_dict(document, kid) {
+                collect_name_tree(document, kid, values, depth + 1);
+            }
+        }
+    }
+}
+
+// Parses a UN/CEFACT Cross Industry Invoice (D16B), the XML of Factur-X and ZUGFeRD 2.
+// Elements are matched on their local name, namespace prefixes vary between producers.
+pub fn parse_cii(xml: &str, format: &str) -> anyhow::Result<Invoice> {
+    let document = roxmltree::Document::parse(xml)?;
+    let root = document.root_element();
+    if root.tag_name().name() != "CrossIndustryInvoice" {
+        anyhow::bail!("not a Cross Industry Invoice: {}", root.tag_name().name());
+    }
+    Ok(cii_invoice(root, format))
+}
+
+fn cii_invoice(root: Node, format: &str) -> Invoice {
+    let exchanged = child(root, "ExchangedDocument");
+    let transaction = child(root, "SupplyChainTradeTransaction");
+    let agreement = transaction.and_then(|t| child(t, "ApplicableHeaderTradeAgreement"));
+    let settlement = transaction.and_then(|t| child(t, "ApplicableHeaderTradeSettlement"));
+    let currency = settlement.and_then(|s| text(s, &["InvoiceCurrencyCode"]));
+    let summation =
+        settlement.and_then(|s| child(s, "SpecifiedTradeSettlementHeaderMonetarySummation"));
+
+    let lines = transaction
+        .map(|t| {
+            t.children()
+                .filter(|node| node.has_tag_name_local("IncludedSupplyChainTradeLineItem"))
+                .map(cii_line)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut invoice = Invoice {
+        format: format.to_string(),
+        profile: text(
+            root,
+            &[
+                "ExchangedDocumentContext",
+                "GuidelineSpecifiedDocumentContextParameter",
+                "ID",
+            ],
+        ),
+        number: exchanged.and_then(|document| text(document, &["ID"])),
+        type_code: exchanged.and_then(|document| text(document, &["TypeCode"])),
+        issue_date: exchanged
+            .and_then(|document| date(document, &["IssueDateTime", "DateTimeString"])),
+        due_date: settlement.and_then(|s| {
+            date(
+                s,
+                &[
+                    "SpecifiedTradePaymentTerms",
+                    "DueDateDateTime",
+                    "DateTimeString",
+                ],
+            )
+        }),
+        seller: agreement
+            .and_then(|a| child(a, "SellerTradeParty"))
+            .map(cii_party)
+            .unwrap_or_default(),
+        buyer: agreement
+            .and_then(|a| child(a, "BuyerTradeParty"))
+            .map(cii_party)
+            .unwrap_or_default(),
+        net_total: summation.and_then(|s| amount(s, &["TaxBasisTotalAmount"])),
+        vat_total: summation.and_then(|s| amount_in(s, "TaxTotalAmount", currency.as_deref())),
+        gross_total: summation.and_then(|s| amount(s, &["GrandTotalAmount"])),
+        currency,
+        lines,
+        validation_errors: Vec::new(),
+    };
+
+    let mut errors = Vec::new();
+    if root.tag_name().namespace() != Some(CII_NAMESPACE) {
+        errors.push(format!(
+            "The root element is not in the {} namespace",
+            CII_NAMESPACE
+        ));
+    }
+    errors.extend(validate(
+        &invoice,
+        summation.and_then(|s| amount(s, &["LineTotalAmount"])),
+    ));
+    invoice.validation_errors = errors;
+    invoice
+}
+
+fn cii_party(party: Node) -> InvoiceParty {
+    let vat_number = party
+        .children()
+        .filter(|node| node.has_tag_name_local("SpecifiedTaxRegistration"))
+        .filter_map(|registration| child(registration, "ID"))
+        .find(|id| id.attribute("schemeID") == Some("VA"))
+        .and_then(|id| node_text(id));
+    // SIREN is scheme 0002 of ISO 6523, the SIRET (0009) starts with it
+    let legal_id = child(party, "SpecifiedLegalOrganization").and_then(|o| child(o, "ID"));
+    let siren = legal_id
+        .and_then(|id| {
+            let value = node_text(id)?;
+            match id.attribute("schemeID") {
+                Some("0002") | Some("0009") | None => siren_from(&value),
+                _ => None,
+            }
+        })
+        .or_else(|| vat_number.as_deref().and_then(siren_from_vat));
+
+    InvoiceParty {
+        name: text(party, &["Name"]),
+        siren,
+        vat_number,
+    }
+}
+
+fn cii_line(item: Node) -> InvoiceLine {
+    let settlement = child(item, "SpecifiedLineTradeSettlement");
+    InvoiceLine {
+        id: text(item, &["AssociatedDocumentLineDocument", "LineID"]),
+        description: text(item, &["SpecifiedTradeProduct", "Name"]),
+        quantity: amount(item, &["SpecifiedLineTradeDelivery", "BilledQuantity"]),
+        unit_price: amount(
+            item,
+            &[
+                "SpecifiedLineTradeAgreement",
+                "NetPriceProductTradePrice",
+                "ChargeAmount",
+            ],
+        ),
+        net_amount: settlement.and_then(|s| {
+            amount(
+                s,
+                &[
+                    "SpecifiedTradeSettlementLineMonetarySummation",
+                    "LineTotalAmount",
+                ],
+            )
+        }),
+        vat_rate: settlement
+            .and_then(|s| amount(s, &["ApplicableTradeTax", "RateApplicablePercent"])),
+    }
+}
+
+// Parses a UBL 2.1 invoice or credit note, the syntax of Peppol and XRechnung
+pub fn parse_ubl(xml: &str) -> anyhow::Result<Invoice> {
+    let document = roxmltree::Document::parse(xml)?;
+    let root = document.root_element();
+    if !matches!(root.tag_name().name(), "Invoice" | "CreditNote") {
+        anyhow::bail!("not a UBL invoice: {}", root.tag_name().name());
+    }
+    Ok(ubl_invoice(root))
+}
+
+fn ubl_invoice(root: Node) -> Invoice {
+    // Credit notes name their elements after themselves
+    let (type_element, line_element, quantity_element) = match root.tag_name().name() {
+        "CreditNote" => ("CreditNoteTypeCode", "CreditNoteLine", "CreditedQuantity"),
+        _ => ("InvoiceTypeCode", "InvoiceLine", "InvoicedQuantity"),
+    };
+    let currency = text(root, &["DocumentCurrencyCode"]);
+    let totals = child(root, "LegalMonetaryTotal");
+    // The VAT total is repeated in the accounting currency when it differs
+    let vat_total = root
+        .children()
+        .filter(|node| node.has_tag_name_local("TaxTotal"))
+        .find_map(|total| amount_in(total, "TaxAmount", currency.as_deref()));
+
+    let lines = root
+        .children()
+        .filter(|node| node.has_tag_name_local(line_element))
+        .map(|line| ubl_line(line, quantity_element))
+        .collect();
+
+    let mut invoice = Invoice {
+        format: INVOICE_UBL.to_string(),
+        profile: text(root, &["CustomizationID"]),
+        number: text(root, &["ID"]),
+        type_code: text(root, &[type_element]),
+        issue_date: ubl_date(root, &["IssueDate"]),
+        due_date: ubl_date(root, &["DueDate"])
+            .or_else(|| ubl_date(root, &["PaymentMeans", "PaymentDueDate"])),
+        seller: descendant(root, &["AccountingSupplierParty", "Party"])
+            .map(ubl_party)
+            .unwrap_or_default(),
+        buyer: descendant(root, &["AccountingCustomerParty", "Party"])
+            .map(ubl_party)
+            .unwrap_or_default(),
+        net_total: totals.and_then(|t| amount(t, &["TaxExclusiveAmount"])),
+        vat_total,
+        gross_total: totals.and_then(|t| amount(t, &["TaxInclusiveAmount"])),
+        currency,
+        lines,
+        validation_errors: Vec::new(),
+    };
+    invoice.validation_errors = validate(
+        &invoice,
+        totals.and_then(|t| amount(t, &["LineExtensionAmount"])),
+    );
+    invoice
+}
+
+fn ubl_party(party: Node) -> InvoiceParty {
+    let vat_number = party
+        .children()
+        .filter(|node| node.has_tag_name_local("PartyTaxScheme"))
+        .find(|scheme| text(*scheme, &["TaxScheme", "ID"]).as_deref() == Some("VAT"))
+        .and_then(|scheme| text(scheme, &["CompanyID"]));
+    let legal_entity = child(party, "PartyLegalEntity");
+    let siren = legal_entity
+        .and_then(|entity| child(entity, "CompanyID"))
+        .and_then(|id| {
+            let value = node_text(id)?;
+            match id.attribute("schemeID") {
+                Some("0002") | Some("0009") | None => siren_from(&value),
+                _ => None,
+            }
+        })
+        .or_else(|| vat_number.as_deref().and_then(siren_from_vat));
+
+    InvoiceParty {
+        name: text(party, &["PartyName", "Name"])
+            .or_else(|| legal_entity.and_then(|entity| text(entity, &["RegistrationName"]))),
+        siren,
+        vat_number,
+    }
+}
+
+fn ubl_line(line: Node, quantity_element: &str) -> InvoiceLine {
+    let item = child(line, "Item");
+    InvoiceLine {
+        id: text(line, &["ID"]),
+        description: item
+            .and_then(|item| text(item, &["Name"]).or_else(|| text(item, &["Description"]))),
+        quantity: amount(line, &[quantity_element]),
+        unit_price: amount(line, &["Price", "PriceAmount"]),
+        net_amount: amount(line, &["LineExtensionAmount"]),
+        vat_rate: item.and_then(|item| amount(item, &["ClassifiedTaxCategory", "Percent"])),
+    }
+}
+
+// Checks the EN 16931 business rules that can be verified on the normalized invoice, and the
+// French company identifiers. `line_total` is the sum of the lines announced by the invoice.
+// Rules on addresses, allowances and the VAT breakdown, which the normalized invoice does not
+// keep, are not checked.
+fn validate(invoice: &Invoice, line_total: Option<Decimal>) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut require = |present: bool, rule: &str| {
+        if !present {
+            errors.push(rule.to_string());
+        }
+    };
+    require(
+        invoice.profile.is_some(),
+        "BR-01: An invoice shall have a specification identifier",
+    );
+    require(
+        invoice.number.is_some(),
+        "BR-02: An invoice shall have an invoice number",
+    );
+    require(
+        invoice.issue_date.is_some(),
+        "BR-03: An invoice shall have an issue date",
+    );
+    require(
+        invoice.type_code.is_some(),
+        "BR-04: An invoice shall have an invoice type code",
+    );
+    require(
+        invoice.currency.is_some(),
+        "BR-05: An invoice shall have a currency code",
+    );
+    require(
+        invoice.seller.name.is_some(),
+        "BR-06: An invoice shall contain the seller name",
+    );
+    require(
+        invoice.buyer.name.is_some(),
+        "BR-07: An invoice shall contain the buyer name",
+    );
+    require(
+        line_total.is_some(),
+        "BR-12: An invoice shall have the sum of invoice line net amount",
+    );
+    require(
+        invoice.net_total.is_some(),
+        "BR-13: An invoice shall have the invoice total amount without VAT",
+    );
+    require(
+        invoice.gross_total.is_some(),
+        "BR-14: An invoice shall have the invoice total amount with VAT",
+    );
+    require(
+        !invoice.lines.is_empty(),
+        "BR-16: An invoice shall have at least one invoice line",
+    );
+
+    for (index, line) in invoice.lines.iter().enumerate() {
+        let label = line.id.clone().unwrap_or_else(|| (index + 1).to_string());
+        let mut require_on_line = |present: bool, rule: &str| {
+            if !present {
+                errors.push(format!("{} (line {})", rule, label));
+            }
+        };
+        require_on_line(
+            line.id.is_some(),
+            "BR-21: Each invoice line shall have an invoice line identifier",
+        );
+        require_on_line(
+            line.quantity.is_some(),
+            "BR-22: Each invoice line shall have an invoiced quantity",
+        );
+        require_on_line(
+            line.net_amount.is_some(),
+            "BR-24: Each invoice line shall have an invoice line net amount",
+        );
+        require_on_line(
+            line.description.is_some(),
+            "BR-25: Each invoice line shall contain the item name",
+        );
+        require_on_line(
+            line.unit_price.is_some(),
+            "BR-26: Each invoice line shall contain the item net price",
+        );
+        require_on_line(
+            line.unit_price
+                .is_none_or(|price| !price.is_sign_negative()),
+            "BR-27: The item net price shall not be negative",
+        );
+        require_on_line(
+            has_cents(line.net_amount),
+            "BR-DEC-23: The invoice line net amount shall have at most 2 decimals",
+        );
+    }
+
+    for (amount, rule) in [
+        (
+            line_total,
+            "BR-DEC-09: The sum of invoice line net amount shall have at most 2 decimals",
+        ),
+        (
+            invoice.net_total,
+            "BR-DEC-12: The invoice total amount without VAT shall have at most 2 decimals",
+        ),
+        (
+            invoice.vat_total,
+            "BR-DEC-13: The invoice total VAT amount shall have at most 2 decimals",
+        ),
+        (
+            invoice.gross_total,
+            "BR-DEC-14: The invoice total amount with VAT shall have at most 2 decimals",
+        ),
+    ] {
+        if !has_cents(amount) {
+            errors.push(rule.to_string());
+        }
+    }
+
+    // Decimal amounts add up exactly, those too large to add are left unchecked
+    let lines_sum = invoice
+        .lines
+        .iter()
+        .filter_map(|line| line.net_amount)
+        .try_fold(Decimal::ZERO, |sum, amount| sum.checked_add(amount));
+    if let (Some(line_total), Some(lines_sum)) = (line_total, lines_sum) {
+        if line_total != lines_sum {
+            errors.push(format!(
+                "BR-CO-10: The sum of invoice line net amounts ({:.2}) shall equal the total ({:.2})",
+                lines_sum, line_total
+            ));
+        }
+    }
+    if let (Some(net), Some(vat), Some(gross)) =
+        (invoice.net_total, invoice.vat_total, invoice.gross_total)
+    {
+        if net.checked_add(vat).is_some_and(|total| total != gross) {
+            errors.push(format!(
+                "BR-CO-15: The total with VAT ({:.2}) shall equal the total without VAT ({:.2}) plus the VAT ({:.2})",
+                gross, net, vat
+            ));
+        }
+    }
+
+    for (role, party) in [("seller", &invoice.seller), ("buyer", &invoice.buyer)] {
+        if let Some(vat_number) = &party.vat_number {
+            if !vat_number
+                .get(..2)
+                .is_some_and(|prefix| prefix.chars().all(|c| c.is_ascii_uppercase()))
+            {
+                errors.push(format!(
+                    "BR-CO-9: The {} VAT identifier {} shall have an ISO country code prefix",
+                    role, vat_number
+                ));
+            } else if vat_number.starts_with("FR") && !is_valid_french_vat(vat_number) {
+                errors.push(format!(
+                    "The {} VAT identifier {} is not a valid French VAT number",
+                    role, vat_number
+                ));
+            }
+        }
+        if let Some(siren) = &party.siren {
+            if !is_valid_siren(siren) {
+                errors.push(format!("The {} SIREN {} is not valid", role, siren));
+            }
+        }
+    }
+
+    errors
+}
+
+// SIREN numbers end with a Luhn check digit
+pub fn is_valid_siren(siren: &str) -> bool {
+    has_luhn_key(siren, 9)
+}
+
+// So do SIRET numbers, the SIREN followed by the five digits of an establishment
+pub fn is_valid_siret(siret: &str) -> bool {
+    has_luhn_key(siret, 14)
+}
+
+fn has_luhn_key(number: &str, length: usize) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() != length {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(position, &digit)| match position % 2 {
+            0 => digit,
+            _ if digit * 2 > 9 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+// The key of a French VAT number is computed from the SIREN
+pub fn is_valid_french_vat(vat_number: &str) -> bool {
+    let vat_number = normalize_vat(vat_number);
+    let Some(siren) = siren_from_vat(&vat_number) else {
+        return false;
+    };
+    let (Ok(key), Ok(siren)) = (vat_number[2..4].parse::<u64>(), siren.parse::<u64>()) else {
+        return false;
+    };
+    key == (12 + 3 * (siren % 97)) % 97
+}
+
+// The first nine digits of a SIREN or SIRET
+pub fn siren_from(value: &str) -> Option<String> {
+    let digits: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+    (matches!(digits.len(), 9 | 14) && digits.chars().all(|c| c.is_ascii_digit()))
+        .then(|| digits[..9].to_string())
+}
+
+// French VAT numbers are "FR", a two characters key and the SIREN
+pub fn siren_from_vat(vat_number: &str) -> Option<String> {
+    normalize_vat(vat_number)
+        .strip_prefix("FR")
+        .filter(|rest| rest.is_ascii() && rest.len() == 11)
+        .and_then(|rest| siren_from(&rest[2..]))
+}
+
+// VAT numbers are written in upper case, often with spaces
+fn normalize_vat(vat_number: &str) -> String {
+    vat_number
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+// Amounts are written with at most two decimals (BR-DEC rules), as their scale keeps
+fn has_cents(amount: Option<Decimal>) -> bool {
+    amount.is_none_or(|amount| amount.scale() <= 2)
+}
+
+fn amount(node: Node, path: &[&str]) -> Option<Decimal> {
+    descendant(node, path).and_then(|node| parse_amount(node.text()?))
+}
+
+// An amount repeated in several currencies, in the given one when it is known
+fn amount_in(node: Node, name: &str, currency: Option<&str>) -> Option<Decimal> {
+    node.children()
+        .filter(|node| node.has_tag_name_local(name))
+        .find(|node| {
+            currency.is_none()
+                || node.attribute("currencyID").is_none()
+                || node.attribute("currencyID") == currency
+        })
+        .and_then(|node| parse_amount(node.text()?))
+}
+
+fn parse_amount(value: &str) -> Option<Decimal> {
+    value.trim().parse().ok()
+}
+
+// UBL dates are "YYYY-MM-DD"
+fn ubl_date(node: Node, path: &[&str]) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(&text(node, path)?, "%Y-%m-%d").ok()
+}
+
+// CII dates are "YYYYMMDD" (format 102)
+fn date(node: Node, path: &[&str]) -> Option<NaiveDate> {
+    let node = descendant(node, path)?;
+    let value = node.text()?.trim();
+    match node.attribute("format") {
+        Some("102") | None => NaiveDate::parse_from_str(value, "%Y%m%d").ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,503 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::{stream, StreamExt};
+use sqlx::{PgConnection, PgPool};
+use std::io::{Cursor, ErrorKind};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+use crate::storage::{key_firm, Storage, StorageReader};
+
+// Envelope encryption of stored objects: every object is encrypted with a data key of its own,
+// kept in `data_keys` encrypted with the key of its firm, itself kept in `firm_keys` encrypted
+// with the master key from the configuration.
+
+// Encrypted objects start with it and the id of their data key, followed by their chunks
+const MAGIC: &[u8; 5] = b"TRBE2";
+// Objects encrypted before they named their data key, which is the oldest of their storage key
+const UNNAMED_MAGIC: &[u8; 5] = b"TRBE1";
+const KEY_ID_SIZE: usize = 16;
+// Plaintext of a chunk. Chunks are sealed one by one so that objects are streamed, the last one
+// being shorter, or empty, so that a truncated object is told apart.
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+
+// Data keys re-wrapped by each UPDATE of a rotation
+const ROTATION_BATCH: usize = 1000;
+
+pub type KeyBytes = [u8; 32];
+
+// Wraps the firm keys, given in base64 (e.g. from `openssl rand -base64 32`)
+#[derive(Clone)]
+pub struct MasterKey(KeyBytes);
+
+impl MasterKey {
+    pub fn from_base64(encoded: &str) -> anyhow::Result<Self> {
+        let key = STANDARD.decode(encoded.trim())?;
+        let key = key
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("a master key is 32 bytes long"))?;
+        Ok(Self(key))
+    }
+
+    // None when the variable is not set
+    pub fn from_env(name: &str) -> anyhow::Result<Option<Self>> {
+        match std::env::var(name) {
+            Ok(encoded) => Ok(Some(
+                Self::from_base64(&encoded).with_context(|| format!("invalid {}", name))?,
+            )),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+pub fn generate_key() -> KeyBytes {
+    Aes256Gcm::generate_key(OsRng).into()
+}
+
+// Encrypts a key with another one, as a random nonce followed by the sealed key
+pub fn wrap_key(wrapping_key: &KeyBytes, key: &KeyBytes) -> Vec<u8> {
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let sealed = cipher(wrapping_key)
+        .encrypt(&nonce, key.as_slice())
+        .expect("AES-GCM encrypts any key");
+    [nonce.as_slice(), &sealed].concat()
+}
+
+pub fn unwrap_key(wrapping_key: &KeyBytes, wrapped: &[u8]) -> anyhow::Result<KeyBytes> {
+    if wrapped.len() < NONCE_SIZE {
+        anyhow::bail!("the wrapped key is truncated");
+    }
+    let (nonce, sealed) = wrapped.split_at(NONCE_SIZE);
+    let key = cipher(wrapping_key)
+        .decrypt(Nonce::from_slice(nonce), sealed)
+        .map_err(|_| anyhow::anyhow!("the key was wrapped with another key or is corrupted"))?;
+    key.try_into()
+        .map_err(|_| anyhow::anyhow!("the unwrapped key is not 32 bytes long"))
+}
+
+fn cipher(key: &KeyBytes) -> Aes256Gcm {
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+}
+
+// Data keys are never reused, so chunks are numbered rather than given random nonces
+fn chunk_nonce(index: u64, last: bool) -> [u8; NONCE_SIZE] {
+    let mut nonce = [0; NONCE_SIZE];
+    nonce[..8].copy_from_slice(&index.to_be_bytes());
+    nonce[NONCE_SIZE - 1] = last as u8;
+    nonce
+}
+
+// Size of the content of an encrypted object of `encrypted_size` bytes
+pub fn plaintext_size(encrypted_size: u64) -> u64 {
+    let sealed = encrypted_size.saturating_sub((MAGIC.len() + KEY_ID_SIZE) as u64);
+    let chunks = sealed.div_ceil((CHUNK_SIZE + TAG_SIZE) as u64);
+    sealed.saturating_sub(chunks * TAG_SIZE as u64)
+}
+
+// Reads until the buffer is full or the reader ends, returning how much was read
+async fn read_full(
+    reader: &mut (dyn AsyncRead + Send + Unpin),
+    buffer: &mut [u8],
+) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]).await? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+// Streams the encryption of everything the reader yields, with the data key `key_id`
+pub fn encrypt<'a>(
+    key: &KeyBytes,
+    key_id: Uuid,
+    reader: &'a mut (dyn AsyncRead + Send + Unpin),
+) -> impl AsyncRead + Send + Unpin + 'a {
+    let cipher = cipher(key);
+    let header = [MAGIC.as_slice(), key_id.as_bytes()].concat();
+    let header = stream::once(async { Ok::<_, std::io::Error>(Cursor::new(header)) });
+    let chunks = stream::try_unfold((reader, 0u64, false), move |(reader, index, done)| {
+        let cipher = cipher.clone();
+        async move {
+            if done {
+                return Ok(None);
+            }
+            let mut chunk = vec![0; CHUNK_SIZE];
+            let read = read_full(reader, &mut chunk).await?;
+            chunk.truncate(read);
+            let last = read < CHUNK_SIZE;
+            let sealed = cipher
+                .encrypt(
+                    Nonce::from_slice(&chunk_nonce(index, last)),
+                    chunk.as_slice(),
+                )
+                .map_err(|_| std::io::Error::other("failed to encrypt a chunk"))?;
+            Ok(Some((Cursor::new(sealed), (reader, index + 1, last))))
+        }
+    });
+    StreamReader::new(Box::pin(header.chain(chunks)))
+}
+
+// Streams the content of an encrypted object, failing on any tampered or missing chunk
+pub async fn decrypt(key: &KeyBytes, mut reader: StorageReader) -> anyhow::Result<StorageReader> {
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic).await?;
+    if &magic == MAGIC {
+        reader.read_exact(&mut [0; KEY_ID_SIZE]).await?;
+    } else if &magic != UNNAMED_MAGIC {
+        anyhow::bail!("the object is not encrypted");
+    }
+
+    let cipher = cipher(key);
+    let chunks = stream::try_unfold((reader, 0u64, false), move |(mut reader, index, done)| {
+        let cipher = cipher.clone();
+        async move {
+            if done {
+                return Ok(None);
+            }
+            let mut chunk = vec![0; CHUNK_SIZE + TAG_SIZE];
+            let read = read_full(&mut *reader, &mut chunk).await?;
+            if read < TAG_SIZE {
+                return Err(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "the encrypted object is truncated",
+                ));
+            }
+            chunk.truncate(read);
+            let last = read < CHUNK_SIZE + TAG_SIZE;
+            let plaintext = cipher
+                .decrypt(
+                    Nonce::from_slice(&chunk_nonce(index, last)),
+                    chunk.as_slice(),
+                )
+                .map_err(|_| {
+                    std::io::Error::new(ErrorKind::InvalidData, "a chunk failed to decrypt")
+                })?;
+            Ok(Some((Cursor::new(plaintext), (reader, index + 1, last))))
+        }
+    });
+    Ok(Box::new(StreamReader::new(Box::pin(chunks))))
+}
+
+// The firm and data keys, kept wrapped in the database
+#[derive(Clone)]
+pub struct Keyring {
+    db_pool: PgPool,
+    master_key: MasterKey,
+}
+
+// Outcome of a key rotation
+#[derive(Debug, Default)]
+pub struct Rotation {
+    pub firms: usize,
+    pub data_keys: usize,
+}
+
+impl Keyring {
+    pub fn new(db_pool: PgPool, master_key: MasterKey) -> Self {
+        Self {
+            db_pool,
+            master_key,
+        }
+    }
+
+    // Key of a firm, created along with its first object. The row is locked until the end of
+    // the transaction, so that no data key is wrapped with a key being rotated.
+    async fn firm_key(&self, conn: &mut PgConnection, firm_id: Uuid) -> anyhow::Result<KeyBytes> {
+        sqlx::query!(
+            "INSERT INTO firm_keys (firm_id, wrapped_key) VALUES ($1, $2) ON CONFLICT (firm_id) DO NOTHING",
+            firm_id,
+            wrap_key(&self.master_key.0, &generate_key())
+        )
+        .execute(&mut *conn)
+        .await?;
+        let wrapped = sqlx::query_scalar!(
+            "SELECT wrapped_key FROM firm_keys WHERE firm_id = $1 FOR SHARE",
+            firm_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        unwrap_key(&self.master_key.0, &wrapped)
+    }
+
+    // Adds a data key next to those of the object's previous content. Returns its id.
+    async fn save_data_key(
+        &self,
+        storage_key: &str,
+        firm_id: Uuid,
+        data_key: &KeyBytes,
+    ) -> anyhow::Result<Uuid> {
+        let mut tx = self.db_pool.begin().await?;
+        let firm_key = self.firm_key(&mut tx, firm_id).await?;
+        let key_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO data_keys (storage_key, firm_id, wrapped_key) VALUES ($1, $2, $3)
+            RETURNING id
+            "#,
+            storage_key,
+            firm_id,
+            wrap_key(&firm_key, data_key)
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(key_id)
+    }
+
+    // The data key `key_id` of an object, or its oldest one for objects which don't name their
+    // key. None when it is missing.
+    async fn data_key(
+        &self,
+        storage_key: &str,
+        key_id: Option<Uuid>,
+    ) -> anyhow::Result<Option<KeyBytes>> {
+        let row = sqlx::query!(
+            r#"
+            SELECT d.wrapped_key, f.wrapped_key as firm_wrapped_key
+            FROM data_keys d
+            JOIN firm_keys f ON f.firm_id = d.firm_id
+            WHERE d.storage_key = $1 AND ($2::uuid IS NULL OR d.id = $2)
+            ORDER BY d.created_at
+            LIMIT 1
+            "#,
+            storage_key,
+            key_id
+        )
+        .fetch_optional(&self.db_pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let firm_key = unwrap_key(&self.master_key.0, &row.firm_wrapped_key)?;
+        Ok(Some(unwrap_key(&firm_key, &row.wrapped_key)?))
+    }
+
+    // Firm of the file an object belongs to, for objects stored before keys were prefixed with
+    // their firm and those derived from them, like their preview
+    async fn owner_firm(&self, storage_key: &str) -> anyhow::Result<Option<Uuid>> {
+        let file_key = storage_key.split('.').next().unwrap_or(storage_key);
+        let firm_id = sqlx::query_scalar!(
+            r#"
+            SELECT cl.firm_id
+            FROM files f
+            JOIN requests r ON f.request_id = r.id
+            JOIN collections c ON r.collection_id = c.id
+            JOIN clients cl ON c.client_id = cl.id
+            WHERE f.storage_key = $1
+            "#,
+            file_key
+        )
+        .fetch_optional(&self.db_pool)
+        .await?;
+        Ok(firm_id)
+    }
+
+    // Objects stored before encryption was enabled have none
+    async fn has_data_key(&self, storage_key: &str) -> anyhow::Result<bool> {
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM data_keys WHERE storage_key = $1) as "exists!""#,
+            storage_key
+        )
+        .fetch_one(&self.db_pool)
+        .await?;
+        Ok(exists)
+    }
+
+    async fn delete_data_key(&self, key_id: Uuid) -> anyhow::Result<()> {
+        sqlx::query!("DELETE FROM data_keys WHERE id = $1", key_id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    // Drops the data keys of an object other than `kept_key_id`, all of them when None
+    async fn delete_data_keys(
+        &self,
+        storage_key: &str,
+        kept_key_id: Option<Uuid>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "DELETE FROM data_keys WHERE storage_key = $1 AND id IS DISTINCT FROM $2",
+            storage_key,
+            kept_key_id
+        )
+        .execute(&self.db_pool)
+        .await?;
+        Ok(())
+    }
+
+    // Rotates the keys of every firm, see `rotate_firm`
+    pub async fn rotate(
+        &self,
+        previous_master_key: Option<&MasterKey>,
+    ) -> anyhow::Result<Rotation> {
+        let firm_ids = sqlx::query_scalar!("SELECT firm_id FROM firm_keys ORDER BY firm_id")
+            .fetch_all(&self.db_pool)
+            .await?;
+        let mut rotation = Rotation::default();
+        for firm_id in firm_ids {
+            rotation.data_keys += self.rotate_firm(firm_id, previous_master_key).await?;
+            rotation.firms += 1;
+        }
+        Ok(rotation)
+    }
+
+    // Gives a firm a new key, re-wrapping its data keys with it, and wraps it with the master
+    // key. The objects themselves are not rewritten. A firm key still wrapped with
+    // `previous_master_key` is accepted, which is how the master key is replaced. Returns the
+    // number of data keys re-wrapped.
+    pub async fn rotate_firm(
+        &self,
+        firm_id: Uuid,
+        previous_master_key: Option<&MasterKey>,
+    ) -> anyhow::Result<usize> {
+        let mut tx = self.db_pool.begin().await?;
+        let wrapped = sqlx::query_scalar!(
+            "SELECT wrapped_key FROM firm_keys WHERE firm_id = $1 FOR UPDATE",
+            firm_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let firm_key = unwrap_key(&self.master_key.0, &wrapped)
+            .or_else(|e| match previous_master_key {
+                Some(previous) => unwrap_key(&previous.0, &wrapped),
+                None => Err(e),
+            })
+            .with_context(|| format!("the key of firm {} opens with no master key", firm_id))?;
+
+        let new_firm_key = generate_key();
+        let data_keys = sqlx::query!(
+            "SELECT id, storage_key, wrapped_key FROM data_keys WHERE firm_id = $1",
+            firm_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for batch in data_keys.chunks(ROTATION_BATCH) {
+            let mut key_ids = Vec::with_capacity(batch.len());
+            let mut wrapped_keys = Vec::with_capacity(batch.len());
+            for data_key in batch {
+                let key = unwrap_key(&firm_key, &data_key.wrapped_key).with_context(|| {
+                    format!("failed to unwrap the key of {}", data_key.storage_key)
+                })?;
+                key_ids.push(data_key.id);
+                wrapped_keys.push(wrap_key(&new_firm_key, &key));
+            }
+            sqlx::query!(
+                r#"
+                UPDATE data_keys d SET wrapped_key = u.wrapped_key
+                FROM UNNEST($1::uuid[], $2::bytea[]) AS u(id, wrapped_key)
+                WHERE d.id = u.id
+                "#,
+                &key_ids,
+                &wrapped_keys
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query!(
+            "UPDATE firm_keys SET wrapped_key = $1, rotated_at = now() WHERE firm_id = $2",
+            wrap_key(&self.master_key.0, &new_firm_key),
+            firm_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(data_keys.len())
+    }
+}
+
+// Encrypts objects before they reach the storage behind it, whatever it is, and decrypts them
+// when they are read. The firm whose key wraps an object's data key is told by its storage key,
+// or else by the file it belongs to.
+pub struct EncryptedStorage {
+    inner: Arc<dyn Storage>,
+    keyring: Keyring,
+}
+
+impl EncryptedStorage {
+    pub fn new(inner: Arc<dyn Storage>, keyring: Keyring) -> Self {
+        Self { inner, keyring }
+    }
+}
+
+#[async_trait]
+impl Storage for EncryptedStorage {
+    async fn put(
+        &self,
+        key: &str,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> anyhow::Result<u64> {
+        let firm_id = match key_firm(key) {
+            Some(firm_id) => firm_id,
+            None => self
+                .keyring
+                .owner_firm(key)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("no firm owns storage key {:?}", key))?,
+        };
+
+        // The key is saved first, so that no encrypted object is ever left without it, and the
+        // keys of the content it replaces are kept until it is written
+        let data_key = generate_key();
+        let key_id = self.keyring.save_data_key(key, firm_id, &data_key).await?;
+        match self
+            .inner
+            .put(key, &mut encrypt(&data_key, key_id, reader))
+            .await
+        {
+            Ok(written) => {
+                if let Err(e) = self.keyring.delete_data_keys(key, Some(key_id)).await {
+                    eprintln!("Failed to delete the previous data keys of {}: {}", key, e);
+                }
+                Ok(plaintext_size(written))
+            }
+            Err(e) => {
+                let _ = self.keyring.delete_data_key(key_id).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<StorageReader> {
+        let mut reader = self.inner.get(key).await?;
+        let mut head = vec![0; MAGIC.len() + KEY_ID_SIZE];
+        let read = read_full(&mut *reader, &mut head).await?;
+        head.truncate(read);
+        let key_id = match head.get(..MAGIC.len()) {
+            Some(magic) if magic == MAGIC => Some(Some(Uuid::from_slice(&head[MAGIC.len()..])?)),
+            Some(magic) if magic == UNNAMED_MAGIC => Some(None),
+            _ => None,
+        };
+        let reader: StorageReader = Box::new(Cursor::new(head).chain(reader));
+
+        // An encrypted object whose key is missing is never served as it is
+        if let Some(key_id) = key_id {
+            let data_key = self.keyring.data_key(key, key_id).await?.ok_or_else(|| {
+                anyhow::anyhow!("the data key of storage key {:?} is missing", key)
+            })?;
+            return decrypt(&data_key, reader).await;
+        }
+
+        // Objects stored before encryption was enabled are read as they are, nor is any other
+        // object whose content isn't encrypted
+        if self.keyring.has_data_key(key).await? {
+            anyhow::bail!("the object of storage key {:?} is not encrypted", key);
+        }
+        Ok(reader)
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.inner.delete(key).await?;
+        self.keyring.delete_data_keys(key, None).await
+    }
+}
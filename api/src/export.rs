@@ -0,0 +1,441 @@
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipDateTime, ZipEntryBuilder};
+use chrono::Utc;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashSet};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::archive::{sanitize_file_name, unique_name, write_stored_entry};
+use crate::classifier::normalize;
+use crate::model::classification::CATEGORY_SALES_INVOICE;
+use crate::model::export::{
+    ExportDocument, ExportEntry, EXPORT_CSV, EXPORT_FEC, EXPORT_SOURCE_EINVOICE,
+    EXPORT_SOURCE_EXTRACTED,
+};
+use crate::storage::Storage;
+
+// Folder of the bundle holding the documents, named after their invoice data when known
+pub const DOCUMENTS_FOLDER: &str = "documents";
+
+// Writes the import file of an accounting package from the invoices of a collection
+pub trait Exporter: Send + Sync {
+    // Name of the import file at the root of the bundle
+    fn file_name(&self) -> &'static str;
+
+    fn write(&self, entries: &[ExportEntry]) -> anyhow::Result<Vec<u8>>;
+}
+
+// Exporter of a format, None when the format is unknown
+pub fn exporter(format: &str) -> Option<Box<dyn Exporter>> {
+    match format {
+        EXPORT_CSV => Some(Box::new(CsvExporter)),
+        EXPORT_FEC => Some(Box::new(FecExporter)),
+        _ => None,
+    }
+}
+
+// Paths of the documents in the bundle, and the entries of those which are invoices. Invoices
+// are named after their data unless the firm has its own naming template, which their file
+// names already follow.
+pub fn export_entries(
+    documents: &[ExportDocument],
+    templated_names: bool,
+) -> (Vec<String>, Vec<ExportEntry>) {
+    let mut used_names = HashSet::new();
+    let mut paths = Vec::new();
+    let mut entries = Vec::new();
+
+    for document in documents {
+        let entry = export_entry(document);
+        let name = match &entry {
+            Some(entry) if !templated_names => document_name(entry, &document.file_name),
+            _ => sanitize_file_name(&document.file_name),
+        };
+        let path = format!(
+            "{}/{}",
+            DOCUMENTS_FOLDER,
+            unique_name(&mut used_names, name)
+        );
+        if let Some(mut entry) = entry {
+            entry.document = path.clone();
+            entries.push(entry);
+        }
+        paths.push(path);
+    }
+
+    (paths, entries)
+}
+
+// Invoice data of a document from its e-invoice, or else from the fields read in its text
+fn export_entry(document: &ExportDocument) -> Option<ExportEntry> {
+    let sale = document.category.as_deref() == Some(CATEGORY_SALES_INVOICE);
+    let entry = ExportEntry {
+        document: String::new(),
+        file_id: document.file_id,
+        request: document.request_title.clone(),
+        sale,
+        source: String::new(),
+        number: None,
+        issue_date: None,
+        due_date: None,
+        counterparty: None,
+        siren: None,
+        vat_number: None,
+        currency: None,
+        net_total: None,
+        vat_total: None,
+        gross_total: None,
+    };
+
+    if let Some(invoice) = &document.invoice {
+        let party = if sale {
+            &invoice.buyer
+        } else {
+            &invoice.seller
+        };
+        let sign = if invoice.type_code.as_deref() == Some("381") {
+            Decimal::NEGATIVE_ONE
+        } else {
+            Decimal::ONE
+        };
+        return Some(ExportEntry {
+            source: EXPORT_SOURCE_EINVOICE.to_string(),
+            number: invoice.number.clone(),
+            issue_date: invoice.issue_date,
+            due_date: invoice.due_date,
+            counterparty: party.name.clone(),
+            siren: party.siren.clone(),
+            vat_number: party.vat_number.clone(),
+            currency: invoice.currency.clone(),
+            net_total: invoice.net_total.map(|amount| sign * amount),
+            vat_total: invoice.vat_total.map(|amount| sign * amount),
+            gross_total: invoice.gross_total.map(|amount| sign * amount),
+            ..entry
+        });
+    }
+
+    // The supplier read from the text is the client itself on its own sales invoices
+    let fields = document.invoice_fields.as_ref()?;
+    let entry = ExportEntry {
+        source: EXPORT_SOURCE_EXTRACTED.to_string(),
+        number: fields.number.as_ref().map(|field| field.value.clone()),
+        issue_date: fields.issue_date.as_ref().map(|field| field.value),
+        counterparty: fields
+            .supplier_name
+            .as_ref()
+            .filter(|_| !sale)
+            .map(|field| field.value.clone()),
+        siren: fields
+            .siret
+            .as_ref()
+            .filter(|_| !sale)
+            .and_then(|field| field.value.get(..9))
+            .map(str::to_string),
+        vat_number: fields
+            .vat_number
+            .as_ref()
+            .filter(|_| !sale)
+            .map(|field| field.value.clone()),
+        net_total: fields.net_total.as_ref().map(|field| field.value),
+        vat_total: fields.vat_total.as_ref().map(|field| field.value),
+        gross_total: fields.gross_total.as_ref().map(|field| field.value),
+        ..entry
+    };
+    (entry.number.is_some() || entry.issue_date.is_some() || entry.gross_total.is_some())
+        .then_some(entry)
+}
+
+// "2025-01-08 Metro F-101.pdf", keeping the extension of the uploaded file
+fn document_name(entry: &ExportEntry, file_name: &str) -> String {
+    let parts: Vec<String> = [
+        entry.issue_date.map(|date| date.to_string()),
+        entry.counterparty.clone(),
+        entry.number.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if parts.is_empty() {
+        return sanitize_file_name(file_name);
+    }
+    let extension = match file_name.rfind('.') {
+        Some(dot) if dot > 0 => &file_name[dot..],
+        _ => "",
+    };
+    sanitize_file_name(&format!("{}{}", parts.join(" "), extension))
+}
+
+// Writes the bundle to `writer`: the import file, then the documents read one by one from
+// storage
+pub async fn write_export<W>(
+    storage: &dyn Storage,
+    exporter: &dyn Exporter,
+    documents: Vec<ExportDocument>,
+    templated_names: bool,
+    writer: W,
+) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let (paths, entries) = export_entries(&documents, templated_names);
+    let mut zip = ZipFileWriter::with_tokio(writer);
+
+    let import = exporter.write(&entries)?;
+    let entry = ZipEntryBuilder::new(
+        exporter.file_name().to_string().into(),
+        Compression::Deflate,
+    )
+    .last_modification_date(ZipDateTime::from_chrono(&Utc::now()));
+    zip.write_entry_whole(entry, &import).await?;
+
+    let mut buffer = vec![0; 64 * 1024];
+    for (document, path) in documents.into_iter().zip(paths) {
+        let reader = match storage.get(&document.storage_key).await {
+            Ok(reader) => reader,
+            Err(e) => {
+                eprintln!(
+                    "Skipping file {} missing from storage: {}",
+                    document.file_id, e
+                );
+                continue;
+            }
+        };
+        write_stored_entry(&mut zip, reader, path, &document.uploaded_at, &mut buffer).await?;
+    }
+
+    zip.close().await?.into_inner().shutdown().await?;
+
+    Ok(())
+}
+
+// One line per invoice with its document, amounts with a decimal point
+pub struct CsvExporter;
+
+impl Exporter for CsvExporter {
+    fn file_name(&self) -> &'static str {
+        "invoices.csv"
+    }
+
+    fn write(&self, entries: &[ExportEntry]) -> anyhow::Result<Vec<u8>> {
+        let mut csv = csv::Writer::from_writer(Vec::new());
+        csv.write_record([
+            "document",
+            "file_id",
+            "request",
+            "type",
+            "source",
+            "number",
+            "issue_date",
+            "due_date",
+            "counterparty",
+            "siren",
+            "vat_number",
+            "currency",
+            "net_total",
+            "vat_total",
+            "gross_total",
+        ])?;
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        let date = |value: Option<chrono::NaiveDate>| {
+            value.map(|date| date.to_string()).unwrap_or_default()
+        };
+        let amount = |value: Option<Decimal>| {
+            value
+                .map(|amount| format!("{:.2}", amount))
+                .unwrap_or_default()
+        };
+        for entry in entries {
+            csv.write_record([
+                entry.document.clone(),
+                entry.file_id.to_string(),
+                entry.request.clone(),
+                if entry.sale { "sale" } else { "purchase" }.to_string(),
+                entry.source.clone(),
+                text(&entry.number),
+                date(entry.issue_date),
+                date(entry.due_date),
+                text(&entry.counterparty),
+                text(&entry.siren),
+                text(&entry.vat_number),
+                text(&entry.currency),
+                amount(entry.net_total),
+                amount(entry.vat_total),
+                amount(entry.gross_total),
+            ])?;
+        }
+        Ok(csv.into_inner()?)
+    }
+}
+
+// Accounts of the entries drafted for an invoice
+struct Accounts {
+    journal: (&'static str, &'static str),
+    counterparty: (&'static str, &'static str), // Debited on sales, credited on purchases
+    net: (&'static str, &'static str),
+    vat: (&'static str, &'static str),
+}
+
+const SALES: Accounts = Accounts {
+    journal: ("VE", "Ventes"),
+    counterparty: ("411000", "Clients"),
+    net: ("706000", "Prestations de services"),
+    vat: ("445710", "TVA collectee"),
+};
+
+const PURCHASES: Accounts = Accounts {
+    journal: ("AC", "Achats"),
+    counterparty: ("401000", "Fournisseurs"),
+    net: ("607000", "Achats de marchandises"),
+    vat: ("445660", "TVA deductible sur ABS"),
+};
+
+// Draft entries in the tab-delimited FEC layout, one per invoice, for the accountant to review
+// the accounts of before importing. They have no ValidDate since they are not validated yet.
+// Invoices without a date or a total, or in another currency than euros, are left out.
+pub struct FecExporter;
+
+impl Exporter for FecExporter {
+    fn file_name(&self) -> &'static str {
+        "fec-draft.txt"
+    }
+
+    fn write(&self, entries: &[ExportEntry]) -> anyhow::Result<Vec<u8>> {
+        let mut lines = vec![crate::fec::COLUMNS.join("\t")];
+
+        // Numbered in chronological order within each journal
+        let mut journals: BTreeMap<&str, Vec<&ExportEntry>> = BTreeMap::new();
+        for entry in entries {
+            if entry.issue_date.is_none()
+                || entry.gross_total.is_none()
+                || entry
+                    .currency
+                    .as_deref()
+                    .is_some_and(|currency| currency != "EUR")
+            {
+                continue;
+            }
+            let accounts = if entry.sale { &SALES } else { &PURCHASES };
+            journals.entry(accounts.journal.0).or_default().push(entry);
+        }
+
+        for entries in journals.values_mut() {
+            entries.sort_by_key(|entry| entry.issue_date);
+            for (index, entry) in entries.iter().enumerate() {
+                lines.extend(fec_lines(entry, index + 1));
+            }
+        }
+
+        let mut content = lines.join("\r\n");
+        content.push_str("\r\n");
+        Ok(content.into_bytes())
+    }
+}
+
+// Lines of the entry of an invoice: its total on the customer or supplier account, balanced by
+// its net amount and its VAT
+fn fec_lines(entry: &ExportEntry, number: usize) -> Vec<String> {
+    let accounts = if entry.sale { &SALES } else { &PURCHASES };
+    let cents = |amount: Decimal| {
+        amount
+            .checked_mul(Decimal::ONE_HUNDRED)
+            .and_then(|cents| i64::try_from(cents.round()).ok())
+            .unwrap_or(0)
+    };
+    let gross = entry.gross_total.map(cents).unwrap_or(0);
+    let vat = match (entry.net_total, entry.vat_total) {
+        (Some(net), _) => gross - cents(net),
+        (None, Some(vat)) => cents(vat),
+        (None, None) => 0,
+    };
+    let net = gross - vat;
+
+    let date = entry
+        .issue_date
+        .map(|date| date.format("%Y%m%d").to_string())
+        .unwrap_or_default();
+    let piece = entry
+        .number
+        .clone()
+        .unwrap_or_else(|| entry.document.clone());
+    let label = match &entry.counterparty {
+        Some(counterparty) => format!("Facture {} {}", counterparty, piece),
+        None => format!("Facture {}", piece),
+    };
+    let auxiliary = entry
+        .counterparty
+        .as_deref()
+        .map(auxiliary_account)
+        .filter(|account| !account.is_empty());
+
+    // The counterparty is debited on sales, so the net amount and the VAT are credited
+    let sides = |amount: i64, debit_side: bool| {
+        if (amount >= 0) == debit_side {
+            (amount.abs(), 0)
+        } else {
+            (0, amount.abs())
+        }
+    };
+    let mut lines = Vec::new();
+    let mut line = |account: (&str, &str), auxiliary: Option<&str>, amount: i64, debit: bool| {
+        if amount == 0 {
+            return;
+        }
+        let (debit, credit) = sides(amount, debit);
+        let counterparty = entry.counterparty.as_deref().unwrap_or_default();
+        lines.push(
+            [
+                accounts.journal.0,
+                accounts.journal.1,
+                &number.to_string(),
+                &date,
+                account.0,
+                account.1,
+                auxiliary.unwrap_or_default(),
+                if auxiliary.is_some() {
+                    counterparty
+                } else {
+                    ""
+                },
+                &fec_field(&piece),
+                &date,
+                &fec_field(&label),
+                &fec_amount(debit),
+                &fec_amount(credit),
+                "",
+                "",
+                "",
+                "",
+                "",
+            ]
+            .join("\t"),
+        );
+    };
+    line(
+        accounts.counterparty,
+        auxiliary.as_deref(),
+        gross,
+        entry.sale,
+    );
+    line(accounts.net, None, net, !entry.sale);
+    line(accounts.vat, None, vat, !entry.sale);
+    lines
+}
+
+// "METROCASHC" for "Metro Cash & Carry", from the first ten letters and digits of the name
+fn auxiliary_account(name: &str) -> String {
+    normalize(name)
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .take(10)
+        .collect::<String>()
+        .to_uppercase()
+}
+
+// Fields can't hold the delimiter nor line breaks
+fn fec_field(value: &str) -> String {
+    value.replace(['\t', '\r', '\n'], " ")
+}
+
+fn fec_amount(cents: i64) -> String {
+    format!("{},{:02}", cents / 100, cents % 100)
+}
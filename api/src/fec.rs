@@ -0,0 +1,493 @@
+use bytes::Bytes;
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::bank_statement::parse_amount;
+use crate::einvoice::is_valid_siren;
+use crate::model::fec::{
+    FecAccountClass, FecIssue, FecJournal, FecReport, FecSummary, FEC_RULE_AMOUNT,
+    FEC_RULE_BALANCE, FEC_RULE_COLUMNS, FEC_RULE_DATE, FEC_RULE_FILE_NAME, FEC_RULE_REQUIRED,
+    FEC_RULE_SEQUENCE,
+};
+use crate::text_extraction::decode_text;
+
+// Columns of article A47 A-1 of the Livre des procédures fiscales, in order
+pub const COLUMNS: [&str; 18] = [
+    "JournalCode",
+    "JournalLib",
+    "EcritureNum",
+    "EcritureDate",
+    "CompteNum",
+    "CompteLib",
+    "CompAuxNum",
+    "CompAuxLib",
+    "PieceRef",
+    "PieceDate",
+    "EcritureLib",
+    "Debit",
+    "Credit",
+    "EcritureLet",
+    "DateLet",
+    "ValidDate",
+    "Montantdevise",
+    "Idevise",
+];
+
+// Variant with an unsigned amount and its direction in place of Debit and Credit
+const MONTANT: &str = "Montant";
+const SENS: &str = "Sens";
+
+const REQUIRED: [&str; 10] = [
+    "JournalCode",
+    "JournalLib",
+    "EcritureNum",
+    "EcritureDate",
+    "CompteNum",
+    "CompteLib",
+    "PieceRef",
+    "PieceDate",
+    "EcritureLib",
+    "ValidDate",
+];
+
+// Fields which go in pairs, both filled or both empty, except a DateLet without EcritureLet
+const PAIRS: [(&str, &str); 3] = [
+    ("CompAuxNum", "CompAuxLib"),
+    ("EcritureLet", "DateLet"),
+    ("Montantdevise", "Idevise"),
+];
+
+// Longest list of issues kept in a report, all of them are counted
+const MAX_ISSUES: usize = 100;
+
+pub struct Fec {
+    pub report: FecReport,
+    pub summary: FecSummary,
+}
+
+// Parses and validates an FEC, None when the file is not one
+pub async fn parse_fec(
+    mime_type: &str,
+    file_name: &str,
+    content: Bytes,
+) -> anyhow::Result<Option<Fec>> {
+    if !matches!(mime_type, "text/plain" | "text/csv") {
+        return Ok(None);
+    }
+    let file_name = file_name.to_string();
+    // Yearly FECs run to hundreds of thousands of lines
+    Ok(tokio::task::spawn_blocking(move || read_fec(&file_name, &decode_text(&content))).await?)
+}
+
+#[derive(Default)]
+struct Issues {
+    count: usize,
+    list: Vec<FecIssue>,
+}
+
+impl Issues {
+    fn add(&mut self, line: Option<usize>, rule: &str, message: String) {
+        self.count += 1;
+        if self.list.len() < MAX_ISSUES {
+            self.list.push(FecIssue {
+                line,
+                rule: rule.to_string(),
+                message,
+            });
+        }
+    }
+}
+
+struct Entry {
+    journal: String,
+    number: String,
+    line: usize, // Of its first line
+    date: Option<NaiveDate>,
+    debit: i64, // In cents
+    credit: i64,
+}
+
+pub fn read_fec(file_name: &str, text: &str) -> Option<Fec> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut lines = text.lines().enumerate();
+    let (_, header) = lines.next()?;
+
+    let (delimiter, delimiter_name) = if header.contains('\t') {
+        ('\t', "tab")
+    } else if header.contains('|') {
+        ('|', "pipe")
+    } else {
+        return None;
+    };
+    let header: Vec<&str> = header.split(delimiter).map(str::trim).collect();
+    let position = |name: &str| {
+        header
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(name))
+    };
+    position("JournalCode")?;
+    position("EcritureNum")?;
+
+    let mut issues = Issues::default();
+    let signed = position(MONTANT).is_some() && position(SENS).is_some();
+    let expected: Vec<&str> = COLUMNS
+        .iter()
+        .map(|&column| match column {
+            "Debit" if signed => MONTANT,
+            "Credit" if signed => SENS,
+            column => column,
+        })
+        .collect();
+    if header.len() != expected.len() {
+        issues.add(
+            Some(1),
+            FEC_RULE_COLUMNS,
+            format!("The header has {} columns instead of 18", header.len()),
+        );
+    }
+    for (index, (found, expected)) in header.iter().zip(&expected).enumerate() {
+        if !found.eq_ignore_ascii_case(expected) {
+            issues.add(
+                Some(1),
+                FEC_RULE_COLUMNS,
+                format!("Column {} should be {}, not {}", index + 1, expected, found),
+            );
+        }
+    }
+    let columns: HashMap<&str, usize> = expected
+        .iter()
+        .filter_map(|&column| Some((column, position(column)?)))
+        .collect();
+
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut entry_index: HashMap<(String, String), usize> = HashMap::new();
+    let mut journals: BTreeMap<String, FecJournal> = BTreeMap::new();
+    let mut classes: BTreeMap<String, FecAccountClass> = BTreeMap::new();
+    let mut line_count = 0;
+
+    for (index, line) in lines {
+        let line_number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        line_count += 1;
+        let fields: Vec<&str> = line.split(delimiter).map(str::trim).collect();
+        if fields.len() != header.len() {
+            issues.add(
+                Some(line_number),
+                FEC_RULE_COLUMNS,
+                format!(
+                    "The line has {} fields instead of {}",
+                    fields.len(),
+                    header.len()
+                ),
+            );
+            continue;
+        }
+        let field = |name: &str| columns.get(name).map(|&index| fields[index]).unwrap_or("");
+
+        for name in REQUIRED {
+            if field(name).is_empty() {
+                issues.add(
+                    Some(line_number),
+                    FEC_RULE_REQUIRED,
+                    format!("{} is missing", name),
+                );
+            }
+        }
+        for (first, second) in PAIRS {
+            if !field(first).is_empty() && field(second).is_empty() {
+                issues.add(
+                    Some(line_number),
+                    FEC_RULE_REQUIRED,
+                    format!("{} is missing for {} {}", second, first, field(first)),
+                );
+            } else if first != "EcritureLet" && field(first).is_empty() && !field(second).is_empty()
+            {
+                issues.add(
+                    Some(line_number),
+                    FEC_RULE_REQUIRED,
+                    format!("{} is missing for {} {}", first, second, field(second)),
+                );
+            }
+        }
+
+        let mut date = |name: &str| {
+            let value = field(name);
+            if value.is_empty() {
+                return None;
+            }
+            let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok();
+            if date.is_none() {
+                issues.add(
+                    Some(line_number),
+                    FEC_RULE_DATE,
+                    format!("{} {} is not a date in the AAAAMMJJ format", name, value),
+                );
+            }
+            date
+        };
+        let entry_date = date("EcritureDate");
+        date("PieceDate");
+        date("ValidDate");
+        date("DateLet");
+
+        let (debit, credit) = line_amounts(&field, signed, line_number, &mut issues);
+
+        let journal_code = field("JournalCode").to_string();
+        let number = field("EcritureNum").to_string();
+        let key = (journal_code.clone(), number.clone());
+        let is_new_entry = !entry_index.contains_key(&key);
+        if is_new_entry {
+            entry_index.insert(key.clone(), entries.len());
+            entries.push(Entry {
+                journal: journal_code.clone(),
+                number: number.clone(),
+                line: line_number,
+                date: entry_date,
+                debit: 0,
+                credit: 0,
+            });
+        }
+        let entry = &mut entries[entry_index[&key]];
+        entry.debit += debit;
+        entry.credit += credit;
+        if !is_new_entry && entry_date.is_some() && entry.date.is_some() && entry_date != entry.date
+        {
+            issues.add(
+                Some(line_number),
+                FEC_RULE_DATE,
+                format!(
+                    "The lines of entry {} of journal {} have different dates",
+                    number, journal_code
+                ),
+            );
+        }
+
+        let journal = journals
+            .entry(journal_code.clone())
+            .or_insert_with(|| FecJournal {
+                code: journal_code,
+                label: field("JournalLib").to_string(),
+                entry_count: 0,
+                line_count: 0,
+                debit: 0.0,
+                credit: 0.0,
+            });
+        if is_new_entry {
+            journal.entry_count += 1;
+        }
+        journal.line_count += 1;
+        journal.debit += cents_to_amount(debit);
+        journal.credit += cents_to_amount(credit);
+
+        if let Some(class) = field("CompteNum")
+            .chars()
+            .next()
+            .filter(|c| c.is_ascii_digit())
+        {
+            let class = classes
+                .entry(class.to_string())
+                .or_insert_with(|| FecAccountClass {
+                    class: class.to_string(),
+                    debit: 0.0,
+                    credit: 0.0,
+                });
+            class.debit += cents_to_amount(debit);
+            class.credit += cents_to_amount(credit);
+        }
+    }
+
+    // Entries of a journal are numbered in chronological order
+    let mut previous_dates: HashMap<&str, NaiveDate> = HashMap::new();
+    for entry in &entries {
+        if entry.debit != entry.credit {
+            issues.add(
+                Some(entry.line),
+                FEC_RULE_BALANCE,
+                format!(
+                    "Entry {} of journal {} is not balanced: {:.2} debit, {:.2} credit",
+                    entry.number,
+                    entry.journal,
+                    cents_to_amount(entry.debit),
+                    cents_to_amount(entry.credit)
+                ),
+            );
+        }
+        if let Some(date) = entry.date {
+            if let Some(previous) = previous_dates.insert(&entry.journal, date) {
+                if date < previous {
+                    issues.add(
+                        Some(entry.line),
+                        FEC_RULE_SEQUENCE,
+                        format!(
+                            "Entry {} of journal {} is dated {}, before the previous entry of {}",
+                            entry.number, entry.journal, date, previous
+                        ),
+                    );
+                    previous_dates.insert(&entry.journal, previous);
+                }
+            }
+        }
+    }
+
+    let (siren, closing_date) = file_name_parts(file_name);
+    match (&siren, closing_date) {
+        (Some(siren), Some(closing_date)) => {
+            if !is_valid_siren(siren) {
+                issues.add(
+                    None,
+                    FEC_RULE_FILE_NAME,
+                    format!("The SIREN {} of the file name is not valid", siren),
+                );
+            }
+            for entry in entries.iter().filter(|entry| entry.date > Some(closing_date)) {
+                issues.add(
+                    Some(entry.line),
+                    FEC_RULE_DATE,
+                    format!(
+                        "Entry {} of journal {} is dated after the closing date {}",
+                        entry.number, entry.journal, closing_date
+                    ),
+                );
+            }
+        }
+        _ => issues.add(
+            None,
+            FEC_RULE_FILE_NAME,
+            "The file name should be the SIREN, FEC and the closing date, e.g. 123456789FEC20251231.txt"
+                .to_string(),
+        ),
+    }
+
+    let journals: Vec<FecJournal> = journals
+        .into_values()
+        .map(|journal| FecJournal {
+            debit: round_cents(journal.debit),
+            credit: round_cents(journal.credit),
+            ..journal
+        })
+        .collect();
+    let account_classes = classes
+        .into_values()
+        .map(|class| FecAccountClass {
+            debit: round_cents(class.debit),
+            credit: round_cents(class.credit),
+            ..class
+        })
+        .collect();
+    let summary = FecSummary {
+        siren,
+        closing_date,
+        start_date: entries.iter().filter_map(|entry| entry.date).min(),
+        end_date: entries.iter().filter_map(|entry| entry.date).max(),
+        total_debit: round_cents(journals.iter().map(|journal| journal.debit).sum()),
+        total_credit: round_cents(journals.iter().map(|journal| journal.credit).sum()),
+        journals,
+        account_classes,
+    };
+    let report = FecReport {
+        valid: issues.count == 0,
+        delimiter: delimiter_name.to_string(),
+        line_count,
+        entry_count: entries.len(),
+        error_count: issues.count,
+        errors: issues.list,
+    };
+
+    Some(Fec { report, summary })
+}
+
+// Debit and credit of a line in cents, from Debit and Credit or from Montant and Sens
+fn line_amounts<'a>(
+    field: &impl Fn(&str) -> &'a str,
+    signed: bool,
+    line_number: usize,
+    issues: &mut Issues,
+) -> (i64, i64) {
+    let mut amount = |name: &str| {
+        let value = field(name);
+        if value.is_empty() {
+            issues.add(
+                Some(line_number),
+                FEC_RULE_REQUIRED,
+                format!("{} is missing", name),
+            );
+            return 0;
+        }
+        match parse_amount(value) {
+            Some(amount) if amount >= 0.0 => (amount * 100.0).round() as i64,
+            Some(_) => {
+                issues.add(
+                    Some(line_number),
+                    FEC_RULE_AMOUNT,
+                    format!("{} {} is negative", name, value),
+                );
+                0
+            }
+            None => {
+                issues.add(
+                    Some(line_number),
+                    FEC_RULE_AMOUNT,
+                    format!("{} {} is not an amount", name, value),
+                );
+                0
+            }
+        }
+    };
+
+    if signed {
+        let amount = amount(MONTANT);
+        return match field(SENS) {
+            "D" | "d" | "+1" | "1" => (amount, 0),
+            "C" | "c" | "-1" => (0, amount),
+            sens => {
+                issues.add(
+                    Some(line_number),
+                    FEC_RULE_AMOUNT,
+                    format!("Sens {} should be D or C", sens),
+                );
+                (0, 0)
+            }
+        };
+    }
+
+    let (debit, credit) = (amount("Debit"), amount("Credit"));
+    if debit != 0 && credit != 0 {
+        issues.add(
+            Some(line_number),
+            FEC_RULE_AMOUNT,
+            "A line is either a debit or a credit".to_string(),
+        );
+    }
+    (debit, credit)
+}
+
+// "123456789FEC20251231.txt" gives the SIREN and the closing date
+fn file_name_parts(file_name: &str) -> (Option<String>, Option<NaiveDate>) {
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem);
+    let Some((siren, rest)) = stem.get(..9).zip(stem.get(9..)) else {
+        return (None, None);
+    };
+    if !siren.chars().all(|c| c.is_ascii_digit())
+        || !rest
+            .get(..3)
+            .is_some_and(|fec| fec.eq_ignore_ascii_case("FEC"))
+    {
+        return (None, None);
+    }
+    let closing_date = rest
+        .get(3..11)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok());
+    (Some(siren.to_string()), closing_date)
+}
+
+fn cents_to_amount(cents: i64) -> f64 {
+    cents as f64 / 100.0
+}
+
+fn round_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
@@ -0,0 +1,250 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// Number of leading bytes inspected to recognize a file
+pub const SNIFF_LENGTH: usize = 8 * 1024;
+
+// An extension clients commonly upload, with the types its content may be recognized as
+struct KnownExtension {
+    extension: &'static str,
+    mime_type: &'static str,
+    sniffed_as: &'static [&'static str],
+}
+
+const TEXT: &[&str] = &["text/plain"];
+const MARKUP: &[&str] = &["text/xml", "text/plain"];
+const OLE: &[&str] = &["application/x-ole-storage"];
+// Office Open XML and OpenDocument files are ZIP containers, only recognized when their
+// first entry is the expected one
+const ZIP: &[&str] = &["application/zip"];
+
+const KNOWN_EXTENSIONS: &[KnownExtension] = &[
+    KnownExtension {
+        extension: "pdf",
+        mime_type: "application/pdf",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "jpg",
+        mime_type: "image/jpeg",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "jpeg",
+        mime_type: "image/jpeg",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "png",
+        mime_type: "image/png",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "gif",
+        mime_type: "image/gif",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "webp",
+        mime_type: "image/webp",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "heic",
+        mime_type: "image/heif",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "heif",
+        mime_type: "image/heif",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "tif",
+        mime_type: "image/tiff",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "tiff",
+        mime_type: "image/tiff",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "bmp",
+        mime_type: "image/bmp",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "doc",
+        mime_type: "application/msword",
+        sniffed_as: OLE,
+    },
+    KnownExtension {
+        extension: "xls",
+        mime_type: "application/vnd.ms-excel",
+        sniffed_as: OLE,
+    },
+    KnownExtension {
+        extension: "docx",
+        mime_type: "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        sniffed_as: ZIP,
+    },
+    KnownExtension {
+        extension: "xlsx",
+        mime_type: "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        sniffed_as: ZIP,
+    },
+    KnownExtension {
+        extension: "odt",
+        mime_type: "application/vnd.oasis.opendocument.text",
+        sniffed_as: ZIP,
+    },
+    KnownExtension {
+        extension: "ods",
+        mime_type: "application/vnd.oasis.opendocument.spreadsheet",
+        sniffed_as: ZIP,
+    },
+    KnownExtension {
+        extension: "zip",
+        mime_type: "application/zip",
+        sniffed_as: &[],
+    },
+    KnownExtension {
+        extension: "txt",
+        mime_type: "text/plain",
+        sniffed_as: TEXT,
+    },
+    KnownExtension {
+        extension: "csv",
+        mime_type: "text/csv",
+        sniffed_as: TEXT,
+    },
+    KnownExtension {
+        extension: "xml",
+        mime_type: "application/xml",
+        sniffed_as: MARKUP,
+    },
+    // Bank statements, SGML (OFX 1) or XML (OFX 2)
+    KnownExtension {
+        extension: "ofx",
+        mime_type: "application/x-ofx",
+        sniffed_as: MARKUP,
+    },
+    KnownExtension {
+        extension: "qfx",
+        mime_type: "application/vnd.intu.qfx",
+        sniffed_as: MARKUP,
+    },
+];
+
+fn known_extension(file_name: &str) -> Option<&'static KnownExtension> {
+    let (_, extension) = file_name.rsplit_once('.')?;
+    KNOWN_EXTENSIONS
+        .iter()
+        .find(|known| known.extension.eq_ignore_ascii_case(extension))
+}
+
+// Type recognized from the magic bytes at the start of a file, or text/plain for UTF-8 text
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    if let Some(kind) = infer::get(head) {
+        return Some(kind.mime_type());
+    }
+
+    // The head may end in the middle of a multi-byte character
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    match text {
+        Some(text) if !text.is_empty() && !text.contains('\0') => Some("text/plain"),
+        _ => None,
+    }
+}
+
+// Type of an uploaded file from its content, checked against its extension
+pub fn detect(file_name: &str, head: &[u8]) -> Result<String, String> {
+    let sniffed = sniff(head);
+    match (known_extension(file_name), sniffed) {
+        (Some(known), Some(sniffed))
+            if known.mime_type == sniffed || known.sniffed_as.contains(&sniffed) =>
+        {
+            Ok(known.mime_type.to_string())
+        }
+        (Some(known), Some(sniffed)) => Err(format!(
+            "The content of \"{}\" does not match its extension: it looks like {}, not .{}.",
+            file_name, sniffed, known.extension
+        )),
+        // Text in a legacy encoding (e.g. a Windows-1252 CSV export) has no signature
+        (Some(known), None) if known.sniffed_as.contains(&"text/plain") => {
+            Ok(known.mime_type.to_string())
+        }
+        (Some(known), None) => Err(format!(
+            "The content of \"{}\" is not a valid .{} file.",
+            file_name, known.extension
+        )),
+        (None, Some(sniffed)) => Ok(sniffed.to_string()),
+        (None, None) => Ok(mime::APPLICATION_OCTET_STREAM.to_string()),
+    }
+}
+
+// Whether a type matches one of the allowed patterns ("application/pdf", "image/*")
+pub fn is_allowed(mime_type: &str, allowed_types: &[String]) -> bool {
+    allowed_types
+        .iter()
+        .any(|allowed| match allowed.strip_suffix("/*") {
+            Some(prefix) => mime_type
+                .split_once('/')
+                .is_some_and(|(kind, _)| kind == prefix),
+            None => allowed == mime_type,
+        })
+}
+
+// Checks and lowercases the allowed types of a request, an empty list allows everything
+pub fn normalize_allowed_types(allowed_types: Vec<String>) -> Result<Option<Vec<String>>, String> {
+    let mut normalized = Vec::new();
+    for allowed in allowed_types {
+        let allowed = allowed.trim().to_ascii_lowercase();
+        let valid = allowed.split_once('/').is_some_and(|(kind, subtype)| {
+            let is_token = |part: &str| {
+                !part.is_empty()
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+            };
+            is_token(kind) && (subtype == "*" || is_token(subtype))
+        });
+        if !valid {
+            return Err(format!(
+                "Invalid allowed type \"{}\", expected e.g. \"application/pdf\" or \"image/*\".",
+                allowed
+            ));
+        }
+        if !normalized.contains(&allowed) {
+            normalized.push(allowed);
+        }
+    }
+    Ok(if normalized.is_empty() {
+        None
+    } else {
+        Some(normalized)
+    })
+}
+
+// Message shown to the client when a request does not accept a file
+pub fn not_allowed_message(mime_type: &str, allowed_types: &[String]) -> String {
+    format!(
+        "This request only accepts {} files, this file is {}.",
+        allowed_types.join(", "),
+        mime_type
+    )
+}
+
+// Reads the first SNIFF_LENGTH bytes (or less for a shorter file)
+pub async fn read_head(reader: &mut (dyn AsyncRead + Send + Unpin)) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(SNIFF_LENGTH);
+    reader
+        .take(SNIFF_LENGTH as u64)
+        .read_to_end(&mut head)
+        .await?;
+    Ok(head)
+}
@@ -1,6 +1,13 @@
+pub mod bank_statement;
 pub mod client;
 pub mod firm;
 pub mod user;
 pub mod collection;
+pub mod comment;
+pub mod export;
+pub mod fec;
 pub mod file;
+pub mod reminder;
 pub mod request;
+pub mod search;
+pub mod upload;
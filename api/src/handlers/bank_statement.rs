@@ -0,0 +1,71 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use sqlx::types::Json as JsonColumn;
+use uuid::Uuid;
+
+use crate::app_error::AppError;
+use crate::app_state::AppState;
+use crate::bank_statement::find_gaps;
+use crate::model::bank_statement::{BankStatementSummary, BankTransaction, StatementGap};
+
+// GET /files/:id/transactions - Transactions of a bank statement, oldest first
+pub async fn get_transactions(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<BankTransaction>>, AppError> {
+    let file = sqlx::query!(
+        r#"SELECT bank_statement->'transactions' as "transactions: JsonColumn<Vec<BankTransaction>>" FROM files WHERE id = $1"#,
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "File not found"))?;
+
+    let transactions = file.transactions.ok_or_else(|| {
+        AppError::new(StatusCode::NOT_FOUND, "This file is not a bank statement.")
+    })?;
+    Ok(Json(transactions.0))
+}
+
+// GET /collections/:id/statement-gaps - Missing periods and unmatched balances between the
+// bank statements uploaded to the collection
+pub async fn get_statement_gaps(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<StatementGap>>, AppError> {
+    sqlx::query!("SELECT id FROM collections WHERE id = $1", id)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Collection not found"))?;
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT f.id, f.bank_statement - 'transactions' as "statement!: JsonColumn<BankStatementSummary>"
+        FROM files f
+        JOIN requests r ON f.request_id = r.id
+        WHERE r.collection_id = $1
+            AND f.bank_statement IS NOT NULL
+            AND f.converted_into IS NULL AND f.is_current
+            AND f.scan_status IN ('clean', 'unscanned')
+        "#,
+        id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch bank statements: {}", e);
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to fetch bank statements",
+        )
+    })?;
+
+    let statements = rows
+        .into_iter()
+        .map(|row| (row.id, row.statement.0))
+        .collect();
+    Ok(Json(find_gaps(statements)))
+}
@@ -1,21 +1,37 @@
+use crate::app_error::AppError;
+use crate::archive::{attachment_disposition, write_archive, ArchiveFile};
+use crate::handlers::request::{check_allowed_types, check_image_conversion, check_kind};
+use crate::mailer::Email;
 use crate::model::client::ClientResponse;
 use crate::model::collection::{
-    Collection, CollectionResponse, CreateCollectionPayload, UpdateCollectionPayload,
+    BulkCreateCollectionsPayload, BulkCreateResponse, BulkCreateResult, CloneCollectionPayload,
+    Collection, CollectionProgress, CollectionQuery, CollectionResponse, CollectionSummary,
+    CreateCollectionPayload, UpdateCollectionPayload, BULK_CREATED, BULK_FAILED, BULK_SKIPPED,
 };
 use crate::model::firm::Firm;
+use crate::model::question::Question;
+use crate::model::request::RequestTemplate;
 use crate::model::user::UserResponse;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
     Json,
 };
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use tokio_util::io::ReaderStream;
 use uuid::Uuid;
 
 use crate::app_state::AppState;
 
-// GET /collections
+// GET /collections?expand=progress
 pub async fn get_all(
     State(app_state): State<AppState>,
+    Query(query): Query<CollectionQuery>,
 ) -> Result<Json<Vec<CollectionResponse>>, StatusCode> {
     let records = sqlx::query!(
         r#"
@@ -39,6 +55,18 @@ pub async fn get_all(
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
+    let mut progress = if query.expands("progress") {
+        let ids: Vec<Uuid> = records.iter().map(|row| row.collection_id).collect();
+        fetch_progress(&app_state.db_pool, &ids)
+            .await
+            .map_err(|e| {
+                eprintln!("Failed to compute collections progress: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+    } else {
+        HashMap::new()
+    };
+
     let responses = records
         .into_iter()
         .map(|row| {
@@ -83,6 +111,7 @@ pub async fn get_all(
                 expires_at: row.expires_at,
                 created_at: row.collection_created_at,
                 updated_at: row.collection_updated_at,
+                progress: progress.remove(&row.collection_id),
             }
         })
         .collect();
@@ -90,10 +119,11 @@ pub async fn get_all(
     Ok(Json(responses))
 }
 
-// GET /collections/:id
+// GET /collections/:id?expand=progress
 pub async fn get_one(
     State(app_state): State<AppState>,
     Path(id): Path<Uuid>,
+    Query(query): Query<CollectionQuery>,
 ) -> Result<Json<CollectionResponse>, StatusCode> {
     let row = sqlx::query!(
         r#"
@@ -147,6 +177,18 @@ pub async fn get_one(
         updated_at: row.user_updated_at,
     };
 
+    let progress = if query.expands("progress") {
+        fetch_progress(&app_state.db_pool, &[id])
+            .await
+            .map_err(|e| {
+                eprintln!("Failed to compute collection progress: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .remove(&id)
+    } else {
+        None
+    };
+
     let response = CollectionResponse {
         id: row.collection_id,
         client,
@@ -157,11 +199,266 @@ pub async fn get_one(
         expires_at: row.expires_at,
         created_at: row.collection_created_at,
         updated_at: row.collection_updated_at,
+        progress,
     };
 
     Ok(Json(response))
 }
 
+// Computes the progress of the given collections with a single aggregate query
+pub async fn fetch_progress(
+    db_pool: &PgPool,
+    collection_ids: &[Uuid],
+) -> Result<HashMap<Uuid, CollectionProgress>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        WITH request_counts AS (
+            SELECT
+                collection_id, status, COUNT(*) as count,
+                COUNT(*) FILTER (WHERE status = 'pending' AND due_date < CURRENT_DATE) as overdue
+            FROM requests
+            WHERE collection_id = ANY($1)
+            GROUP BY collection_id, status
+        ),
+        file_stats AS (
+            SELECT
+                r.collection_id, COUNT(f.id) as file_count, SUM(f.file_size) as total_bytes,
+                MAX(f.created_at) as last_upload_at
+            FROM files f
+            JOIN requests r ON f.request_id = r.id
+            WHERE r.collection_id = ANY($1) AND f.converted_into IS NULL AND f.is_current
+            GROUP BY r.collection_id
+        ),
+        client_comments AS (
+            SELECT r.collection_id, MAX(cm.created_at) as last_comment_at
+            FROM comments cm
+            JOIN requests r ON cm.request_id = r.id
+            WHERE r.collection_id = ANY($1) AND cm.author_type = 'client'
+            GROUP BY r.collection_id
+        )
+        SELECT
+            c.id as "collection_id!",
+            COALESCE(ARRAY_AGG(rc.status) FILTER (WHERE rc.status IS NOT NULL), '{}') as "statuses!",
+            COALESCE(ARRAY_AGG(rc.count) FILTER (WHERE rc.status IS NOT NULL), '{}') as "status_counts!",
+            COALESCE(SUM(rc.overdue), 0)::BIGINT as "overdue!",
+            COALESCE(MAX(fs.file_count), 0) as "file_count!",
+            COALESCE(MAX(fs.total_bytes), 0)::BIGINT as "total_bytes!",
+            GREATEST(MAX(fs.last_upload_at), MAX(cc.last_comment_at)) as last_client_activity_at
+        FROM UNNEST($1::UUID[]) AS c(id)
+        LEFT JOIN request_counts rc ON rc.collection_id = c.id
+        LEFT JOIN file_stats fs ON fs.collection_id = c.id
+        LEFT JOIN client_comments cc ON cc.collection_id = c.id
+        GROUP BY c.id
+        "#,
+        collection_ids
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    let progress = rows
+        .into_iter()
+        .map(|row| {
+            let requests_by_status: HashMap<String, i64> =
+                row.statuses.into_iter().zip(row.status_counts).collect();
+            let total_requests: i64 = requests_by_status.values().sum();
+            let pending = requests_by_status.get("pending").copied().unwrap_or(0);
+            let completion_percentage = if total_requests == 0 {
+                0.0
+            } else {
+                ((total_requests - pending) as f64 * 1000.0 / total_requests as f64).round() / 10.0
+            };
+
+            let progress = CollectionProgress {
+                total_requests,
+                requests_by_status,
+                overdue_requests: row.overdue,
+                file_count: row.file_count,
+                total_bytes: row.total_bytes,
+                last_client_activity_at: row.last_client_activity_at,
+                completion_percentage,
+            };
+            (row.collection_id, progress)
+        })
+        .collect();
+
+    Ok(progress)
+}
+
+// GET /collections/:id/summary
+pub async fn summary(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<CollectionSummary>, StatusCode> {
+    // Pending and overdue are disjoint so that done + pending + overdue = total
+    let summary = sqlx::query_as!(
+        CollectionSummary,
+        r#"
+        SELECT
+            c.id as collection_id,
+            COUNT(r.id) as "total!",
+            COUNT(r.id) FILTER (WHERE r.status <> 'pending') as "done!",
+            COUNT(r.id) FILTER (WHERE r.status = 'pending' AND (r.due_date IS NULL OR r.due_date >= CURRENT_DATE)) as "pending!",
+            COUNT(r.id) FILTER (WHERE r.status = 'pending' AND r.due_date < CURRENT_DATE) as "overdue!"
+        FROM collections c
+        LEFT JOIN requests r ON r.collection_id = c.id
+        WHERE c.id = $1
+        GROUP BY c.id
+        "#,
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    Ok(Json(summary))
+}
+
+// GET /collections/:id/answers - CSV export of the questionnaire answers, one line per question
+pub async fn export_answers(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let collection = sqlx::query!("SELECT title FROM collections WHERE id = $1", id)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let requests = sqlx::query!(
+        r#"
+        SELECT id, title, questions, answers, answered_at
+        FROM requests
+        WHERE collection_id = $1 AND kind = 'questionnaire'
+        ORDER BY created_at
+        "#,
+        id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch questionnaire requests: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut write = |record: [&str; 7]| {
+        writer.write_record(record).map_err(|e| {
+            eprintln!("Failed to write answers export: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    };
+
+    write([
+        "collection",
+        "request_id",
+        "request",
+        "question_key",
+        "question",
+        "answer",
+        "answered_at",
+    ])?;
+    for request in requests {
+        let questions: Vec<Question> = request
+            .questions
+            .and_then(|questions| serde_json::from_value(questions).ok())
+            .unwrap_or_default();
+        let answered_at = request
+            .answered_at
+            .map(|answered_at| answered_at.to_rfc3339())
+            .unwrap_or_default();
+
+        for question in questions {
+            let answer = match request.answers.as_ref().and_then(|a| a.get(&question.key)) {
+                Some(Value::String(text)) => text.clone(),
+                Some(Value::Bool(true)) => "yes".to_string(),
+                Some(Value::Bool(false)) => "no".to_string(),
+                Some(Value::Null) | None => String::new(),
+                Some(other) => other.to_string(),
+            };
+            write([
+                &collection.title,
+                &request.id.to_string(),
+                &request.title,
+                &question.key,
+                &question.label,
+                &answer,
+                &answered_at,
+            ])?;
+        }
+    }
+
+    let csv = writer
+        .into_inner()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"answers.csv\"",
+            ),
+        ],
+        csv,
+    ))
+}
+
+// GET /collections/:id/archive - Streams every file of the collection as a ZIP, one folder per request
+pub async fn archive(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let collection = sqlx::query!("SELECT title FROM collections WHERE id = $1", id)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let files = sqlx::query_as!(
+        ArchiveFile,
+        r#"
+        SELECT
+            r.id as request_id, r.title as request_title,
+            f.id as file_id, f.file_name, f.storage_key, f.created_at as uploaded_at
+        FROM files f
+        JOIN requests r ON f.request_id = r.id
+        WHERE r.collection_id = $1 AND f.scan_status IN ('clean', 'unscanned')
+            AND f.converted_into IS NULL AND f.is_current AND f.rejected_at IS NULL
+        ORDER BY r.created_at, r.id, f.created_at
+        "#,
+        id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch files for archive: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // The archive is written into a pipe while the response body reads from the other end
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+    let storage = app_state.storage.clone();
+    tokio::spawn(async move {
+        if let Err(e) = write_archive(storage.as_ref(), files, writer).await {
+            eprintln!("Failed to build archive of collection {}: {}", id, e);
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                attachment_disposition(&format!("{}.zip", collection.title)),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(reader)),
+    ))
+}
+
+// Random token used by the client to reach the collection through the portal
+pub fn generate_access_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
 // POST /collections
 pub async fn create(
     State(app_state): State<AppState>,
@@ -170,12 +467,13 @@ pub async fn create(
     let collection = sqlx::query!(
         r#"
         INSERT INTO collections (client_id, user_id, title, status, access_token, expires_at)
-        VALUES ($1, $2, $3, 'pending', 'token', now() + interval '1 day')
+        VALUES ($1, $2, $3, 'pending', $4, now() + interval '1 day')
         RETURNING id
         "#,
         payload.client_id,
         payload.user_id,
         payload.title,
+        generate_access_token(),
     )
     .fetch_one(&app_state.db_pool)
     .await
@@ -184,7 +482,522 @@ pub async fn create(
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    get_one(State(app_state), Path(collection.id)).await
+    get_one(
+        State(app_state),
+        Path(collection.id),
+        Query(CollectionQuery::default()),
+    )
+    .await
+}
+
+// Requests of a template, checked once and inserted in every new collection
+struct RequestRows {
+    titles: Vec<String>,
+    descriptions: Vec<Option<String>>,
+    due_dates: Vec<Option<NaiveDate>>,
+    kinds: Vec<String>,
+    questions: Vec<Option<Value>>,
+    allowed_types: Vec<Option<Value>>, // JSON arrays, UNNEST can't take arrays of arrays
+    convert_images_to_pdf: Vec<bool>,
+    combine_images: Vec<bool>,
+}
+
+impl RequestRows {
+    fn new(templates: Vec<RequestTemplate>) -> Result<Self, AppError> {
+        let mut rows = RequestRows {
+            titles: Vec::new(),
+            descriptions: Vec::new(),
+            due_dates: Vec::new(),
+            kinds: Vec::new(),
+            questions: Vec::new(),
+            allowed_types: Vec::new(),
+            convert_images_to_pdf: Vec::new(),
+            combine_images: Vec::new(),
+        };
+        for template in templates {
+            let (kind, questions) = check_kind(template.kind.as_deref(), template.questions)?;
+            let allowed_types = check_allowed_types(&kind, template.allowed_types)?;
+            check_image_conversion(
+                &kind,
+                template.convert_images_to_pdf,
+                template.combine_images,
+            )?;
+            rows.titles.push(template.title);
+            rows.descriptions.push(template.description);
+            rows.due_dates.push(template.due_date);
+            rows.kinds.push(kind);
+            rows.questions.push(questions);
+            rows.allowed_types.push(allowed_types.map(Value::from));
+            rows.convert_images_to_pdf
+                .push(template.convert_images_to_pdf);
+            rows.combine_images.push(template.combine_images);
+        }
+        Ok(rows)
+    }
+}
+
+// A client that gets a new collection
+struct BulkClient {
+    id: Uuid,
+    email: String,
+    company_name: String,
+}
+
+// POST /collections/bulk - Creates the same collection for many clients
+pub async fn bulk_create(
+    State(app_state): State<AppState>,
+    Json(payload): Json<BulkCreateCollectionsPayload>,
+) -> Result<Json<BulkCreateResponse>, AppError> {
+    if payload.title.trim().is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "A title is required.",
+        ));
+    }
+    if payload.client_ids.is_none() && payload.client_filter.is_none() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Either client_ids or client_filter is required.",
+        ));
+    }
+    if let Some(filter) = &payload.client_filter {
+        let company_name = filter.company_name.as_deref().map(str::trim);
+        // An empty filter would select every client of every firm
+        if filter.firm_id.is_none() && company_name.is_none_or(str::is_empty) {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "client_filter needs a firm_id or a company_name.",
+            ));
+        }
+    }
+    if payload.batch_size == Some(0) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "batch_size must be at least 1.",
+        ));
+    }
+
+    let mut templates = match payload.template_collection_id {
+        Some(template_id) => fetch_request_templates(&app_state.db_pool, template_id)
+            .await?
+            .into_iter()
+            .map(|(_, template)| template)
+            .collect(),
+        None => Vec::new(),
+    };
+    templates.extend(payload.requests);
+    if templates.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Give a template collection or at least one request.",
+        ));
+    }
+    let requests = RequestRows::new(templates)?;
+
+    let (firm_id, company_name) = payload
+        .client_filter
+        .map(|filter| (filter.firm_id, filter.company_name))
+        .unwrap_or_default();
+    let clients = sqlx::query_as!(
+        BulkClient,
+        r#"
+        SELECT id, email, company_name
+        FROM clients
+        WHERE ($1::UUID[] IS NULL OR id = ANY($1))
+          AND ($2::UUID IS NULL OR firm_id = $2)
+          AND ($3::TEXT IS NULL OR strpos(lower(company_name), lower($3)) > 0)
+        ORDER BY company_name, id
+        "#,
+        payload.client_ids.as_deref(),
+        firm_id,
+        company_name
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch clients: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch clients")
+    })?;
+
+    let mut results = Vec::new();
+    for client_id in payload.client_ids.iter().flatten() {
+        if !clients.iter().any(|client| client.id == *client_id)
+            && !results
+                .iter()
+                .any(|result: &BulkCreateResult| result.client_id == *client_id)
+        {
+            results.push(BulkCreateResult {
+                client_id: *client_id,
+                status: BULK_FAILED.to_string(),
+                collection_id: None,
+                error: Some("Client not found".to_string()),
+            });
+        }
+    }
+    if payload.batch_size.is_none() && !results.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Some clients do not exist; nothing was created.",
+        ));
+    }
+
+    // Clients that already have this collection are skipped, so that a partial run can be resumed
+    let client_ids: Vec<Uuid> = clients.iter().map(|client| client.id).collect();
+    let existing: HashMap<Uuid, Uuid> = sqlx::query!(
+        "SELECT client_id, id FROM collections WHERE title = $1 AND client_id = ANY($2)",
+        payload.title,
+        &client_ids
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch existing collections: {}", e);
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to fetch collections",
+        )
+    })?
+    .into_iter()
+    .map(|row| (row.client_id, row.id))
+    .collect();
+
+    let mut to_create = Vec::new();
+    for client in clients {
+        match existing.get(&client.id) {
+            Some(collection_id) => results.push(BulkCreateResult {
+                client_id: client.id,
+                status: BULK_SKIPPED.to_string(),
+                collection_id: Some(*collection_id),
+                error: None,
+            }),
+            None => to_create.push(client),
+        }
+    }
+
+    let batch_size = payload.batch_size.unwrap_or(to_create.len()).max(1);
+    let mut invitations = Vec::new();
+    for batch in to_create.chunks(batch_size) {
+        match create_batch(
+            &app_state.db_pool,
+            payload.user_id,
+            &payload.title,
+            payload.expires_at,
+            &requests,
+            batch,
+        )
+        .await
+        {
+            Ok(created) => {
+                for (client, (collection_id, access_token)) in batch.iter().zip(created) {
+                    results.push(BulkCreateResult {
+                        client_id: client.id,
+                        status: BULK_CREATED.to_string(),
+                        collection_id: Some(collection_id),
+                        error: None,
+                    });
+                    invitations.push(Email {
+                        to: client.email.clone(),
+                        subject: format!("Documents needed for \"{}\"", payload.title),
+                        body: format!(
+                            "Hello {},\n\nPlease send us the documents for \"{}\" here: {}/{}\n",
+                            client.company_name,
+                            payload.title,
+                            app_state.portal_url.trim_end_matches('/'),
+                            access_token,
+                        ),
+                    });
+                }
+            }
+            Err(e) if payload.batch_size.is_none() => {
+                eprintln!("Failed to create collections: {}", e);
+                return Err(AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to create collections; nothing was created.",
+                ));
+            }
+            Err(e) => {
+                eprintln!("Failed to create a batch of collections: {}", e);
+                results.extend(batch.iter().map(|client| BulkCreateResult {
+                    client_id: client.id,
+                    status: BULK_FAILED.to_string(),
+                    collection_id: None,
+                    error: Some("Failed to create collection".to_string()),
+                }));
+            }
+        }
+    }
+
+    let count = |status: &str| results.iter().filter(|r| r.status == status).count();
+    let created = count(BULK_CREATED);
+    let skipped = count(BULK_SKIPPED);
+    let failed = count(BULK_FAILED);
+
+    // Invitations are sent in the background so that hundreds of emails don't hold the response
+    let invitations_queued = if payload.send_invitations {
+        let queued = invitations.len();
+        let mailer = app_state.mailer.clone();
+        tokio::spawn(async move {
+            for email in invitations {
+                let to = email.to.clone();
+                if let Err(e) = mailer.send(email).await {
+                    eprintln!("Failed to send invitation to {}: {}", to, e);
+                }
+            }
+        });
+        queued
+    } else {
+        0
+    };
+
+    Ok(Json(BulkCreateResponse {
+        created,
+        skipped,
+        failed,
+        invitations_queued,
+        results,
+    }))
+}
+
+// Creates a collection with its requests for every client of the batch in one transaction,
+// returning the ids and access tokens in the same order
+async fn create_batch(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    title: &str,
+    expires_at: Option<DateTime<Utc>>,
+    requests: &RequestRows,
+    clients: &[BulkClient],
+) -> Result<Vec<(Uuid, String)>, sqlx::Error> {
+    let mut tx = db_pool.begin().await?;
+    let mut created = Vec::new();
+    for client in clients {
+        let collection =
+            insert_collection(&mut tx, client.id, user_id, title, expires_at, requests).await?;
+        created.push((collection.id, collection.access_token));
+    }
+    tx.commit().await?;
+    Ok(created)
+}
+
+// A collection inserted from templates, with the ids of its requests in the templates' order
+struct InsertedCollection {
+    id: Uuid,
+    access_token: String,
+    request_ids: Vec<Uuid>,
+}
+
+async fn insert_collection(
+    tx: &mut Transaction<'_, Postgres>,
+    client_id: Uuid,
+    user_id: Uuid,
+    title: &str,
+    expires_at: Option<DateTime<Utc>>,
+    requests: &RequestRows,
+) -> Result<InsertedCollection, sqlx::Error> {
+    let collection = sqlx::query!(
+        r#"
+        INSERT INTO collections (client_id, user_id, title, status, access_token, expires_at)
+        VALUES ($1, $2, $3, 'pending', $4, COALESCE($5, now() + interval '1 day'))
+        RETURNING id, access_token
+        "#,
+        client_id,
+        user_id,
+        title,
+        generate_access_token(),
+        expires_at
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let request_ids: Vec<Uuid> = requests.titles.iter().map(|_| Uuid::new_v4()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO requests (
+            id, collection_id, title, description, status, due_date, kind, questions, allowed_types,
+            convert_images_to_pdf, combine_images
+        )
+        SELECT t.id, $1, t.title, t.description, 'pending', t.due_date, t.kind, t.questions,
+            CASE WHEN t.allowed_types IS NOT NULL
+                THEN ARRAY(SELECT jsonb_array_elements_text(t.allowed_types)) END,
+            t.convert_images_to_pdf, t.combine_images
+        FROM UNNEST(
+            $2::UUID[], $3::TEXT[], $4::TEXT[], $5::DATE[], $6::TEXT[], $7::JSONB[], $8::JSONB[],
+            $9::BOOLEAN[], $10::BOOLEAN[]
+        ) AS t(
+            id, title, description, due_date, kind, questions, allowed_types,
+            convert_images_to_pdf, combine_images
+        )
+        "#,
+        collection.id,
+        &request_ids,
+        &requests.titles,
+        &requests.descriptions as &[Option<String>],
+        &requests.due_dates as &[Option<NaiveDate>],
+        &requests.kinds,
+        &requests.questions as &[Option<Value>],
+        &requests.allowed_types as &[Option<Value>],
+        &requests.convert_images_to_pdf,
+        &requests.combine_images
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(InsertedCollection {
+        id: collection.id,
+        access_token: collection.access_token,
+        request_ids,
+    })
+}
+
+// Requests of an existing collection with their ids, reusable as templates
+// (due dates belong to the old period and are not copied)
+async fn fetch_request_templates(
+    db_pool: &PgPool,
+    collection_id: Uuid,
+) -> Result<Vec<(Uuid, RequestTemplate)>, AppError> {
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM collections WHERE id = $1) as "exists!""#,
+        collection_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .map_err(|_| {
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to fetch collection",
+        )
+    })?;
+    if !exists {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "Template collection not found",
+        ));
+    }
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, title, description, kind, questions, allowed_types, convert_images_to_pdf, combine_images
+        FROM requests
+        WHERE collection_id = $1
+        ORDER BY created_at
+        "#,
+        collection_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch template requests: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch template requests")
+    })?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let template = RequestTemplate {
+                title: row.title,
+                description: row.description,
+                due_date: None,
+                kind: Some(row.kind),
+                questions: row
+                    .questions
+                    .and_then(|questions| serde_json::from_value(questions).ok()),
+                allowed_types: row.allowed_types,
+                convert_images_to_pdf: row.convert_images_to_pdf,
+                combine_images: row.combine_images,
+            };
+            (row.id, template)
+        })
+        .collect())
+}
+
+// POST /collections/:id/clone - Starts a new period from an existing collection
+pub async fn clone(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CloneCollectionPayload>,
+) -> Result<Json<CollectionResponse>, AppError> {
+    if payload.title.trim().is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "A title is required.",
+        ));
+    }
+
+    let source = sqlx::query!(
+        "SELECT client_id, user_id FROM collections WHERE id = $1",
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Collection not found"))?;
+
+    let (source_request_ids, templates): (Vec<Uuid>, Vec<RequestTemplate>) =
+        fetch_request_templates(&app_state.db_pool, id)
+            .await?
+            .into_iter()
+            .unzip();
+    let requests = RequestRows::new(templates)?;
+
+    let mut tx = app_state.db_pool.begin().await.map_err(|_| {
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error cloning collection.",
+        )
+    })?;
+
+    let collection = insert_collection(
+        &mut tx,
+        source.client_id,
+        source.user_id,
+        &payload.title,
+        payload.expires_at,
+        &requests,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to clone collection: {}", e);
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error cloning collection.",
+        )
+    })?;
+
+    if payload.carry_over_files {
+        sqlx::query!(
+            r#"
+            INSERT INTO request_references (request_id, file_id)
+            SELECT m.new_id, f.id
+            FROM UNNEST($1::UUID[], $2::UUID[]) AS m(old_id, new_id)
+            JOIN files f ON f.request_id = m.old_id AND f.converted_into IS NULL AND f.is_current
+                AND f.scan_status IN ('clean', 'unscanned') AND f.rejected_at IS NULL
+            "#,
+            &source_request_ids,
+            &collection.request_ids
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to carry over files: {}", e);
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error cloning collection.",
+            )
+        })?;
+    }
+
+    tx.commit().await.map_err(|_| {
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error cloning collection.",
+        )
+    })?;
+
+    Ok(get_one(
+        State(app_state),
+        Path(collection.id),
+        Query(CollectionQuery::default()),
+    )
+    .await?)
 }
 
 // PATCH /collections/:id
@@ -230,7 +1043,12 @@ pub async fn update(
     .await
     .map_err(|_| StatusCode::NOT_FOUND)?;
 
-    get_one(State(app_state), Path(id)).await
+    get_one(
+        State(app_state),
+        Path(id),
+        Query(CollectionQuery::default()),
+    )
+    .await
 }
 
 // DELETE /collections/:id
@@ -0,0 +1,419 @@
+use crate::app_error::AppError;
+use crate::auth::PortalAccess;
+use crate::handlers::file::{file_response, query_files};
+use crate::handlers::request as request_handler;
+use crate::mailer::Email;
+use crate::model::comment::{
+    CommentAuthor, CommentResponse, CreateCommentPayload, PortalCommentResponse, AUTHOR_CLIENT,
+    AUTHOR_USER,
+};
+use crate::model::file::{FileResponse, SCAN_CLEAN, SCAN_UNSCANNED};
+use crate::model::request::RequestResponse;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+
+// The side of a thread a caller is on: a firm user (JWT) or the end client (collection access token)
+#[derive(Debug, Clone, Copy)]
+enum Participant {
+    User(Uuid),
+    Client(PortalAccess),
+}
+
+impl Participant {
+    fn side(&self) -> &'static str {
+        match self {
+            Participant::User(_) => AUTHOR_USER,
+            Participant::Client(_) => AUTHOR_CLIENT,
+        }
+    }
+}
+
+// GET /requests/:request_id/comments
+pub async fn get_all_for_request(
+    State(app_state): State<AppState>,
+    Path(request_id): Path<Uuid>,
+) -> Result<Json<Vec<CommentResponse>>, AppError> {
+    find_request_collection(&app_state, request_id).await?;
+    Ok(Json(fetch_thread(&app_state, request_id).await?))
+}
+
+// POST /requests/:request_id/comments
+pub async fn create(
+    State(app_state): State<AppState>,
+    Extension(user_id): Extension<Uuid>,
+    Path(request_id): Path<Uuid>,
+    Json(payload): Json<CreateCommentPayload>,
+) -> Result<Json<CommentResponse>, AppError> {
+    find_request_collection(&app_state, request_id).await?;
+    post_comment(&app_state, Participant::User(user_id), request_id, payload).await
+}
+
+// POST /requests/:request_id/comments/read - Marks the thread as read by the firm
+pub async fn mark_read(
+    State(app_state): State<AppState>,
+    Path(request_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    find_request_collection(&app_state, request_id).await?;
+    mark_thread_read(&app_state, request_id, AUTHOR_USER).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// GET /portal/requests/:request_id/comments
+pub async fn portal_get_all_for_request(
+    State(app_state): State<AppState>,
+    Extension(access): Extension<PortalAccess>,
+    Path(request_id): Path<Uuid>,
+) -> Result<Json<Vec<PortalCommentResponse>>, AppError> {
+    access.ensure_request(&app_state, request_id).await?;
+    let thread = fetch_thread(&app_state, request_id).await?;
+    Ok(Json(
+        thread
+            .into_iter()
+            .map(PortalCommentResponse::from)
+            .collect(),
+    ))
+}
+
+// POST /portal/requests/:request_id/comments
+pub async fn portal_create(
+    State(app_state): State<AppState>,
+    Extension(access): Extension<PortalAccess>,
+    Path(request_id): Path<Uuid>,
+    Json(payload): Json<CreateCommentPayload>,
+) -> Result<Json<PortalCommentResponse>, AppError> {
+    access.ensure_request(&app_state, request_id).await?;
+    let comment =
+        post_comment(&app_state, Participant::Client(access), request_id, payload).await?;
+    Ok(Json(comment.0.into()))
+}
+
+// POST /portal/requests/:request_id/comments/read - Marks the thread as read by the client
+pub async fn portal_mark_read(
+    State(app_state): State<AppState>,
+    Extension(access): Extension<PortalAccess>,
+    Path(request_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    access.ensure_request(&app_state, request_id).await?;
+    mark_thread_read(&app_state, request_id, AUTHOR_CLIENT).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn find_request_collection(app_state: &AppState, request_id: Uuid) -> Result<Uuid, AppError> {
+    sqlx::query_scalar!(
+        "SELECT collection_id FROM requests WHERE id = $1",
+        request_id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Request not found"))
+}
+
+async fn fetch_thread(
+    app_state: &AppState,
+    request_id: Uuid,
+) -> Result<Vec<CommentResponse>, AppError> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            c.id, c.request_id, c.author_type, c.user_id, c.client_id, c.body, c.created_at, c.updated_at,
+            COALESCE(u.first_name || ' ' || u.last_name, cl.company_name, 'Deleted author') as "author_name!",
+            COALESCE(c.created_at <= cr.last_read_at, FALSE) as "read_by_recipient!"
+        FROM comments c
+        LEFT JOIN users u ON c.user_id = u.id
+        LEFT JOIN clients cl ON c.client_id = cl.id
+        LEFT JOIN comment_reads cr ON cr.request_id = c.request_id AND cr.reader_type <> c.author_type
+        WHERE c.request_id = $1
+        ORDER BY c.created_at
+        "#,
+        request_id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch comments: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch comments")
+    })?;
+
+    let comment_ids: Vec<Uuid> = rows.iter().map(|row| row.id).collect();
+    let fetch_error = |e: sqlx::Error| {
+        eprintln!("Failed to fetch comment attachments: {}", e);
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to fetch comment attachments",
+        )
+    };
+    let attached = sqlx::query!(
+        "SELECT comment_id, file_id FROM comment_attachments WHERE comment_id = ANY($1)",
+        &comment_ids
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(fetch_error)?;
+    let file_ids: Vec<Uuid> = attached
+        .iter()
+        .map(|attachment| attachment.file_id)
+        .collect();
+    // Quarantined files and the photos a PDF was made from are not shown
+    let files = query_files!(
+        r#"
+        WHERE f.id = ANY($1) AND f.converted_into IS NULL AND f.scan_status IN ($2, $3)
+        ORDER BY f.created_at
+        "#,
+        &file_ids,
+        SCAN_CLEAN,
+        SCAN_UNSCANNED
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(fetch_error)?;
+
+    // Files in the order they were uploaded, under each comment they are attached to
+    let mut requests: HashMap<Uuid, RequestResponse> = HashMap::new();
+    let mut attachments: HashMap<Uuid, Vec<FileResponse>> = HashMap::new();
+    for file in files {
+        let request = match requests.entry(file.request_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let request =
+                    request_handler::get_one(State(app_state.clone()), Path(file.request_id))
+                        .await
+                        .map_err(|status| {
+                            AppError::new(status, "Failed to fetch comment attachments")
+                        })?
+                        .0;
+                entry.insert(request)
+            }
+        };
+        let file = file_response(file, request.clone());
+        for attachment in attached
+            .iter()
+            .filter(|attachment| attachment.file_id == file.id)
+        {
+            attachments
+                .entry(attachment.comment_id)
+                .or_default()
+                .push(file.clone());
+        }
+    }
+
+    let comments = rows
+        .into_iter()
+        .map(|row| CommentResponse {
+            id: row.id,
+            request_id: row.request_id,
+            author: CommentAuthor {
+                id: if row.author_type == AUTHOR_USER {
+                    row.user_id
+                } else {
+                    row.client_id
+                },
+                author_type: row.author_type,
+                name: row.author_name,
+            },
+            body: row.body,
+            attachments: attachments.remove(&row.id).unwrap_or_default(),
+            read_by_recipient: row.read_by_recipient,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+        .collect();
+
+    Ok(comments)
+}
+
+async fn mark_thread_read(
+    app_state: &AppState,
+    request_id: Uuid,
+    reader_type: &str,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO comment_reads (request_id, reader_type, last_read_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (request_id, reader_type) DO UPDATE SET last_read_at = now()
+        "#,
+        request_id,
+        reader_type
+    )
+    .execute(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to mark comments as read: {}", e);
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to mark comments as read",
+        )
+    })?;
+
+    Ok(())
+}
+
+async fn post_comment(
+    app_state: &AppState,
+    author: Participant,
+    request_id: Uuid,
+    payload: CreateCommentPayload,
+) -> Result<Json<CommentResponse>, AppError> {
+    let body = payload.body.trim();
+    if body.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "A comment cannot be empty.",
+        ));
+    }
+
+    let collection_id = find_request_collection(app_state, request_id).await?;
+
+    if !payload.attachment_ids.is_empty() {
+        let attachable = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!"
+            FROM files f
+            JOIN requests r ON f.request_id = r.id
+            WHERE f.id = ANY($1) AND r.collection_id = $2
+            "#,
+            &payload.attachment_ids,
+            collection_id
+        )
+        .fetch_one(&app_state.db_pool)
+        .await
+        .map_err(|_| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to check attachments",
+            )
+        })?;
+
+        if attachable as usize != payload.attachment_ids.len() {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Attachments must be files of the same collection.",
+            ));
+        }
+    }
+
+    let (user_id, client_id) = match author {
+        Participant::User(user_id) => (Some(user_id), None),
+        Participant::Client(access) => (None, Some(access.client_id)),
+    };
+
+    let mut tx =
+        app_state.db_pool.begin().await.map_err(|_| {
+            AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error creating comment.")
+        })?;
+
+    let comment_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO comments (request_id, author_type, user_id, client_id, body)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        "#,
+        request_id,
+        author.side(),
+        user_id,
+        client_id,
+        body
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| {
+        eprintln!("Error inserting comment: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error creating comment.")
+    })?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO comment_attachments (comment_id, file_id)
+        SELECT $1, UNNEST($2::UUID[])
+        ON CONFLICT DO NOTHING
+        "#,
+        comment_id,
+        &payload.attachment_ids
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        eprintln!("Error attaching files to comment: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error creating comment.")
+    })?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error creating comment."))?;
+
+    // Writing in a thread implies having read it
+    mark_thread_read(app_state, request_id, author.side()).await?;
+
+    let comment = fetch_thread(app_state, request_id)
+        .await?
+        .into_iter()
+        .find(|comment| comment.id == comment_id)
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Comment not found"))?;
+
+    // A failed notification must not fail the comment itself
+    if let Err(e) = notify_other_side(app_state, author, request_id, &comment).await {
+        eprintln!("Failed to send comment notification: {}", e);
+    }
+
+    Ok(Json(comment))
+}
+
+// Emails the client when a firm user writes, and the collection's owner when the client writes
+async fn notify_other_side(
+    app_state: &AppState,
+    author: Participant,
+    request_id: Uuid,
+    comment: &CommentResponse,
+) -> anyhow::Result<()> {
+    let context = sqlx::query!(
+        r#"
+        SELECT r.title as request_title, c.title as collection_title, c.access_token,
+               cl.email as client_email, u.email as user_email
+        FROM requests r
+        JOIN collections c ON r.collection_id = c.id
+        JOIN clients cl ON c.client_id = cl.id
+        JOIN users u ON c.user_id = u.id
+        WHERE r.id = $1
+        "#,
+        request_id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await?;
+
+    let (to, link) = match author {
+        Participant::User(_) => (
+            context.client_email,
+            format!(
+                "{}/{}",
+                app_state.portal_url.trim_end_matches('/'),
+                context.access_token
+            ),
+        ),
+        Participant::Client(_) => (context.user_email, String::new()),
+    };
+
+    let mut body = format!(
+        "{} wrote about \"{}\" ({}):\n\n{}\n",
+        comment.author.name, context.request_title, context.collection_title, comment.body
+    );
+    if !link.is_empty() {
+        body.push_str(&format!("\nReply here: {}\n", link));
+    }
+
+    app_state
+        .mailer
+        .send(Email {
+            to,
+            subject: format!("New message about \"{}\"", context.request_title),
+            body,
+        })
+        .await
+}
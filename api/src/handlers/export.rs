@@ -0,0 +1,113 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use sqlx::types::Json as JsonColumn;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::app_error::AppError;
+use crate::app_state::AppState;
+use crate::archive::attachment_disposition;
+use crate::export::{exporter, write_export};
+use crate::model::export::{ExportDocument, ExportQuery, EXPORT_CSV};
+use crate::model::invoice::{Invoice, InvoiceFields};
+
+// GET /collections/:id/export?format=fec - ZIP with an import file of the invoices of the
+// collection for an accounting package ("csv" or "fec") and its documents, renamed after them
+// unless the firm names files with its own template
+pub async fn export(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let format = query.format.unwrap_or_else(|| EXPORT_CSV.to_string());
+    let exporter = exporter(&format).ok_or_else(|| {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Unknown export format, use csv or fec.",
+        )
+    })?;
+
+    let collection = sqlx::query!(
+        r#"
+        SELECT c.title, fs.file_name_template IS NOT NULL as "templated_names!"
+        FROM collections c
+        JOIN clients cl ON c.client_id = cl.id
+        LEFT JOIN firm_settings fs ON fs.firm_id = cl.firm_id
+        WHERE c.id = $1
+        "#,
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Collection not found"))?;
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            f.id, r.title as request_title, f.file_name, f.storage_key, f.created_at,
+            f.category,
+            f.invoice as "invoice: JsonColumn<Invoice>",
+            f.invoice_fields as "invoice_fields: JsonColumn<InvoiceFields>"
+        FROM files f
+        JOIN requests r ON f.request_id = r.id
+        WHERE r.collection_id = $1 AND f.scan_status IN ('clean', 'unscanned')
+            AND f.converted_into IS NULL AND f.is_current AND f.rejected_at IS NULL
+        ORDER BY r.created_at, r.id, f.created_at
+        "#,
+        id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch files for export: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch files")
+    })?;
+
+    let documents = rows
+        .into_iter()
+        .map(|row| ExportDocument {
+            file_id: row.id,
+            request_title: row.request_title,
+            file_name: row.file_name,
+            storage_key: row.storage_key,
+            uploaded_at: row.created_at,
+            category: row.category,
+            invoice: row.invoice.map(|invoice| invoice.0),
+            invoice_fields: row.invoice_fields.map(|fields| fields.0),
+        })
+        .collect();
+
+    // The bundle is written into a pipe while the response body reads from the other end
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+    let storage = app_state.storage.clone();
+    let templated_names = collection.templated_names;
+    tokio::spawn(async move {
+        let exporter = exporter.as_ref();
+        if let Err(e) = write_export(
+            storage.as_ref(),
+            exporter,
+            documents,
+            templated_names,
+            writer,
+        )
+        .await
+        {
+            eprintln!("Failed to build export of collection {}: {}", id, e);
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                attachment_disposition(&format!("{} - {}.zip", collection.title, format)),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(reader)),
+    ))
+}
@@ -0,0 +1,30 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use sqlx::types::Json as JsonColumn;
+use uuid::Uuid;
+
+use crate::app_error::AppError;
+use crate::app_state::AppState;
+use crate::model::fec::FecSummary;
+
+// GET /files/:id/fec - Journals and totals of an FEC, its validation being in the file
+pub async fn get_fec_summary(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<FecSummary>, AppError> {
+    let file = sqlx::query!(
+        r#"SELECT fec_summary as "fec_summary: JsonColumn<FecSummary>" FROM files WHERE id = $1"#,
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "File not found"))?;
+
+    let summary = file
+        .fec_summary
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "This file is not an FEC."))?;
+    Ok(Json(summary.0))
+}
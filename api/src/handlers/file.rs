@@ -1,14 +1,69 @@
+use crate::app_error::AppError;
+use crate::archive::attachment_disposition;
+use crate::encryption::{decrypt, encrypt, generate_key, KeyBytes};
+use crate::file_type;
 use crate::handlers::request as request_handler;
-use crate::model::file::{File, FileResponse};
+use crate::model::file::{
+    File, FileResponse, FileVersion, RejectFilePayload, FIELDS_EXTRACTED, INVOICE_PARSED,
+    PREVIEW_READY, SCAN_CLEAN, SCAN_FAILED, SCAN_INFECTED, SCAN_PENDING, SCAN_UNSCANNED,
+};
+use crate::model::invoice::{
+    ExtractedField, InvoiceFields, UpdateInvoiceFieldsPayload, INVOICE_FACTURX,
+};
+use crate::model::request::RequestResponse;
+use crate::naming::{apply_template, NamingContext};
+use crate::pdf::{images_to_pdf, page_image, rendition_key, CONVERTIBLE_TYPES};
+use crate::pipeline::process_file;
+use crate::preview::preview_key;
+use crate::scanner::{ScanVerdict, MAX_SCAN_BYTES};
+use crate::storage::{new_key, sibling_key, HashingReader, StorageReader};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{multipart::Field, Multipart, Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
     Json,
 };
+use chrono::Utc;
+use futures::TryStreamExt;
+use sqlx::types::Json as JsonColumn;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::{ReaderStream, StreamReader};
 use uuid::Uuid;
 
 use crate::app_state::AppState;
 
+// Queries `File`s from `files f`, followed by the rest of the query, so that every listing of
+// files selects the same columns
+macro_rules! query_files {
+    ($rest:literal $(, $args:expr)* $(,)?) => {{
+        use crate::model::bank_statement::BankStatementSummary;
+        use crate::model::fec::FecReport;
+        use crate::model::file::File;
+        use crate::model::invoice::{Invoice, InvoiceFields};
+        use sqlx::types::Json as JsonColumn;
+
+        sqlx::query_as!(
+            File,
+            r#"
+            SELECT f.id, f.request_id, f.file_name, f.original_file_name, f.storage_key,
+                f.file_size, f.mime_type, f.sha256, f.duplicate_of, f.scan_status, f.scan_result,
+                f.scanned_at, f.preview_status, f.converted_into,
+                f.invoice as "invoice: JsonColumn<Invoice>",
+                f.bank_statement - 'transactions' as "bank_statement: JsonColumn<BankStatementSummary>",
+                f.category, f.category_confidence, f.suggested_request_id,
+                f.invoice_fields as "invoice_fields: JsonColumn<InvoiceFields>",
+                f.fec_report as "fec_report: JsonColumn<FecReport>",
+                f.version_of, f.version, f.is_current, f.rejection_reason, f.rejected_at,
+                f.created_at, f.updated_at
+            FROM files f
+            "# + $rest
+            $(, $args)*
+        )
+    }};
+}
+pub(crate) use query_files;
+
 // GET /requests/:request_id/files
 pub async fn get_all_for_request(
     State(app_state): State<AppState>,
@@ -18,37 +73,83 @@ pub async fn get_all_for_request(
         .await? // Ensure the request exists
         .0;
 
-    let files = sqlx::query_as!(File, "SELECT id, request_id, file_name, storage_key, file_size, mime_type, created_at, updated_at FROM files WHERE request_id = $1", request_id)
-        .fetch_all(&app_state.db_pool)
-        .await
-        .map_err(|e| {
-            eprintln!("Failed to fetch files for request: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    let files = query_files!(
+        "WHERE f.request_id = $1 AND f.converted_into IS NULL AND f.is_current",
+        request_id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch files for request: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
     let file_responses = files
         .into_iter()
-        .map(|file| FileResponse {
-            id: file.id,
-            request: request_response.clone(), // Clone the fetched RequestResponse for each file
-            file_name: file.file_name,
-            storage_key: file.storage_key,
-            file_size: file.file_size,
-            mime_type: file.mime_type,
-            created_at: file.created_at,
-            updated_at: file.updated_at,
-        })
+        .map(|file| file_response(file, request_response.clone()))
         .collect();
 
     Ok(Json(file_responses))
 }
 
+// GET /requests/:request_id/references - Files of a previous period given as examples
+pub async fn get_references_for_request(
+    State(app_state): State<AppState>,
+    Path(request_id): Path<Uuid>,
+) -> Result<Json<Vec<File>>, StatusCode> {
+    sqlx::query!("SELECT id FROM requests WHERE id = $1", request_id)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?; // Ensure the request exists
+
+    let files = query_files!(
+        r#"
+        JOIN request_references rr ON rr.file_id = f.id
+        WHERE rr.request_id = $1
+        ORDER BY f.created_at
+        "#,
+        request_id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch reference files for request: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(files))
+}
+
+// GET /files/:id/originals - Photos a PDF was converted from
+pub async fn get_originals(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<File>>, StatusCode> {
+    sqlx::query!("SELECT id FROM files WHERE id = $1", id)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?; // Ensure the file exists
+
+    let files = query_files!(
+        "WHERE f.converted_into = $1 ORDER BY f.created_at, f.file_name",
+        id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch original files: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(files))
+}
+
 // GET /files/:id
 pub async fn get_one(
     State(app_state): State<AppState>,
     Path(id): Path<Uuid>,
 ) -> Result<Json<FileResponse>, StatusCode> {
-    let file = sqlx::query_as!(File, "SELECT id, request_id, file_name, storage_key, file_size, mime_type, created_at, updated_at FROM files WHERE id = $1", id)
+    let file = query_files!("WHERE f.id = $1", id)
         .fetch_one(&app_state.db_pool)
         .await
         .map_err(|_| StatusCode::NOT_FOUND)?;
@@ -57,36 +158,1330 @@ pub async fn get_one(
         .await?
         .0;
 
-    let file_response = FileResponse {
+    Ok(Json(file_response(file, request_response)))
+}
+
+pub(crate) fn file_response(file: File, request: RequestResponse) -> FileResponse {
+    FileResponse {
         id: file.id,
-        request: request_response,
+        request,
         file_name: file.file_name,
+        original_file_name: file.original_file_name,
         storage_key: file.storage_key,
         file_size: file.file_size,
         mime_type: file.mime_type,
+        sha256: file.sha256,
+        duplicate_of: file.duplicate_of,
+        scan_status: file.scan_status,
+        scan_result: file.scan_result,
+        scanned_at: file.scanned_at,
+        preview_available: file.preview_status == PREVIEW_READY,
+        preview_status: file.preview_status,
+        converted_into: file.converted_into,
+        rendition_available: file
+            .invoice
+            .as_ref()
+            .is_some_and(|invoice| invoice.format != INVOICE_FACTURX),
+        invoice: file.invoice.map(|invoice| invoice.0),
+        bank_statement: file.bank_statement.map(|statement| statement.0),
+        category: file.category,
+        category_confidence: file.category_confidence,
+        suggested_request_id: file.suggested_request_id,
+        invoice_fields: file.invoice_fields.map(|fields| fields.0),
+        fec_report: file.fec_report.map(|report| report.0),
+        version_of: file.version_of,
+        version: file.version,
+        is_current: file.is_current,
+        rejection_reason: file.rejection_reason,
+        rejected_at: file.rejected_at,
         created_at: file.created_at,
         updated_at: file.updated_at,
-    };
+    }
+}
+
+// Largest upload accepted by POST /files, as clamd refuses to scan larger streams
+pub const MAX_UPLOAD_BYTES: usize = MAX_SCAN_BYTES;
 
-    Ok(Json(file_response))
+// Photos combined into one PDF by a single upload
+pub const MAX_COMBINED_PHOTOS: usize = 20;
+
+// A file written to storage that has no `files` row yet
+pub(crate) struct StoredUpload {
+    pub(crate) storage_key: String,
+    pub(crate) file_name: String,
+    pub(crate) original_file_name: String, // As uploaded, `file_name` being renamed by the template
+    pub(crate) file_size: i64,
+    pub(crate) sha256: String,
 }
 
-// POST /requests/:request_id/files - Placeholder for file upload
+// POST /files - Multipart form with a `request_id` field and a `file` field. Requests that
+// combine photos into one PDF accept several `file` fields. A `replaces` field with the id
+// of a file of the request uploads a new version of it, which becomes the current one.
 pub async fn upload(
-    Path(request_id): Path<Uuid>,
-    // Multipart form data would be extracted here
-) -> Result<Json<FileResponse>, StatusCode> {
-    // 1. Process multipart form data, save file to storage (e.g., S3, local disk)
-    // 2. Create a `File` record in the database with metadata
-    // 3. Return the `FileResponse` by calling `get_one`
-    eprintln!("Placeholder for file upload for request {}", request_id);
-    Err(StatusCode::NOT_IMPLEMENTED)
-}
-
-// DELETE /files/:id - Placeholder for file deletion
-pub async fn delete(Path(id): Path<Uuid>) -> Result<StatusCode, StatusCode> {
-    // 1. Delete file from storage
-    // 2. Delete `File` record from the database
-    eprintln!("Placeholder for file deletion for file {}", id);
-    Err(StatusCode::NOT_IMPLEMENTED)
+    State(app_state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<FileResponse>, AppError> {
+    let mut request_id = None;
+    let mut firm_id = None;
+    let mut replaces = None;
+    let mut stored: Vec<StoredUpload> = Vec::new();
+    // Files sent before the request_id, spooled until the firm whose keys store them is known
+    let mut early_files: Vec<EarlyFile> = Vec::new();
+
+    let parsed = async {
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, &e.body_text()))?
+        {
+            match field.name() {
+                Some("request_id") => {
+                    let text = field.text().await.unwrap_or_default();
+                    let id = text.trim().parse::<Uuid>().map_err(|_| {
+                        AppError::new(StatusCode::BAD_REQUEST, "Invalid request_id.")
+                    })?;
+                    request_id = Some(id);
+                    let firm = firm_of_request(&app_state, id).await?;
+                    firm_id = Some(firm);
+                    for early_file in early_files.drain(..) {
+                        let file_name = early_file.file_name.clone();
+                        let content = early_file.content().await?;
+                        stored.push(store_content(&app_state, firm, file_name, content).await?);
+                    }
+                }
+                Some("replaces") => {
+                    let text = field.text().await.unwrap_or_default();
+                    replaces = Some(text.trim().parse::<Uuid>().map_err(|_| {
+                        AppError::new(StatusCode::BAD_REQUEST, "Invalid replaces.")
+                    })?);
+                }
+                Some("file") => {
+                    if stored.len() + early_files.len() == MAX_COMBINED_PHOTOS {
+                        return Err(AppError::new(
+                            StatusCode::BAD_REQUEST,
+                            &format!("At most {} photos can be combined.", MAX_COMBINED_PHOTOS),
+                        ));
+                    }
+                    // Files are streamed to storage with the keys of the request's firm, or
+                    // spooled until the request is known
+                    let file_name = field_file_name(&field);
+                    let reader = StreamReader::new(field.map_err(std::io::Error::other));
+                    match firm_id {
+                        Some(firm_id) => {
+                            stored
+                                .push(store_content(&app_state, firm_id, file_name, reader).await?);
+                        }
+                        None => early_files.push(EarlyFile::spool(file_name, reader).await?),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let request_id = request_id
+            .ok_or_else(|| AppError::new(StatusCode::BAD_REQUEST, "A request_id is required."))?;
+        if stored.is_empty() {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "A file is required.",
+            ));
+        }
+        Ok(request_id)
+    }
+    .await;
+
+    match parsed {
+        Ok(request_id) => save_upload(&app_state, request_id, replaces, stored).await,
+        Err(e) => {
+            delete_stored(&app_state, stored).await;
+            Err(e)
+        }
+    }
+}
+
+// A file sent before the request_id, written to a temporary file encrypted with a key that
+// never leaves memory, so that it is neither held in memory nor stored in plain text
+struct EarlyFile {
+    file_name: String,
+    key: KeyBytes,
+    spool: tokio::fs::File, // Removed when dropped
+}
+
+impl EarlyFile {
+    async fn spool(
+        file_name: String,
+        mut content: impl AsyncRead + Send + Unpin,
+    ) -> Result<Self, AppError> {
+        let key = generate_key();
+        let spooled = async {
+            let mut spool = tokio::fs::File::from_std(tempfile::tempfile()?);
+            tokio::io::copy(&mut encrypt(&key, Uuid::new_v4(), &mut content), &mut spool).await?;
+            spool.rewind().await?;
+            std::io::Result::Ok(spool)
+        }
+        .await;
+        let spool = spooled.map_err(|e| {
+            eprintln!("Failed to spool upload {}: {}", file_name, e);
+            AppError::new(StatusCode::BAD_REQUEST, "The file could not be read.")
+        })?;
+        Ok(Self {
+            file_name,
+            key,
+            spool,
+        })
+    }
+
+    async fn content(self) -> Result<StorageReader, AppError> {
+        decrypt(&self.key, Box::new(self.spool)).await.map_err(|e| {
+            eprintln!("Failed to read spooled upload {}: {}", self.file_name, e);
+            AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+        })
+    }
+}
+
+// Claims the next sequence number of the files of a request
+async fn next_file_seq(app_state: &AppState, request_id: Uuid) -> Result<i64, AppError> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO request_file_sequences (request_id, last_seq) VALUES ($1, 1)
+        ON CONFLICT (request_id) DO UPDATE SET last_seq = request_file_sequences.last_seq + 1
+        RETURNING last_seq
+        "#,
+        request_id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to number the file of request {}: {}", request_id, e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+    })
+}
+
+// Records uploads already written to storage as the request's file, scans it and starts its
+// background processing. Refused uploads are removed from storage.
+pub(crate) async fn save_upload(
+    app_state: &AppState,
+    request_id: Uuid,
+    replaces: Option<Uuid>,
+    mut stored: Vec<StoredUpload>,
+) -> Result<Json<FileResponse>, AppError> {
+    let result = async {
+        let request = sqlx::query!(
+            r#"
+            SELECT
+                r.allowed_types, r.convert_images_to_pdf, r.combine_images,
+                COALESCE(fs.reject_duplicate_uploads, FALSE) as "reject_duplicates!",
+                fs.file_name_template as "file_name_template?",
+                cl.company_name, c.title as collection_title, r.title as request_title
+            FROM requests r
+            JOIN collections c ON r.collection_id = c.id
+            JOIN clients cl ON c.client_id = cl.id
+            LEFT JOIN firm_settings fs ON fs.firm_id = cl.firm_id
+            WHERE r.id = $1
+            "#,
+            request_id
+        )
+        .fetch_optional(&app_state.db_pool)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to fetch request of upload: {}", e);
+            AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+        })?
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Request not found"))?;
+
+        let first_version = match replaces {
+            Some(replaced_id) => Some(first_version_of(app_state, request_id, replaced_id).await?),
+            None => None,
+        };
+
+        // The type claimed by the browser is not trusted, the content decides
+        let mut mime_types = Vec::new();
+        for upload in &stored {
+            mime_types.push(detect_type(app_state, upload).await?);
+        }
+
+        let convert = request.convert_images_to_pdf
+            && mime_types
+                .iter()
+                .all(|mime_type| CONVERTIBLE_TYPES.contains(&mime_type.as_str()));
+        if stored.len() > 1 && !(convert && request.combine_images) {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                if request.combine_images {
+                    "Only photos can be uploaded together."
+                } else {
+                    "Upload one file at a time."
+                },
+            ));
+        }
+
+        let mime_type = if convert {
+            mime::APPLICATION_PDF.as_ref()
+        } else {
+            mime_types[0].as_str()
+        };
+        if let Some(allowed_types) = &request.allowed_types {
+            if !file_type::is_allowed(mime_type, allowed_types) {
+                return Err(AppError::new(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    &file_type::not_allowed_message(mime_type, allowed_types),
+                ));
+            }
+        }
+
+        // Photos are only decoded once found clean, so they are scanned before the conversion
+        if convert {
+            for upload in &stored {
+                match scan_stored(app_state, &upload.storage_key).await {
+                    Ok(ScanVerdict::Clean) => {}
+                    Ok(ScanVerdict::Infected(signature)) => return Err(infected_error(&signature)),
+                    Err(e) => {
+                        eprintln!("Failed to scan upload {}: {}", upload.storage_key, e);
+                        return Err(AppError::new(
+                            StatusCode::SERVICE_UNAVAILABLE,
+                            "The photos could not be scanned for malware, try again later.",
+                        ));
+                    }
+                }
+            }
+        }
+
+        // Same content already sent by the client, in this collection or another one
+        let mut duplicates = Vec::new();
+        for upload in &stored {
+            let duplicate = find_duplicate(app_state, request_id, &upload.sha256).await?;
+            if let (true, Some(duplicate)) = (request.reject_duplicates, &duplicate) {
+                return Err(AppError::new(
+                    StatusCode::CONFLICT,
+                    &format!(
+                        "This file was already uploaded as \"{}\".",
+                        duplicate.file_name
+                    ),
+                ));
+            }
+            duplicates.push(duplicate.map(|duplicate| duplicate.id));
+        }
+
+        // Uploads are renamed by the firm's template, keeping the name they were sent with
+        let seq = match &request.file_name_template {
+            Some(_) => next_file_seq(app_state, request_id).await?,
+            None => 0,
+        };
+        let rename = |original_file_name: &str| match &request.file_name_template {
+            Some(template) => apply_template(
+                template,
+                &NamingContext {
+                    client: &request.company_name,
+                    collection: &request.collection_title,
+                    request: &request.request_title,
+                    date: Utc::now().date_naive(),
+                    seq,
+                    original_file_name,
+                },
+            ),
+            None => original_file_name.to_string(),
+        };
+
+        if convert {
+            insert_converted(
+                app_state,
+                request_id,
+                &stored,
+                &mime_types,
+                &duplicates,
+                &rename,
+                first_version,
+            )
+            .await
+        } else {
+            stored[0].file_name = rename(&stored[0].original_file_name);
+            let inserted = async {
+                let mut tx = app_state.db_pool.begin().await?;
+                let file_id = insert_file(
+                    &mut *tx,
+                    request_id,
+                    &stored[0],
+                    mime_type,
+                    duplicates[0],
+                    None,
+                )
+                .await?;
+                if let Some(first_version) = first_version {
+                    link_version(&mut tx, file_id, first_version).await?;
+                }
+                tx.commit().await?;
+                Ok::<_, sqlx::Error>(file_id)
+            };
+            let file_id = inserted.await.map_err(|e| {
+                eprintln!("Failed to record uploaded file: {}", e);
+                AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+            })?;
+            Ok(file_id)
+        }
+    }
+    .await;
+
+    match result {
+        Ok(file_id) => {
+            // Files stay quarantined if the scanner is unavailable, they are retried in the background
+            match scan_file(app_state, file_id).await {
+                Ok(ScanVerdict::Clean) => {}
+                Ok(ScanVerdict::Infected(signature)) => return Err(infected_error(&signature)),
+                Err(e) => eprintln!("Failed to scan file {}: {}", file_id, e),
+            }
+
+            let background_state = app_state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = process_file(&background_state, file_id).await {
+                    eprintln!("Failed to process file {}: {}", file_id, e);
+                }
+            });
+            Ok(get_one(State(app_state.clone()), Path(file_id)).await?)
+        }
+        Err(e) => {
+            delete_stored(app_state, stored).await;
+            Err(e)
+        }
+    }
+}
+
+fn infected_error(signature: &str) -> AppError {
+    AppError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        &format!("The file was rejected: {} detected.", signature),
+    )
+}
+
+// Don't leave orphan objects behind
+async fn delete_stored(app_state: &AppState, stored: Vec<StoredUpload>) {
+    for upload in stored {
+        if let Err(e) = app_state.storage.delete(&upload.storage_key).await {
+            eprintln!(
+                "Failed to delete orphan upload {}: {}",
+                upload.storage_key, e
+            );
+        }
+    }
+}
+
+// Firm of a request, whose keys encrypt the files uploaded to it
+pub(crate) async fn firm_of_request(
+    app_state: &AppState,
+    request_id: Uuid,
+) -> Result<Uuid, AppError> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT cl.firm_id
+        FROM requests r
+        JOIN collections c ON r.collection_id = c.id
+        JOIN clients cl ON c.client_id = cl.id
+        WHERE r.id = $1
+        "#,
+        request_id
+    )
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch request of upload: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+    })?
+    .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Request not found"))
+}
+
+// Earliest file of the same client with the same content, as listed (the PDF of a converted photo)
+struct Duplicate {
+    id: Uuid,
+    file_name: String,
+}
+
+async fn find_duplicate(
+    app_state: &AppState,
+    request_id: Uuid,
+    sha256: &str,
+) -> Result<Option<Duplicate>, AppError> {
+    sqlx::query_as!(
+        Duplicate,
+        r#"
+        SELECT
+            COALESCE(pdf.id, f.id) as "id!",
+            COALESCE(pdf.file_name, f.file_name) as "file_name!"
+        FROM requests r
+        JOIN collections c ON r.collection_id = c.id
+        JOIN collections fc ON fc.client_id = c.client_id
+        JOIN requests fr ON fr.collection_id = fc.id
+        JOIN files f ON f.request_id = fr.id
+        LEFT JOIN files pdf ON pdf.id = f.converted_into
+        WHERE r.id = $1 AND f.sha256 = $2 AND f.scan_status <> 'infected'
+        ORDER BY f.created_at
+        LIMIT 1
+        "#,
+        request_id,
+        sha256
+    )
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to look for duplicate uploads: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+    })
+}
+
+async fn insert_file<'e, E: sqlx::PgExecutor<'e>>(
+    executor: E,
+    request_id: Uuid,
+    upload: &StoredUpload,
+    mime_type: &str,
+    duplicate_of: Option<Uuid>,
+    converted_into: Option<Uuid>,
+) -> Result<Uuid, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO files (request_id, file_name, original_file_name, storage_key, file_size, mime_type, sha256, duplicate_of, converted_into)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING id
+        "#,
+        request_id,
+        upload.file_name,
+        upload.original_file_name,
+        upload.storage_key,
+        upload.file_size,
+        mime_type,
+        upload.sha256,
+        duplicate_of,
+        converted_into
+    )
+    .fetch_one(executor)
+    .await
+}
+
+// First version of a file re-uploaded to the same request, which links all its versions
+pub(crate) async fn first_version_of(
+    app_state: &AppState,
+    request_id: Uuid,
+    replaced_id: Uuid,
+) -> Result<Uuid, AppError> {
+    let replaced = sqlx::query!(
+        r#"
+        SELECT request_id, converted_into, COALESCE(version_of, id) as "first_version!"
+        FROM files WHERE id = $1
+        "#,
+        replaced_id
+    )
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch replaced file: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+    })?
+    .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "The replaced file was not found."))?;
+
+    if replaced.request_id != request_id || replaced.converted_into.is_some() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Only a file of the same request can be replaced.",
+        ));
+    }
+    Ok(replaced.first_version)
+}
+
+// Makes a new file the latest and current version of those linked to `first_version`
+async fn link_version(
+    tx: &mut sqlx::PgConnection,
+    file_id: Uuid,
+    first_version: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE files
+        SET version_of = $2,
+            version = (SELECT MAX(version) + 1 FROM files WHERE id = $2 OR version_of = $2)
+        WHERE id = $1
+        "#,
+        file_id,
+        first_version
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE files SET is_current = (id = $1) WHERE id = $2 OR version_of = $2",
+        file_id,
+        first_version
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+// Turns uploaded photos into one PDF, recorded as the request's file with the photos kept as
+// its originals. Returns the id of the PDF.
+async fn insert_converted(
+    app_state: &AppState,
+    request_id: Uuid,
+    originals: &[StoredUpload],
+    mime_types: &[String],
+    duplicates: &[Option<Uuid>],
+    rename: &(dyn Fn(&str) -> String + Sync),
+    first_version: Option<Uuid>,
+) -> Result<Uuid, AppError> {
+    let pdf = async {
+        let mut pages = Vec::new();
+        for (original, mime_type) in originals.iter().zip(mime_types) {
+            let mut content = Vec::new();
+            let mut reader = app_state.storage.get(&original.storage_key).await?;
+            reader.read_to_end(&mut content).await?;
+            pages.push(page_image(mime_type, content).await?);
+        }
+        tokio::task::spawn_blocking(move || images_to_pdf(pages)).await?
+    }
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to convert photos to PDF: {}", e);
+        AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The photos could not be converted to PDF.",
+        )
+    })?;
+
+    let stem = match originals[0].file_name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => originals[0].file_name.as_str(),
+    };
+    let storage_key = sibling_key(&originals[0].storage_key);
+    let mut reader = HashingReader::new(pdf.as_slice());
+    let file_size = app_state
+        .storage
+        .put(&storage_key, &mut reader)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to store converted PDF: {}", e);
+            AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+        })?;
+    let file_name = format!("{}.pdf", stem);
+    let converted = StoredUpload {
+        storage_key,
+        file_name: rename(&file_name),
+        original_file_name: file_name,
+        file_size: file_size as i64,
+        sha256: reader.sha256(),
+    };
+
+    let inserted = async {
+        let mut tx = app_state.db_pool.begin().await?;
+        let duplicate_of = duplicates.iter().flatten().next().copied();
+        let file_id = insert_file(
+            &mut *tx,
+            request_id,
+            &converted,
+            mime::APPLICATION_PDF.as_ref(),
+            duplicate_of,
+            None,
+        )
+        .await?;
+        if let Some(first_version) = first_version {
+            link_version(&mut tx, file_id, first_version).await?;
+        }
+        for ((original, mime_type), duplicate_of) in
+            originals.iter().zip(mime_types).zip(duplicates)
+        {
+            insert_file(
+                &mut *tx,
+                request_id,
+                original,
+                mime_type,
+                *duplicate_of,
+                Some(file_id),
+            )
+            .await?;
+        }
+        // The photos were scanned before their conversion
+        sqlx::query!(
+            "UPDATE files SET scan_status = $1, scanned_at = now() WHERE converted_into = $2",
+            SCAN_CLEAN,
+            file_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(file_id)
+    }
+    .await;
+
+    inserted.map_err(|e| {
+        eprintln!("Failed to record converted PDF: {}", e);
+        let storage = app_state.storage.clone();
+        let storage_key = converted.storage_key.clone();
+        tokio::spawn(async move { storage.delete(&storage_key).await });
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+    })
+}
+
+// Removes an infected file from storage. A PDF made from photos goes along with all of them, so
+// `file_id` is that of the PDF when one of its photos is infected.
+async fn quarantine_infected(
+    app_state: &AppState,
+    file_id: Uuid,
+    signature: &str,
+) -> anyhow::Result<()> {
+    let storage_keys = sqlx::query_scalar!(
+        r#"
+        UPDATE files
+        SET scan_status = $1, scan_result = $2, scanned_at = now(), updated_at = now()
+        WHERE id = $3 OR converted_into = $3
+        RETURNING storage_key
+        "#,
+        SCAN_INFECTED,
+        signature,
+        file_id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await?;
+    restore_previous_version(app_state, file_id).await?;
+
+    for storage_key in storage_keys {
+        app_state.storage.delete(&storage_key).await?;
+    }
+    Ok(())
+}
+
+// Lists the previous version again when the current one turns out infected or unscannable: the
+// latest clean one that was not rejected, if any
+async fn restore_previous_version(app_state: &AppState, file_id: Uuid) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        WITH previous AS (
+            SELECT p.id FROM files f
+            JOIN files p
+                ON p.id = COALESCE(f.version_of, f.id) OR p.version_of = COALESCE(f.version_of, f.id)
+            WHERE f.id = $1 AND f.is_current AND p.id <> f.id AND p.converted_into IS NULL
+                AND p.scan_status IN ($2, $3) AND p.rejected_at IS NULL
+            ORDER BY p.version DESC LIMIT 1
+        )
+        UPDATE files SET is_current = (id <> $1), updated_at = now()
+        WHERE (id = $1 OR id = (SELECT id FROM previous)) AND EXISTS (SELECT 1 FROM previous)
+        "#,
+        file_id,
+        SCAN_CLEAN,
+        SCAN_UNSCANNED
+    )
+    .execute(&app_state.db_pool)
+    .await?;
+    Ok(())
+}
+
+// Name of an uploaded file, "file" when the browser gave none
+fn field_file_name(field: &Field<'_>) -> String {
+    field
+        .file_name()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "file".to_string())
+}
+
+// Streams an uploaded file to storage under a new key of the firm
+async fn store_content(
+    app_state: &AppState,
+    firm_id: Uuid,
+    file_name: String,
+    content: impl AsyncRead + Send + Unpin,
+) -> Result<StoredUpload, AppError> {
+    let storage_key = new_key(firm_id);
+
+    let mut reader = HashingReader::new(content);
+    let file_size = app_state
+        .storage
+        .put(&storage_key, &mut reader)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to store upload: {}", e);
+            AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+        })?;
+
+    Ok(StoredUpload {
+        storage_key,
+        original_file_name: file_name.clone(),
+        file_name,
+        file_size: file_size as i64,
+        sha256: reader.sha256(),
+    })
+}
+
+// Recognizes a stored upload from its first bytes, rejecting content that contradicts its extension
+async fn detect_type(app_state: &AppState, upload: &StoredUpload) -> Result<String, AppError> {
+    let head = async {
+        let mut reader = app_state.storage.get(&upload.storage_key).await?;
+        anyhow::Ok(file_type::read_head(&mut reader).await?)
+    }
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to read upload {}: {}", upload.storage_key, e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+    })?;
+
+    file_type::detect(&upload.file_name, &head)
+        .map_err(|e| AppError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, &e))
+}
+
+// Failed scans of a file before it is marked as failed
+pub const MAX_SCAN_ATTEMPTS: i32 = 5;
+
+// Scans a stored file and records the result. Infected files are removed from storage, and
+// files larger than the scanner accepts are failed at once, staying quarantined.
+pub async fn scan_file(app_state: &AppState, file_id: Uuid) -> anyhow::Result<ScanVerdict> {
+    let file = sqlx::query!(
+        "SELECT storage_key, converted_into, file_size FROM files WHERE id = $1",
+        file_id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await?;
+
+    let max_bytes = app_state.scanner.max_bytes();
+    if file.file_size as u64 > max_bytes {
+        sqlx::query!(
+            r#"
+            UPDATE files
+            SET scan_status = $1, scan_result = $2, last_scan_attempt_at = now(), updated_at = now()
+            WHERE id = $3
+            "#,
+            SCAN_FAILED,
+            format!("Larger than the {} bytes the scanner accepts", max_bytes),
+            file_id
+        )
+        .execute(&app_state.db_pool)
+        .await?;
+        restore_previous_version(app_state, file.converted_into.unwrap_or(file_id)).await?;
+        anyhow::bail!(
+            "the file is larger than the {} bytes the scanner accepts",
+            max_bytes
+        );
+    }
+
+    let verdict = match scan_stored(app_state, &file.storage_key).await {
+        Ok(verdict) => verdict,
+        Err(e) => {
+            // Given up after a few attempts, the file stays quarantined
+            let scan_status = sqlx::query_scalar!(
+                r#"
+                UPDATE files
+                SET scan_result = $1, scan_attempts = scan_attempts + 1,
+                    last_scan_attempt_at = now(),
+                    scan_status = CASE WHEN scan_attempts + 1 >= $2 THEN $3 ELSE scan_status END,
+                    updated_at = now()
+                WHERE id = $4
+                RETURNING scan_status
+                "#,
+                format!("Scan failed: {}", e),
+                MAX_SCAN_ATTEMPTS,
+                SCAN_FAILED,
+                file_id
+            )
+            .fetch_one(&app_state.db_pool)
+            .await?;
+            if scan_status == SCAN_FAILED {
+                restore_previous_version(app_state, file.converted_into.unwrap_or(file_id)).await?;
+            }
+            return Err(e);
+        }
+    };
+
+    match &verdict {
+        ScanVerdict::Clean => {
+            sqlx::query!(
+                r#"
+                UPDATE files
+                SET scan_status = $1, scan_result = NULL, scanned_at = now(), updated_at = now()
+                WHERE id = $2
+                "#,
+                SCAN_CLEAN,
+                file_id
+            )
+            .execute(&app_state.db_pool)
+            .await?;
+        }
+        ScanVerdict::Infected(signature) => {
+            let pdf_id = file.converted_into.unwrap_or(file_id);
+            quarantine_infected(app_state, pdf_id, signature).await?;
+        }
+    }
+
+    Ok(verdict)
+}
+
+// Scans a stored object, whether or not it is recorded as a file yet
+async fn scan_stored(app_state: &AppState, storage_key: &str) -> anyhow::Result<ScanVerdict> {
+    let mut reader = app_state.storage.get(storage_key).await?;
+    app_state.scanner.scan(&mut reader).await
+}
+
+// Retries the scans of quarantined files and returns how many were scanned. Files never
+// attempted come first, then the ones that failed longest ago, so failing files don't starve the
+// others.
+pub async fn scan_pending_files(app_state: &AppState) -> anyhow::Result<usize> {
+    let file_ids = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM files
+        WHERE scan_status = $1
+        ORDER BY last_scan_attempt_at NULLS FIRST, created_at
+        LIMIT 100
+        "#,
+        SCAN_PENDING
+    )
+    .fetch_all(&app_state.db_pool)
+    .await?;
+
+    let mut scanned = 0;
+    for file_id in file_ids {
+        match scan_file(app_state, file_id).await {
+            Ok(_) => scanned += 1,
+            Err(e) => eprintln!("Failed to scan file {}: {}", file_id, e),
+        }
+    }
+
+    Ok(scanned)
+}
+
+// GET /files/:id/preview - PNG thumbnail of a PDF's first page or of a photo
+pub async fn preview(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let file = sqlx::query!(
+        "SELECT storage_key, preview_status FROM files WHERE id = $1",
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "File not found"))?;
+
+    if file.preview_status != PREVIEW_READY {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "No preview is available for this file.",
+        ));
+    }
+
+    let reader = app_state
+        .storage
+        .get(&preview_key(&file.storage_key))
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to read preview of file {} from storage: {}", id, e);
+            AppError::new(StatusCode::NOT_FOUND, "Preview not found")
+        })?;
+
+    Ok((
+        [(header::CONTENT_TYPE, mime::IMAGE_PNG.as_ref())],
+        Body::from_stream(ReaderStream::new(reader)),
+    ))
+}
+
+// GET /files/:id/rendition - PDF rendering of an XML e-invoice
+pub async fn rendition(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let file = sqlx::query!(
+        "SELECT storage_key, invoice->>'format' as format FROM files WHERE id = $1",
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "File not found"))?;
+
+    if file.format.is_none_or(|format| format == INVOICE_FACTURX) {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "No rendition is available for this file.",
+        ));
+    }
+
+    let reader = app_state
+        .storage
+        .get(&rendition_key(&file.storage_key))
+        .await
+        .map_err(|e| {
+            eprintln!(
+                "Failed to read rendition of file {} from storage: {}",
+                id, e
+            );
+            AppError::new(StatusCode::NOT_FOUND, "Rendition not found")
+        })?;
+
+    Ok((
+        [(header::CONTENT_TYPE, mime::APPLICATION_PDF.as_ref())],
+        Body::from_stream(ReaderStream::new(reader)),
+    ))
+}
+
+// PATCH /files/:id/invoice-fields - Corrects the fields read from a PDF invoice. Null, or an
+// empty text, clears a field.
+pub async fn update_invoice_fields(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateInvoiceFieldsPayload>,
+) -> Result<Json<FileResponse>, AppError> {
+    let file = sqlx::query!(
+        r#"SELECT invoice_status, invoice_fields as "invoice_fields: JsonColumn<InvoiceFields>" FROM files WHERE id = $1"#,
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "File not found"))?;
+
+    if file.invoice_status == INVOICE_PARSED {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "The data of an e-invoice cannot be edited.",
+        ));
+    }
+
+    fn corrected<T>(value: T) -> Option<ExtractedField<T>> {
+        Some(ExtractedField {
+            value,
+            confidence: 1.0,
+            corrected: true,
+        })
+    }
+    fn corrected_text(value: Option<String>) -> Option<ExtractedField<String>> {
+        let value = value?.trim().to_string();
+        (!value.is_empty()).then(|| corrected(value)).flatten()
+    }
+
+    let mut fields = file
+        .invoice_fields
+        .map(|fields| fields.0)
+        .unwrap_or_default();
+    if let Some(number) = payload.number {
+        fields.number = corrected_text(number);
+    }
+    if let Some(issue_date) = payload.issue_date {
+        fields.issue_date = issue_date.and_then(corrected);
+    }
+    if let Some(supplier_name) = payload.supplier_name {
+        fields.supplier_name = corrected_text(supplier_name);
+    }
+    if let Some(siret) = payload.siret {
+        let siret: String = siret
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if !siret.is_empty() && (siret.len() != 14 || !siret.chars().all(|c| c.is_ascii_digit())) {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "A SIRET number has 14 digits.",
+            ));
+        }
+        fields.siret = corrected_text(Some(siret));
+    }
+    if let Some(vat_number) = payload.vat_number {
+        let vat_number: String = vat_number
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+        if !vat_number.is_empty()
+            && !vat_number
+                .get(..2)
+                .is_some_and(|prefix| prefix.chars().all(|c| c.is_ascii_uppercase()))
+        {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "A VAT number starts with a country code.",
+            ));
+        }
+        fields.vat_number = corrected_text(Some(vat_number));
+    }
+    if let Some(net_total) = payload.net_total {
+        fields.net_total = net_total.and_then(corrected);
+    }
+    if let Some(vat_total) = payload.vat_total {
+        fields.vat_total = vat_total.and_then(corrected);
+    }
+    if let Some(gross_total) = payload.gross_total {
+        fields.gross_total = gross_total.and_then(corrected);
+    }
+
+    sqlx::query!(
+        "UPDATE files SET fields_status = $1, invoice_fields = $2, updated_at = now() WHERE id = $3",
+        FIELDS_EXTRACTED,
+        JsonColumn(fields) as JsonColumn<InvoiceFields>,
+        id
+    )
+    .execute(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update the file"))?;
+
+    Ok(get_one(State(app_state), Path(id)).await?)
+}
+
+// GET /files/:id/versions - All the versions of the file, first one first
+pub async fn get_versions(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<FileVersion>>, AppError> {
+    let first_version = sqlx::query_scalar!(
+        r#"SELECT COALESCE(version_of, id) as "first_version!" FROM files WHERE id = $1"#,
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "File not found"))?;
+
+    let versions = sqlx::query_as!(
+        FileVersion,
+        r#"
+        SELECT
+            id, version, file_name, original_file_name, file_size, mime_type, scan_status,
+            is_current, rejection_reason, rejected_at, created_at
+        FROM files
+        WHERE (id = $1 OR version_of = $1) AND converted_into IS NULL
+        ORDER BY version
+        "#,
+        first_version
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch versions of file {}: {}", id, e);
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to fetch versions",
+        )
+    })?;
+
+    Ok(Json(versions))
+}
+
+// PUT /files/:id/current - Makes this version the one listed with the request's files
+pub async fn make_current(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<FileResponse>, AppError> {
+    let file = sqlx::query!(
+        r#"
+        SELECT
+            COALESCE(version_of, id) as "first_version!", converted_into, rejected_at, scan_status
+        FROM files WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "File not found"))?;
+    if file.converted_into.is_some() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "This photo was converted, its PDF is the version.",
+        ));
+    }
+    if file.rejected_at.is_some() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "A rejected version can't be made current.",
+        ));
+    }
+    if !matches!(file.scan_status.as_str(), SCAN_CLEAN | SCAN_UNSCANNED) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Only a version found clean by the malware scan can be made current.",
+        ));
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE files SET is_current = (id = $1), updated_at = now()
+        WHERE (id = $2 OR version_of = $2) AND converted_into IS NULL
+        "#,
+        id,
+        file.first_version
+    )
+    .execute(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to make file {} current: {}", id, e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update file")
+    })?;
+
+    Ok(get_one(State(app_state), Path(id)).await?)
+}
+
+// POST /files/:id/reject - Turns a version down with a reason for the client. It stays in the
+// history of the file, and listed if it is the current version until a new one replaces it.
+pub async fn reject(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<RejectFilePayload>,
+) -> Result<Json<FileResponse>, AppError> {
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "A rejection reason is required.",
+        ));
+    }
+
+    let rejected = sqlx::query!(
+        r#"
+        UPDATE files SET rejection_reason = $1, rejected_at = now(), updated_at = now()
+        WHERE id = $2 AND converted_into IS NULL
+        "#,
+        reason,
+        id
+    )
+    .execute(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to reject file {}: {}", id, e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update file")
+    })?;
+    if rejected.rows_affected() == 0 {
+        return Err(AppError::new(StatusCode::NOT_FOUND, "File not found"));
+    }
+
+    Ok(get_one(State(app_state), Path(id)).await?)
+}
+
+// GET /files/:id/download - Only files that passed the malware scan can be downloaded
+pub async fn download(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let file = sqlx::query!(
+        "SELECT file_name, storage_key, mime_type, scan_status FROM files WHERE id = $1",
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "File not found"))?;
+
+    match file.scan_status.as_str() {
+        SCAN_CLEAN | SCAN_UNSCANNED => {}
+        SCAN_INFECTED => {
+            return Err(AppError::new(
+                StatusCode::FORBIDDEN,
+                "The file is infected and was removed.",
+            ))
+        }
+        SCAN_FAILED => {
+            return Err(AppError::new(
+                StatusCode::LOCKED,
+                "The file could not be scanned for malware and stays quarantined.",
+            ))
+        }
+        _ => {
+            return Err(AppError::new(
+                StatusCode::LOCKED,
+                "The file is quarantined until its malware scan completes.",
+            ))
+        }
+    }
+
+    let reader = app_state
+        .storage
+        .get(&file.storage_key)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to read file {} from storage: {}", id, e);
+            AppError::new(StatusCode::NOT_FOUND, "File content not found")
+        })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, file.mime_type),
+            (
+                header::CONTENT_DISPOSITION,
+                attachment_disposition(&file.file_name),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(reader)),
+    ))
+}
+
+// DELETE /files/:id
+pub async fn delete(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let storage_keys = async {
+        let mut tx = app_state.db_pool.begin().await?;
+
+        // The next version links the others once the first one is gone
+        let first_version = sqlx::query_scalar!(
+            r#"
+            WITH next AS (SELECT id FROM files WHERE version_of = $1 ORDER BY version LIMIT 1)
+            UPDATE files SET version_of = NULLIF((SELECT id FROM next), id)
+            WHERE version_of = $1
+            RETURNING COALESCE(version_of, id) as "first_version!"
+            "#,
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let first_version = match first_version {
+            Some(first_version) => Some(first_version),
+            None => sqlx::query_scalar!("SELECT version_of FROM files WHERE id = $1", id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .flatten(),
+        };
+
+        // The originals of a converted PDF go with it
+        let storage_keys = sqlx::query_scalar!(
+            "DELETE FROM files WHERE id = $1 OR converted_into = $1 RETURNING storage_key",
+            id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // The latest remaining clean version is listed when the current one is deleted
+        if let Some(first_version) = first_version {
+            sqlx::query!(
+                r#"
+                UPDATE files SET is_current = TRUE
+                WHERE id = (
+                    SELECT id FROM files
+                    WHERE (id = $1 OR version_of = $1) AND converted_into IS NULL
+                        AND scan_status IN ($2, $3) AND rejected_at IS NULL
+                    ORDER BY version DESC LIMIT 1
+                )
+                AND NOT EXISTS (
+                    SELECT 1 FROM files
+                    WHERE (id = $1 OR version_of = $1) AND converted_into IS NULL AND is_current
+                )
+                "#,
+                first_version,
+                SCAN_CLEAN,
+                SCAN_UNSCANNED
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(storage_keys)
+    }
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to delete file {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if storage_keys.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // The rows are gone either way; a leftover object is only wasted space
+    for storage_key in storage_keys {
+        for key in [
+            preview_key(&storage_key),
+            rendition_key(&storage_key),
+            storage_key,
+        ] {
+            if let Err(e) = app_state.storage.delete(&key).await {
+                eprintln!("Failed to delete {} from storage: {}", key, e);
+            }
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
 }
@@ -1,7 +1,11 @@
 use crate::app_error::AppError;
 use crate::model::client::{Client, ClientResponse};
-use crate::model::firm::{CreateFirmPayload, Firm, FirmResponse, UpdateFirmPayload};
+use crate::model::firm::{
+    CreateFirmPayload, Firm, FirmResponse, FirmSettings, UpdateFirmPayload,
+    UpdateFirmSettingsPayload,
+};
 use crate::model::user::{User, UserResponse};
+use crate::naming::validate_template;
 use axum::{
     extract::{Path, State},
     http::StatusCode,
@@ -155,3 +159,76 @@ pub async fn delete(
     Ok(StatusCode::NO_CONTENT)
 }
 
+// GET /firms/:id/settings
+pub async fn get_settings(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<FirmSettings>, AppError> {
+    let settings = sqlx::query_as!(
+        FirmSettings,
+        r#"
+        SELECT
+            f.id as firm_id,
+            COALESCE(s.reject_duplicate_uploads, FALSE) as "reject_duplicate_uploads!",
+            s.file_name_template as "file_name_template?"
+        FROM firms f
+        LEFT JOIN firm_settings s ON s.firm_id = f.id
+        WHERE f.id = $1
+        "#,
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Firm not found"))?;
+
+    Ok(Json(settings))
+}
+
+// PUT /firms/:id/settings - Updates the provided settings
+pub async fn put_settings(
+    State(app_state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateFirmSettingsPayload>,
+) -> Result<Json<FirmSettings>, AppError> {
+    let template = payload
+        .file_name_template
+        .map(|template| template.trim().to_string());
+    if let Some(template) = template.as_deref().filter(|template| !template.is_empty()) {
+        validate_template(template).map_err(|e| AppError::new(StatusCode::BAD_REQUEST, &e))?;
+    }
+
+    let settings = sqlx::query_as!(
+        FirmSettings,
+        r#"
+        INSERT INTO firm_settings (firm_id, reject_duplicate_uploads, file_name_template)
+        VALUES ($1, COALESCE($2, FALSE), NULLIF($3, ''))
+        ON CONFLICT (firm_id) DO UPDATE
+        SET reject_duplicate_uploads = COALESCE($2, firm_settings.reject_duplicate_uploads),
+            file_name_template = CASE
+                WHEN $3::TEXT IS NULL THEN firm_settings.file_name_template
+                ELSE NULLIF($3, '')
+            END,
+            updated_at = now()
+        RETURNING firm_id, reject_duplicate_uploads, file_name_template
+        "#,
+        id,
+        payload.reject_duplicate_uploads,
+        template
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to save firm settings: {}", e);
+        match e.as_database_error() {
+            Some(db_err) if db_err.is_foreign_key_violation() => {
+                AppError::new(StatusCode::NOT_FOUND, "Firm not found")
+            }
+            _ => AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to save firm settings",
+            ),
+        }
+    })?;
+
+    Ok(Json(settings))
+}
@@ -0,0 +1,317 @@
+use crate::app_error::AppError;
+use crate::mailer::Email;
+use crate::model::reminder::{
+    Reminder, ReminderPolicy, UpdateReminderPolicyPayload, TRIGGER_MANUAL, TRIGGER_SCHEDULED,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+
+// GET /collections/:id/reminder-policy
+pub async fn get_policy(
+    State(app_state): State<AppState>,
+    Path(collection_id): Path<Uuid>,
+) -> Result<Json<ReminderPolicy>, AppError> {
+    let policy = sqlx::query_as!(
+        ReminderPolicy,
+        "SELECT * FROM reminder_policies WHERE collection_id = $1",
+        collection_id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Reminder policy not found"))?;
+
+    Ok(Json(policy))
+}
+
+// PUT /collections/:id/reminder-policy - Creates the policy or updates the provided fields
+pub async fn put_policy(
+    State(app_state): State<AppState>,
+    Path(collection_id): Path<Uuid>,
+    Json(payload): Json<UpdateReminderPolicyPayload>,
+) -> Result<Json<ReminderPolicy>, AppError> {
+    if let Some(days_before) = &payload.days_before {
+        if days_before.iter().any(|days| *days < 0) {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "days_before must only contain positive numbers of days.",
+            ));
+        }
+    }
+
+    if payload.repeat_every_days.is_some_and(|every| every < 0) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "repeat_every_days must be a positive number of days.",
+        ));
+    }
+
+    let policy = sqlx::query_as!(
+        ReminderPolicy,
+        r#"
+        INSERT INTO reminder_policies (collection_id, days_before, repeat_every_days, enabled)
+        VALUES ($1, COALESCE($2, '{7,2}'::INTEGER[]), COALESCE($3, 7), COALESCE($4, TRUE))
+        ON CONFLICT (collection_id) DO UPDATE
+        SET days_before = COALESCE($2, reminder_policies.days_before),
+            repeat_every_days = COALESCE($3, reminder_policies.repeat_every_days),
+            enabled = COALESCE($4, reminder_policies.enabled),
+            updated_at = now()
+        RETURNING *
+        "#,
+        collection_id,
+        payload.days_before.as_deref(),
+        payload.repeat_every_days,
+        payload.enabled
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to save reminder policy: {}", e);
+        if let Some(db_err) = e.as_database_error() {
+            if db_err.is_foreign_key_violation() {
+                return AppError::new(StatusCode::NOT_FOUND, "Collection not found");
+            }
+        }
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error saving reminder policy.",
+        )
+    })?;
+
+    Ok(Json(policy))
+}
+
+// DELETE /collections/:id/reminder-policy
+pub async fn delete_policy(
+    State(app_state): State<AppState>,
+    Path(collection_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let rows_affected = sqlx::query!(
+        "DELETE FROM reminder_policies WHERE collection_id = $1",
+        collection_id
+    )
+    .execute(&app_state.db_pool)
+    .await
+    .map_err(|_| {
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error deleting reminder policy",
+        )
+    })?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "Reminder policy not found",
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// GET /collections/:id/reminders
+pub async fn get_all_for_collection(
+    State(app_state): State<AppState>,
+    Path(collection_id): Path<Uuid>,
+) -> Result<Json<Vec<Reminder>>, AppError> {
+    let reminders = sqlx::query_as!(
+        Reminder,
+        "SELECT * FROM reminders WHERE collection_id = $1 ORDER BY sent_at DESC",
+        collection_id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch reminders: {}", e);
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to fetch reminders",
+        )
+    })?;
+
+    Ok(Json(reminders))
+}
+
+// POST /collections/:id/reminders - Sends a reminder right away
+pub async fn send_now(
+    State(app_state): State<AppState>,
+    Path(collection_id): Path<Uuid>,
+) -> Result<Json<Reminder>, AppError> {
+    let reminder = send_reminder(&app_state, collection_id, TRIGGER_MANUAL, None)
+        .await?
+        .ok_or_else(|| AppError::new(StatusCode::CONFLICT, "The reminder was already sent."))?;
+    Ok(Json(reminder))
+}
+
+// Emails the client the list of still-pending requests and records the reminder.
+// The reminder is recorded first, claiming its scheduled slot, and removed if the email fails.
+// Returns None when the slot was already claimed.
+pub async fn send_reminder(
+    app_state: &AppState,
+    collection_id: Uuid,
+    trigger: &str,
+    slot_at: Option<DateTime<Utc>>,
+) -> Result<Option<Reminder>, AppError> {
+    let collection = sqlx::query!(
+        r#"
+        SELECT c.title, c.access_token, c.expires_at, cl.email as client_email, cl.company_name
+        FROM collections c
+        JOIN clients cl ON c.client_id = cl.id
+        WHERE c.id = $1
+        "#,
+        collection_id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Collection not found"))?;
+
+    let pending_titles = sqlx::query_scalar!(
+        "SELECT title FROM requests WHERE collection_id = $1 AND status = 'pending' ORDER BY created_at",
+        collection_id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch pending requests: {}", e);
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to fetch pending requests",
+        )
+    })?;
+
+    if pending_titles.is_empty() {
+        return Err(AppError::new(
+            StatusCode::CONFLICT,
+            "Every request of this collection is already fulfilled.",
+        ));
+    }
+
+    let items: String = pending_titles
+        .iter()
+        .map(|title| format!("  - {}\n", title))
+        .collect();
+    let email = Email {
+        to: collection.client_email.clone(),
+        subject: format!("Reminder: documents needed for \"{}\"", collection.title),
+        body: format!(
+            "Hello {},\n\nWe are still waiting for the following documents for \"{}\" (due {}):\n\n{}\nYou can upload them here: {}/{}\n",
+            collection.company_name,
+            collection.title,
+            collection.expires_at.format("%Y-%m-%d"),
+            items,
+            app_state.portal_url.trim_end_matches('/'),
+            collection.access_token,
+        ),
+    };
+
+    let reminder = sqlx::query_as!(
+        Reminder,
+        r#"
+        INSERT INTO reminders (collection_id, recipient, trigger, pending_requests, slot_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (collection_id, slot_at) WHERE slot_at IS NOT NULL DO NOTHING
+        RETURNING *
+        "#,
+        collection_id,
+        collection.client_email,
+        trigger,
+        pending_titles.len() as i32,
+        slot_at
+    )
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to record reminder: {}", e);
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to record reminder",
+        )
+    })?;
+    let Some(reminder) = reminder else {
+        return Ok(None);
+    };
+
+    if let Err(e) = app_state.mailer.send(email).await {
+        eprintln!("Failed to send reminder email: {}", e);
+        if let Err(e) = sqlx::query!("DELETE FROM reminders WHERE id = $1", reminder.id)
+            .execute(&app_state.db_pool)
+            .await
+        {
+            eprintln!("Failed to remove unsent reminder {}: {}", reminder.id, e);
+        }
+        return Err(AppError::new(
+            StatusCode::BAD_GATEWAY,
+            "Failed to send reminder email",
+        ));
+    }
+
+    Ok(Some(reminder))
+}
+
+// Sends every scheduled reminder that is due and returns how many were sent.
+// Collections whose requests are all fulfilled are skipped, which stops the reminders automatically.
+pub async fn send_due_reminders(app_state: &AppState) -> anyhow::Result<usize> {
+    let candidates = sqlx::query!(
+        r#"
+        SELECT
+            p.collection_id, p.days_before, p.repeat_every_days, p.enabled, p.created_at, p.updated_at,
+            c.expires_at,
+            (SELECT MAX(r.sent_at) FROM reminders r WHERE r.collection_id = p.collection_id AND r.trigger = $1) as last_sent_at
+        FROM reminder_policies p
+        JOIN collections c ON c.id = p.collection_id
+        WHERE p.enabled
+          AND EXISTS (SELECT 1 FROM requests rq WHERE rq.collection_id = p.collection_id AND rq.status = 'pending')
+        "#,
+        TRIGGER_SCHEDULED
+    )
+    .fetch_all(&app_state.db_pool)
+    .await?;
+
+    let now = Utc::now();
+    let mut sent = 0;
+    for row in candidates {
+        let policy = ReminderPolicy {
+            collection_id: row.collection_id,
+            days_before: row.days_before,
+            repeat_every_days: row.repeat_every_days,
+            enabled: row.enabled,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        };
+
+        let Some(slot) = policy.due_slot(row.expires_at, now) else {
+            continue;
+        };
+        if row
+            .last_sent_at
+            .is_some_and(|last_sent_at| last_sent_at >= slot)
+        {
+            continue;
+        }
+
+        match send_reminder(
+            app_state,
+            policy.collection_id,
+            TRIGGER_SCHEDULED,
+            Some(slot),
+        )
+        .await
+        {
+            Ok(Some(_)) => sent += 1,
+            Ok(None) => {}
+            Err(_) => eprintln!(
+                "Failed to send scheduled reminder for collection {}",
+                policy.collection_id
+            ),
+        }
+    }
+
+    Ok(sent)
+}
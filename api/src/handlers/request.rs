@@ -1,23 +1,41 @@
+use crate::app_error::AppError;
+use crate::auth::PortalAccess;
+use crate::file_type::normalize_allowed_types;
 use crate::handlers::collection as collection_handler;
-use crate::model::request::{CreateRequestPayload, Request, RequestResponse, UpdateRequestPayload};
+use crate::model::collection::CollectionQuery;
+use crate::model::question::{validate_answers, validate_schema, Question};
+use crate::model::request::{
+    CreateRequestPayload, PortalRequestResponse, Request, RequestFilters, RequestResponse,
+    UpdateRequestPayload, KIND_FILES, KIND_QUESTIONNAIRE,
+};
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
-    Json,
+    Extension, Json,
 };
+use serde_json::{Map, Value};
 use uuid::Uuid;
 
 use crate::app_state::AppState;
 
-// GET /requests
+// GET /requests?overdue=true
 pub async fn get_all(
     State(app_state): State<AppState>,
+    Query(filters): Query<RequestFilters>,
 ) -> Result<Json<Vec<RequestResponse>>, StatusCode> {
     // This is inefficient due to N+1, but simple. A real implementation would use a more complex query.
-    let requests = sqlx::query_as!(Request, "SELECT * FROM requests")
-        .fetch_all(&app_state.db_pool)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let requests = sqlx::query_as!(
+        Request,
+        r#"
+        SELECT * FROM requests
+        WHERE $1::BOOLEAN IS NULL
+           OR (status = 'pending' AND due_date IS NOT NULL AND due_date < CURRENT_DATE) = $1
+        "#,
+        filters.overdue
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let mut responses = Vec::new();
     for request in requests {
@@ -33,15 +51,27 @@ pub async fn get_one(
     State(app_state): State<AppState>,
     Path(id): Path<Uuid>,
 ) -> Result<Json<RequestResponse>, StatusCode> {
-    let request = sqlx::query_as!(Request, "SELECT * FROM requests WHERE id = $1", id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let request = sqlx::query!(
+        r#"
+        SELECT
+            r.*,
+            (r.status = 'pending' AND r.due_date IS NOT NULL AND r.due_date < CURRENT_DATE) as "overdue!"
+        FROM requests r
+        WHERE r.id = $1
+        "#,
+        id
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|_| StatusCode::NOT_FOUND)?;
 
-    let collection_response =
-        collection_handler::get_one(State(app_state), Path(request.collection_id))
-            .await?
-            .0;
+    let collection_response = collection_handler::get_one(
+        State(app_state),
+        Path(request.collection_id),
+        Query(CollectionQuery::default()),
+    )
+    .await?
+    .0;
 
     let request_response = RequestResponse {
         id: request.id,
@@ -49,6 +79,20 @@ pub async fn get_one(
         title: request.title,
         description: request.description,
         status: request.status,
+        due_date: request.due_date,
+        overdue: request.overdue,
+        kind: request.kind,
+        questions: request
+            .questions
+            .and_then(|questions| serde_json::from_value(questions).ok()),
+        answers: match request.answers {
+            Some(Value::Object(answers)) => Some(answers),
+            _ => None,
+        },
+        answered_at: request.answered_at,
+        allowed_types: request.allowed_types,
+        convert_images_to_pdf: request.convert_images_to_pdf,
+        combine_images: request.combine_images,
         created_at: request.created_at,
         updated_at: request.updated_at,
     };
@@ -60,25 +104,118 @@ pub async fn get_one(
 pub async fn create(
     State(app_state): State<AppState>,
     Json(payload): Json<CreateRequestPayload>,
-) -> Result<Json<RequestResponse>, StatusCode> {
+) -> Result<Json<RequestResponse>, AppError> {
+    let (kind, questions) = check_kind(payload.kind.as_deref(), payload.questions)?;
+    let allowed_types = check_allowed_types(&kind, payload.allowed_types)?;
+    check_image_conversion(&kind, payload.convert_images_to_pdf, payload.combine_images)?;
+
     let request = sqlx::query!(
         r#"
-        INSERT INTO requests (collection_id, title, description, status)
-        VALUES ($1, $2, $3, 'pending')
+        INSERT INTO requests (
+            collection_id, title, description, status, due_date, kind, questions, allowed_types,
+            convert_images_to_pdf, combine_images
+        )
+        VALUES ($1, $2, $3, 'pending', $4, $5, $6, $7, $8, $9)
         RETURNING id
         "#,
         payload.collection_id,
         payload.title,
-        payload.description
+        payload.description,
+        payload.due_date,
+        kind,
+        questions,
+        allowed_types.as_deref(),
+        payload.convert_images_to_pdf,
+        payload.combine_images
     )
     .fetch_one(&app_state.db_pool)
     .await
     .map_err(|e| {
         eprintln!("Failed to create request: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error creating request.")
     })?;
 
-    get_one(State(app_state), Path(request.id)).await
+    Ok(get_one(State(app_state), Path(request.id)).await?)
+}
+
+// Validates the kind of a new request and serializes its questions
+pub fn check_kind(
+    kind: Option<&str>,
+    questions: Option<Vec<Question>>,
+) -> Result<(String, Option<Value>), AppError> {
+    let kind = kind.unwrap_or(KIND_FILES);
+    let questions = match (kind, questions) {
+        (KIND_QUESTIONNAIRE, Some(questions)) => {
+            validate_schema(&questions).map_err(|e| AppError::new(StatusCode::BAD_REQUEST, &e))?;
+            Some(questions_to_json(&questions)?)
+        }
+        (KIND_QUESTIONNAIRE, None) => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "A questionnaire request needs questions.",
+            ))
+        }
+        (KIND_FILES, None) => None,
+        (KIND_FILES, Some(_)) => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Only questionnaire requests can have questions.",
+            ))
+        }
+        _ => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "kind must be \"files\" or \"questionnaire\".",
+            ))
+        }
+    };
+
+    Ok((kind.to_string(), questions))
+}
+
+// Validates the file types accepted by a request, None when it accepts any type
+pub fn check_allowed_types(
+    kind: &str,
+    allowed_types: Option<Vec<String>>,
+) -> Result<Option<Vec<String>>, AppError> {
+    let allowed_types = match allowed_types {
+        Some(allowed_types) => normalize_allowed_types(allowed_types)
+            .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, &e))?,
+        None => None,
+    };
+    if allowed_types.is_some() && kind != KIND_FILES {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Only files requests can restrict file types.",
+        ));
+    }
+    Ok(allowed_types)
+}
+
+// Photos can only be converted by files requests, and only combined when converted
+pub fn check_image_conversion(kind: &str, convert: bool, combine: bool) -> Result<(), AppError> {
+    if convert && kind != KIND_FILES {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Only files requests can convert images to PDF.",
+        ));
+    }
+    if combine && !convert {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "combine_images requires convert_images_to_pdf.",
+        ));
+    }
+    Ok(())
+}
+
+fn questions_to_json(questions: &[Question]) -> Result<Value, AppError> {
+    serde_json::to_value(questions).map_err(|_| {
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error serializing questions.",
+        )
+    })
 }
 
 // PATCH /requests/:id
@@ -86,11 +223,11 @@ pub async fn update(
     State(app_state): State<AppState>,
     Path(id): Path<Uuid>,
     Json(payload): Json<UpdateRequestPayload>,
-) -> Result<Json<RequestResponse>, StatusCode> {
+) -> Result<Json<RequestResponse>, AppError> {
     let mut request = sqlx::query_as!(Request, "SELECT * FROM requests WHERE id = $1", id)
         .fetch_one(&app_state.db_pool)
         .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Request not found"))?;
 
     if let Some(title) = payload.title {
         request.title = title;
@@ -104,22 +241,124 @@ pub async fn update(
         request.status = status;
     }
 
+    if let Some(due_date) = payload.due_date {
+        request.due_date = due_date;
+    }
+
+    if let Some(questions) = payload.questions {
+        if request.kind != KIND_QUESTIONNAIRE {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Only questionnaire requests can have questions.",
+            ));
+        }
+        validate_schema(&questions).map_err(|e| AppError::new(StatusCode::BAD_REQUEST, &e))?;
+        request.questions = Some(questions_to_json(&questions)?);
+    }
+
+    if let Some(allowed_types) = payload.allowed_types {
+        request.allowed_types = check_allowed_types(&request.kind, Some(allowed_types))?;
+    }
+
+    if let Some(convert_images_to_pdf) = payload.convert_images_to_pdf {
+        request.convert_images_to_pdf = convert_images_to_pdf;
+        // Combining makes no sense without converting
+        request.combine_images &= convert_images_to_pdf;
+    }
+    if let Some(combine_images) = payload.combine_images {
+        request.combine_images = combine_images;
+    }
+    check_image_conversion(
+        &request.kind,
+        request.convert_images_to_pdf,
+        request.combine_images,
+    )?;
+
     sqlx::query!(
         r#"
         UPDATE requests
-        SET title = $1, description = $2, status = $3, updated_at = now()
-        WHERE id = $4
+        SET title = $1, description = $2, status = $3, due_date = $4, questions = $5,
+            allowed_types = $6, convert_images_to_pdf = $7, combine_images = $8, updated_at = now()
+        WHERE id = $9
         "#,
         request.title,
         request.description,
         request.status,
+        request.due_date,
+        request.questions,
+        request.allowed_types.as_deref(),
+        request.convert_images_to_pdf,
+        request.combine_images,
         id
     )
     .execute(&app_state.db_pool)
     .await
-    .map_err(|_| StatusCode::NOT_FOUND)?;
+    .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Request not found"))?;
+
+    Ok(get_one(State(app_state), Path(id)).await?)
+}
+
+// GET /portal/requests/:request_id
+pub async fn portal_get_one(
+    State(app_state): State<AppState>,
+    Extension(access): Extension<PortalAccess>,
+    Path(request_id): Path<Uuid>,
+) -> Result<Json<PortalRequestResponse>, AppError> {
+    access.ensure_request(&app_state, request_id).await?;
+    let request = get_one(State(app_state), Path(request_id)).await?.0;
+    Ok(Json(request.into()))
+}
+
+// PUT /portal/requests/:request_id/answers - The client answers a questionnaire request
+pub async fn portal_submit_answers(
+    State(app_state): State<AppState>,
+    Extension(access): Extension<PortalAccess>,
+    Path(request_id): Path<Uuid>,
+    Json(answers): Json<Map<String, Value>>,
+) -> Result<Json<PortalRequestResponse>, AppError> {
+    access.ensure_request(&app_state, request_id).await?;
+
+    let request = sqlx::query_as!(Request, "SELECT * FROM requests WHERE id = $1", request_id)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Request not found"))?;
+
+    let questions: Vec<Question> = match (request.kind.as_str(), request.questions) {
+        (KIND_QUESTIONNAIRE, Some(questions)) => serde_json::from_value(questions).map_err(|_| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Invalid questionnaire.",
+            )
+        })?,
+        _ => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "This request does not expect answers.",
+            ))
+        }
+    };
+
+    let answers = validate_answers(&questions, &answers)
+        .map_err(|errors| AppError::new(StatusCode::UNPROCESSABLE_ENTITY, &errors.join("\n")))?;
+
+    sqlx::query!(
+        r#"
+        UPDATE requests
+        SET answers = $1, answered_at = now(), status = 'fulfilled', updated_at = now()
+        WHERE id = $2
+        "#,
+        Value::Object(answers),
+        request_id
+    )
+    .execute(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to save answers: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving answers.")
+    })?;
 
-    get_one(State(app_state), Path(id)).await
+    let request = get_one(State(app_state), Path(request_id)).await?.0;
+    Ok(Json(request.into()))
 }
 
 // DELETE /requests/:id
@@ -0,0 +1,102 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use uuid::Uuid;
+
+use crate::app_error::AppError;
+use crate::app_state::AppState;
+use crate::model::search::{SearchContext, SearchQuery, SearchResult};
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+// GET /search?q=orange invoice - Files of the user's firm matching the words, best matches first
+pub async fn search(
+    State(app_state): State<AppState>,
+    Extension(user_id): Extension<Uuid>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchResult>>, AppError> {
+    if query.q.trim().is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "A search query is required.",
+        ));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let firm_id = sqlx::query_scalar!("SELECT firm_id FROM users WHERE id = $1", user_id)
+        .fetch_optional(&app_state.db_pool)
+        .await
+        .map_err(|_| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch user"))?
+        .flatten()
+        .ok_or_else(|| {
+            AppError::new(StatusCode::FORBIDDEN, "Your account is not part of a firm.")
+        })?;
+
+    // Converted photos are found through their PDF, quarantined files and replaced versions are
+    // not searchable
+    let rows = sqlx::query!(
+        r#"
+        WITH query AS (SELECT websearch_to_tsquery('simple', $2) AS tsquery)
+        SELECT
+            f.id, f.file_name, f.mime_type, f.created_at,
+            ts_rank(f.search_vector, query.tsquery) as "rank!",
+            ts_headline(
+                'simple',
+                COALESCE(NULLIF(f.extracted_text, ''), f.file_name),
+                query.tsquery,
+                'StartSel=**, StopSel=**, MaxWords=30, MinWords=10, MaxFragments=2'
+            ) as "snippet!",
+            r.id as request_id, r.title as request_title,
+            c.id as collection_id, c.title as collection_title,
+            cl.id as client_id, cl.company_name
+        FROM query, files f
+        JOIN requests r ON f.request_id = r.id
+        JOIN collections c ON r.collection_id = c.id
+        JOIN clients cl ON c.client_id = cl.id
+        WHERE cl.firm_id = $1
+            AND f.search_vector @@ query.tsquery
+            AND f.converted_into IS NULL AND f.is_current
+            AND f.scan_status IN ('clean', 'unscanned')
+        ORDER BY 5 DESC, f.created_at DESC
+        LIMIT $3
+        "#,
+        firm_id,
+        query.q,
+        limit
+    )
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to search files: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to search files")
+    })?;
+
+    let results = rows
+        .into_iter()
+        .map(|row| SearchResult {
+            file_id: row.id,
+            file_name: row.file_name,
+            mime_type: row.mime_type,
+            uploaded_at: row.created_at,
+            rank: row.rank,
+            snippet: row.snippet.replace('\n', " "), // Texts keep their line breaks
+            request: SearchContext {
+                id: row.request_id,
+                name: row.request_title,
+            },
+            collection: SearchContext {
+                id: row.collection_id,
+                name: row.collection_title,
+            },
+            client: SearchContext {
+                id: row.client_id,
+                name: row.company_name,
+            },
+        })
+        .collect();
+
+    Ok(Json(results))
+}
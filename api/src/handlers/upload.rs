@@ -0,0 +1,576 @@
+use axum::{
+    body::Body,
+    extract::{OriginalUri, Path, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Duration, Utc};
+use futures::{stream, StreamExt};
+use std::collections::HashMap;
+use tokio::io::AsyncReadExt;
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+use crate::app_error::AppError;
+use crate::app_state::AppState;
+use crate::auth::PortalAccess;
+use crate::handlers::file::{firm_of_request, first_version_of, save_upload, StoredUpload};
+use crate::model::upload::{
+    Upload, COMPLETION_TIMEOUT_MINUTES, MAX_RESUMABLE_UPLOAD_BYTES, TUS_EXTENSIONS, TUS_VERSION,
+    UPLOAD_LIFETIME_HOURS,
+};
+use crate::storage::{new_key, HashingReader, StorageReader};
+
+// Resumable uploads with the tus protocol (https://tus.io/protocols/resumable-upload), for
+// large files sent over poor connections. The same routes are served to the firm's users under
+// /uploads and to clients under /portal/uploads, who only reach the requests of their collection.
+
+const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+const UPLOAD_DEFER_LENGTH: HeaderName = HeaderName::from_static("upload-defer-length");
+// Id of the file a completed upload became
+const FILE_ID: HeaderName = HeaderName::from_static("file-id");
+
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+// Requests other than OPTIONS must speak our version of tus, and every response tells it
+pub async fn tus_middleware(request: Request, next: Next) -> Response {
+    let supported = request.method() == Method::OPTIONS
+        || request
+            .headers()
+            .get(TUS_RESUMABLE)
+            .map(HeaderValue::as_bytes)
+            == Some(TUS_VERSION.as_bytes());
+    let mut response = if supported {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::PRECONDITION_FAILED,
+            [(TUS_VERSION_HEADER, TUS_VERSION)],
+            "Unsupported tus version.",
+        )
+            .into_response()
+    };
+    response
+        .headers_mut()
+        .insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+// OPTIONS /uploads - What the server supports
+pub async fn options() -> impl IntoResponse {
+    (
+        StatusCode::NO_CONTENT,
+        [
+            (TUS_VERSION_HEADER, TUS_VERSION.to_string()),
+            (TUS_EXTENSION, TUS_EXTENSIONS.to_string()),
+            (TUS_MAX_SIZE, MAX_RESUMABLE_UPLOAD_BYTES.to_string()),
+        ],
+    )
+}
+
+// POST /uploads - Creates an upload of Upload-Length bytes. Upload-Metadata gives the
+// `request_id` it is for, its `filename` and the id of the file it `replaces`, if any.
+pub async fn create(
+    State(app_state): State<AppState>,
+    access: Option<Extension<PortalAccess>>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let upload_length = header_number(&headers, &UPLOAD_LENGTH)?.ok_or_else(|| {
+        if headers.contains_key(UPLOAD_DEFER_LENGTH) {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Deferred lengths are not supported.",
+            )
+        } else {
+            AppError::new(StatusCode::BAD_REQUEST, "Upload-Length is required.")
+        }
+    })?;
+    if upload_length == 0 {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "A file is required.",
+        ));
+    }
+    if upload_length > MAX_RESUMABLE_UPLOAD_BYTES {
+        return Err(AppError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "The file is too large.",
+        ));
+    }
+
+    let raw_metadata = headers
+        .get(UPLOAD_METADATA)
+        .map(|value| value.to_str())
+        .transpose()
+        .map_err(|_| AppError::new(StatusCode::BAD_REQUEST, "Invalid Upload-Metadata."))?;
+    let metadata = parse_metadata(raw_metadata.unwrap_or_default())?;
+    let request_id = metadata
+        .get("request_id")
+        .ok_or_else(|| AppError::new(StatusCode::BAD_REQUEST, "A request_id is required."))?
+        .trim()
+        .parse::<Uuid>()
+        .map_err(|_| AppError::new(StatusCode::BAD_REQUEST, "Invalid request_id."))?;
+    let file_name = metadata
+        .get("filename")
+        .or_else(|| metadata.get("name"))
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("file");
+    let replaces = metadata
+        .get("replaces")
+        .map(|id| id.trim().parse::<Uuid>())
+        .transpose()
+        .map_err(|_| AppError::new(StatusCode::BAD_REQUEST, "Invalid replaces."))?;
+
+    if let Some(Extension(access)) = access {
+        access.ensure_request(&app_state, request_id).await?;
+    }
+    firm_of_request(&app_state, request_id).await?;
+    if let Some(replaced_id) = replaces {
+        first_version_of(&app_state, request_id, replaced_id).await?;
+    }
+
+    let upload_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO uploads (request_id, file_name, replaces, metadata, upload_length, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id
+        "#,
+        request_id,
+        file_name,
+        replaces,
+        raw_metadata,
+        upload_length,
+        Utc::now() + Duration::hours(UPLOAD_LIFETIME_HOURS)
+    )
+    .fetch_one(&app_state.db_pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to create upload: {}", e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+    })?;
+
+    let location = format!("{}/{}", uri.path().trim_end_matches('/'), upload_id);
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)]).into_response())
+}
+
+// HEAD /uploads/:id - How much of the upload was received, to resume it from there. Once
+// complete, File-Id gives the file it became, or the error tells why it was refused.
+pub async fn head(
+    State(app_state): State<AppState>,
+    access: Option<Extension<PortalAccess>>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let upload = fetch_upload(&app_state, access.as_deref(), id).await?;
+    ensure_not_refused(&upload)?;
+    Ok((StatusCode::OK, upload_headers(&upload)).into_response())
+}
+
+// PATCH /uploads/:id - Appends the body at Upload-Offset. What arrived before a dropped
+// connection is kept. The last chunk has the upload assembled, scanned and recorded as a file
+// of its request in the background, the file being given in File-Id by HEAD once it is.
+pub async fn patch(
+    State(app_state): State<AppState>,
+    access: Option<Extension<PortalAccess>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, AppError> {
+    if headers.get(header::CONTENT_TYPE).map(HeaderValue::as_bytes)
+        != Some(OFFSET_OCTET_STREAM.as_bytes())
+    {
+        return Err(AppError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "The Content-Type must be application/offset+octet-stream.",
+        ));
+    }
+    let offset = header_number(&headers, &UPLOAD_OFFSET)?
+        .ok_or_else(|| AppError::new(StatusCode::BAD_REQUEST, "Upload-Offset is required."))?;
+
+    let mut upload = fetch_upload(&app_state, access.as_deref(), id).await?;
+    ensure_not_refused(&upload)?;
+    let conflict = || {
+        AppError::new(
+            StatusCode::CONFLICT,
+            &format!("The upload is at offset {}.", upload.upload_offset),
+        )
+    };
+    if upload.upload_offset == upload.upload_length {
+        // Once received, only confirmed again to a client whose response was lost
+        let more = body
+            .into_data_stream()
+            .any(|chunk| async move { chunk.map_or(true, |chunk| !chunk.is_empty()) });
+        if offset != upload.upload_length || more.await {
+            return Err(conflict());
+        }
+        return Ok((StatusCode::NO_CONTENT, upload_headers(&upload)).into_response());
+    }
+    if offset != upload.upload_offset {
+        return Err(conflict());
+    }
+
+    // Each chunk is an object of its own, encrypted like any other, since objects can't be
+    // appended to
+    let firm_id = firm_of_request(&app_state, upload.request_id).await?;
+    let storage_key = new_key(firm_id);
+    let received = stream::unfold(body.into_data_stream(), |mut body| async move {
+        match body.next().await {
+            Some(Ok(data)) => Some((Ok::<_, std::io::Error>(data), body)),
+            _ => None,
+        }
+    });
+    let mut reader = StreamReader::new(Box::pin(received))
+        .take((upload.upload_length - upload.upload_offset) as u64);
+    let size = app_state
+        .storage
+        .put(&storage_key, &mut reader)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to store chunk of upload {}: {}", id, e);
+            AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Error saving file.")
+        })? as i64;
+
+    if size == 0 {
+        // Nothing arrived
+        let _ = app_state.storage.delete(&storage_key).await;
+    } else {
+        match record_chunk(&app_state, id, offset, &storage_key, size).await {
+            Ok(true) => upload.upload_offset += size,
+            Ok(false) => {
+                let _ = app_state.storage.delete(&storage_key).await;
+                return Err(AppError::new(
+                    StatusCode::CONFLICT,
+                    "The upload was resumed by another request.",
+                ));
+            }
+            Err(e) => {
+                eprintln!("Failed to record chunk of upload {}: {}", id, e);
+                let _ = app_state.storage.delete(&storage_key).await;
+                return Err(AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Error saving file.",
+                ));
+            }
+        }
+    }
+
+    if upload.upload_offset == upload.upload_length {
+        spawn_completion(&app_state, id).await;
+    }
+    Ok((StatusCode::NO_CONTENT, upload_headers(&upload)).into_response())
+}
+
+// DELETE /uploads/:id - Abandons an upload, removing what was received
+pub async fn delete(
+    State(app_state): State<AppState>,
+    access: Option<Extension<PortalAccess>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    fetch_upload(&app_state, access.as_deref(), id).await?;
+    remove_upload(&app_state, id).await.map_err(|e| {
+        eprintln!("Failed to delete upload {}: {}", id, e);
+        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete upload")
+    })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Completes the uploads whose assembly never started or stalled, e.g. on a restart
+pub async fn complete_stalled_uploads(app_state: &AppState) -> anyhow::Result<usize> {
+    let ids = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM uploads
+        WHERE upload_offset = upload_length AND file_id IS NULL AND failure IS NULL
+            AND expires_at > now()
+            AND (completing_at IS NULL OR completing_at < now() - make_interval(mins => $1))
+        "#,
+        COMPLETION_TIMEOUT_MINUTES
+    )
+    .fetch_all(&app_state.db_pool)
+    .await?;
+    let mut completed = 0;
+    for id in ids {
+        if let Some(upload) = claim_completion(app_state, id).await? {
+            complete(app_state, &upload).await?;
+            completed += 1;
+        }
+    }
+    Ok(completed)
+}
+
+// Removes the uploads that were abandoned or completed long enough ago
+pub async fn delete_expired_uploads(app_state: &AppState) -> anyhow::Result<usize> {
+    let ids = sqlx::query_scalar!("SELECT id FROM uploads WHERE expires_at <= now()")
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    for id in &ids {
+        remove_upload(app_state, *id).await?;
+    }
+    Ok(ids.len())
+}
+
+// An upload reachable by the caller: clients only reach those of their collection
+async fn fetch_upload(
+    app_state: &AppState,
+    access: Option<&PortalAccess>,
+    id: Uuid,
+) -> Result<Upload, AppError> {
+    let upload = sqlx::query_as!(
+        Upload,
+        r#"
+        SELECT id, request_id, file_name, replaces, metadata, upload_length, upload_offset,
+            file_id, completing_at, failure, failure_status, expires_at, created_at, updated_at
+        FROM uploads
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .map_err(|_| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch upload"))?
+    .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, "Upload not found"))?;
+
+    if let Some(access) = access {
+        access
+            .ensure_request(app_state, upload.request_id)
+            .await
+            .map_err(|_| AppError::new(StatusCode::NOT_FOUND, "Upload not found"))?;
+    }
+    if upload.expires_at <= Utc::now() {
+        return Err(AppError::new(StatusCode::GONE, "The upload has expired."));
+    }
+    Ok(upload)
+}
+
+// Advances the upload past a stored chunk, unless another request did it first
+async fn record_chunk(
+    app_state: &AppState,
+    upload_id: Uuid,
+    offset: i64,
+    storage_key: &str,
+    size: i64,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = app_state.db_pool.begin().await?;
+    let advanced = sqlx::query!(
+        r#"
+        UPDATE uploads
+        SET upload_offset = upload_offset + $1, expires_at = $2, updated_at = now()
+        WHERE id = $3 AND upload_offset = $4 AND file_id IS NULL
+        "#,
+        size,
+        Utc::now() + Duration::hours(UPLOAD_LIFETIME_HOURS),
+        upload_id,
+        offset
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        == 1;
+    if advanced {
+        sqlx::query!(
+            "INSERT INTO upload_chunks (upload_id, upload_offset, storage_key, size) VALUES ($1, $2, $3, $4)",
+            upload_id,
+            offset,
+            storage_key,
+            size
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    }
+    Ok(advanced)
+}
+
+// Refused uploads answer with the error the file was refused with
+fn ensure_not_refused(upload: &Upload) -> Result<(), AppError> {
+    match (&upload.failure, upload.failure_status) {
+        (Some(failure), Some(status)) => Err(AppError::new(
+            StatusCode::from_u16(status as u16).unwrap_or(StatusCode::UNPROCESSABLE_ENTITY),
+            failure,
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Claims the assembly of a complete upload, unless another request has it under way or done
+async fn claim_completion(
+    app_state: &AppState,
+    upload_id: Uuid,
+) -> Result<Option<Upload>, sqlx::Error> {
+    sqlx::query_as!(
+        Upload,
+        r#"
+        UPDATE uploads SET completing_at = now(), updated_at = now()
+        WHERE id = $1 AND upload_offset = upload_length AND file_id IS NULL AND failure IS NULL
+            AND (completing_at IS NULL OR completing_at < now() - make_interval(mins => $2))
+        RETURNING id, request_id, file_name, replaces, metadata, upload_length, upload_offset,
+            file_id, completing_at, failure, failure_status, expires_at, created_at, updated_at
+        "#,
+        upload_id,
+        COMPLETION_TIMEOUT_MINUTES
+    )
+    .fetch_optional(&app_state.db_pool)
+    .await
+}
+
+// Completes the upload in the background, once claimed
+async fn spawn_completion(app_state: &AppState, upload_id: Uuid) {
+    match claim_completion(app_state, upload_id).await {
+        Ok(Some(upload)) => {
+            let app_state = app_state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = complete(&app_state, &upload).await {
+                    eprintln!("Failed to complete upload {}: {}", upload.id, e);
+                }
+            });
+        }
+        Ok(None) => {}
+        // The scheduler claims it later
+        Err(e) => eprintln!("Failed to claim upload {}: {}", upload_id, e),
+    }
+}
+
+// Assembles the chunks into the file, recorded as if it was uploaded at once. A refused file
+// ends the upload with the reason. Other failures leave it to be completed again once the claim
+// stalls.
+async fn complete(app_state: &AppState, upload: &Upload) -> anyhow::Result<()> {
+    let firm_id = firm_of_request(app_state, upload.request_id)
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e.message()))?;
+    let chunk_keys = sqlx::query_scalar!(
+        "SELECT storage_key FROM upload_chunks WHERE upload_id = $1 ORDER BY upload_offset",
+        upload.id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await?;
+
+    // Chunks read one after the other
+    let mut chunks: StorageReader = Box::new(tokio::io::empty());
+    for chunk_key in &chunk_keys {
+        chunks = Box::new(chunks.chain(app_state.storage.get(chunk_key).await?));
+    }
+    let mut reader = HashingReader::new(chunks);
+    let storage_key = new_key(firm_id);
+    let file_size = app_state.storage.put(&storage_key, &mut reader).await?;
+    let stored = StoredUpload {
+        storage_key,
+        file_name: upload.file_name.clone(),
+        original_file_name: upload.file_name.clone(),
+        file_size: file_size as i64,
+        sha256: reader.sha256(),
+    };
+
+    match save_upload(app_state, upload.request_id, upload.replaces, vec![stored]).await {
+        Ok(file) => {
+            sqlx::query!(
+                "UPDATE uploads SET file_id = $1, completing_at = NULL, updated_at = now() WHERE id = $2",
+                file.id,
+                upload.id
+            )
+            .execute(&app_state.db_pool)
+            .await?;
+        }
+        Err(e) if e.code().is_client_error() => {
+            sqlx::query!(
+                r#"
+                UPDATE uploads
+                SET failure = $1, failure_status = $2, completing_at = NULL, updated_at = now()
+                WHERE id = $3
+                "#,
+                e.message(),
+                e.code().as_u16() as i16,
+                upload.id
+            )
+            .execute(&app_state.db_pool)
+            .await?;
+        }
+        Err(e) => anyhow::bail!("{}", e.message()),
+    }
+    delete_chunks(app_state, upload.id).await
+}
+
+async fn delete_chunks(app_state: &AppState, upload_id: Uuid) -> anyhow::Result<()> {
+    let storage_keys = sqlx::query_scalar!(
+        "DELETE FROM upload_chunks WHERE upload_id = $1 RETURNING storage_key",
+        upload_id
+    )
+    .fetch_all(&app_state.db_pool)
+    .await?;
+    for storage_key in storage_keys {
+        app_state.storage.delete(&storage_key).await?;
+    }
+    Ok(())
+}
+
+async fn remove_upload(app_state: &AppState, upload_id: Uuid) -> anyhow::Result<()> {
+    delete_chunks(app_state, upload_id).await?;
+    sqlx::query!("DELETE FROM uploads WHERE id = $1", upload_id)
+        .execute(&app_state.db_pool)
+        .await?;
+    Ok(())
+}
+
+fn upload_headers(upload: &Upload) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(UPLOAD_OFFSET, upload.upload_offset.into());
+    headers.insert(UPLOAD_LENGTH, upload.upload_length.into());
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    if let Some(metadata) = upload
+        .metadata
+        .as_deref()
+        .and_then(|metadata| HeaderValue::from_str(metadata).ok())
+    {
+        headers.insert(UPLOAD_METADATA, metadata);
+    }
+    if let Some(file_id) = upload.file_id {
+        headers.insert(
+            FILE_ID,
+            HeaderValue::from_str(&file_id.to_string()).unwrap(),
+        );
+    }
+    headers
+}
+
+fn header_number(headers: &HeaderMap, name: &HeaderName) -> Result<Option<i64>, AppError> {
+    headers
+        .get(name)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse::<i64>().ok())
+                .filter(|number| *number >= 0)
+                .ok_or_else(|| {
+                    AppError::new(StatusCode::BAD_REQUEST, &format!("Invalid {}.", name))
+                })
+        })
+        .transpose()
+}
+
+// Upload-Metadata is a comma separated list of keys, each followed by its value in base64
+fn parse_metadata(metadata: &str) -> Result<HashMap<String, String>, AppError> {
+    let mut values = HashMap::new();
+    for pair in metadata
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+        let value = STANDARD
+            .decode(value.trim())
+            .ok()
+            .and_then(|value| String::from_utf8(value).ok())
+            .ok_or_else(|| AppError::new(StatusCode::BAD_REQUEST, "Invalid Upload-Metadata."))?;
+        values.insert(key.to_string(), value);
+    }
+    Ok(values)
+}
@@ -0,0 +1,625 @@
+use chrono::NaiveDate;
+use regex::Regex;
+use rust_decimal::Decimal;
+use std::sync::LazyLock;
+
+use crate::bank_statement::normalize_amount;
+use crate::classifier::normalize;
+use crate::einvoice::{is_valid_french_vat, is_valid_siret, siren_from, siren_from_vat};
+use crate::model::invoice::{ExtractedField, InvoiceFields};
+
+// "Facture N° F-2026-031", "Invoice number: 12345"
+static LABELLED_NUMBER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(?:facture|invoice|avoir|credit note)\s*(?:n\s?[°º]|n[o°º]\.|num[ée]ro|number|no|#)\s*:?\s*([A-Z0-9][A-Z0-9/_.-]*)",
+    )
+    .unwrap()
+});
+
+// "N° de facture : 2026/031"
+static NUMBER_AFTER_LABEL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(?:n\s?[°º]|num[ée]ro)\s*(?:de\s+(?:la\s+)?)?facture\s*:?\s*([A-Z0-9][A-Z0-9/_.-]*)",
+    )
+    .unwrap()
+});
+
+// A "FACTURE F-2026-031" title
+static TITLE_NUMBER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^\s*(?:facture|invoice)\s*:?\s+([A-Z0-9][A-Z0-9/_.-]*)").unwrap()
+});
+
+// 14/03/2026, 14-03-2026, 14.03.2026, 14/03/26 or 2026-03-14
+static NUMERIC_DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(\d{1,2})[/.-](\d{1,2})[/.-](\d{4}|\d{2})\b|\b(\d{4})-(\d{2})-(\d{2})\b")
+        .unwrap()
+});
+
+// "14 mars 2026", "1er mars 2026" once normalized
+static TEXT_DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\b(\d{1,2})(?:er)?\s+(janvier|fevrier|mars|avril|mai|juin|juillet|aout|septembre|octobre|novembre|decembre|january|february|march|april|may|june|july|august|september|october|november|december)\s+(\d{4})\b",
+    )
+    .unwrap()
+});
+
+static SIRET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\bsiret\s*(?:n\s?[°º]\s*)?:?\s*(\d{3}\s?\d{3}\s?\d{3}\s?\d{5})\b").unwrap()
+});
+
+static UNLABELLED_SIRET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{3} \d{3} \d{3} \d{5})\b").unwrap());
+
+// "FR" with a two characters key and the SIREN, maybe spaced out
+static FRENCH_VAT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\bFR\s?([0-9A-HJ-NP-Z]{2})\s?(\d{3})\s?(\d{3})\s?(\d{3})\b").unwrap()
+});
+
+// Intra-community VAT numbers of other member states, after a label
+static VAT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\b(?i:tva|vat|ust-idnr|btw|iva)\b[^:]*:?\s*((?:AT|BE|BG|CY|CZ|DE|DK|EE|EL|ES|FI|HR|HU|IE|IT|LT|LU|LV|MT|NL|PL|PT|RO|SE|SI|SK)[0-9A-Z]{8,12})\b",
+    )
+    .unwrap()
+});
+
+// "1 234,56", "1.234,56", "1,234.56" or "450 €"
+static AMOUNT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"-?(?:\d{1,3}(?:[ .,']\d{3})+|\d+)(?:[.,]\d{2})?(?:\s?(?:€|eur\b))?").unwrap()
+});
+
+const MONTHS: [&str; 24] = [
+    "janvier",
+    "fevrier",
+    "mars",
+    "avril",
+    "mai",
+    "juin",
+    "juillet",
+    "aout",
+    "septembre",
+    "octobre",
+    "novembre",
+    "decembre",
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+const ISSUE_DATE_LABELS: &[&str] = &[
+    "date de facture",
+    "date de la facture",
+    "date de facturation",
+    "date d'emission",
+    "emise le",
+    "emis le",
+    "invoice date",
+    "date of issue",
+    "issue date",
+];
+
+// Other dates of an invoice
+const OTHER_DATE_WORDS: &[&str] = &[
+    "echeance",
+    "due",
+    "livraison",
+    "delivery",
+    "paiement",
+    "payment",
+    "limite",
+    "commande",
+    "order",
+    "periode",
+    "period",
+    "validite",
+];
+
+const NET_LABELS: &[&str] = &[
+    "total ht",
+    "montant ht",
+    "total hors tax",
+    "total hors tva",
+    "sous-total ht",
+    "total net ht",
+    "net ht",
+    "base ht",
+    "subtotal",
+    "sub-total",
+    "total excl",
+    "total net",
+];
+const VAT_LABELS: &[&str] = &[
+    "total tva",
+    "montant tva",
+    "montant de la tva",
+    "total vat",
+    "vat amount",
+    "total taxes",
+];
+const GROSS_LABELS: &[&str] = &[
+    "total ttc",
+    "montant ttc",
+    "net a payer",
+    "total a payer",
+    "montant a payer",
+    "reste a payer",
+    "total toutes taxes",
+    "amount due",
+    "total due",
+    "balance due",
+    "total incl",
+];
+
+// Lines about the VAT number rather than the VAT amount
+const VAT_ID_WORDS: &[&str] = &["intracom", "numero", "n°", "identifiant", "number", "id"];
+
+// Start of the buyer's address block
+const BILL_TO: &[&str] = &[
+    "facture a",
+    "facturer a",
+    "facture pour",
+    "adresse de facturation",
+    "destinataire",
+    "client :",
+    "bill to",
+    "billed to",
+    "invoice to",
+];
+
+const LEGAL_FORMS: &[&str] = &[
+    "sas", "sasu", "sarl", "eurl", "sa", "sci", "snc", "scop", "selarl", "eirl", "ltd", "limited",
+    "llc", "inc", "gmbh", "bv", "srl", "sprl",
+];
+
+// Words of lines which cannot be a company name
+const NOT_A_NAME: &[&str] = &[
+    "facture", "invoice", "date", "page", "tel", "fax", "@", "www", "siret", "siren", "tva",
+    "devis", "avoir", "client", "rcs",
+];
+
+// Small business exemption: no VAT is charged
+const VAT_EXEMPTION: &str = "293 b";
+
+// One cent
+const AMOUNT_TOLERANCE: Decimal = Decimal::from_parts(1, 0, 0, false, 2);
+
+struct Line<'a> {
+    text: &'a str,      // As extracted
+    normalized: String, // Lowercase, without accents
+    buyer: bool,        // In the buyer's address block
+}
+
+// Reads the key fields of an invoice from its text, None when it has neither a number nor
+// amounts
+pub fn extract_invoice_fields(text: &str) -> Option<InvoiceFields> {
+    let lines = lines(text);
+    let mut fields = InvoiceFields {
+        number: invoice_number(&lines),
+        issue_date: issue_date(&lines),
+        supplier_name: supplier_name(&lines),
+        siret: siret(&lines),
+        ..Default::default()
+    };
+    fields.vat_number = vat_number(&lines, fields.siret.as_ref());
+    totals(&lines, &mut fields);
+
+    let found = fields.number.is_some()
+        || fields.net_total.is_some()
+        || fields.vat_total.is_some()
+        || fields.gross_total.is_some();
+    found.then_some(fields)
+}
+
+fn field<T>(value: T, confidence: f32) -> ExtractedField<T> {
+    ExtractedField {
+        value,
+        confidence,
+        corrected: false,
+    }
+}
+
+fn lines(text: &str) -> Vec<Line<'_>> {
+    let mut lines: Vec<Line> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|text| Line {
+            text,
+            normalized: normalize(text),
+            buyer: false,
+        })
+        .collect();
+
+    // The marker's line and the few address lines after it
+    let mut remaining = 0;
+    for line in lines.iter_mut() {
+        if BILL_TO
+            .iter()
+            .any(|marker| line.normalized.contains(marker))
+        {
+            remaining = 5;
+        }
+        if remaining > 0 {
+            line.buyer = true;
+            remaining -= 1;
+        }
+    }
+    lines
+}
+
+fn invoice_number(lines: &[Line]) -> Option<ExtractedField<String>> {
+    let labelled = lines.iter().find_map(|line| {
+        LABELLED_NUMBER
+            .captures_iter(line.text)
+            .chain(NUMBER_AFTER_LABEL.captures_iter(line.text))
+            .find_map(|captures| number_value(&captures[1]))
+    });
+    if let Some(number) = labelled {
+        return Some(field(number, 0.9));
+    }
+    lines
+        .iter()
+        .find_map(|line| number_value(&TITLE_NUMBER.captures(line.text)?[1]))
+        .map(|number| field(number, 0.7))
+}
+
+// Invoice numbers have digits, unlike the words which may follow a label
+fn number_value(value: &str) -> Option<String> {
+    let value = value.trim_end_matches(['.', '-', '/', '_']);
+    value
+        .chars()
+        .any(|c| c.is_ascii_digit())
+        .then(|| value.to_string())
+}
+
+fn issue_date(lines: &[Line]) -> Option<ExtractedField<NaiveDate>> {
+    let mut labelled = None;
+    let mut mentioned = None;
+    let mut first = None;
+    for (index, line) in lines.iter().enumerate() {
+        // "Date : 14/03/2026 - Échéance : 13/04/2026"
+        let end = OTHER_DATE_WORDS
+            .iter()
+            .filter_map(|word| word_position(&line.normalized, word))
+            .min()
+            .unwrap_or(line.normalized.len());
+        let line = &line.normalized[..end];
+        let date = first_date(line);
+        if ISSUE_DATE_LABELS.iter().any(|label| line.contains(label)) {
+            // The value can be on the next line, in another cell
+            let date = date.or_else(|| first_date(&lines.get(index + 1)?.normalized));
+            if let Some(date) = date {
+                labelled.get_or_insert(date);
+            }
+        } else if let Some(date) = date {
+            if ["date", "facture", "invoice"]
+                .iter()
+                .any(|word| line.contains(word))
+            {
+                mentioned.get_or_insert(date);
+            }
+            first.get_or_insert(date);
+        }
+    }
+
+    labelled
+        .map(|date| field(date, 0.9))
+        .or_else(|| mentioned.map(|date| field(date, 0.7)))
+        .or_else(|| first.map(|date| field(date, 0.4)))
+}
+
+fn first_date(line: &str) -> Option<NaiveDate> {
+    let numeric = NUMERIC_DATE.captures_iter(line).find_map(|captures| {
+        let number = |index: usize| captures.get(index)?.as_str().parse::<u32>().ok();
+        if captures.get(4).is_some() {
+            NaiveDate::from_ymd_opt(number(4)? as i32, number(5)?, number(6)?)
+        } else {
+            let year = number(3)?;
+            let year = if year < 100 { 2000 + year } else { year };
+            NaiveDate::from_ymd_opt(year as i32, number(2)?, number(1)?)
+        }
+    });
+    numeric.or_else(|| {
+        TEXT_DATE.captures_iter(line).find_map(|captures| {
+            let month = MONTHS.iter().position(|month| *month == &captures[2])? % 12;
+            NaiveDate::from_ymd_opt(
+                captures[3].parse().ok()?,
+                month as u32 + 1,
+                captures[1].parse().ok()?,
+            )
+        })
+    })
+}
+
+// The issuer's name heads the invoice, or its legal mentions end it
+fn supplier_name(lines: &[Line]) -> Option<ExtractedField<String>> {
+    let header = lines.iter().take(12).take_while(|line| !line.buyer);
+    if let Some(name) = header
+        .clone()
+        .find(|line| has_legal_form(&line.normalized))
+        .and_then(|line| company_name(line.text))
+    {
+        return Some(field(name, 0.7));
+    }
+
+    let footer = lines.iter().filter(|line| {
+        !line.buyer
+            && ["capital", "siret", "rcs"]
+                .iter()
+                .any(|word| line.normalized.contains(word))
+            && has_legal_form(&line.normalized)
+    });
+    if let Some(name) = footer.filter_map(|line| company_name(line.text)).next() {
+        return Some(field(name, 0.6));
+    }
+
+    header
+        .filter(|line| {
+            line.text.chars().filter(|c| c.is_alphabetic()).count() >= 3
+                && !NOT_A_NAME
+                    .iter()
+                    .any(|word| mentions(&line.normalized, word))
+        })
+        .find_map(|line| company_name(line.text))
+        .map(|name| field(name, 0.4))
+}
+
+fn has_legal_form(line: &str) -> bool {
+    line.split(|c: char| !c.is_alphanumeric() && c != '.')
+        .map(|word| word.replace('.', ""))
+        .any(|word| LEGAL_FORMS.contains(&word.as_str()))
+}
+
+// The name before the address or legal mentions on the same line
+fn company_name(line: &str) -> Option<String> {
+    let mut name = line;
+    for separator in [" - ", " – ", " | ", ", ", " au capital", " capital"] {
+        if let Some(position) = name.find(separator) {
+            name = &name[..position];
+        }
+    }
+    let name = name.trim().trim_end_matches(['.', ',', '-']);
+    (name.chars().filter(|c| c.is_alphabetic()).count() >= 2).then(|| name.to_string())
+}
+
+fn siret(lines: &[Line]) -> Option<ExtractedField<String>> {
+    let mut candidates: Vec<(ExtractedField<String>, bool)> = Vec::new();
+    for line in lines {
+        for captures in SIRET.captures_iter(line.text) {
+            let siret = digits(&captures[1]);
+            let confidence = if is_valid_siret(&siret) { 0.95 } else { 0.6 };
+            candidates.push((field(siret, confidence), line.buyer));
+        }
+        for captures in UNLABELLED_SIRET.captures_iter(line.text) {
+            let siret = digits(&captures[1]);
+            if is_valid_siret(&siret) && !candidates.iter().any(|(known, _)| known.value == siret) {
+                candidates.push((field(siret, 0.6), line.buyer));
+            }
+        }
+    }
+    best(candidates)
+}
+
+// The supplier's number rather than the buyer's, then the surest one
+fn best<T>(candidates: Vec<(ExtractedField<T>, bool)>) -> Option<ExtractedField<T>> {
+    let mut best: Option<(ExtractedField<T>, bool)> = None;
+    for (candidate, buyer) in candidates {
+        let better = best.as_ref().is_none_or(|(best, best_buyer)| {
+            (*best_buyer && !buyer)
+                || (*best_buyer == buyer && candidate.confidence > best.confidence)
+        });
+        if better {
+            best = Some((candidate, buyer));
+        }
+    }
+    best.map(|(candidate, _)| candidate)
+}
+
+fn vat_number(
+    lines: &[Line],
+    siret: Option<&ExtractedField<String>>,
+) -> Option<ExtractedField<String>> {
+    let siren = siret.and_then(|siret| siren_from(&siret.value));
+    let mut candidates = Vec::new();
+    for line in lines {
+        for captures in FRENCH_VAT.captures_iter(line.text) {
+            let vat_number = digits_and_letters(&captures[0]);
+            let confidence = if !is_valid_french_vat(&vat_number) {
+                0.5
+            } else if siren.is_some() && siren_from_vat(&vat_number) == siren {
+                0.99
+            } else {
+                0.95
+            };
+            candidates.push((field(vat_number, confidence), line.buyer));
+        }
+        for captures in VAT.captures_iter(line.text) {
+            let vat_number = captures[1].to_string();
+            if vat_number.chars().any(|c| c.is_ascii_digit()) {
+                candidates.push((field(vat_number, 0.7), line.buyer));
+            }
+        }
+    }
+    best(candidates)
+}
+
+fn digits(value: &str) -> String {
+    value.chars().filter(char::is_ascii_digit).collect()
+}
+
+fn digits_and_letters(value: &str) -> String {
+    value.chars().filter(char::is_ascii_alphanumeric).collect()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Total {
+    Net,
+    Vat,
+    Gross,
+}
+
+// HT, TVA and TTC totals, checked against each other
+fn totals(lines: &[Line], fields: &mut InvoiceFields) {
+    // The totals come last, below the lines with the same labels
+    let mut labelled: [Option<Decimal>; 3] = [None; 3];
+    let mut loose: [Option<Decimal>; 3] = [None; 3];
+    let mut vat_lines = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let Some((total, strong)) = total_label(&line.normalized) else {
+            continue;
+        };
+        let amount = last_amount(&line.normalized).or_else(|| {
+            // The value can be on the next line, in another cell
+            let next = lines.get(index + 1)?;
+            let amount = last_amount(&next.normalized)?;
+            (total_label(&next.normalized).is_none()
+                && next
+                    .normalized
+                    .chars()
+                    .filter(|c| c.is_alphabetic())
+                    .count()
+                    <= 3)
+                .then_some(amount)
+        });
+        let Some(amount) = amount else {
+            continue;
+        };
+        if strong {
+            labelled[total as usize] = Some(amount);
+        } else {
+            loose[total as usize] = Some(amount);
+            if total == Total::Vat {
+                vat_lines.push(amount);
+            }
+        }
+    }
+
+    let pick = |total: Total| {
+        labelled[total as usize]
+            .map(|amount| (amount, 0.8))
+            .or_else(|| loose[total as usize].map(|amount| (amount, 0.6)))
+    };
+    let (mut net, mut vat, mut gross) = (pick(Total::Net), pick(Total::Vat), pick(Total::Gross));
+
+    let exempt = lines
+        .iter()
+        .any(|line| line.normalized.contains(VAT_EXEMPTION));
+    if exempt && vat.is_none() {
+        if let Some((amount, _)) = net.or(gross) {
+            net = Some((amount, 0.9));
+            vat = Some((Decimal::ZERO, 0.9));
+            gross = Some((amount, 0.9));
+        }
+    }
+
+    match (net, vat, gross) {
+        (Some((net_amount, _)), Some((vat_amount, _)), Some((gross_amount, _))) => {
+            let consistent = |vat_amount: Decimal| {
+                (net_amount + vat_amount - gross_amount).abs() <= AMOUNT_TOLERANCE
+            };
+            let vat_sum: Decimal = vat_lines.iter().sum();
+            if consistent(vat_amount) {
+                net = Some((net_amount, 0.95));
+                vat = Some((vat_amount, 0.95));
+                gross = Some((gross_amount, 0.95));
+            } else if labelled[Total::Vat as usize].is_none()
+                && vat_lines.len() > 1
+                && consistent(vat_sum)
+            {
+                // One line per VAT rate
+                net = Some((net_amount, 0.9));
+                vat = Some((vat_sum, 0.9));
+                gross = Some((gross_amount, 0.9));
+            }
+        }
+        // The missing total follows from the other two
+        (Some((net_amount, _)), Some((vat_amount, _)), None) => {
+            gross = Some((net_amount + vat_amount, 0.5));
+        }
+        (Some((net_amount, _)), None, Some((gross_amount, _))) => {
+            vat = Some((gross_amount - net_amount, 0.5));
+        }
+        (None, Some((vat_amount, _)), Some((gross_amount, _))) => {
+            net = Some((gross_amount - vat_amount, 0.5));
+        }
+        _ => {}
+    }
+
+    let into_field =
+        |total: Option<(Decimal, f32)>| total.map(|(amount, confidence)| field(amount, confidence));
+    fields.net_total = into_field(net);
+    fields.vat_total = into_field(vat);
+    fields.gross_total = into_field(gross);
+}
+
+// Which total a line is about, and whether its label is unambiguous
+fn total_label(line: &str) -> Option<(Total, bool)> {
+    if NET_LABELS.iter().any(|label| line.contains(label)) {
+        return Some((Total::Net, true));
+    }
+    if VAT_LABELS.iter().any(|label| line.contains(label)) {
+        return Some((Total::Vat, true));
+    }
+    if GROSS_LABELS.iter().any(|label| line.contains(label)) {
+        return Some((Total::Gross, true));
+    }
+    if (mentions(line, "tva") || mentions(line, "vat"))
+        && !VAT_ID_WORDS.iter().any(|word| mentions(line, word))
+        && !FRENCH_VAT.is_match(&line.to_uppercase())
+    {
+        return Some((Total::Vat, false));
+    }
+    if line.starts_with("total")
+        && !["ht", "hors", "excl", "sub"]
+            .iter()
+            .any(|word| line.contains(word))
+    {
+        return Some((Total::Gross, false));
+    }
+    None
+}
+
+// The rightmost amount of a line, leaving out dates, percentages and numbers without cents
+fn last_amount(line: &str) -> Option<Decimal> {
+    let line = NUMERIC_DATE.replace_all(line, " ");
+    AMOUNT
+        .find_iter(&line)
+        .filter(|amount| {
+            let number = amount
+                .as_str()
+                .trim_end_matches(|c: char| !c.is_ascii_digit());
+            let after = &line[amount.end()..];
+            let has_cents =
+                number.len() > 3 && matches!(number.as_bytes()[number.len() - 3], b',' | b'.');
+            let has_currency = number.len() < amount.as_str().len();
+            !after.trim_start().starts_with('%')
+                && !after.starts_with(|c: char| c.is_ascii_digit())
+                && (has_cents || has_currency)
+        })
+        .filter_map(|amount| normalize_amount(amount.as_str()).parse::<Decimal>().ok())
+        .last()
+}
+
+fn mentions(line: &str, word: &str) -> bool {
+    word_position(line, word).is_some()
+}
+
+// Where the word occurs as a whole word
+fn word_position(line: &str, word: &str) -> Option<usize> {
+    line.match_indices(word)
+        .map(|(start, _)| start)
+        .find(|&start| {
+            let before = line[..start].chars().next_back();
+            let after = line[start + word.len()..].chars().next();
+            !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+        })
+}
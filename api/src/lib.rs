@@ -1,7 +1,26 @@
 pub mod app_error;
+pub mod archive;
 pub mod app_state;
 pub mod auth;
+pub mod bank_statement;
+pub mod classifier;
 pub mod db;
+pub mod einvoice;
+pub mod encryption;
+pub mod export;
+pub mod fec;
+pub mod file_type;
 pub mod handlers;
+pub mod invoice_fields;
+pub mod mailer;
+pub mod naming;
 pub mod model;
-pub mod router;
\ No newline at end of file
+pub mod pdf;
+pub mod pipeline;
+pub mod preview;
+pub mod router;
+pub mod scanner;
+pub mod scheduler;
+pub mod storage;
+pub mod text_extraction;
+pub mod xml;
@@ -1,6 +1,8 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tower_http::cors::CorsLayer;
-use trombone::{app_state::AppState, db, router::router};
+use trombone::classifier::RuleClassifier;
+use trombone::{app_state::AppState, db, mailer, router::router, scanner, scheduler, storage};
 
 #[tokio::main]
 async fn main() {
@@ -8,12 +10,22 @@ async fn main() {
 
     let db_pool = db::setup_database_pool().await;
     let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    let portal_url =
+        std::env::var("PORTAL_URL").unwrap_or_else(|_| "http://localhost:5173/portal".to_string());
 
+    let storage = storage::from_env(&db_pool);
     let app_state = AppState {
         db_pool,
         jwt_secret,
+        mailer: mailer::from_env(),
+        storage,
+        scanner: scanner::from_env(),
+        classifier: Arc::new(RuleClassifier),
+        portal_url,
     };
 
+    scheduler::spawn(app_state.clone());
+
     let app = router(app_state).layer(CorsLayer::very_permissive());
     let addr = SocketAddr::from(([127, 0, 0, 1], 3333));
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
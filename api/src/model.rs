@@ -1,6 +1,28 @@
+pub mod bank_statement;
+pub mod classification;
 pub mod client;
 pub mod collection;
+pub mod comment;
+pub mod export;
+pub mod fec;
 pub mod file;
 pub mod firm;
+pub mod invoice;
+pub mod question;
+pub mod reminder;
 pub mod request;
+pub mod search;
+pub mod upload;
 pub mod user;
+
+use serde::{Deserialize, Deserializer};
+
+// For PATCH payloads, tells an absent field (None) from an explicit null (Some(None)) which
+// clears it, with #[serde(default, deserialize_with = "crate::model::nullable")]
+pub fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
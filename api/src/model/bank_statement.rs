@@ -0,0 +1,59 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const STATEMENT_CSV: &str = "csv";
+pub const STATEMENT_OFX: &str = "ofx"; // Also QFX, Quicken's flavor of OFX
+pub const STATEMENT_CAMT053: &str = "camt.053"; // ISO 20022 bank to customer statement
+
+// Transactions read from a bank statement, with the period and balances of the statement.
+// Balances missing from the statement are computed from the transactions when possible.
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BankStatement {
+    #[serde(flatten)]
+    pub summary: BankStatementSummary,
+    pub transactions: Vec<BankTransaction>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BankStatementSummary {
+    pub format: String,          // "csv", "ofx" or "camt.053"
+    pub account: Option<String>, // IBAN or account number
+    pub currency: Option<String>,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub opening_balance: Option<f64>,
+    pub closing_balance: Option<f64>,
+    pub transaction_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BankTransaction {
+    pub date: NaiveDate, // Booking date
+    pub amount: f64,     // Negative for debits
+    pub currency: Option<String>,
+    pub label: String,
+    pub balance: Option<f64>, // Balance after the transaction
+}
+
+pub const GAP_MISSING_PERIOD: &str = "missing_period";
+pub const GAP_BALANCE_MISMATCH: &str = "balance_mismatch";
+pub const GAP_OVERLAPPING_PERIOD: &str = "overlapping_period"; // Days covered twice
+pub const GAP_DUPLICATE_STATEMENT: &str = "duplicate_statement"; // The same period twice
+
+// A discontinuity between two consecutive statements of the same account
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StatementGap {
+    // "missing_period", "balance_mismatch", "overlapping_period" or "duplicate_statement"
+    pub kind: String,
+    pub account: Option<String>,
+    pub previous_file_id: Uuid,
+    pub next_file_id: Uuid,
+    pub missing_from: Option<NaiveDate>, // Days no statement covers, for missing periods
+    pub missing_to: Option<NaiveDate>,
+    pub overlap_from: Option<NaiveDate>, // Days both statements cover, for overlaps and duplicates
+    pub overlap_to: Option<NaiveDate>,
+    pub previous_closing_balance: Option<f64>,
+    pub next_opening_balance: Option<f64>,
+}
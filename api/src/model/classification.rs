@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+pub const CATEGORY_SALES_INVOICE: &str = "sales_invoice"; // Issued by the client
+pub const CATEGORY_PURCHASE_INVOICE: &str = "purchase_invoice"; // Received from a supplier
+pub const CATEGORY_RECEIPT: &str = "receipt";
+pub const CATEGORY_BANK_STATEMENT: &str = "bank_statement";
+pub const CATEGORY_PAYSLIP: &str = "payslip";
+pub const CATEGORY_TAX_NOTICE: &str = "tax_notice";
+pub const CATEGORY_CONTRACT: &str = "contract";
+pub const CATEGORY_OTHER: &str = "other";
+
+// Category suggested for a file, with how sure the classifier is of it (0 to 1)
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Classification {
+    pub category: String,
+    pub confidence: f32,
+}
@@ -1,9 +1,11 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::model::client::ClientResponse;
+use crate::model::request::RequestTemplate;
 use crate::model::user::UserResponse;
 
 // Represents a specific request for a set of documents (e.g., "Q3 2025 VAT")
@@ -32,6 +34,34 @@ pub struct CollectionResponse {
     pub expires_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<CollectionProgress>, // Only present with ?expand=progress
+}
+
+// Aggregated progress of a Collection, used to render dashboards without fetching every request and file
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CollectionProgress {
+    pub total_requests: i64,
+    pub requests_by_status: HashMap<String, i64>,
+    pub overdue_requests: i64,
+    pub file_count: i64,
+    pub total_bytes: i64,
+    pub last_client_activity_at: Option<DateTime<Utc>>,
+    pub completion_percentage: f64,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct CollectionQuery {
+    pub expand: Option<String>, // Comma-separated list of expansions (e.g. "progress")
+}
+
+impl CollectionQuery {
+    pub fn expands(&self, name: &str) -> bool {
+        self.expand
+            .as_deref()
+            .is_some_and(|expand| expand.split(',').any(|part| part.trim() == name))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -41,6 +71,15 @@ pub struct CreateCollectionPayload {
     pub title: String,
 }
 
+// Copies the requests of a collection into a new one for the next period
+#[derive(Debug, Deserialize)]
+pub struct CloneCollectionPayload {
+    pub title: String, // e.g. "Year-end 2026"
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub carry_over_files: bool, // Link the previous period's files to the new requests as references
+}
+
 #[derive(Debug, Deserialize)]
 pub struct UpdateCollectionPayload {
     pub title: Option<String>,
@@ -48,3 +87,59 @@ pub struct UpdateCollectionPayload {
     pub access_token: Option<String>,
     pub expires_at: Option<DateTime<Utc>>,
 }
+
+// Progress of a Collection's requests, computed in a single query
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CollectionSummary {
+    pub collection_id: Uuid,
+    pub total: i64,
+    pub done: i64,
+    pub pending: i64,
+    pub overdue: i64,
+}
+
+// Creates the same collection for many clients at once (e.g., year-end closing)
+
+#[derive(Debug, Deserialize)]
+pub struct BulkCreateCollectionsPayload {
+    pub user_id: Uuid,
+    pub title: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub client_ids: Option<Vec<Uuid>>,
+    pub client_filter: Option<ClientFilter>,
+    pub template_collection_id: Option<Uuid>, // Copies the requests of this collection
+    #[serde(default)]
+    pub requests: Vec<RequestTemplate>,
+    pub batch_size: Option<usize>, // All or nothing when absent, independent batches otherwise
+    #[serde(default)]
+    pub send_invitations: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClientFilter {
+    pub firm_id: Option<Uuid>,
+    pub company_name: Option<String>, // Case-insensitive substring
+}
+
+pub const BULK_CREATED: &str = "created";
+pub const BULK_SKIPPED: &str = "skipped"; // The client already has a collection with this title
+pub const BULK_FAILED: &str = "failed";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BulkCreateResult {
+    pub client_id: Uuid,
+    pub status: String,
+    pub collection_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BulkCreateResponse {
+    pub created: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub invitations_queued: usize,
+    pub results: Vec<BulkCreateResult>,
+}
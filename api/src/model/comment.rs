@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::model::file::{FileResponse, PortalFileResponse};
+
+pub const AUTHOR_USER: &str = "user";
+pub const AUTHOR_CLIENT: &str = "client";
+
+// Represents a message in the thread of a Request, written by a firm user or by the end client
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommentAuthor {
+    #[serde(rename = "type")]
+    pub author_type: String, // "user" or "client"
+    pub id: Option<Uuid>,    // None once the author has been deleted
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommentResponse {
+    pub id: Uuid,
+    pub request_id: Uuid,
+    pub author: CommentAuthor,
+    pub body: String,
+    pub attachments: Vec<FileResponse>, // Clean files, quarantined ones being left out
+    pub read_by_recipient: bool, // Whether the other side has read the thread since this comment
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// A comment as the client sees it through the portal
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PortalCommentResponse {
+    pub id: Uuid,
+    pub request_id: Uuid,
+    pub author: CommentAuthor,
+    pub body: String,
+    pub attachments: Vec<PortalFileResponse>,
+    pub read_by_recipient: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<CommentResponse> for PortalCommentResponse {
+    fn from(comment: CommentResponse) -> Self {
+        PortalCommentResponse {
+            id: comment.id,
+            request_id: comment.request_id,
+            author: comment.author,
+            body: comment.body,
+            attachments: comment
+                .attachments
+                .into_iter()
+                .map(PortalFileResponse::from)
+                .collect(),
+            read_by_recipient: comment.read_by_recipient,
+            created_at: comment.created_at,
+            updated_at: comment.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCommentPayload {
+    pub body: String,
+    #[serde(default)]
+    pub attachment_ids: Vec<Uuid>, // Files of the same collection
+}
@@ -0,0 +1,54 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::model::invoice::{Invoice, InvoiceFields};
+
+// Formats of the import file in an export bundle
+pub const EXPORT_CSV: &str = "csv"; // One line per invoice, for any accounting package
+pub const EXPORT_FEC: &str = "fec"; // Draft entries in the FEC layout, to be reviewed
+
+pub const EXPORT_SOURCE_EINVOICE: &str = "e-invoice";
+pub const EXPORT_SOURCE_EXTRACTED: &str = "extracted"; // Read from the text of a PDF
+
+// A file of a collection, with what is known of it as an invoice
+
+#[derive(Debug, Clone)]
+pub struct ExportDocument {
+    pub file_id: Uuid,
+    pub request_title: String,
+    pub file_name: String,
+    pub storage_key: String,
+    pub uploaded_at: DateTime<Utc>,
+    pub category: Option<String>,
+    pub invoice: Option<Invoice>,
+    pub invoice_fields: Option<InvoiceFields>,
+}
+
+// Invoice data of a document, normalized whether it came from an e-invoice or from the text
+// of a PDF. Amounts are negative for credit notes.
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExportEntry {
+    pub document: String, // Path of the renamed document in the bundle
+    pub file_id: Uuid,
+    pub request: String,
+    pub sale: bool,     // Issued by the client, otherwise received from a supplier
+    pub source: String, // "e-invoice" or "extracted"
+    pub number: Option<String>,
+    pub issue_date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub counterparty: Option<String>, // The customer of a sale, the supplier of a purchase
+    pub siren: Option<String>,
+    pub vat_number: Option<String>,
+    pub currency: Option<String>,
+    pub net_total: Option<Decimal>,
+    pub vat_total: Option<Decimal>,
+    pub gross_total: Option<Decimal>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ExportQuery {
+    pub format: Option<String>, // "csv" (default) or "fec"
+}
@@ -0,0 +1,63 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+// Rules an FEC (Fichier des Écritures Comptables) is checked against
+pub const FEC_RULE_COLUMNS: &str = "columns"; // The 18 columns of article A47 A-1 of the LPF
+pub const FEC_RULE_REQUIRED: &str = "required"; // Mandatory fields, and fields going in pairs
+pub const FEC_RULE_DATE: &str = "date"; // Dates are AAAAMMJJ
+pub const FEC_RULE_AMOUNT: &str = "amount";
+pub const FEC_RULE_BALANCE: &str = "balance"; // Each entry has as much debit as credit
+pub const FEC_RULE_SEQUENCE: &str = "sequence"; // Entries of a journal are in chronological order
+pub const FEC_RULE_FILE_NAME: &str = "file_name"; // <SIREN>FEC<closing date AAAAMMJJ>
+
+// Outcome of the validation of an FEC, attached to its file. Only the first issues are listed.
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FecReport {
+    pub valid: bool,
+    pub delimiter: String, // "tab" or "pipe"
+    pub line_count: usize, // Entry lines, without the header
+    pub entry_count: usize,
+    pub error_count: usize,
+    pub errors: Vec<FecIssue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FecIssue {
+    pub line: Option<usize>, // In the file, the header being line 1
+    pub rule: String,        // e.g. "balance"
+    pub message: String,
+}
+
+// Journals and totals of an FEC, at GET /files/:id/fec
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FecSummary {
+    pub siren: Option<String>,           // From the file name
+    pub closing_date: Option<NaiveDate>, // From the file name
+    pub start_date: Option<NaiveDate>,   // Of the first entry
+    pub end_date: Option<NaiveDate>,     // Of the last entry
+    pub journals: Vec<FecJournal>,
+    pub account_classes: Vec<FecAccountClass>,
+    pub total_debit: f64,
+    pub total_credit: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FecJournal {
+    pub code: String,
+    pub label: String,
+    pub entry_count: usize,
+    pub line_count: usize,
+    pub debit: f64,
+    pub credit: f64,
+}
+
+// Totals of the accounts of a class of the French chart of accounts, e.g. "6" for expenses
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FecAccountClass {
+    pub class: String,
+    pub debit: f64,
+    pub credit: f64,
+}
@@ -1,10 +1,52 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use sqlx::FromRow;
 use uuid::Uuid;
 
+use crate::model::bank_statement::BankStatementSummary;
+use crate::model::fec::FecReport;
+use crate::model::invoice::{Invoice, InvoiceFields};
 use crate::model::request::RequestResponse;
 
+pub const SCAN_PENDING: &str = "pending";
+pub const SCAN_CLEAN: &str = "clean";
+pub const SCAN_INFECTED: &str = "infected";
+pub const SCAN_UNSCANNED: &str = "unscanned"; // Uploaded before scanning was introduced
+pub const SCAN_FAILED: &str = "failed"; // Gave up after MAX_SCAN_ATTEMPTS, stays quarantined
+
+pub const PREVIEW_PENDING: &str = "pending";
+pub const PREVIEW_READY: &str = "ready";
+pub const PREVIEW_UNSUPPORTED: &str = "unsupported"; // No preview for this type of file
+pub const PREVIEW_FAILED: &str = "failed";
+
+pub const TEXT_PENDING: &str = "pending";
+pub const TEXT_EXTRACTED: &str = "extracted";
+pub const TEXT_UNSUPPORTED: &str = "unsupported"; // No text to read in this type of file
+pub const TEXT_FAILED: &str = "failed";
+
+pub const INVOICE_PENDING: &str = "pending";
+pub const INVOICE_PARSED: &str = "parsed";
+pub const INVOICE_NONE: &str = "none"; // Not an e-invoice
+pub const INVOICE_FAILED: &str = "failed";
+
+pub const STATEMENT_PENDING: &str = "pending";
+pub const STATEMENT_PARSED: &str = "parsed";
+pub const STATEMENT_NONE: &str = "none"; // Not a bank statement
+pub const STATEMENT_FAILED: &str = "failed";
+
+pub const CLASSIFICATION_PENDING: &str = "pending";
+pub const CLASSIFICATION_DONE: &str = "classified";
+
+pub const FIELDS_PENDING: &str = "pending";
+pub const FIELDS_EXTRACTED: &str = "extracted";
+pub const FIELDS_NONE: &str = "none"; // Not a PDF invoice, or nothing found in its text
+
+pub const FEC_PENDING: &str = "pending";
+pub const FEC_PARSED: &str = "parsed";
+pub const FEC_NONE: &str = "none"; // Not an FEC
+pub const FEC_FAILED: &str = "failed";
+
 // Represents a file uploaded by an end-client for a specific Request
 
 #[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
@@ -12,9 +54,29 @@ pub struct File {
     pub id: Uuid,
     pub request_id: Uuid,
     pub file_name: String,
+    pub original_file_name: String, // As uploaded, `file_name` following the firm's template
     pub storage_key: String,
     pub file_size: i64,
     pub mime_type: String,
+    pub sha256: Option<String>,
+    pub duplicate_of: Option<Uuid>,
+    pub scan_status: String,
+    pub scan_result: Option<String>,
+    pub scanned_at: Option<DateTime<Utc>>,
+    pub preview_status: String,
+    pub converted_into: Option<Uuid>,
+    pub invoice: Option<Json<Invoice>>,
+    pub bank_statement: Option<Json<BankStatementSummary>>, // Without its transactions
+    pub category: Option<String>,
+    pub category_confidence: Option<f32>,
+    pub suggested_request_id: Option<Uuid>,
+    pub invoice_fields: Option<Json<InvoiceFields>>,
+    pub fec_report: Option<Json<FecReport>>,
+    pub version_of: Option<Uuid>,
+    pub version: i32,
+    pub is_current: bool,
+    pub rejection_reason: Option<String>,
+    pub rejected_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -24,12 +86,82 @@ pub struct FileResponse {
     pub id: Uuid,
     pub request: RequestResponse,
     pub file_name: String,
+    pub original_file_name: String, // As uploaded, `file_name` following the firm's template
     pub storage_key: String,
     pub file_size: i64,
     pub mime_type: String,
+    pub sha256: Option<String>,     // Hex SHA-256 of the content, unknown for older files
+    pub duplicate_of: Option<Uuid>, // Earlier file of the same client with the same content
+    pub scan_status: String,        // "pending" or "failed" (quarantined), "clean", "infected", "unscanned"
+    pub scan_result: Option<String>, // Detected signature or last scan error
+    pub scanned_at: Option<DateTime<Utc>>,
+    pub preview_status: String,  // "pending", "ready", "unsupported" or "failed" to render
+    pub preview_available: bool, // A thumbnail can be fetched from GET /files/:id/preview
+    pub converted_into: Option<Uuid>, // PDF made from this photo, which is kept as the original
+    pub invoice: Option<Invoice>, // Data of an e-invoice (Factur-X, UBL or CII), parsed after the upload
+    pub rendition_available: bool, // An XML invoice can be read as PDF from GET /files/:id/rendition
+    pub bank_statement: Option<BankStatementSummary>, // Transactions at GET /files/:id/transactions
+    pub category: Option<String>, // Suggested accounting category, e.g. "purchase_invoice"
+    pub category_confidence: Option<f32>, // From 0 to 1
+    pub suggested_request_id: Option<Uuid>, // Pending request of the collection the file likely answers
+    pub invoice_fields: Option<InvoiceFields>, // Read from the text of a PDF invoice, correctable
+    pub fec_report: Option<FecReport>, // Validation of an FEC, its journals at GET /files/:id/fec
+    pub version_of: Option<Uuid>, // First version of the file, the others at GET /files/:id/versions
+    pub version: i32,             // From 1
+    pub is_current: bool,         // Only current versions are listed with the request's files
+    pub rejection_reason: Option<String>, // Why an accountant turned this version down
+    pub rejected_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+// A file as its client sees it through the portal, without its storage details nor the
+// firm's records of the request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PortalFileResponse {
+    pub id: Uuid,
+    pub request_id: Uuid,
+    pub file_name: String,
+    pub file_size: i64,
+    pub mime_type: String,
+    pub version: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<FileResponse> for PortalFileResponse {
+    fn from(file: FileResponse) -> Self {
+        PortalFileResponse {
+            id: file.id,
+            request_id: file.request.id,
+            file_name: file.file_name,
+            file_size: file.file_size,
+            mime_type: file.mime_type,
+            version: file.version,
+            created_at: file.created_at,
+        }
+    }
+}
+
 // Payloads for file creation would typically be handled via multipart forms,
-// not direct JSON, so we don't define Create/Update payloads here.
\ No newline at end of file
+// not direct JSON, so we don't define Create/Update payloads here.
+// A version of a file, at GET /files/:id/versions
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileVersion {
+    pub id: Uuid,
+    pub version: i32,
+    pub file_name: String,
+    pub original_file_name: String,
+    pub file_size: i64,
+    pub mime_type: String,
+    pub scan_status: String,
+    pub is_current: bool,
+    pub rejection_reason: Option<String>,
+    pub rejected_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RejectFilePayload {
+    pub reason: String,
+}
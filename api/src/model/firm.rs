@@ -35,3 +35,18 @@ pub struct CreateFirmPayload {
 pub struct UpdateFirmPayload {
     pub name: Option<String>,
 }
+
+// Per-firm settings; a firm without a row gets the defaults
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FirmSettings {
+    pub firm_id: Uuid,
+    pub reject_duplicate_uploads: bool, // Refuse uploads identical to a file the client already sent
+    pub file_name_template: Option<String>, // Renames uploads, e.g. "{client}_{date}_{seq}.{ext}"
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateFirmSettingsPayload {
+    pub reject_duplicate_uploads: Option<bool>,
+    pub file_name_template: Option<String>, // An empty template keeps the uploaded names
+}
@@ -0,0 +1,92 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+pub const INVOICE_FACTURX: &str = "factur-x"; // CII XML embedded in a PDF (also ZUGFeRD)
+pub const INVOICE_CII: &str = "cii"; // Standalone UN/CEFACT Cross Industry Invoice
+pub const INVOICE_UBL: &str = "ubl"; // Standalone UBL 2.1 invoice or credit note
+
+// Invoice read from an e-invoice, normalized whatever its format.
+// Amounts are in the invoice currency, as decimals so that they add up to the cent.
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Invoice {
+    pub format: String,          // "factur-x", "cii" or "ubl"
+    pub profile: Option<String>, // Guideline, e.g. "urn:cen.eu:en16931:2017"
+    pub number: Option<String>,
+    pub type_code: Option<String>, // UNTDID 1001 code: "380" invoice, "381" credit note
+    pub issue_date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub currency: Option<String>, // ISO 4217, e.g. "EUR"
+    pub seller: InvoiceParty,
+    pub buyer: InvoiceParty,
+    pub net_total: Option<Decimal>, // Total without VAT
+    pub vat_total: Option<Decimal>,
+    pub gross_total: Option<Decimal>, // Total with VAT
+    pub lines: Vec<InvoiceLine>,
+    // EN 16931 business rules checkable on these fields (mandatory fields, totals and
+    // decimals, not the full schematron) and French identifier checks the invoice fails,
+    // e.g. "BR-CO-15: ..."
+    #[serde(default)]
+    pub validation_errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct InvoiceParty {
+    pub name: Option<String>,
+    pub siren: Option<String>,      // French company number, 9 digits
+    pub vat_number: Option<String>, // Intra-community VAT number, e.g. "FR32123456789"
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InvoiceLine {
+    pub id: Option<String>,
+    pub description: Option<String>,
+    pub quantity: Option<Decimal>,
+    pub unit_price: Option<Decimal>, // Net price of one unit
+    pub net_amount: Option<Decimal>,
+    pub vat_rate: Option<Decimal>, // Percent, e.g. 20.0
+}
+
+// Key fields read from the text of a PDF invoice which is not an e-invoice, for accountants to
+// check and correct (PATCH /files/:id/invoice-fields) rather than retype
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct InvoiceFields {
+    pub number: Option<ExtractedField<String>>,
+    pub issue_date: Option<ExtractedField<NaiveDate>>,
+    pub supplier_name: Option<ExtractedField<String>>,
+    pub siret: Option<ExtractedField<String>>, // 14 digits
+    pub vat_number: Option<ExtractedField<String>>,
+    pub net_total: Option<ExtractedField<Decimal>>, // HT
+    pub vat_total: Option<ExtractedField<Decimal>>, // TVA
+    pub gross_total: Option<ExtractedField<Decimal>>, // TTC
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExtractedField<T> {
+    pub value: T,
+    pub confidence: f32, // From 0 to 1
+    #[serde(default)]
+    pub corrected: bool, // Set by an accountant, the confidence is then 1
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateInvoiceFieldsPayload {
+    #[serde(default, deserialize_with = "crate::model::nullable")]
+    pub number: Option<Option<String>>,
+    #[serde(default, deserialize_with = "crate::model::nullable")]
+    pub issue_date: Option<Option<NaiveDate>>,
+    #[serde(default, deserialize_with = "crate::model::nullable")]
+    pub supplier_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "crate::model::nullable")]
+    pub siret: Option<Option<String>>,
+    #[serde(default, deserialize_with = "crate::model::nullable")]
+    pub vat_number: Option<Option<String>>,
+    #[serde(default, deserialize_with = "crate::model::nullable")]
+    pub net_total: Option<Option<Decimal>>,
+    #[serde(default, deserialize_with = "crate::model::nullable")]
+    pub vat_total: Option<Option<Decimal>>,
+    #[serde(default, deserialize_with = "crate::model::nullable")]
+    pub gross_total: Option<Option<Decimal>>,
+}
@@ -0,0 +1,220 @@
+use chrono::NaiveDate;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// A typed question of a questionnaire Request (e.g., "Did you buy a vehicle this year?")
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Question {
+    pub key: String,
+    pub label: String,
+    #[serde(flatten)]
+    pub kind: QuestionKind,
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuestionKind {
+    Text,
+    Number,
+    Date,
+    YesNo,
+    Choice { options: Vec<String> },
+    Iban,
+    Amount,
+}
+
+// Checks that a questionnaire is well formed before it is stored on a Request
+pub fn validate_schema(questions: &[Question]) -> Result<(), String> {
+    if questions.is_empty() {
+        return Err("A questionnaire needs at least one question.".to_string());
+    }
+
+    for (index, question) in questions.iter().enumerate() {
+        if question.key.trim().is_empty() || question.label.trim().is_empty() {
+            return Err("Every question needs a key and a label.".to_string());
+        }
+        if questions[..index].iter().any(|other| other.key == question.key) {
+            return Err(format!("Duplicate question key \"{}\".", question.key));
+        }
+        if let QuestionKind::Choice { options } = &question.kind {
+            if options.is_empty() {
+                return Err(format!(
+                    "Choice question \"{}\" needs at least one option.",
+                    question.key
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Validates submitted answers against the questionnaire and returns them normalized
+// (dates as YYYY-MM-DD, IBANs without spaces, amounts as decimal strings with 2 digits).
+pub fn validate_answers(
+    questions: &[Question],
+    answers: &Map<String, Value>,
+) -> Result<Map<String, Value>, Vec<String>> {
+    let mut errors = Vec::new();
+    let mut normalized = Map::new();
+
+    for key in answers.keys() {
+        if !questions.iter().any(|question| &question.key == key) {
+            errors.push(format!("{}: unknown question", key));
+        }
+    }
+
+    for question in questions {
+        match answers.get(&question.key) {
+            None | Some(Value::Null) => {
+                if question.required {
+                    errors.push(format!("{}: an answer is required", question.key));
+                }
+            }
+            Some(Value::String(text)) if text.trim().is_empty() => {
+                if question.required {
+                    errors.push(format!("{}: an answer is required", question.key));
+                }
+            }
+            Some(value) => match question.kind.normalize(value) {
+                Ok(value) => {
+                    normalized.insert(question.key.clone(), value);
+                }
+                Err(message) => errors.push(format!("{}: {}", question.key, message)),
+            },
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(normalized)
+    } else {
+        Err(errors)
+    }
+}
+
+impl QuestionKind {
+    fn normalize(&self, value: &Value) -> Result<Value, String> {
+        match self {
+            QuestionKind::Text => value
+                .as_str()
+                .map(|text| Value::String(text.trim().to_string()))
+                .ok_or_else(|| "expected text".to_string()),
+            QuestionKind::Number => match value {
+                Value::Number(_) => Ok(value.clone()),
+                Value::String(text) => parse_decimal(text)
+                    .and_then(|number| number.to_f64())
+                    .and_then(serde_json::Number::from_f64)
+                    .map(Value::Number)
+                    .ok_or_else(|| "expected a number".to_string()),
+                _ => Err("expected a number".to_string()),
+            },
+            QuestionKind::Date => value
+                .as_str()
+                .and_then(parse_date)
+                .map(|date| Value::String(date.format("%Y-%m-%d").to_string()))
+                .ok_or_else(|| "expected a date (YYYY-MM-DD or DD/MM/YYYY)".to_string()),
+            QuestionKind::YesNo => match value {
+                Value::Bool(_) => Ok(value.clone()),
+                Value::String(text) => match text.trim().to_lowercase().as_str() {
+                    "yes" | "oui" | "true" => Ok(Value::Bool(true)),
+                    "no" | "non" | "false" => Ok(Value::Bool(false)),
+                    _ => Err("expected yes or no".to_string()),
+                },
+                _ => Err("expected yes or no".to_string()),
+            },
+            QuestionKind::Choice { options } => value
+                .as_str()
+                .filter(|choice| options.iter().any(|option| option == choice))
+                .map(|choice| Value::String(choice.to_string()))
+                .ok_or_else(|| format!("expected one of: {}", options.join(", "))),
+            QuestionKind::Iban => {
+                let iban = value.as_str().map(normalize_iban).unwrap_or_default();
+                if is_valid_iban(&iban) {
+                    Ok(Value::String(iban))
+                } else {
+                    Err("invalid IBAN".to_string())
+                }
+            }
+            QuestionKind::Amount => {
+                let amount = match value {
+                    Value::Number(number) => parse_decimal(&number.to_string()),
+                    Value::String(text) => parse_decimal(text),
+                    _ => None,
+                }
+                .ok_or_else(|| "expected an amount".to_string())?;
+                if amount.normalize().scale() > 2 {
+                    return Err("an amount has at most 2 decimals".to_string());
+                }
+                Ok(Value::String(format!("{:.2}", amount)))
+            }
+        }
+    }
+}
+
+// Accepts "1234.56", "1 234,56" and "1.234,56"
+pub fn parse_decimal(text: &str) -> Option<Decimal> {
+    let compact: String = text
+        .trim()
+        .trim_end_matches('€')
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '\u{a0}' && *c != '\u{202f}')
+        .collect();
+
+    let normalized = match (compact.rfind(','), compact.rfind('.')) {
+        (Some(comma), Some(dot)) if comma > dot => compact.replace('.', "").replace(',', "."),
+        (Some(_), Some(_)) => compact.replace(',', ""),
+        (Some(_), None) => compact.replace(',', "."),
+        _ => compact,
+    };
+
+    normalized.parse().ok()
+}
+
+pub fn parse_date(text: &str) -> Option<NaiveDate> {
+    let text = text.trim();
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(text, "%d/%m/%Y"))
+        .ok()
+}
+
+pub fn normalize_iban(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+// ISO 13616: move the first 4 characters to the end, map letters to numbers and check mod 97 == 1
+pub fn is_valid_iban(iban: &str) -> bool {
+    if iban.len() < 15 || iban.len() > 34 || !iban.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+    let (country, check) = iban.split_at(2);
+    if !country.chars().all(|c| c.is_ascii_alphabetic())
+        || !check[..2].chars().all(|c| c.is_ascii_digit())
+    {
+        return false;
+    }
+
+    let rearranged = iban[4..].chars().chain(iban[..4].chars());
+    let mut remainder: u32 = 0;
+    for c in rearranged {
+        let digits = c.to_digit(36).unwrap();
+        remainder = if digits >= 10 {
+            (remainder * 100 + digits) % 97
+        } else {
+            (remainder * 10 + digits) % 97
+        };
+    }
+
+    remainder == 1
+}
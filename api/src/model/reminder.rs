@@ -0,0 +1,65 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+pub const TRIGGER_SCHEDULED: &str = "scheduled";
+pub const TRIGGER_MANUAL: &str = "manual";
+
+// Describes when a Collection's client should be chased (e.g., 7 and 2 days before expiry, then weekly)
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct ReminderPolicy {
+    pub collection_id: Uuid,
+    pub days_before: Vec<i32>,
+    pub repeat_every_days: Option<i32>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ReminderPolicy {
+    // Returns the most recent point in time (at or before `now`) at which a reminder should have gone out.
+    // Slots that precede the policy's creation are ignored so a late policy doesn't fire retroactively.
+    pub fn due_slot(&self, expires_at: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut slots: Vec<DateTime<Utc>> = self
+            .days_before
+            .iter()
+            .map(|days| expires_at - Duration::days(i64::from(*days)))
+            .collect();
+
+        if let Some(every) = self.repeat_every_days.filter(|every| *every > 0) {
+            let period = Duration::days(i64::from(every));
+            let elapsed_periods = (now - expires_at).num_seconds() / period.num_seconds();
+            if elapsed_periods >= 1 {
+                slots.push(expires_at + period * elapsed_periods as i32);
+            }
+        }
+
+        slots
+            .into_iter()
+            .filter(|slot| *slot <= now && *slot >= self.created_at)
+            .max()
+    }
+}
+
+// A reminder email that was sent to a client
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct Reminder {
+    pub id: Uuid,
+    pub collection_id: Uuid,
+    pub recipient: String,
+    pub trigger: String,
+    pub pending_requests: i32,
+    pub sent_at: DateTime<Utc>,
+    pub slot_at: Option<DateTime<Utc>>, // Scheduled slot it was sent for
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateReminderPolicyPayload {
+    pub days_before: Option<Vec<i32>>,
+    // 0 disables the repeating reminders after expiry
+    pub repeat_every_days: Option<i32>,
+    pub enabled: Option<bool>,
+}
@@ -1,9 +1,13 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
 use crate::model::collection::CollectionResponse;
+use crate::model::question::Question;
+
+pub const KIND_FILES: &str = "files";
+pub const KIND_QUESTIONNAIRE: &str = "questionnaire";
 
 // Represents a single line item within a Collection (e.g., "Sales Invoices for July")
 
@@ -16,6 +20,14 @@ pub struct Request {
     pub status: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub due_date: Option<NaiveDate>,
+    pub kind: String,
+    pub questions: Option<serde_json::Value>,
+    pub answers: Option<serde_json::Value>,
+    pub answered_at: Option<DateTime<Utc>>,
+    pub allowed_types: Option<Vec<String>>,
+    pub convert_images_to_pdf: bool,
+    pub combine_images: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -25,15 +37,77 @@ pub struct RequestResponse {
     pub title: String,
     pub description: Option<String>,
     pub status: String,
+    pub due_date: Option<NaiveDate>,
+    pub overdue: bool, // Still pending after its due date
+    pub kind: String,  // "files" or "questionnaire"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub questions: Option<Vec<Question>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answers: Option<serde_json::Map<String, serde_json::Value>>,
+    pub answered_at: Option<DateTime<Utc>>,
+    pub allowed_types: Option<Vec<String>>, // Accepted file types, any when absent
+    pub convert_images_to_pdf: bool,        // Uploaded photos are turned into PDFs
+    pub combine_images: bool,               // Photos uploaded together make a single PDF
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+// A request as its client sees it through the portal, without the collection's access token
+// nor the details of the firm's client and user records
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PortalRequestResponse {
+    pub id: Uuid,
+    pub collection_id: Uuid,
+    pub collection_title: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub status: String,
+    pub due_date: Option<NaiveDate>,
+    pub overdue: bool,
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub questions: Option<Vec<Question>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answers: Option<serde_json::Map<String, serde_json::Value>>,
+    pub answered_at: Option<DateTime<Utc>>,
+    pub allowed_types: Option<Vec<String>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<RequestResponse> for PortalRequestResponse {
+    fn from(request: RequestResponse) -> Self {
+        PortalRequestResponse {
+            id: request.id,
+            collection_id: request.collection.id,
+            collection_title: request.collection.title,
+            title: request.title,
+            description: request.description,
+            status: request.status,
+            due_date: request.due_date,
+            overdue: request.overdue,
+            kind: request.kind,
+            questions: request.questions,
+            answers: request.answers,
+            answered_at: request.answered_at,
+            allowed_types: request.allowed_types,
+            updated_at: request.updated_at,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateRequestPayload {
     pub collection_id: Uuid,
     pub title: String,
     pub description: Option<String>,
+    pub due_date: Option<NaiveDate>,
+    pub kind: Option<String>,
+    pub questions: Option<Vec<Question>>,
+    pub allowed_types: Option<Vec<String>>, // e.g. ["application/pdf", "image/*"]
+    #[serde(default)]
+    pub convert_images_to_pdf: bool,
+    #[serde(default)]
+    pub combine_images: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -41,4 +115,30 @@ pub struct UpdateRequestPayload {
     pub title: Option<String>,
     pub description: Option<String>,
     pub status: Option<String>,
+    #[serde(default, deserialize_with = "crate::model::nullable")]
+    pub due_date: Option<Option<NaiveDate>>, // null removes the due date
+    pub questions: Option<Vec<Question>>,
+    pub allowed_types: Option<Vec<String>>, // An empty list accepts any type again
+    pub convert_images_to_pdf: Option<bool>,
+    pub combine_images: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequestFilters {
+    pub overdue: Option<bool>,
+}
+
+// A request to create in new collections (bulk creation, templates)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RequestTemplate {
+    pub title: String,
+    pub description: Option<String>,
+    pub due_date: Option<NaiveDate>,
+    pub kind: Option<String>,
+    pub questions: Option<Vec<Question>>,
+    pub allowed_types: Option<Vec<String>>,
+    #[serde(default)]
+    pub convert_images_to_pdf: bool,
+    #[serde(default)]
+    pub combine_images: bool,
 }
@@ -0,0 +1,30 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// Full-text search across the files of the user's firm (e.g., "orange invoice")
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String, // Web search syntax: words, "quoted phrases", -excluded, or
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchResult {
+    pub file_id: Uuid,
+    pub file_name: String,
+    pub mime_type: String,
+    pub uploaded_at: DateTime<Utc>,
+    pub rank: f32,
+    pub snippet: String, // Matches are surrounded by ** (e.g. "Invoice from **Orange** SA")
+    pub request: SearchContext,
+    pub collection: SearchContext,
+    pub client: SearchContext,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchContext {
+    pub id: Uuid,
+    pub name: String, // Title of the request or collection, company name of the client
+}
@@ -0,0 +1,38 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+// Version of the tus protocol spoken by /uploads, and the extensions it supports
+pub const TUS_VERSION: &str = "1.0.0";
+pub const TUS_EXTENSIONS: &str = "creation,termination";
+
+// Largest file accepted by /uploads, meant for large files sent over poor connections. Those
+// larger than what the scanner accepts are kept quarantined, see `scan_file`.
+pub const MAX_RESUMABLE_UPLOAD_BYTES: i64 = 2 * 1024 * 1024 * 1024;
+
+// How long an upload can be resumed after its last chunk
+pub const UPLOAD_LIFETIME_HOURS: i64 = 24;
+
+// After which the assembly of a complete upload is taken as stalled, and claimed again
+pub const COMPLETION_TIMEOUT_MINUTES: i32 = 10;
+
+// A file being uploaded in chunks with the tus protocol
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct Upload {
+    pub id: Uuid,
+    pub request_id: Uuid,
+    pub file_name: String,
+    pub replaces: Option<Uuid>, // File this upload is a new version of
+    pub metadata: Option<String>,
+    pub upload_length: i64,
+    pub upload_offset: i64,                   // Bytes received so far
+    pub file_id: Option<Uuid>,                // The file it became once completed
+    pub completing_at: Option<DateTime<Utc>>, // When its assembly into the file was claimed
+    pub failure: Option<String>,              // Why it was refused as a file
+    pub failure_status: Option<i16>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
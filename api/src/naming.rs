@@ -0,0 +1,104 @@
+use chrono::NaiveDate;
+
+use crate::archive::sanitize_file_name;
+
+// Placeholders of a file name template
+pub const PLACEHOLDERS: [&str; 7] = [
+    "client",     // Company name of the client
+    "collection", // Title of the collection
+    "request",    // Title of the request
+    "date",       // Upload date, e.g. 2026-03-14
+    "seq",        // Position of the file among those of the request, e.g. 01
+    "original",   // Uploaded name without its extension
+    "ext",        // Extension of the uploaded file, e.g. pdf
+];
+
+// What a file name template is filled with
+pub struct NamingContext<'a> {
+    pub client: &'a str,
+    pub collection: &'a str,
+    pub request: &'a str,
+    pub date: NaiveDate,
+    pub seq: i64,
+    pub original_file_name: &'a str, // With its extension
+}
+
+// Checks the placeholders of a template, e.g. "{client}_{request}_{date}_{seq}.{ext}"
+pub fn validate_template(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("The file name template is empty.".to_string());
+    }
+    for part in parts(template)? {
+        if let Part::Placeholder(name) = part {
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "Unknown placeholder {{{}}}, use {}.",
+                    name,
+                    PLACEHOLDERS
+                        .iter()
+                        .map(|placeholder| format!("{{{}}}", placeholder))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Name of a file following the template. The extension is added when the template leaves it
+// out, so that the type of the file can still be told from its name.
+pub fn apply_template(template: &str, context: &NamingContext) -> String {
+    let (stem, extension) = match context.original_file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, extension.to_lowercase()),
+        _ => (context.original_file_name, String::new()),
+    };
+    let Ok(parts) = parts(template) else {
+        return sanitize_file_name(context.original_file_name);
+    };
+
+    let mut name = String::new();
+    for part in &parts {
+        match part {
+            Part::Text(text) => name.push_str(text),
+            Part::Placeholder(placeholder) => name.push_str(&match *placeholder {
+                "client" => sanitize_file_name(context.client),
+                "collection" => sanitize_file_name(context.collection),
+                "request" => sanitize_file_name(context.request),
+                "date" => context.date.to_string(),
+                "seq" => format!("{:02}", context.seq),
+                "original" => sanitize_file_name(stem),
+                "ext" => extension.clone(),
+                _ => String::new(),
+            }),
+        }
+    }
+    if !extension.is_empty() && !parts.contains(&Part::Placeholder("ext")) {
+        name = format!("{}.{}", name, extension);
+    }
+    sanitize_file_name(&name)
+}
+
+#[derive(PartialEq)]
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn parts(template: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            return Err("The file name template has a } without a {.".to_string());
+        }
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| "The file name template has a { without a }.".to_string())?;
+        parts.push(Part::Text(&rest[..start]));
+        parts.push(Part::Placeholder(rest[start + 1..start + end].trim()));
+        rest = &rest[start + end + 1..];
+    }
+    parts.push(Part::Text(rest));
+    Ok(parts)
+}
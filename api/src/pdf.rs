@@ -0,0 +1,387 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::metadata::Orientation;
+use image::{ColorType, DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, Stream};
+use rust_decimal::Decimal;
+use std::io::Cursor;
+
+use crate::model::invoice::Invoice;
+use crate::preview::heif_to_png;
+
+// Types of the photos that can be converted to PDF
+pub const CONVERTIBLE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/heif"];
+
+// A4 portrait, in points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const PAGE_MARGIN: f32 = 24.0;
+const JPEG_QUALITY: u8 = 90;
+
+// Largest photo decoded for a page, 48 megapixels being about 140 MB once decoded
+const MAX_PHOTO_PIXELS: u64 = 48_000_000;
+
+// A photo ready to be placed on a page
+pub struct PageImage {
+    jpeg: Vec<u8>,
+    width: u32,
+    height: u32,
+    grayscale: bool,
+}
+
+// Turns a photo into an upright JPEG, keeping the original bytes when it already is one
+pub async fn page_image(mime_type: &str, content: Vec<u8>) -> anyhow::Result<PageImage> {
+    let content = match mime_type {
+        "image/heif" => heif_to_png(&content).await?,
+        _ => content,
+    };
+    tokio::task::spawn_blocking(move || upright_jpeg(content)).await?
+}
+
+fn upright_jpeg(content: Vec<u8>) -> anyhow::Result<PageImage> {
+    let reader = ImageReader::new(Cursor::new(&content)).with_guessed_format()?;
+    let format = reader.format();
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let color_type = decoder.color_type();
+    let (width, height) = decoder.dimensions();
+    if width as u64 * height as u64 > MAX_PHOTO_PIXELS {
+        anyhow::bail!("photo of {}x{} pixels is too large", width, height);
+    }
+
+    if format == Some(ImageFormat::Jpeg)
+        && orientation == Orientation::NoTransforms
+        && matches!(color_type, ColorType::Rgb8 | ColorType::L8)
+    {
+        drop(decoder);
+        return Ok(PageImage {
+            jpeg: content,
+            width,
+            height,
+            grayscale: color_type == ColorType::L8,
+        });
+    }
+
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    // JPEG has no transparency, the photo is flattened
+    let image = DynamicImage::ImageRgb8(image.to_rgb8());
+
+    let mut jpeg = Vec::new();
+    image.write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))?;
+    Ok(PageImage {
+        jpeg,
+        width: image.width(),
+        height: image.height(),
+        grayscale: false,
+    })
+}
+
+// Builds a PDF with one A4 page per photo, landscape for landscape photos, each photo
+// scaled to fit the page and centered
+pub fn images_to_pdf(images: Vec<PageImage>) -> anyhow::Result<Vec<u8>> {
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+
+    let mut page_ids: Vec<Object> = Vec::new();
+    for image in images {
+        let (page_width, page_height) = if image.width > image.height {
+            (PAGE_HEIGHT, PAGE_WIDTH)
+        } else {
+            (PAGE_WIDTH, PAGE_HEIGHT)
+        };
+        let scale = ((page_width - 2.0 * PAGE_MARGIN) / image.width as f32)
+            .min((page_height - 2.0 * PAGE_MARGIN) / image.height as f32);
+        let (width, height) = (image.width as f32 * scale, image.height as f32 * scale);
+        let (x, y) = ((page_width - width) / 2.0, (page_height - height) / 2.0);
+
+        let image_id = document.add_object(Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => image.width as i64,
+                "Height" => image.height as i64,
+                "ColorSpace" => if image.grayscale { "DeviceGray" } else { "DeviceRGB" },
+                "BitsPerComponent" => 8,
+                "Filter" => "DCTDecode",
+            },
+            image.jpeg,
+        ));
+        let content_id = document.add_object(Stream::new(
+            dictionary! {},
+            format!(
+                "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im0 Do Q",
+                width, height, x, y
+            )
+            .into_bytes(),
+        ));
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), page_width.into(), page_height.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "XObject" => dictionary! { "Im0" => image_id } },
+        });
+        page_ids.push(page_id.into());
+    }
+
+    let count = page_ids.len() as i64;
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => page_ids,
+            "Count" => count,
+        }),
+    );
+    let catalog_id = document.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    document.trailer.set("Root", catalog_id);
+
+    let mut pdf = Vec::new();
+    document.save_to(&mut pdf)?;
+    Ok(pdf)
+}
+
+// Rendered invoices are stored next to the XML they were made from
+pub fn rendition_key(storage_key: &str) -> String {
+    format!("{}.rendition.pdf", storage_key)
+}
+
+const FONT: &str = "F1";
+const BOLD_FONT: &str = "F2";
+const TEXT_SIZE: f32 = 10.0;
+const LINE_HEIGHT: f32 = 14.0;
+// Right edges of the line table columns
+const QUANTITY_RIGHT: f32 = 350.0;
+const UNIT_PRICE_RIGHT: f32 = 430.0;
+const VAT_RATE_RIGHT: f32 = 480.0;
+const NET_AMOUNT_RIGHT: f32 = PAGE_WIDTH - PAGE_MARGIN * 2.0;
+
+// Lays out text on A4 pages, starting a new page when the current one is full
+struct PageWriter {
+    pages: Vec<Vec<Operation>>,
+    y: f32,
+}
+
+impl PageWriter {
+    fn new() -> Self {
+        PageWriter {
+            pages: vec![Vec::new()],
+            y: PAGE_HEIGHT - PAGE_MARGIN * 2.0,
+        }
+    }
+
+    fn text(&mut self, x: f32, font: &str, size: f32, text: &str) {
+        let encoded = Document::encode_text(Some("WinAnsiEncoding"), text);
+        let operations = self.pages.last_mut().unwrap();
+        operations.extend([
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec![font.into(), size.into()]),
+            Operation::new("Td", vec![x.into(), self.y.into()]),
+            Operation::new("Tj", vec![Object::string_literal(encoded)]),
+            Operation::new("ET", vec![]),
+        ]);
+    }
+
+    // Numbers are aligned on their right edge, measured with Helvetica's widths
+    fn number(&mut self, right: f32, font: &str, text: &str) {
+        let width: f32 = text
+            .chars()
+            .map(|c| match c {
+                '0'..='9' => 0.556,
+                ' ' | '.' | ',' => 0.278,
+                '%' => 0.889,
+                _ => 0.667,
+            })
+            .sum::<f32>()
+            * TEXT_SIZE;
+        self.text(right - width, font, TEXT_SIZE, text);
+    }
+
+    fn next_line(&mut self, height: f32) {
+        self.y -= height;
+        if self.y < PAGE_MARGIN * 2.0 {
+            self.pages.push(Vec::new());
+            self.y = PAGE_HEIGHT - PAGE_MARGIN * 2.0;
+        }
+    }
+}
+
+fn format_amount(amount: Option<Decimal>) -> String {
+    amount
+        .map(|amount| format!("{:.2}", amount))
+        .unwrap_or_default()
+}
+
+// Long descriptions are cut to fit their column
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars - 3).collect();
+    truncated.push_str("...");
+    truncated
+}
+
+// Renders a human-readable PDF of an XML e-invoice: parties, lines, totals and the rules it
+// breaks
+pub fn invoice_to_pdf(invoice: &Invoice) -> anyhow::Result<Vec<u8>> {
+    let left = PAGE_MARGIN * 2.0;
+    let mut writer = PageWriter::new();
+
+    let title = match invoice.type_code.as_deref() {
+        Some("381") => "Credit note",
+        _ => "Invoice",
+    };
+    writer.text(
+        left,
+        BOLD_FONT,
+        18.0,
+        &format!("{} {}", title, invoice.number.as_deref().unwrap_or("")),
+    );
+    writer.next_line(LINE_HEIGHT * 1.5);
+    let mut dates = Vec::new();
+    if let Some(issue_date) = invoice.issue_date {
+        dates.push(format!("Issued {}", issue_date));
+    }
+    if let Some(due_date) = invoice.due_date {
+        dates.push(format!("Due {}", due_date));
+    }
+    writer.text(left, FONT, TEXT_SIZE, &dates.join("    "));
+    writer.next_line(LINE_HEIGHT * 2.0);
+
+    // Seller on the left, buyer on the right
+    let parties = [("Seller", &invoice.seller), ("Buyer", &invoice.buyer)].map(|(role, party)| {
+        let mut lines = vec![party.name.clone().unwrap_or_default()];
+        if let Some(siren) = &party.siren {
+            lines.push(format!("SIREN {}", siren));
+        }
+        if let Some(vat_number) = &party.vat_number {
+            lines.push(format!("VAT {}", vat_number));
+        }
+        (role, lines)
+    });
+    let top = writer.y;
+    for (column, (role, lines)) in parties.iter().enumerate() {
+        let x = left + column as f32 * 260.0;
+        writer.y = top;
+        writer.text(x, BOLD_FONT, TEXT_SIZE, role);
+        for line in lines {
+            writer.y -= LINE_HEIGHT;
+            writer.text(x, FONT, TEXT_SIZE, &truncate(line, 45));
+        }
+    }
+    writer.y = top;
+    let block_lines = parties
+        .iter()
+        .map(|(_, lines)| lines.len())
+        .max()
+        .unwrap_or(0);
+    writer.next_line(LINE_HEIGHT * (block_lines as f32 + 2.0));
+
+    writer.text(left, BOLD_FONT, TEXT_SIZE, "Description");
+    writer.number(QUANTITY_RIGHT, BOLD_FONT, "Qty");
+    writer.number(UNIT_PRICE_RIGHT, BOLD_FONT, "Unit price");
+    writer.number(VAT_RATE_RIGHT, BOLD_FONT, "VAT");
+    writer.number(NET_AMOUNT_RIGHT, BOLD_FONT, "Net");
+    for line in &invoice.lines {
+        writer.next_line(LINE_HEIGHT);
+        writer.text(
+            left,
+            FONT,
+            TEXT_SIZE,
+            &truncate(line.description.as_deref().unwrap_or(""), 40),
+        );
+        let quantity = line
+            .quantity
+            .map(|q| q.normalize().to_string())
+            .unwrap_or_default();
+        writer.number(QUANTITY_RIGHT, FONT, &quantity);
+        writer.number(UNIT_PRICE_RIGHT, FONT, &format_amount(line.unit_price));
+        let vat_rate = line
+            .vat_rate
+            .map(|r| format!("{}%", r.normalize()))
+            .unwrap_or_default();
+        writer.number(VAT_RATE_RIGHT, FONT, &vat_rate);
+        writer.number(NET_AMOUNT_RIGHT, FONT, &format_amount(line.net_amount));
+    }
+    writer.next_line(LINE_HEIGHT * 2.0);
+
+    let currency = invoice.currency.as_deref().unwrap_or("");
+    for (label, amount, font) in [
+        ("Total without VAT", invoice.net_total, FONT),
+        ("VAT", invoice.vat_total, FONT),
+        ("Total with VAT", invoice.gross_total, BOLD_FONT),
+    ] {
+        writer.text(UNIT_PRICE_RIGHT - 80.0, font, TEXT_SIZE, label);
+        writer.number(
+            NET_AMOUNT_RIGHT,
+            font,
+            &format!("{} {}", format_amount(amount), currency),
+        );
+        writer.next_line(LINE_HEIGHT);
+    }
+
+    if !invoice.validation_errors.is_empty() {
+        writer.next_line(LINE_HEIGHT);
+        writer.text(left, BOLD_FONT, TEXT_SIZE, "Validation errors");
+        for error in &invoice.validation_errors {
+            writer.next_line(LINE_HEIGHT);
+            writer.text(left, FONT, 8.0, &truncate(error, 110));
+        }
+    }
+
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let bold_font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica-Bold",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let resources_id = document.add_object(dictionary! {
+        "Font" => dictionary! { FONT => font_id, BOLD_FONT => bold_font_id },
+    });
+
+    let mut page_ids: Vec<Object> = Vec::new();
+    for operations in writer.pages {
+        let content = Content { operations }.encode()?;
+        let content_id = document.add_object(Stream::new(dictionary! {}, content));
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), PAGE_WIDTH.into(), PAGE_HEIGHT.into()],
+            "Contents" => content_id,
+            "Resources" => resources_id,
+        });
+        page_ids.push(page_id.into());
+    }
+
+    let count = page_ids.len() as i64;
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => page_ids,
+            "Count" => count,
+        }),
+    );
+    let catalog_id = document.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    document.trailer.set("Root", catalog_id);
+
+    let mut pdf = Vec::new();
+    document.save_to(&mut pdf)?;
+    Ok(pdf)
+}
@@ -0,0 +1,614 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::future::join_all;
+use sqlx::types::Json as JsonColumn;
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::bank_statement::parse_statement;
+use crate::classifier::{Document, RequestCandidate};
+use crate::einvoice::parse_invoice;
+use crate::fec::parse_fec;
+use crate::invoice_fields::extract_invoice_fields;
+use crate::model::bank_statement::{BankStatement, BankStatementSummary};
+use crate::model::fec::{FecReport, FecSummary};
+use crate::model::file::{
+    CLASSIFICATION_DONE, CLASSIFICATION_PENDING, FEC_FAILED, FEC_NONE, FEC_PARSED, FEC_PENDING,
+    FIELDS_EXTRACTED, FIELDS_NONE, FIELDS_PENDING, INVOICE_FAILED, INVOICE_NONE, INVOICE_PARSED,
+    INVOICE_PENDING, PREVIEW_FAILED, PREVIEW_PENDING, PREVIEW_READY, PREVIEW_UNSUPPORTED,
+    SCAN_CLEAN, SCAN_UNSCANNED, STATEMENT_FAILED, STATEMENT_NONE, STATEMENT_PARSED,
+    STATEMENT_PENDING, TEXT_EXTRACTED, TEXT_FAILED, TEXT_PENDING, TEXT_UNSUPPORTED,
+};
+use crate::model::invoice::{Invoice, InvoiceFields, INVOICE_FACTURX};
+use crate::model::request::KIND_FILES;
+use crate::pdf::{invoice_to_pdf, rendition_key};
+use crate::preview::{preview_key, render_preview};
+use crate::text_extraction::extract_text;
+
+// Background processing of the files that passed the malware scan. Each stage records its outcome
+// in a status column of `files`, "pending" until it ran. The stages reading the content run
+// together on one read of the file, then the ones building on their results.
+const CONTENT_STAGES: &[&dyn Stage] = &[
+    &PreviewStage,
+    &TextStage,
+    &InvoiceStage,
+    &StatementStage,
+    &FecStage,
+];
+const DERIVED_STAGES: &[&dyn Stage] = &[&FieldsStage, &ClassificationStage];
+
+// Files processed by each run of the scheduler
+const PENDING_BATCH: i64 = 20;
+
+// Largest file rendered into a preview, to bound the memory used by decoders
+const MAX_PREVIEW_SOURCE_BYTES: i64 = 50 * 1024 * 1024;
+
+// A scanned file and the statuses of its stages
+pub struct PipelineFile {
+    pub id: Uuid,
+    pub storage_key: String,
+    pub original_file_name: String, // As uploaded, before the firm's naming template
+    pub mime_type: String,
+    pub file_size: i64,
+    preview_status: String,
+    text_status: String,
+    invoice_status: String,
+    statement_status: String,
+    fec_status: String,
+    fields_status: String,
+    classification_status: String,
+}
+
+#[async_trait]
+trait Stage: Send + Sync {
+    // What the stage does, for the logs
+    fn name(&self) -> &'static str;
+
+    fn is_pending(&self, file: &PipelineFile) -> bool;
+
+    // Records the outcome of the stage. The content is only read for the CONTENT_STAGES, whose
+    // failure to read it is recorded like any other.
+    async fn run(
+        &self,
+        app_state: &AppState,
+        file: &PipelineFile,
+        content: Result<Bytes, String>,
+    ) -> anyhow::Result<()>;
+}
+
+// Runs the stages still pending for a file, once it passed the malware scan
+pub async fn process_file(app_state: &AppState, file_id: Uuid) -> anyhow::Result<()> {
+    let Some(file) = sqlx::query_as!(
+        PipelineFile,
+        r#"
+        SELECT id, storage_key, original_file_name, mime_type, file_size, preview_status,
+            text_status, invoice_status, statement_status, fec_status, fields_status,
+            classification_status
+        FROM files
+        WHERE id = $1 AND scan_status IN ($2, $3)
+        "#,
+        file_id,
+        SCAN_CLEAN,
+        SCAN_UNSCANNED
+    )
+    .fetch_optional(&app_state.db_pool)
+    .await?
+    else {
+        return Ok(());
+    };
+
+    sqlx::query!(
+        "UPDATE files SET processed_at = now() WHERE id = $1",
+        file_id
+    )
+    .execute(&app_state.db_pool)
+    .await?;
+
+    let pending = |stages: &[&'static dyn Stage]| -> Vec<&'static dyn Stage> {
+        stages
+            .iter()
+            .copied()
+            .filter(|stage| stage.is_pending(&file))
+            .collect()
+    };
+
+    let content_stages = pending(CONTENT_STAGES);
+    if !content_stages.is_empty() {
+        let content = async {
+            let mut content = Vec::new();
+            let mut reader = app_state.storage.get(&file.storage_key).await?;
+            reader.read_to_end(&mut content).await?;
+            anyhow::Ok(Bytes::from(content))
+        }
+        .await
+        .map_err(|e| format!("Failed to read file: {}", e));
+        run_stages(app_state, &file, &content_stages, content).await;
+    }
+
+    run_stages(
+        app_state,
+        &file,
+        &pending(DERIVED_STAGES),
+        Err("Not read for this stage".to_string()),
+    )
+    .await;
+
+    Ok(())
+}
+
+async fn run_stages(
+    app_state: &AppState,
+    file: &PipelineFile,
+    stages: &[&dyn Stage],
+    content: Result<Bytes, String>,
+) {
+    join_all(stages.iter().map(|stage| {
+        let content = content.clone();
+        async move {
+            if let Err(e) = stage.run(app_state, file, content).await {
+                eprintln!("Failed to {} of file {}: {}", stage.name(), file.id, e);
+            }
+        }
+    }))
+    .await;
+}
+
+// Runs the stages left pending for scanned files, those not tried for the longest first, and
+// returns how many files were processed
+pub async fn process_pending_files(app_state: &AppState) -> anyhow::Result<usize> {
+    let file_ids = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM files
+        WHERE scan_status IN ($1, $2)
+            AND (preview_status = $3 OR text_status = $4 OR invoice_status = $5
+                OR statement_status = $6 OR fec_status = $7 OR fields_status = $8
+                OR classification_status = $9)
+        ORDER BY processed_at NULLS FIRST, created_at
+        LIMIT $10
+        "#,
+        SCAN_CLEAN,
+        SCAN_UNSCANNED,
+        PREVIEW_PENDING,
+        TEXT_PENDING,
+        INVOICE_PENDING,
+        STATEMENT_PENDING,
+        FEC_PENDING,
+        FIELDS_PENDING,
+        CLASSIFICATION_PENDING,
+        PENDING_BATCH
+    )
+    .fetch_all(&app_state.db_pool)
+    .await?;
+
+    let mut processed = 0;
+    for file_id in file_ids {
+        match process_file(app_state, file_id).await {
+            Ok(()) => processed += 1,
+            Err(e) => eprintln!("Failed to process file {}: {}", file_id, e),
+        }
+    }
+
+    Ok(processed)
+}
+
+// Renders and stores the PNG preview of the file
+struct PreviewStage;
+
+#[async_trait]
+impl Stage for PreviewStage {
+    fn name(&self) -> &'static str {
+        "generate preview"
+    }
+
+    fn is_pending(&self, file: &PipelineFile) -> bool {
+        file.preview_status == PREVIEW_PENDING
+    }
+
+    async fn run(
+        &self,
+        app_state: &AppState,
+        file: &PipelineFile,
+        content: Result<Bytes, String>,
+    ) -> anyhow::Result<()> {
+        let rendered = if file.file_size > MAX_PREVIEW_SOURCE_BYTES {
+            Ok(None)
+        } else {
+            match content {
+                Ok(content) => render_preview(&file.mime_type, content).await,
+                Err(e) => Err(anyhow::anyhow!(e)),
+            }
+        };
+        let status = match rendered {
+            Ok(Some(png)) => {
+                app_state
+                    .storage
+                    .put(&preview_key(&file.storage_key), &mut png.as_slice())
+                    .await?;
+                PREVIEW_READY
+            }
+            Ok(None) => PREVIEW_UNSUPPORTED,
+            Err(e) => {
+                eprintln!("Failed to render preview of file {}: {}", file.id, e);
+                PREVIEW_FAILED
+            }
+        };
+
+        sqlx::query!(
+            "UPDATE files SET preview_status = $1, updated_at = now() WHERE id = $2",
+            status,
+            file.id
+        )
+        .execute(&app_state.db_pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Extracts the text of the file for search
+struct TextStage;
+
+#[async_trait]
+impl Stage for TextStage {
+    fn name(&self) -> &'static str {
+        "extract text"
+    }
+
+    fn is_pending(&self, file: &PipelineFile) -> bool {
+        file.text_status == TEXT_PENDING
+    }
+
+    async fn run(
+        &self,
+        app_state: &AppState,
+        file: &PipelineFile,
+        content: Result<Bytes, String>,
+    ) -> anyhow::Result<()> {
+        let extracted = match content {
+            Ok(content) => extract_text(&file.mime_type, content).await,
+            Err(e) => Err(anyhow::anyhow!(e)),
+        };
+        let (status, text) = match extracted {
+            Ok(Some(text)) => (TEXT_EXTRACTED, Some(text)),
+            Ok(None) => (TEXT_UNSUPPORTED, None),
+            Err(e) => {
+                eprintln!("Failed to extract text of file {}: {}", file.id, e);
+                (TEXT_FAILED, None)
+            }
+        };
+
+        sqlx::query!(
+            "UPDATE files SET text_status = $1, extracted_text = $2, updated_at = now() WHERE id = $3",
+            status,
+            text,
+            file.id
+        )
+        .execute(&app_state.db_pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Parses the e-invoice embedded in a PDF or making up an XML file
+struct InvoiceStage;
+
+#[async_trait]
+impl Stage for InvoiceStage {
+    fn name(&self) -> &'static str {
+        "parse invoice"
+    }
+
+    fn is_pending(&self, file: &PipelineFile) -> bool {
+        file.invoice_status == INVOICE_PENDING
+    }
+
+    async fn run(
+        &self,
+        app_state: &AppState,
+        file: &PipelineFile,
+        content: Result<Bytes, String>,
+    ) -> anyhow::Result<()> {
+        let parsed = match content {
+            Ok(content) => parse_invoice(&file.mime_type, content).await,
+            Err(e) => Err(anyhow::anyhow!(e)),
+        };
+        let (status, invoice) = match parsed {
+            Ok(Some(invoice)) => match store_rendition(app_state, file, &invoice).await {
+                Ok(()) => (INVOICE_PARSED, Some(JsonColumn(invoice))),
+                Err(e) => {
+                    eprintln!("Failed to render invoice of file {}: {}", file.id, e);
+                    (INVOICE_FAILED, None)
+                }
+            },
+            Ok(None) => (INVOICE_NONE, None),
+            Err(e) => {
+                eprintln!("Failed to parse invoice of file {}: {}", file.id, e);
+                (INVOICE_FAILED, None)
+            }
+        };
+
+        sqlx::query!(
+            "UPDATE files SET invoice_status = $1, invoice = $2, updated_at = now() WHERE id = $3",
+            status,
+            invoice as Option<JsonColumn<Invoice>>,
+            file.id
+        )
+        .execute(&app_state.db_pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Accountants read XML invoices through their rendition
+async fn store_rendition(
+    app_state: &AppState,
+    file: &PipelineFile,
+    invoice: &Invoice,
+) -> anyhow::Result<()> {
+    if invoice.format == INVOICE_FACTURX {
+        return Ok(());
+    }
+    let invoice = invoice.clone();
+    let pdf = tokio::task::spawn_blocking(move || invoice_to_pdf(&invoice)).await??;
+    app_state
+        .storage
+        .put(&rendition_key(&file.storage_key), &mut pdf.as_slice())
+        .await?;
+    Ok(())
+}
+
+// Parses the transactions of a bank statement
+struct StatementStage;
+
+#[async_trait]
+impl Stage for StatementStage {
+    fn name(&self) -> &'static str {
+        "parse bank statement"
+    }
+
+    fn is_pending(&self, file: &PipelineFile) -> bool {
+        file.statement_status == STATEMENT_PENDING
+    }
+
+    async fn run(
+        &self,
+        app_state: &AppState,
+        file: &PipelineFile,
+        content: Result<Bytes, String>,
+    ) -> anyhow::Result<()> {
+        let parsed = match content {
+            Ok(content) => parse_statement(&file.mime_type, content).await,
+            Err(e) => Err(anyhow::anyhow!(e)),
+        };
+        let (status, statement) = match parsed {
+            Ok(Some(statement)) => (STATEMENT_PARSED, Some(JsonColumn(statement))),
+            Ok(None) => (STATEMENT_NONE, None),
+            Err(e) => {
+                eprintln!("Failed to parse bank statement of file {}: {}", file.id, e);
+                (STATEMENT_FAILED, None)
+            }
+        };
+
+        sqlx::query!(
+            "UPDATE files SET statement_status = $1, bank_statement = $2, updated_at = now() WHERE id = $3",
+            status,
+            statement as Option<JsonColumn<BankStatement>>,
+            file.id
+        )
+        .execute(&app_state.db_pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Validates an FEC and totals its journals
+struct FecStage;
+
+#[async_trait]
+impl Stage for FecStage {
+    fn name(&self) -> &'static str {
+        "parse FEC"
+    }
+
+    fn is_pending(&self, file: &PipelineFile) -> bool {
+        file.fec_status == FEC_PENDING
+    }
+
+    async fn run(
+        &self,
+        app_state: &AppState,
+        file: &PipelineFile,
+        content: Result<Bytes, String>,
+    ) -> anyhow::Result<()> {
+        let parsed = match content {
+            Ok(content) => parse_fec(&file.mime_type, &file.original_file_name, content).await,
+            Err(e) => Err(anyhow::anyhow!(e)),
+        };
+        let (status, report, summary) = match parsed {
+            Ok(Some(fec)) => (
+                FEC_PARSED,
+                Some(JsonColumn(fec.report)),
+                Some(JsonColumn(fec.summary)),
+            ),
+            Ok(None) => (FEC_NONE, None, None),
+            Err(e) => {
+                eprintln!("Failed to parse FEC of file {}: {}", file.id, e);
+                (FEC_FAILED, None, None)
+            }
+        };
+
+        sqlx::query!(
+            r#"
+            UPDATE files SET fec_status = $1, fec_report = $2, fec_summary = $3, updated_at = now()
+            WHERE id = $4
+            "#,
+            status,
+            report as Option<JsonColumn<FecReport>>,
+            summary as Option<JsonColumn<FecSummary>>,
+            file.id
+        )
+        .execute(&app_state.db_pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Reads the key fields of a PDF invoice from its text, unless it is an e-invoice, once its text
+// and e-invoice data were extracted
+struct FieldsStage;
+
+#[async_trait]
+impl Stage for FieldsStage {
+    fn name(&self) -> &'static str {
+        "extract invoice fields"
+    }
+
+    fn is_pending(&self, file: &PipelineFile) -> bool {
+        file.fields_status == FIELDS_PENDING
+    }
+
+    async fn run(
+        &self,
+        app_state: &AppState,
+        file: &PipelineFile,
+        _: Result<Bytes, String>,
+    ) -> anyhow::Result<()> {
+        let Some(extracted) = sqlx::query!(
+            r#"
+            SELECT extracted_text, invoice_status FROM files
+            WHERE id = $1 AND text_status <> $2 AND invoice_status <> $3
+            "#,
+            file.id,
+            TEXT_PENDING,
+            INVOICE_PENDING
+        )
+        .fetch_optional(&app_state.db_pool)
+        .await?
+        else {
+            return Ok(());
+        };
+
+        let fields = match extracted.extracted_text {
+            Some(text)
+                if file.mime_type == mime::APPLICATION_PDF.as_ref()
+                    && extracted.invoice_status == INVOICE_NONE =>
+            {
+                tokio::task::spawn_blocking(move || extract_invoice_fields(&text)).await?
+            }
+            _ => None,
+        };
+        let status = if fields.is_some() {
+            FIELDS_EXTRACTED
+        } else {
+            FIELDS_NONE
+        };
+
+        // Corrections made in the meantime are kept
+        sqlx::query!(
+            "UPDATE files SET fields_status = $1, invoice_fields = $2, updated_at = now() WHERE id = $3 AND fields_status = $4",
+            status,
+            fields.map(JsonColumn) as Option<JsonColumn<InvoiceFields>>,
+            file.id,
+            FIELDS_PENDING
+        )
+        .execute(&app_state.db_pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Suggests the accounting category of a file and the pending request it most likely answers,
+// once its text, e-invoice and bank statement were extracted
+struct ClassificationStage;
+
+#[async_trait]
+impl Stage for ClassificationStage {
+    fn name(&self) -> &'static str {
+        "classify"
+    }
+
+    fn is_pending(&self, file: &PipelineFile) -> bool {
+        file.classification_status == CLASSIFICATION_PENDING
+    }
+
+    async fn run(
+        &self,
+        app_state: &AppState,
+        file: &PipelineFile,
+        _: Result<Bytes, String>,
+    ) -> anyhow::Result<()> {
+        let Some(extracted) = sqlx::query!(
+            r#"
+            SELECT f.extracted_text,
+                f.invoice as "invoice: JsonColumn<Invoice>",
+                f.bank_statement - 'transactions' as "bank_statement: JsonColumn<BankStatementSummary>",
+                r.collection_id, cl.company_name
+            FROM files f
+            JOIN requests r ON f.request_id = r.id
+            JOIN collections c ON r.collection_id = c.id
+            JOIN clients cl ON c.client_id = cl.id
+            WHERE f.id = $1 AND f.text_status <> $2 AND f.invoice_status <> $3
+                AND f.statement_status <> $4
+            "#,
+            file.id,
+            TEXT_PENDING,
+            INVOICE_PENDING,
+            STATEMENT_PENDING
+        )
+        .fetch_optional(&app_state.db_pool)
+        .await?
+        else {
+            return Ok(());
+        };
+
+        let requests = sqlx::query_as!(
+            RequestCandidate,
+            "SELECT id, title, description FROM requests WHERE collection_id = $1 AND status = 'pending' AND kind = $2 ORDER BY created_at",
+            extracted.collection_id,
+            KIND_FILES
+        )
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+        let document = Document {
+            file_name: &file.original_file_name,
+            mime_type: &file.mime_type,
+            text: extracted.extracted_text.as_deref(),
+            invoice: extracted.invoice.as_ref().map(|invoice| &invoice.0),
+            bank_statement: extracted
+                .bank_statement
+                .as_ref()
+                .map(|statement| &statement.0),
+            client_name: &extracted.company_name,
+        };
+        let classification = app_state.classifier.classify(&document).await;
+        let suggested_request_id = app_state
+            .classifier
+            .suggest_request(&document, &classification, &requests)
+            .await;
+
+        sqlx::query!(
+            r#"
+            UPDATE files
+            SET classification_status = $1, category = $2, category_confidence = $3,
+                suggested_request_id = $4, updated_at = now()
+            WHERE id = $5
+            "#,
+            CLASSIFICATION_DONE,
+            classification.category,
+            classification.confidence,
+            suggested_request_id,
+            file.id
+        )
+        .execute(&app_state.db_pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use bytes::Bytes;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use lopdf::{Document, Object};
+use std::io::Cursor;
+use tokio::process::Command;
+
+// Longest side of a preview, in pixels
+pub const PREVIEW_SIZE: u32 = 512;
+
+// Previews are PNG objects stored next to the original
+pub fn preview_key(storage_key: &str) -> String {
+    format!("{}.preview.png", storage_key)
+}
+
+// Renders the PNG preview of a file, None when its type has no preview.
+// The first page of PDFs is rendered with `pdftoppm` (poppler-utils) and HEIC photos decoded with
+// `heif-convert` (libheif), both provisioned by the flake. A scanned page, which is nothing but a
+// JPEG, is previewed from that JPEG without rendering.
+pub async fn render_preview(mime_type: &str, content: Bytes) -> anyhow::Result<Option<Vec<u8>>> {
+    let content = match mime_type {
+        "image/jpeg" | "image/png" => content,
+        "application/pdf" => match scanned_page_jpeg(&content) {
+            Some(jpeg) => jpeg.into(),
+            None => {
+                convert_with_command(
+                    &content,
+                    "input.pdf",
+                    "pdftoppm",
+                    &[
+                        "-png",
+                        "-f",
+                        "1",
+                        "-l",
+                        "1",
+                        "-singlefile",
+                        "-scale-to",
+                        &PREVIEW_SIZE.to_string(),
+                        "input.pdf",
+                        "preview",
+                    ],
+                )
+                .await?
+                .into()
+            }
+        },
+        "image/heif" => heif_to_png(&content).await?.into(),
+        _ => return Ok(None),
+    };
+
+    // Decoding large photos is CPU bound
+    let preview = tokio::task::spawn_blocking(move || thumbnail(&content)).await??;
+    Ok(Some(preview))
+}
+
+// Downscales an image to PREVIEW_SIZE, upright according to its EXIF orientation, as PNG
+fn thumbnail(content: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut decoder = ImageReader::new(Cursor::new(content))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    if image.width() > PREVIEW_SIZE || image.height() > PREVIEW_SIZE {
+        image = image.thumbnail(PREVIEW_SIZE, PREVIEW_SIZE);
+    }
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+// The JPEG of a first page that only draws it over the whole page, as scanners produce, which
+// looks the same as the rendered page. None for any other page.
+fn scanned_page_jpeg(content: &[u8]) -> Option<Vec<u8>> {
+    let document = Document::load_mem(content).ok()?;
+    let (_, page_id) = document.get_pages().into_iter().next()?;
+
+    let page_content = document.get_and_decode_page_content(page_id).ok()?;
+
+    let mut image_name = None;
+    let mut size = None;
+    for operation in page_content.operations {
+        match operation.operator.as_str() {
+            "q" | "Q" => {}
+            "cm" if size.is_none() => {
+                let matrix = numbers(&operation.operands)?;
+                // Scaled and moved, not rotated or skewed
+                let [width, b, c, height, _, _] = matrix[..] else {
+                    return None;
+                };
+                if b != 0.0 || c != 0.0 {
+                    return None;
+                }
+                size = Some((width, height));
+            }
+            "Do" if image_name.is_none() => {
+                image_name = Some(operation.operands.first()?.as_name().ok()?.to_vec());
+            }
+            _ => return None,
+        }
+    }
+
+    let media_box = document
+        .get_object(page_id)
+        .and_then(Object::as_dict)
+        .and_then(|page| page.get_deref(b"MediaBox", &document))
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|media_box| numbers(media_box))?;
+    let [left, bottom, right, top] = media_box[..] else {
+        return None;
+    };
+    let (width, height) = size?;
+    if (width - (right - left)).abs() > 1.0 || (height - (top - bottom)).abs() > 1.0 {
+        return None;
+    }
+
+    let image_name = image_name?;
+    let (resources, resource_ids) = document.get_page_resources(page_id);
+    let stream = resources
+        .into_iter()
+        .chain(
+            resource_ids
+                .iter()
+                .filter_map(|id| document.get_dictionary(*id).ok()),
+        )
+        .find_map(|resources| {
+            let xobjects = resources
+                .get_deref(b"XObject", &document)
+                .and_then(Object::as_dict)
+                .ok()?;
+            match xobjects.get_deref(&image_name, &document).ok()? {
+                Object::Stream(stream) => Some(stream),
+                _ => None,
+            }
+        })?;
+    let is_jpeg = stream
+        .dict
+        .get(b"Subtype")
+        .and_then(Object::as_name)
+        .is_ok_and(|subtype| subtype == b"Image")
+        && stream
+            .filters()
+            .is_ok_and(|filters| filters == ["DCTDecode"]);
+
+    is_jpeg.then(|| stream.content.clone())
+}
+
+fn numbers(operands: &[Object]) -> Option<Vec<f32>> {
+    operands
+        .iter()
+        .map(|operand| operand.as_float().ok())
+        .collect()
+}
+
+// Decodes a HEIC photo, which the image crate can't read, to PNG
+pub async fn heif_to_png(content: &[u8]) -> anyhow::Result<Vec<u8>> {
+    convert_with_command(
+        content,
+        "input.heic",
+        "heif-convert",
+        &["input.heic", "preview.png"],
+    )
+    .await
+}
+
+// Runs a converter in a temporary directory holding the input, and reads back preview.png
+async fn convert_with_command(
+    content: &[u8],
+    input_name: &str,
+    program: &str,
+    args: &[&str],
+) -> anyhow::Result<Vec<u8>> {
+    let directory = tempfile::tempdir()?;
+    tokio::fs::write(directory.path().join(input_name), content).await?;
+
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(directory.path())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(tokio::fs::read(directory.path().join("preview.png")).await?)
+}
@@ -1,27 +1,54 @@
 use axum::{
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    routing::{get, head, patch, post, put},
     Router,
 };
 
 use crate::handlers::{
+    bank_statement::{get_statement_gaps, get_transactions},
     client::{
         create as create_client, delete as delete_client, get_all as get_all_clients,
         get_one as get_one_client, update as update_client,
     },
     collection::{
-        create as create_collection, delete as delete_collection, get_all as get_all_collections,
-        get_one as get_one_collection, update as update_collection,
+        archive as get_collection_archive, bulk_create as bulk_create_collections,
+        clone as clone_collection, create as create_collection, delete as delete_collection,
+        export_answers, get_all as get_all_collections, get_one as get_one_collection,
+        summary as get_collection_summary, update as update_collection,
     },
+    comment::{
+        create as create_comment, get_all_for_request as get_all_comments,
+        mark_read as mark_comments_read, portal_create as portal_create_comment,
+        portal_get_all_for_request as portal_get_all_comments,
+        portal_mark_read as portal_mark_comments_read,
+    },
+    export::export as export_collection,
+    fec::get_fec_summary,
     file::{
-        delete as delete_file, get_all_for_request, get_one as get_one_file, upload as upload_file,
+        delete as delete_file, download as download_file, get_all_for_request,
+        get_one as get_one_file, get_originals, get_references_for_request, get_versions,
+        make_current, preview as preview_file, reject as reject_file, rendition as rendition_file,
+        update_invoice_fields, upload as upload_file, MAX_UPLOAD_BYTES,
     },
     firm::{
         create as create_firm, delete as delete_firm, get_all as get_all_firms,
-        get_one as get_one_firm, update as update_firm,
+        get_one as get_one_firm, get_settings as get_firm_settings,
+        put_settings as put_firm_settings, update as update_firm,
+    },
+    reminder::{
+        delete_policy as delete_reminder_policy, get_all_for_collection as get_all_reminders,
+        get_policy as get_reminder_policy, put_policy as put_reminder_policy,
+        send_now as send_reminder_now,
     },
     request::{
         create as create_request, delete as delete_request, get_all as get_all_requests,
-        get_one as get_one_request, update as update_request,
+        get_one as get_one_request, portal_get_one as portal_get_one_request,
+        portal_submit_answers, update as update_request,
+    },
+    search::search,
+    upload::{
+        create as create_upload, delete as delete_upload, head as head_upload,
+        options as upload_options, patch as patch_upload, tus_middleware,
     },
     user::{
         create as create_user, delete as delete_user, get_all as get_all_users,
@@ -30,7 +57,7 @@ use crate::handlers::{
 };
 
 use crate::app_state::AppState;
-use crate::auth::auth_middleware;
+use crate::auth::{auth_middleware, portal_auth_middleware};
 
 pub fn router(app_state: AppState) -> Router {
     // Public routes for users (register, login)
@@ -64,11 +91,26 @@ pub fn router(app_state: AppState) -> Router {
             "/:id",
             get(get_one_firm).patch(update_firm).delete(delete_firm),
         )
+        .route(
+            "/:id/settings",
+            get(get_firm_settings).put(put_firm_settings),
+        )
         .with_state(app_state.clone());
 
     let files_router = Router::new()
         .route("/", post(upload_file)) // Upload is now on /files
         .route("/:id", get(get_one_file).delete(delete_file))
+        .route("/:id/download", get(download_file))
+        .route("/:id/preview", get(preview_file))
+        .route("/:id/rendition", get(rendition_file))
+        .route("/:id/invoice-fields", patch(update_invoice_fields))
+        .route("/:id/transactions", get(get_transactions))
+        .route("/:id/fec", get(get_fec_summary))
+        .route("/:id/originals", get(get_originals))
+        .route("/:id/versions", get(get_versions))
+        .route("/:id/current", put(make_current))
+        .route("/:id/reject", post(reject_file))
+        .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES))
         .with_state(app_state.clone());
 
     let requests_router = Router::new()
@@ -80,16 +122,68 @@ pub fn router(app_state: AppState) -> Router {
                 .delete(delete_request),
         )
         .route("/:request_id/files", get(get_all_for_request)) // Removed post(upload_file) as it's now on /files
+        .route("/:request_id/references", get(get_references_for_request))
+        .route(
+            "/:request_id/comments",
+            get(get_all_comments).post(create_comment),
+        )
+        .route("/:request_id/comments/read", post(mark_comments_read))
         .with_state(app_state.clone());
 
     let collections_router = Router::new()
         .route("/", post(create_collection).get(get_all_collections))
+        .route("/bulk", post(bulk_create_collections))
         .route(
             "/:id",
             get(get_one_collection)
                 .patch(update_collection)
                 .delete(delete_collection),
         )
+        .route("/:id/archive", get(get_collection_archive))
+        .route("/:id/export", get(export_collection))
+        .route("/:id/clone", post(clone_collection))
+        .route("/:id/summary", get(get_collection_summary))
+        .route("/:id/answers", get(export_answers))
+        .route("/:id/statement-gaps", get(get_statement_gaps))
+        .route(
+            "/:id/reminder-policy",
+            get(get_reminder_policy)
+                .put(put_reminder_policy)
+                .delete(delete_reminder_policy),
+        )
+        .route(
+            "/:id/reminders",
+            get(get_all_reminders).post(send_reminder_now),
+        )
+        .with_state(app_state.clone());
+
+    // Resumable uploads, served to users and to clients through the portal
+    let uploads_router = Router::new()
+        .route("/", post(create_upload).options(upload_options))
+        .route(
+            "/:id",
+            head(head_upload).patch(patch_upload).delete(delete_upload),
+        )
+        .layer(axum::middleware::from_fn(tus_middleware))
+        .with_state(app_state.clone());
+
+    // Routes for end clients, authorized by their collection's access token instead of a JWT
+    let portal_router = Router::new()
+        .route("/requests/:request_id", get(portal_get_one_request))
+        .route("/requests/:request_id/answers", put(portal_submit_answers))
+        .route(
+            "/requests/:request_id/comments",
+            get(portal_get_all_comments).post(portal_create_comment),
+        )
+        .route(
+            "/requests/:request_id/comments/read",
+            post(portal_mark_comments_read),
+        )
+        .nest("/uploads", uploads_router.clone())
+        .layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            portal_auth_middleware,
+        ))
         .with_state(app_state.clone());
 
     // Group all protected routes and apply the middleware
@@ -100,6 +194,8 @@ pub fn router(app_state: AppState) -> Router {
         .nest("/files", files_router)
         .nest("/requests", requests_router)
         .nest("/collections", collections_router)
+        .nest("/uploads", uploads_router)
+        .route("/search", get(search))
         .layer(axum::middleware::from_fn_with_state(
             app_state.clone(),
             auth_middleware,
@@ -108,6 +204,7 @@ pub fn router(app_state: AppState) -> Router {
     Router::new()
         .nest("/", public_users_router) // Public user routes
         .merge(protected_routes) // Merge protected routes
+        .nest("/portal", portal_router)
         .with_state(app_state)
 }
 
@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+// Outcome of a malware scan
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanVerdict {
+    Clean,
+    Infected(String), // Name of the detected signature
+}
+
+#[async_trait]
+pub trait Scanner: Send + Sync {
+    async fn scan(
+        &self,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> anyhow::Result<ScanVerdict>;
+
+    // Largest content scanned, larger files can't be found clean
+    fn max_bytes(&self) -> u64 {
+        MAX_SCAN_BYTES as u64
+    }
+}
+
+// Scans through a clamd daemon with the INSTREAM command, configured with CLAMD_ADDRESS (host:port)
+// and CLAMD_MAX_STREAM_BYTES, which must match clamd's StreamMaxLength when it is raised
+pub struct ClamdScanner {
+    address: String,
+    max_bytes: u64,
+}
+
+impl ClamdScanner {
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            max_bytes: MAX_SCAN_BYTES as u64,
+        }
+    }
+}
+
+// Size of the chunks sent to clamd, well below its default StreamMaxLength
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Largest stream clamd scans with its default StreamMaxLength (25M), larger ones are refused
+pub const MAX_SCAN_BYTES: usize = 25 * 1024 * 1024;
+
+#[async_trait]
+impl Scanner for ClamdScanner {
+    async fn scan(
+        &self,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> anyhow::Result<ScanVerdict> {
+        let mut stream = TcpStream::connect(&self.address).await?;
+        stream.write_all(b"zINSTREAM\0").await?;
+
+        // Each chunk is prefixed with its length as a big-endian u32, a zero length ends the stream
+        let mut buffer = vec![0; CHUNK_SIZE];
+        loop {
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            stream.write_all(&(read as u32).to_be_bytes()).await?;
+            stream.write_all(&buffer[..read]).await?;
+        }
+        stream.write_all(&0u32.to_be_bytes()).await?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        parse_clamd_reply(&String::from_utf8_lossy(&reply))
+    }
+
+    fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+}
+
+// Parses "stream: OK", "stream: <signature> FOUND" or "<message> ERROR"
+pub fn parse_clamd_reply(reply: &str) -> anyhow::Result<ScanVerdict> {
+    let reply = reply.trim_end_matches('\0').trim();
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+
+    if result == "OK" {
+        Ok(ScanVerdict::Clean)
+    } else if let Some(signature) = result.strip_suffix(" FOUND") {
+        Ok(ScanVerdict::Infected(signature.trim().to_string()))
+    } else {
+        anyhow::bail!("clamd replied {:?}", reply)
+    }
+}
+
+pub fn from_env() -> Arc<dyn Scanner> {
+    let address = std::env::var("CLAMD_ADDRESS").unwrap_or_else(|_| "127.0.0.1:3310".to_string());
+    let max_bytes = match std::env::var("CLAMD_MAX_STREAM_BYTES") {
+        Ok(value) => value
+            .parse()
+            .expect("CLAMD_MAX_STREAM_BYTES must be a number of bytes"),
+        Err(_) => MAX_SCAN_BYTES as u64,
+    };
+    Arc::new(ClamdScanner { address, max_bytes })
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use crate::app_state::AppState;
+use crate::handlers::file::scan_pending_files;
+use crate::handlers::reminder::send_due_reminders;
+use crate::handlers::upload::{complete_stalled_uploads, delete_expired_uploads};
+use crate::pipeline::process_pending_files;
+
+// Periodically sends the scheduled reminder emails in the background, and retries the
+// malware scans that could not complete at upload time along with the missing previews,
+// search texts, e-invoice data, bank transactions, FEC reports, invoice fields and categories.
+// Resumable uploads whose assembly stalled are completed, and removed once expired.
+// The interval can be tuned with REMINDER_INTERVAL_SECS (defaults to hourly).
+pub fn spawn(app_state: AppState) {
+    let interval_secs = std::env::var("REMINDER_INTERVAL_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(3600);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = send_due_reminders(&app_state).await {
+                eprintln!("Failed to send due reminders: {}", e);
+            }
+            if let Err(e) = scan_pending_files(&app_state).await {
+                eprintln!("Failed to scan pending files: {}", e);
+            }
+            if let Err(e) = process_pending_files(&app_state).await {
+                eprintln!("Failed to process pending files: {}", e);
+            }
+            if let Err(e) = complete_stalled_uploads(&app_state).await {
+                eprintln!("Failed to complete stalled uploads: {}", e);
+            }
+            if let Err(e) = delete_expired_uploads(&app_state).await {
+                eprintln!("Failed to delete expired uploads: {}", e);
+            }
+        }
+    });
+}
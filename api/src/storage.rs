@@ -0,0 +1,162 @@
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncWriteExt, ReadBuf};
+use uuid::Uuid;
+
+use crate::encryption::{EncryptedStorage, Keyring, MasterKey};
+
+pub type StorageReader = Box<dyn AsyncRead + Send + Unpin>;
+
+// Where uploaded files are kept, addressed by the `storage_key` of their `files` row
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // Stores everything the reader yields and returns the number of bytes written
+    async fn put(
+        &self,
+        key: &str,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> anyhow::Result<u64>;
+
+    async fn get(&self, key: &str) -> anyhow::Result<StorageReader>;
+
+    // Deleting a missing object is not an error
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+}
+
+// Keeps the objects as plain files in a directory
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        let valid = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            && !key.starts_with('.');
+        if !valid {
+            anyhow::bail!("invalid storage key {:?}", key);
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(
+        &self,
+        key: &str,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> anyhow::Result<u64> {
+        let path = self.path(key)?;
+        fs::create_dir_all(&self.root).await?;
+
+        // Written next to its destination then renamed, so that a failed upload leaves no partial object
+        let partial = path.with_extension("part");
+        let mut file = fs::File::create(&partial).await?;
+        let written = match tokio::io::copy(reader, &mut file).await {
+            Ok(written) => written,
+            Err(e) => {
+                drop(file);
+                let _ = fs::remove_file(&partial).await;
+                return Err(e.into());
+            }
+        };
+        file.flush().await?;
+        file.sync_all().await?;
+        fs::rename(&partial, &path).await?;
+
+        Ok(written)
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<StorageReader> {
+        let file = fs::File::open(self.path(key)?).await?;
+        Ok(Box::new(file))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Computes the SHA-256 of everything read through it, so that content is hashed while it is stored
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    // Hex digest of the bytes read so far
+    pub fn sha256(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let already_filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            self.hasher.update(&buf.filled()[already_filled..]);
+        }
+        poll
+    }
+}
+
+// Key of a new object of a firm. Objects derived from it, like its preview, share its prefix,
+// which tells whose keys encrypt them.
+pub fn new_key(firm_id: Uuid) -> String {
+    format!("{}_{}", firm_id, Uuid::new_v4())
+}
+
+// Key of a new object of the same firm as `key`
+pub fn sibling_key(key: &str) -> String {
+    match key_firm(key) {
+        Some(firm_id) => new_key(firm_id),
+        None => Uuid::new_v4().to_string(),
+    }
+}
+
+// Firm of an object, None for those stored before keys were prefixed
+pub fn key_firm(key: &str) -> Option<Uuid> {
+    key.split_once('_')?.0.parse().ok()
+}
+
+// Files are kept in STORAGE_DIR (./storage by default), encrypted when MASTER_KEY is set
+pub fn from_env(db_pool: &PgPool) -> Arc<dyn Storage> {
+    let root = std::env::var("STORAGE_DIR").unwrap_or_else(|_| "storage".to_string());
+    let storage = Arc::new(LocalStorage::new(root));
+    match MasterKey::from_env("MASTER_KEY").expect("MASTER_KEY must be 32 bytes in base64") {
+        Some(master_key) => Arc::new(EncryptedStorage::new(
+            storage,
+            Keyring::new(db_pool.clone(), master_key),
+        )),
+        None => storage,
+    }
+}
@@ -0,0 +1,104 @@
+use bytes::Bytes;
+use lopdf::{Document, Object};
+use std::collections::BTreeMap;
+
+// Longest text kept per file (in bytes), well below the 1MB limit of a tsvector
+pub const MAX_TEXT_BYTES: usize = 200_000;
+
+// Extracts the searchable text of a file, None when its type has no text to read.
+// Scanned PDFs have no text layer and give an empty text.
+pub async fn extract_text(mime_type: &str, content: Bytes) -> anyhow::Result<Option<String>> {
+    let mime_type = mime_type.to_string();
+    // Parsing PDFs is CPU bound
+    tokio::task::spawn_blocking(move || {
+        let text = match mime_type.as_str() {
+            "application/pdf" => pdf_text(&content)?,
+            "text/plain" | "text/csv" => decode_text(&content),
+            _ => return Ok(None),
+        };
+        Ok(Some(clean(&text)))
+    })
+    .await?
+}
+
+// Like lopdf's extract_text, but also breaks lines on the operators moving to the next line,
+// which billing software uses instead of a text object per line. Pages that can't be read
+// are skipped.
+fn pdf_text(content: &[u8]) -> anyhow::Result<String> {
+    let document = Document::load_mem(content)?;
+    let mut text = String::new();
+    for page_id in document.get_pages().into_values() {
+        let encodings: BTreeMap<Vec<u8>, &str> = document
+            .get_page_fonts(page_id)
+            .into_iter()
+            .map(|(name, font)| (name, font.get_font_encoding()))
+            .collect();
+        let Ok(content) = document.get_and_decode_page_content(page_id) else {
+            continue;
+        };
+        let mut encoding = None;
+        for operation in &content.operations {
+            match operation.operator.as_str() {
+                "Tf" => {
+                    encoding = operation
+                        .operands
+                        .first()
+                        .and_then(|font| font.as_name().ok())
+                        .and_then(|font| encodings.get(font).copied());
+                }
+                "Tj" | "TJ" => collect_text(&mut text, encoding, &operation.operands),
+                "'" | "\"" => {
+                    text.push('\n');
+                    collect_text(&mut text, encoding, &operation.operands);
+                }
+                "Td" | "TD" | "T*" | "ET" => text.push('\n'),
+                _ => {}
+            }
+        }
+    }
+    Ok(text)
+}
+
+fn collect_text(text: &mut String, encoding: Option<&str>, operands: &[Object]) {
+    for operand in operands {
+        match operand {
+            Object::String(bytes, _) => text.push_str(&Document::decode_text(encoding, bytes)),
+            Object::Array(items) => collect_text(text, encoding, items),
+            // A large negative kerning in a TJ array is a word space
+            Object::Integer(adjustment) if *adjustment < -100 => text.push(' '),
+            Object::Real(adjustment) if *adjustment < -100.0 => text.push(' '),
+            _ => {}
+        }
+    }
+}
+
+// UTF-8, or Latin-1 for the legacy exports of accounting software
+pub fn decode_text(content: &[u8]) -> String {
+    match std::str::from_utf8(content) {
+        Ok(text) => text.to_string(),
+        Err(_) => content.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+// Drops the characters Postgres can't store and collapses whitespace, keeping the line breaks
+// which the invoice field extraction relies on
+fn clean(text: &str) -> String {
+    let mut cleaned = String::new();
+    for line in text.split(['\n', '\r']) {
+        let mut separator = (!cleaned.is_empty()).then_some('\n');
+        for word in line
+            .split(|c: char| c.is_whitespace() || c.is_control())
+            .filter(|word| !word.is_empty())
+        {
+            if cleaned.len() + word.len() + 1 > MAX_TEXT_BYTES {
+                return cleaned;
+            }
+            if let Some(separator) = separator {
+                cleaned.push(separator);
+            }
+            cleaned.push_str(word);
+            separator = Some(' ');
+        }
+    }
+    cleaned
+}
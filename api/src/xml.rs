@@ -0,0 +1,42 @@
+use roxmltree::Node;
+
+// Helpers to read XML documents (e-invoices, bank statements) by local element names, the
+// namespace prefixes vary between producers
+
+pub trait LocalName {
+    fn has_tag_name_local(&self, name: &str) -> bool;
+}
+
+impl LocalName for Node<'_, '_> {
+    fn has_tag_name_local(&self, name: &str) -> bool {
+        self.is_element() && self.tag_name().name() == name
+    }
+}
+
+pub fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name_local(name))
+}
+
+pub fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.has_tag_name_local(name))
+}
+
+pub fn descendant<'a, 'input>(node: Node<'a, 'input>, path: &[&str]) -> Option<Node<'a, 'input>> {
+    path.iter().try_fold(node, |node, name| child(node, name))
+}
+
+pub fn node_text(node: Node) -> Option<String> {
+    node.text()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+// Trimmed text of the element at the end of the path, None when missing or empty
+pub fn text(node: Node, path: &[&str]) -> Option<String> {
+    descendant(node, path).and_then(node_text)
+}
@@ -0,0 +1,165 @@
+use async_zip::base::read::mem::ZipFileReader;
+use axum::http::{self, StatusCode};
+use serde_json::{json, Value};
+
+use trombone::archive::{archive_paths, sanitize_file_name, ArchiveFile};
+
+mod common;
+
+use common::{create_collection, create_request, get, multipart_upload, post, send, send_raw};
+
+async fn upload(
+    app: &axum::Router,
+    token: &str,
+    request_id: &Value,
+    file_name: &str,
+    content: &str,
+) -> Value {
+    let request_id = request_id.as_str().unwrap();
+    let (status, file) = send(
+        app,
+        multipart_upload(token, request_id, file_name, content.as_bytes()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    file
+}
+
+#[tokio::test]
+async fn test_download_collection_archive() {
+    let (app, token) = common::setup().await;
+    let collection_id = create_collection(&app, &token, "Q4 2025: VAT").await;
+    let invoices = create_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Invoices / sales" }),
+    )
+    .await;
+    let statements = create_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Bank statements" }),
+    )
+    .await;
+
+    upload(&app, &token, &invoices["id"], "invoice.txt", "invoice 1").await;
+    upload(&app, &token, &invoices["id"], "INVOICE.txt", "invoice 2").await;
+    upload(
+        &app,
+        &token,
+        &statements["id"],
+        "../october.txt",
+        "statement",
+    )
+    .await;
+
+    // Lost from storage, only listed in the manifest
+    let lost = upload(&app, &token, &statements["id"], "november.txt", "lost").await;
+    std::fs::remove_file(common::storage_dir().join(lost["storage_key"].as_str().unwrap()))
+        .unwrap();
+
+    // Turned down by the accountant, left out like in exports
+    let rejected = upload(&app, &token, &statements["id"], "wrong-month.txt", "june").await;
+    let (status, _) = send(
+        &app,
+        post(
+            &format!("/files/{}/reject", rejected["id"].as_str().unwrap()),
+            &token,
+            json!({ "reason": "Wrong month." }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, headers, body) = send_raw(
+        &app,
+        get(&format!("/collections/{}/archive", collection_id), &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[http::header::CONTENT_TYPE], "application/zip");
+    assert_eq!(
+        headers[http::header::CONTENT_DISPOSITION],
+        "attachment; filename=\"Q4 2025_ VAT.zip\""
+    );
+
+    let zip = ZipFileReader::new(body).await.unwrap();
+    let mut contents = Vec::new();
+    for index in 0..zip.file().entries().len() {
+        let name = zip.file().entries()[index]
+            .filename()
+            .as_str()
+            .unwrap()
+            .to_string();
+        let mut content = String::new();
+        zip.reader_with_entry(index)
+            .await
+            .unwrap()
+            .read_to_string_checked(&mut content)
+            .await
+            .unwrap();
+        contents.push((name, content));
+    }
+
+    let names: Vec<&str> = contents.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "Invoices _ sales/invoice.txt",
+            "Invoices _ sales/INVOICE (2).txt",
+            "Bank statements/_october.txt",
+            "manifest.csv"
+        ]
+    );
+    assert_eq!(contents[1].1, "invoice 2");
+
+    let manifest = &contents[3].1;
+    assert_eq!(manifest.lines().count(), 5);
+    assert!(manifest
+        .starts_with("path,request_id,request,file_id,file_name,size,sha256,uploaded_at,status"));
+    // sha256("invoice 1")
+    assert!(manifest.contains(
+        "invoice.txt,9,55cd62f7f45dce42d37072c46dedc5ffbf761632fc8d0f04837adb65121bd1c5,"
+    ));
+    let missing = manifest
+        .lines()
+        .find(|line| line.starts_with("Bank statements/november.txt,"))
+        .unwrap();
+    assert!(missing.contains(",november.txt,,,"));
+    assert!(missing.ends_with(",missing"));
+    assert!(!manifest.contains("wrong-month.txt"));
+}
+
+#[test]
+fn test_archive_paths() {
+    let file = |request: u128, request_title: &str, file_name: &str| ArchiveFile {
+        request_id: uuid::Uuid::from_u128(request),
+        request_title: request_title.to_string(),
+        file_id: uuid::Uuid::new_v4(),
+        file_name: file_name.to_string(),
+        storage_key: String::new(),
+        uploaded_at: chrono::Utc::now(),
+    };
+
+    let paths = archive_paths(&[
+        file(1, "Receipts", "a.pdf"),
+        file(1, "Receipts", "a.pdf"),
+        file(2, "receipts", "a.pdf"),
+        file(3, "manifest.csv", "b.pdf"),
+    ]);
+    assert_eq!(
+        paths,
+        [
+            "Receipts/a.pdf",
+            "Receipts/a (2).pdf",
+            "receipts (2)/a.pdf",
+            "manifest (2).csv/b.pdf"
+        ]
+    );
+
+    assert_eq!(
+        sanitize_file_name("  ..\\secret:file?.pdf "),
+        "_secret_file_.pdf"
+    );
+    assert_eq!(sanitize_file_name("..."), "untitled");
+}
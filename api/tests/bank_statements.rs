@@ -0,0 +1,419 @@
+use axum::http::StatusCode;
+use bytes::Bytes;
+use chrono::NaiveDate;
+use serde_json::json;
+use uuid::Uuid;
+
+use trombone::bank_statement::{find_gaps, parse_ofx, parse_statement};
+use trombone::model::bank_statement::{BankStatementSummary, BankTransaction};
+
+mod common;
+
+use common::{create_request, date, get, multipart_upload, send, wait_for_file};
+
+// A collection of the seeded client with one request, returns (collection id, request id)
+async fn create_collection(app: &axum::Router, token: &str) -> (String, String) {
+    let collection_id = common::create_collection(app, token, "Year-end 2026").await;
+    let request = create_request(
+        app,
+        token,
+        json!({ "collection_id": collection_id, "title": "Bank statements" }),
+    )
+    .await;
+    (collection_id, request["id"].as_str().unwrap().to_string())
+}
+
+fn transaction(date: NaiveDate, amount: f64, label: &str, balance: f64) -> BankTransaction {
+    BankTransaction {
+        date,
+        amount,
+        currency: Some("EUR".to_string()),
+        label: label.to_string(),
+        balance: Some(balance),
+    }
+}
+
+// A CAMT.053 statement of one account, entries as (booking date, signed amount, label)
+fn camt053(
+    from: NaiveDate,
+    to: NaiveDate,
+    opening: f64,
+    entries: &[(NaiveDate, f64, &str)],
+) -> String {
+    let balance = |code: &str, amount: f64, date: NaiveDate| {
+        format!(
+            r#"<Bal><Tp><CdOrPrtry><Cd>{}</Cd></CdOrPrtry></Tp><Amt Ccy="EUR">{:.2}</Amt><CdtDbtInd>{}</CdtDbtInd><Dt><Dt>{}</Dt></Dt></Bal>"#,
+            code,
+            amount.abs(),
+            if amount < 0.0 { "DBIT" } else { "CRDT" },
+            date
+        )
+    };
+    let closing = opening + entries.iter().map(|(_, amount, _)| amount).sum::<f64>();
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.08"><BkToCstmrStmt>
+<GrpHdr><MsgId>STMT-{from}</MsgId><CreDtTm>{to}T23:00:00</CreDtTm></GrpHdr>
+<Stmt><Id>{from}</Id><FrToDt><FrDtTm>{from}T00:00:00</FrDtTm><ToDtTm>{to}T23:59:59</ToDtTm></FrToDt>
+<Acct><Id><IBAN>FR7630006000011234567890189</IBAN></Id><Ccy>EUR</Ccy></Acct>
+{}{}"#,
+        balance("OPBD", opening, from),
+        balance("CLBD", closing, to),
+    );
+    for (date, amount, label) in entries {
+        xml.push_str(&format!(
+            r#"<Ntry><Amt Ccy="EUR">{:.2}</Amt><CdtDbtInd>{}</CdtDbtInd><Sts><Cd>BOOK</Cd></Sts><BookgDt><Dt>{}</Dt></BookgDt><NtryDtls><TxDtls><RmtInf><Ustrd>{}</Ustrd></RmtInf></TxDtls></NtryDtls></Ntry>"#,
+            amount.abs(),
+            if *amount < 0.0 { "DBIT" } else { "CRDT" },
+            date,
+            label
+        ));
+    }
+    xml.push_str("</Stmt></BkToCstmrStmt></Document>");
+    xml
+}
+
+async fn upload_statement(
+    app: &axum::Router,
+    token: &str,
+    request_id: &str,
+    file_name: &str,
+    content: &[u8],
+) -> (String, BankStatementSummary) {
+    let (status, file) = send(app, multipart_upload(token, request_id, file_name, content)).await;
+    assert_eq!(status, StatusCode::OK, "{}", file);
+    let file_id = file["id"].as_str().unwrap().to_string();
+    // Statements are parsed in the background after the upload
+    let file = wait_for_file(app, token, &file_id, |file| file.bank_statement.is_some()).await;
+    let statement = file.bank_statement.unwrap();
+    (file_id, statement)
+}
+
+#[tokio::test]
+async fn test_camt053_statement() {
+    let (app, token) = common::setup().await;
+    let (_, request_id) = create_collection(&app, &token).await;
+
+    let xml = camt053(
+        date(2026, 1, 1),
+        date(2026, 1, 31),
+        1000.0,
+        &[
+            (date(2026, 1, 5), -42.9, "CB CARREFOUR"),
+            (date(2026, 1, 20), 1500.0, "VIR SALAIRE JANVIER"),
+        ],
+    );
+    let (file_id, statement) = upload_statement(
+        &app,
+        &token,
+        &request_id,
+        "releve-janvier.xml",
+        xml.as_bytes(),
+    )
+    .await;
+    assert_eq!(
+        statement,
+        BankStatementSummary {
+            format: "camt.053".to_string(),
+            account: Some("FR7630006000011234567890189".to_string()),
+            currency: Some("EUR".to_string()),
+            start_date: Some(date(2026, 1, 1)),
+            end_date: Some(date(2026, 1, 31)),
+            opening_balance: Some(1000.0),
+            closing_balance: Some(2457.1),
+            transaction_count: 2,
+        }
+    );
+
+    let (status, transactions) = send(
+        &app,
+        get(&format!("/files/{}/transactions", file_id), &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let transactions: Vec<BankTransaction> = serde_json::from_value(transactions).unwrap();
+    assert_eq!(
+        transactions,
+        vec![
+            transaction(date(2026, 1, 5), -42.9, "CB CARREFOUR", 957.1),
+            transaction(date(2026, 1, 20), 1500.0, "VIR SALAIRE JANVIER", 2457.1),
+        ]
+    );
+
+    // Other files have no transactions
+    let (status, file) = send(
+        &app,
+        multipart_upload(&token, &request_id, "notes.txt", b"paid in cash"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        get(
+            &format!("/files/{}/transactions", file["id"].as_str().unwrap()),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_statement_gaps() {
+    let (app, token) = common::setup().await;
+    let (collection_id, request_id) = create_collection(&app, &token).await;
+
+    // January, then March: February is missing
+    let (january, _) = upload_statement(
+        &app,
+        &token,
+        &request_id,
+        "2026-01.xml",
+        camt053(
+            date(2026, 1, 1),
+            date(2026, 1, 31),
+            1000.0,
+            &[(date(2026, 1, 10), 100.0, "VIR")],
+        )
+        .as_bytes(),
+    )
+    .await;
+    let (march, _) = upload_statement(
+        &app,
+        &token,
+        &request_id,
+        "2026-03.xml",
+        camt053(
+            date(2026, 3, 1),
+            date(2026, 3, 31),
+            1100.0,
+            &[(date(2026, 3, 10), 100.0, "VIR")],
+        )
+        .as_bytes(),
+    )
+    .await;
+    // April should open at March's closing balance of 1200.00
+    let (april, _) = upload_statement(
+        &app,
+        &token,
+        &request_id,
+        "2026-04.xml",
+        camt053(
+            date(2026, 4, 1),
+            date(2026, 4, 30),
+            1250.0,
+            &[(date(2026, 4, 10), -50.0, "PRLV")],
+        )
+        .as_bytes(),
+    )
+    .await;
+
+    let (status, gaps) = send(
+        &app,
+        get(
+            &format!("/collections/{}/statement-gaps", collection_id),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        gaps,
+        json!([
+            {
+                "kind": "missing_period",
+                "account": "FR7630006000011234567890189",
+                "previous_file_id": january,
+                "next_file_id": march,
+                "missing_from": "2026-02-01",
+                "missing_to": "2026-02-28",
+                "overlap_from": null,
+                "overlap_to": null,
+                "previous_closing_balance": 1100.0,
+                "next_opening_balance": 1100.0
+            },
+            {
+                "kind": "balance_mismatch",
+                "account": "FR7630006000011234567890189",
+                "previous_file_id": march,
+                "next_file_id": april,
+                "missing_from": null,
+                "missing_to": null,
+                "overlap_from": null,
+                "overlap_to": null,
+                "previous_closing_balance": 1200.0,
+                "next_opening_balance": 1250.0
+            }
+        ])
+    );
+}
+
+fn summary(
+    format: &str,
+    period: (NaiveDate, NaiveDate),
+    balances: (f64, f64),
+) -> BankStatementSummary {
+    BankStatementSummary {
+        format: format.to_string(),
+        account: Some("FR7630006000011234567890189".to_string()),
+        currency: Some("EUR".to_string()),
+        start_date: Some(period.0),
+        end_date: Some(period.1),
+        opening_balance: Some(balances.0),
+        closing_balance: Some(balances.1),
+        transaction_count: 1,
+    }
+}
+
+#[test]
+fn test_overlapping_statements() {
+    let january = (date(2026, 1, 1), date(2026, 1, 31));
+    let (first, again, overlapping) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let gaps = find_gaps(vec![
+        (first, summary("camt.053", january, (1000.0, 1100.0))),
+        (again, summary("camt.053", january, (1000.0, 1100.0))),
+        (
+            overlapping,
+            summary(
+                "camt.053",
+                (date(2026, 1, 15), date(2026, 2, 15)),
+                (1050.0, 1200.0),
+            ),
+        ),
+    ]);
+    let kinds: Vec<(&str, Uuid, Option<NaiveDate>, Option<NaiveDate>)> = gaps
+        .iter()
+        .map(|gap| {
+            (
+                gap.kind.as_str(),
+                gap.next_file_id,
+                gap.overlap_from,
+                gap.overlap_to,
+            )
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (
+                "duplicate_statement",
+                again,
+                Some(january.0),
+                Some(january.1)
+            ),
+            (
+                "overlapping_period",
+                overlapping,
+                Some(date(2026, 1, 15)),
+                Some(date(2026, 1, 31))
+            ),
+        ]
+    );
+    assert!(gaps.iter().all(|gap| gap.missing_from.is_none()));
+
+    // CSV exports whose balances chain may share the day they were cut on
+    let gaps = find_gaps(vec![
+        (first, summary("csv", january, (1000.0, 1100.0))),
+        (
+            overlapping,
+            summary(
+                "csv",
+                (date(2026, 1, 31), date(2026, 2, 27)),
+                (1100.0, 1200.0),
+            ),
+        ),
+    ]);
+    assert!(gaps.is_empty(), "{:?}", gaps);
+}
+
+#[tokio::test]
+async fn test_csv_statements() {
+    // A French bank export: account details above the header, latest first, Windows-1252
+    let csv = "Compte courant n° 00012345678\n\
+               Date;Libellé;Débit;Crédit;Solde\n\
+               15/01/2026;PRLV SEPA URSSAF;1 234,56;;2 765,44\n\
+               03/01/2026;VIR REÇU CLIENT DUPONT;;2 000,00;4 000,00\n\
+               Solde au 31/01/2026;;;;2 765,44\n";
+    let latin1: Vec<u8> = csv.chars().map(|c| c as u32 as u8).collect();
+    let statement = parse_statement("text/csv", Bytes::from(latin1))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(statement.summary.format, "csv");
+    assert_eq!(statement.summary.start_date, Some(date(2026, 1, 3)));
+    assert_eq!(statement.summary.end_date, Some(date(2026, 1, 15)));
+    assert_eq!(statement.summary.opening_balance, Some(2000.0));
+    assert_eq!(statement.summary.closing_balance, Some(2765.44));
+    let labels: Vec<(&str, f64)> = statement
+        .transactions
+        .iter()
+        .map(|transaction| (transaction.label.as_str(), transaction.amount))
+        .collect();
+    assert_eq!(
+        labels,
+        vec![
+            ("VIR REÇU CLIENT DUPONT", 2000.0),
+            ("PRLV SEPA URSSAF", -1234.56)
+        ]
+    );
+
+    // An English export with a signed amount and a currency
+    let csv = "Booking Date,Description,Amount,Currency,Balance\n\
+               2026-02-02,\"STRIPE PAYOUT\",\"1,250.00\",EUR,\"3,250.00\"\n\
+               2026-02-05,OVH CLOUD,-19.99,EUR,\"3,230.01\"\n";
+    let statement = parse_statement("text/csv", Bytes::copy_from_slice(csv.as_bytes()))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(statement.summary.currency, Some("EUR".to_string()));
+    assert_eq!(statement.summary.opening_balance, Some(2000.0));
+    assert_eq!(statement.transactions[1].amount, -19.99);
+    assert_eq!(statement.transactions[1].balance, Some(3230.01));
+
+    // Other CSV files are not statements
+    let statement = parse_statement(
+        "text/csv",
+        Bytes::from_static(b"name;email\nAda;ada@example.com\n"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(statement, None);
+}
+
+#[test]
+fn test_ofx_statement() {
+    // OFX 1 is SGML, elements are not closed
+    let ofx = "OFXHEADER:100\nDATA:OFXSGML\nVERSION:102\nCHARSET:1252\n\n\
+<OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS>\n\
+<CURDEF>EUR\n\
+<BANKACCTFROM><BANKID>30004<BRANCHID>00001<ACCTID>00012345678<ACCTTYPE>CHECKING</BANKACCTFROM>\n\
+<BANKTRANLIST><DTSTART>20260201<DTEND>20260228\n\
+<STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20260203120000[+1:CET]<TRNAMT>-19,99<FITID>1<NAME>PRLV FREE MOBILE<MEMO>Forfait</STMTTRN>\n\
+<STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20260210<TRNAMT>850.00<FITID>2<NAME>VIR LOYER &amp; CHARGES</STMTTRN>\n\
+</BANKTRANLIST>\n\
+<LEDGERBAL><BALAMT>1830.01<DTASOF>20260228</LEDGERBAL>\n\
+</STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>\n";
+    let statement = parse_ofx(ofx).unwrap();
+    assert_eq!(
+        statement.summary,
+        BankStatementSummary {
+            format: "ofx".to_string(),
+            account: Some("00012345678".to_string()),
+            currency: Some("EUR".to_string()),
+            start_date: Some(date(2026, 2, 1)),
+            end_date: Some(date(2026, 2, 28)),
+            opening_balance: Some(1000.0),
+            closing_balance: Some(1830.01),
+            transaction_count: 2,
+        }
+    );
+    assert_eq!(
+        statement.transactions,
+        vec![
+            transaction(date(2026, 2, 3), -19.99, "PRLV FREE MOBILE Forfait", 980.01),
+            transaction(date(2026, 2, 10), 850.0, "VIR LOYER & CHARGES", 1830.01),
+        ]
+    );
+
+    assert!(parse_ofx("date;amount").is_err());
+}
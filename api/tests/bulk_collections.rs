@@ -0,0 +1,211 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+mod common;
+
+use common::{get, post, send, SEED_COLLECTION_ID, SEED_FIRM_ID, SEED_USER_ID};
+
+async fn create_test_client(app: &axum::Router, token: &str, company_name: &str) -> String {
+    let (status, client) = send(
+        app,
+        post(
+            "/clients",
+            token,
+            json!({
+                "firm_id": SEED_FIRM_ID,
+                "company_name": company_name,
+                "email": "owner@example.com"
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    client["id"].as_str().unwrap().to_string()
+}
+
+fn result_for<'a>(response: &'a Value, client_id: &str) -> &'a Value {
+    response["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|result| result["client_id"] == client_id)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_bulk_create_collections_is_resumable() {
+    let (app, token) = common::setup().await;
+    let title = format!("Year-end {}", Uuid::new_v4());
+    let bakery = create_test_client(&app, &token, "Bakery").await;
+    let garage = create_test_client(&app, &token, "Garage").await;
+
+    let payload = json!({
+        "user_id": SEED_USER_ID,
+        "title": title,
+        "client_ids": [bakery, garage],
+        "requests": [
+            { "title": "Bank statements", "description": "December" },
+            { "title": "Inventory", "due_date": "2026-01-15" }
+        ],
+        "send_invitations": true
+    });
+    let (status, body) = send(&app, post("/collections/bulk", &token, payload.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["created"], 2);
+    assert_eq!(body["invitations_queued"], 2);
+
+    let collection_id = result_for(&body, &bakery)["collection_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (status, summary) = send(
+        &app,
+        get(&format!("/collections/{}/summary", collection_id), &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["total"], 2);
+
+    // Running it again skips the clients that already have the collection
+    let (status, body) = send(&app, post("/collections/bulk", &token, payload)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["created"], 0);
+    assert_eq!(body["skipped"], 2);
+    assert_eq!(
+        result_for(&body, &bakery)["collection_id"],
+        collection_id.as_str()
+    );
+}
+
+#[tokio::test]
+async fn test_bulk_create_with_unknown_client() {
+    let (app, token) = common::setup().await;
+    let title = format!("Year-end {}", Uuid::new_v4());
+    let bakery = create_test_client(&app, &token, "Bakery").await;
+    let unknown = Uuid::new_v4().to_string();
+
+    let mut payload = json!({
+        "user_id": SEED_USER_ID,
+        "title": title,
+        "client_ids": [bakery, unknown],
+        "requests": [{ "title": "Bank statements" }]
+    });
+
+    // All or nothing by default
+    let (status, _) = send(&app, post("/collections/bulk", &token, payload.clone())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // In batches, the known clients still get their collection
+    payload["batch_size"] = json!(50);
+    let (status, body) = send(&app, post("/collections/bulk", &token, payload)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["created"], 1);
+    assert_eq!(body["failed"], 1);
+    assert_eq!(result_for(&body, &unknown)["error"], "Client not found");
+    assert_eq!(result_for(&body, &bakery)["status"], "created");
+}
+
+#[tokio::test]
+async fn test_bulk_create_from_template_and_filter() {
+    let (app, token) = common::setup().await;
+    let company_name = format!("Florist {}", Uuid::new_v4());
+    let florist = create_test_client(&app, &token, &company_name).await;
+
+    let (status, body) = send(
+        &app,
+        post(
+            "/collections/bulk",
+            &token,
+            json!({
+                "user_id": SEED_USER_ID,
+                "title": "Year-end closing",
+                "client_filter": { "firm_id": SEED_FIRM_ID, "company_name": company_name.to_uppercase() },
+                "template_collection_id": SEED_COLLECTION_ID
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["created"], 1);
+    assert_eq!(body["results"][0]["client_id"], florist.as_str());
+
+    // Without a template nor requests there is nothing to create
+    let (status, _) = send(
+        &app,
+        post(
+            "/collections/bulk",
+            &token,
+            json!({
+                "user_id": SEED_USER_ID,
+                "title": "Year-end closing",
+                "client_ids": [florist]
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_bulk_filter_matches_names_literally() {
+    let (app, token) = common::setup().await;
+    let suffix = Uuid::new_v4();
+    let organic = create_test_client(&app, &token, &format!("Primeurs 100% bio {}", suffix)).await;
+    let other = create_test_client(&app, &token, &format!("Primeurs 1000 bio {}", suffix)).await;
+
+    // % and _ are matched as such, not as wildcards
+    for (company_name, expected) in [
+        (format!("100% BIO {}", suffix), vec![organic.as_str()]),
+        (format!("100_ bio {}", suffix), vec![]),
+        ("%".to_string(), vec![organic.as_str()]),
+    ] {
+        let (status, body) = send(
+            &app,
+            post(
+                "/collections/bulk",
+                &token,
+                json!({
+                    "user_id": SEED_USER_ID,
+                    "title": "Year-end closing",
+                    "client_filter": { "firm_id": SEED_FIRM_ID, "company_name": company_name },
+                    "template_collection_id": SEED_COLLECTION_ID
+                }),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", company_name);
+        let selected: Vec<&str> = body["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["client_id"].as_str().unwrap())
+            .filter(|client_id| [organic.as_str(), other.as_str()].contains(client_id))
+            .collect();
+        assert_eq!(selected, expected, "{}", company_name);
+    }
+}
+
+#[tokio::test]
+async fn test_bulk_create_rejects_empty_filter() {
+    let (app, token) = common::setup().await;
+
+    // Neither an empty filter nor a blank name may select every client
+    for client_filter in [json!({}), json!({ "company_name": "  " })] {
+        let (status, _) = send(
+            &app,
+            post(
+                "/collections/bulk",
+                &token,
+                json!({
+                    "user_id": SEED_USER_ID,
+                    "title": "Year-end closing",
+                    "client_filter": client_filter,
+                    "template_collection_id": SEED_COLLECTION_ID
+                }),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,214 @@
+use axum::http::StatusCode;
+use chrono::NaiveDate;
+use rust_decimal_macros::dec;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use trombone::classifier::{Classifier, Document, RequestCandidate, RuleClassifier};
+use trombone::model::classification::Classification;
+use trombone::model::invoice::{Invoice, InvoiceParty};
+
+mod common;
+
+use common::{create_collection, create_request, multipart_upload, send, wait_for_file};
+
+fn document<'a>(file_name: &'a str, text: &'a str) -> Document<'a> {
+    Document {
+        file_name,
+        mime_type: "text/plain",
+        text: Some(text),
+        invoice: None,
+        bank_statement: None,
+        client_name: "Boulangerie Martin",
+    }
+}
+
+async fn category(document: &Document<'_>) -> String {
+    RuleClassifier.classify(document).await.category
+}
+
+fn candidate(title: &str, description: Option<&str>) -> RequestCandidate {
+    RequestCandidate {
+        id: Uuid::new_v4(),
+        title: title.to_string(),
+        description: description.map(str::to_string),
+    }
+}
+
+#[tokio::test]
+async fn test_classify_text() {
+    let payslip =
+        "BULLETIN DE PAIE\nPériode du 01/03/2026 au 31/03/2026\nSalaire de base 2 100,00\n\
+                   Cotisations salariales 462,00\nNet imposable 1 702,35\nNet à payer 1 638,00";
+    let classification = RuleClassifier
+        .classify(&document("scan.pdf", payslip))
+        .await;
+    assert_eq!(classification.category, "payslip");
+    assert!(classification.confidence > 0.6, "{:?}", classification);
+
+    let tax_notice = "DIRECTION GÉNÉRALE DES FINANCES PUBLIQUES\nAvis d'impôt 2026\n\
+                      Cotisation foncière des entreprises\nMontant à payer : 412 €";
+    assert_eq!(
+        category(&document("avis.pdf", tax_notice)).await,
+        "tax_notice"
+    );
+
+    let contract = "CONTRAT DE PRESTATION DE SERVICES\nEntre les soussignés : Boulangerie Martin, \
+                    ci-après dénommée le Client\nIl a été convenu ce qui suit";
+    assert_eq!(category(&document("doc.pdf", contract)).await, "contract");
+
+    let statement = "Relevé de compte n°42\nIBAN FR76 3000 6000 0112 3456 7890 189\n\
+                     Solde précédent 1 200,00\nNouveau solde 1 450,00";
+    assert_eq!(
+        category(&document("doc.pdf", statement)).await,
+        "bank_statement"
+    );
+
+    // Photos of receipts have no text, their name tells
+    let photo = Document {
+        mime_type: "image/jpeg",
+        text: None,
+        ..document("ticket-restaurant.jpg", "")
+    };
+    assert_eq!(category(&photo).await, "receipt");
+
+    let classification = RuleClassifier
+        .classify(&document("notes.txt", "Rendez-vous mardi à 10h avec Paul"))
+        .await;
+    assert_eq!(
+        classification,
+        Classification {
+            category: "other".to_string(),
+            confidence: 0.5
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_classify_invoices() {
+    // The client's letterhead comes first on the invoices it issues
+    let sale = "Boulangerie Martin\n12 rue des Lilas, Lyon\nFACTURE N° 2026-031\n\
+                Facturé à : Hôtel du Parc\nTotal HT 450,00\nTVA 20% 90,00";
+    assert_eq!(
+        category(&document("2026-031.pdf", sale)).await,
+        "sales_invoice"
+    );
+
+    let purchase = "Minoterie Dupuis SA\nFacture F-8812\nFacturé à : Boulangerie Martin\n\
+                    Farine T65 - 20 sacs\nTotal HT 640,00\nDate d'échéance 30/04/2026";
+    assert_eq!(
+        category(&document("F-8812.pdf", purchase)).await,
+        "purchase_invoice"
+    );
+
+    // File names can tell the side too
+    assert_eq!(
+        category(&document("factures-ventes-mars.pdf", purchase)).await,
+        "sales_invoice"
+    );
+
+    // E-invoices name both parties
+    let invoice = Invoice {
+        format: "ubl".to_string(),
+        profile: None,
+        number: Some("F-8812".to_string()),
+        type_code: Some("380".to_string()),
+        issue_date: NaiveDate::from_ymd_opt(2026, 3, 14),
+        due_date: None,
+        currency: Some("EUR".to_string()),
+        seller: InvoiceParty {
+            name: Some("Minoterie Dupuis SA".to_string()),
+            ..Default::default()
+        },
+        buyer: InvoiceParty {
+            name: Some("BOULANGERIE MARTIN".to_string()),
+            ..Default::default()
+        },
+        net_total: Some(dec!(640.0)),
+        vat_total: Some(dec!(35.2)),
+        gross_total: Some(dec!(675.2)),
+        lines: vec![],
+        validation_errors: vec![],
+    };
+    let e_invoice = Document {
+        mime_type: "application/xml",
+        invoice: Some(&invoice),
+        ..document("F-8812.xml", "")
+    };
+    assert_eq!(
+        RuleClassifier.classify(&e_invoice).await,
+        Classification {
+            category: "purchase_invoice".to_string(),
+            confidence: 0.95
+        }
+    );
+
+    // Requests are matched on their wording, the month of the invoice breaks ties
+    let requests = vec![
+        candidate("Relevés bancaires", None),
+        candidate("Factures d'achat de février", None),
+        candidate(
+            "Factures d'achat de mars",
+            Some("Toutes les factures fournisseurs"),
+        ),
+        candidate("Factures de vente de mars", None),
+    ];
+    let classification = RuleClassifier.classify(&e_invoice).await;
+    assert_eq!(
+        RuleClassifier
+            .suggest_request(&e_invoice, &classification, &requests)
+            .await,
+        Some(requests[2].id)
+    );
+    let other = Classification {
+        category: "other".to_string(),
+        confidence: 0.5,
+    };
+    assert_eq!(
+        RuleClassifier
+            .suggest_request(&e_invoice, &other, &requests)
+            .await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_classify_uploads() {
+    let (app, token) = common::setup().await;
+
+    let collection_id = create_collection(&app, &token, "Payroll 2026").await;
+    let mut request_ids = Vec::new();
+    for title in ["Factures fournisseurs", "Bulletins de paie", "Contrats"] {
+        let request = create_request(
+            &app,
+            &token,
+            json!({ "collection_id": collection_id, "title": title }),
+        )
+        .await;
+        request_ids.push(request["id"].as_str().unwrap().to_string());
+    }
+
+    // A payslip uploaded under the wrong request
+    let (status, file) = send(
+        &app,
+        multipart_upload(
+            &token,
+            &request_ids[0],
+            "mars.txt",
+            "Bulletin de salaire - mars 2026\nSalaire brut 2 400,00\nNet imposable 1 880,10"
+                .as_bytes(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", file);
+    assert_eq!(file["category"], Value::Null);
+
+    let file_id = file["id"].as_str().unwrap();
+    let file = wait_for_file(&app, &token, file_id, |file| file.category.is_some()).await;
+    assert_eq!(file.category.unwrap(), "payslip");
+    assert!(file.category_confidence.unwrap() > 0.5);
+    assert_eq!(
+        file.suggested_request_id.unwrap().to_string(),
+        request_ids[1]
+    );
+}
@@ -8,6 +8,8 @@ use tower::ServiceExt; // for `oneshot`
 
 mod common;
 
+use common::{create_collection, create_request, get, multipart_upload, post, send, FAKE_VIRUS};
+
 async fn create_test_collection(app: &axum::Router, token: &str) -> Value {
     // Add token parameter
     let client_id = "e2b1c3d4-5f6a-7b8c-9d0e-f1a2b3c4d5e6";
@@ -173,3 +175,338 @@ async fn test_delete_collection() {
         .unwrap();
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
+
+async fn create_test_request(app: &axum::Router, token: &str, body: Value) -> Value {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/requests")
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_collection_summary() {
+    let (app, token) = common::setup().await;
+    let collection = create_test_collection(&app, &token).await;
+    let collection_id = collection["id"].as_str().unwrap();
+
+    create_test_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Payroll", "due_date": "2020-01-31" }),
+    )
+    .await;
+    create_test_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Bank statements", "due_date": "2999-01-31" }),
+    )
+    .await;
+    let done = create_test_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Sales invoices" }),
+    )
+    .await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::PATCH)
+                .uri(format!("/requests/{}", done["id"].as_str().unwrap()))
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "status": "fulfilled" })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri(format!("/collections/{}/summary", collection_id))
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(body["total"], 3);
+    assert_eq!(body["done"], 1);
+    assert_eq!(body["pending"], 1);
+    assert_eq!(body["overdue"], 1);
+}
+
+#[tokio::test]
+async fn test_get_collection_with_progress() {
+    let (app, token) = common::setup().await;
+    let collection = create_test_collection(&app, &token).await;
+    let collection_id = collection["id"].as_str().unwrap();
+    assert!(collection.get("progress").is_none());
+
+    create_test_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Payroll" }),
+    )
+    .await;
+    let done = create_test_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Sales invoices" }),
+    )
+    .await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::PATCH)
+                .uri(format!("/requests/{}", done["id"].as_str().unwrap()))
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "status": "fulfilled" })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri(format!("/collections/{}?expand=progress", collection_id))
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+
+    let progress = &body["progress"];
+    assert_eq!(progress["total_requests"], 2);
+    assert_eq!(progress["requests_by_status"]["pending"], 1);
+    assert_eq!(progress["requests_by_status"]["fulfilled"], 1);
+    assert_eq!(progress["completion_percentage"], 50.0);
+    assert_eq!(progress["file_count"], 0);
+    assert!(progress["last_client_activity_at"].is_null());
+}
+
+#[tokio::test]
+async fn test_get_all_collections_with_progress() {
+    let (app, token) = common::setup().await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/collections?expand=progress")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Vec<Value> = serde_json::from_slice(&body).unwrap();
+
+    // The seeded collection holds the seeded file
+    let seeded = body
+        .iter()
+        .find(|c| c["id"] == "c1d2e3f4-5a6b-7c8d-9e0f-a1b2c3d4e5f6")
+        .unwrap();
+    assert!(seeded["progress"]["file_count"].as_i64().unwrap() >= 1);
+    assert!(seeded["progress"]["total_bytes"].as_i64().unwrap() >= 1024);
+    assert!(seeded["progress"]["last_client_activity_at"].is_string());
+}
+
+#[tokio::test]
+async fn test_clone_collection() {
+    let (app, token) = common::setup().await;
+    let source_id = "c1d2e3f4-5a6b-7c8d-9e0f-a1b2c3d4e5f6"; // Seeded, with a file on its request
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri(format!("/collections/{}/clone", source_id))
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::from(
+                    serde_json::to_vec(&json!({
+                        "title": "Default Collection 2026",
+                        "expires_at": "2027-01-31T00:00:00Z",
+                        "carry_over_files": true
+                    }))
+                    .unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let clone: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(clone["title"], "Default Collection 2026");
+    assert_eq!(clone["status"], "pending");
+    assert_eq!(clone["expires_at"], "2027-01-31T00:00:00Z");
+    assert_eq!(clone["client"]["id"], "e2b1c3d4-5f6a-7b8c-9d0e-f1a2b3c4d5e6");
+    assert_ne!(clone["access_token"], "access_token_example");
+
+    // The seeded request is copied, pending, with last period's file as a reference
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/requests")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let requests: Value = serde_json::from_slice(&body).unwrap();
+    let copied = requests
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|request| {
+            request["collection"]["id"] == clone["id"] && request["title"] == "Default Request"
+        })
+        .unwrap();
+    assert_eq!(copied["status"], "pending");
+    assert_eq!(copied["description"], "This is a default request description.");
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri(format!(
+                    "/requests/{}/references",
+                    copied["id"].as_str().unwrap()
+                ))
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let references: Value = serde_json::from_slice(&body).unwrap();
+    // The seeded file was never scanned clean
+    assert_eq!(references, json!([]));
+}
+
+#[tokio::test]
+async fn test_clone_carries_over_clean_files_only() {
+    let (app, token) = common::setup().await;
+    let collection_id = create_collection(&app, &token, "Q1 2026").await;
+    let request = create_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Bank statements" }),
+    )
+    .await;
+    let request_id = request["id"].as_str().unwrap();
+
+    let (status, clean) = send(
+        &app,
+        multipart_upload(&token, request_id, "march.txt", b"March statement"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, rejected) = send(
+        &app,
+        multipart_upload(&token, request_id, "april.txt", b"April statement"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        post(
+            &format!("/files/{}/reject", rejected["id"].as_str().unwrap()),
+            &token,
+            json!({ "reason": "Wrong account." }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        multipart_upload(&token, request_id, "virus.txt", FAKE_VIRUS.as_bytes()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, clone) = send(
+        &app,
+        post(
+            &format!("/collections/{}/clone", collection_id),
+            &token,
+            json!({
+                "title": "Q2 2026",
+                "expires_at": "2026-07-31T00:00:00Z",
+                "carry_over_files": true
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, requests) = send(&app, get("/requests", &token)).await;
+    let copied = requests
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|request| request["collection"]["id"] == clone["id"])
+        .unwrap();
+
+    let (status, references) = send(
+        &app,
+        get(
+            &format!("/requests/{}/references", copied["id"].as_str().unwrap()),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let references = references.as_array().unwrap();
+    assert_eq!(references.len(), 1);
+    assert_eq!(references[0]["id"], clean["id"]);
+}
@@ -0,0 +1,208 @@
+use axum::http::{self, StatusCode};
+use serde_json::{json, Value};
+
+mod common;
+
+use common::{
+    create_request, get, multipart_upload, portal_get, portal_request, post, send, FAKE_VIRUS,
+    SEED_ACCESS_TOKEN, SEED_CLIENT_ID, SEED_USER_ID,
+};
+
+async fn create_test_request(app: &axum::Router, token: &str) -> String {
+    let request = create_request(app, token, json!({ "title": "Bank statements" })).await;
+    request["id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_comment_thread_between_user_and_client() {
+    let (app, token) = common::setup().await;
+    let request_id = create_test_request(&app, &token).await;
+
+    // The accountant asks a question
+    let (status, comment) = send(
+        &app,
+        post(
+            &format!("/requests/{}/comments", request_id),
+            &token,
+            json!({ "body": "We need the statements of both accounts." }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(comment["author"]["type"], "user");
+    assert_eq!(comment["author"]["name"], "Test User");
+    assert_eq!(comment["read_by_recipient"], false);
+
+    // The client answers through the portal
+    let (status, reply) = send(
+        &app,
+        portal_request(
+            http::Method::POST,
+            &format!("/portal/requests/{}/comments", request_id),
+            SEED_ACCESS_TOKEN,
+            json!({ "body": "Which statement do you mean?" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(reply["author"]["type"], "client");
+    assert_eq!(reply["author"]["name"], "Default Client");
+
+    // Replying marked the accountant's comment as read by the client
+    let (status, thread) = send(
+        &app,
+        get(&format!("/requests/{}/comments", request_id), &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let thread = thread.as_array().unwrap();
+    assert_eq!(thread.len(), 2);
+    assert_eq!(thread[0]["read_by_recipient"], true);
+    assert_eq!(thread[1]["read_by_recipient"], false);
+
+    // The accountant reads the reply
+    let (status, _) = send(
+        &app,
+        post(
+            &format!("/requests/{}/comments/read", request_id),
+            &token,
+            Value::Null,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, thread) = send(
+        &app,
+        portal_get(
+            &format!("/portal/requests/{}/comments", request_id),
+            SEED_ACCESS_TOKEN,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(thread[1]["read_by_recipient"], true);
+}
+
+#[tokio::test]
+async fn test_comment_with_attachment() {
+    let (app, token) = common::setup().await;
+
+    let request_id = create_test_request(&app, &token).await;
+    let request_id = request_id.as_str();
+    let (status, file) = send(
+        &app,
+        multipart_upload(&token, request_id, "statement.txt", b"October statement"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file_id = file["id"].as_str().unwrap();
+    let (status, _) = send(
+        &app,
+        multipart_upload(&token, request_id, "virus.txt", FAKE_VIRUS.as_bytes()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (_, files) = send(
+        &app,
+        get(&format!("/requests/{}/files", request_id), &token),
+    )
+    .await;
+    let infected_id = files
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|file| file["file_name"] == "virus.txt")
+        .unwrap()["id"]
+        .clone();
+
+    // The client sees the clean file without its storage details, the infected one not at all
+    let (status, comment) = send(
+        &app,
+        portal_request(
+            http::Method::POST,
+            &format!("/portal/requests/{}/comments", request_id),
+            SEED_ACCESS_TOKEN,
+            json!({
+                "body": "Is this the right one?",
+                "attachment_ids": [file_id, infected_id]
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let attachments = comment["attachments"].as_array().unwrap();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0]["id"], file_id);
+    assert_eq!(attachments[0]["file_name"], "statement.txt");
+    assert!(attachments[0].get("storage_key").is_none());
+    assert!(attachments[0].get("scan_status").is_none());
+    assert!(attachments[0].get("request").is_none());
+
+    let (status, thread) = send(
+        &app,
+        get(&format!("/requests/{}/comments", request_id), &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let attachments = thread[0]["attachments"].as_array().unwrap();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0]["scan_status"], "clean");
+    assert_eq!(attachments[0]["request"]["id"], request_id);
+
+    let (status, _) = send(
+        &app,
+        post(
+            &format!("/requests/{}/comments", request_id),
+            &token,
+            json!({
+                "body": "Unknown attachment",
+                "attachment_ids": ["00000000-0000-0000-0000-000000000000"]
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_portal_requires_valid_access_token() {
+    let (app, token) = common::setup().await;
+    let request_id = create_test_request(&app, &token).await;
+
+    let (status, _) = send(
+        &app,
+        portal_get(
+            &format!("/portal/requests/{}/comments", request_id),
+            "not_a_token",
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // A collection's token does not give access to another collection's requests
+    let (status, collection) = send(
+        &app,
+        post(
+            "/collections",
+            &token,
+            json!({
+                "client_id": SEED_CLIENT_ID,
+                "user_id": SEED_USER_ID,
+                "title": "Q3 2025 VAT"
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        portal_get(
+            &format!("/portal/requests/{}/comments", request_id),
+            collection["access_token"].as_str().unwrap(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -1,18 +1,89 @@
-use chrono::Utc;
+// Shared by the integration tests, each of which only uses some of these helpers
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    http::{self, HeaderMap, Request, StatusCode},
+};
+use chrono::{NaiveDate, Utc};
+use http_body_util::BodyExt;
 use jsonwebtoken::{encode, EncodingKey, Header};
+use lopdf::{dictionary, Document, Object, Stream};
+use serde_json::{json, Value};
 use sqlx::migrate::Migrator;
 use sqlx::Executor;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tower::ServiceExt;
 use uuid::Uuid;
 
 use trombone::app_state::AppState;
 use trombone::auth::Claims;
+use trombone::classifier::RuleClassifier;
+use trombone::encryption::{EncryptedStorage, Keyring, MasterKey};
+use trombone::mailer::LogMailer;
+use trombone::model::file::FileResponse;
+use trombone::scanner::{ScanVerdict, Scanner};
+use trombone::storage::LocalStorage;
 use trombone::{db::setup_database_pool, router::router};
 
 static MIGRATOR: Migrator = sqlx::migrate!();
 
+// Rows of tests/seed.sql
+pub const SEED_FIRM_ID: &str = "a6a7572a-5553-4653-a733-35a0b602790f";
+pub const SEED_CLIENT_ID: &str = "e2b1c3d4-5f6a-7b8c-9d0e-f1a2b3c4d5e6";
+pub const SEED_USER_ID: &str = "b1c2d3e4-5f6a-7b8c-9d0e-f1a2b3c4d5e6";
+pub const SEED_COLLECTION_ID: &str = "c1d2e3f4-5a6b-7c8d-9e0f-a1b2c3d4e5f6";
+pub const SEED_ACCESS_TOKEN: &str = "access_token_example"; // Of the seeded collection
+
+// Encrypts the test storage, so that every test goes through encryption like production
+pub const MASTER_KEY: &str = "dHJvbWJvbmUtdGVzdC1tYXN0ZXIta2V5LTMyLWJ5dGU=";
+
+// Where the test storage keeps its encrypted objects
+pub fn storage_dir() -> PathBuf {
+    std::env::temp_dir().join("trombone-test-storage")
+}
+
+// Content flagged by FakeScanner, like the EICAR test file of real antiviruses
+pub const FAKE_VIRUS: &str =
+    "X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+
+// Content FakeScanner fails to scan, like clamd being unreachable
+pub const FAKE_SCAN_ERROR: &str = "FAKE-SCANNER-ERROR";
+
+// Stands in for clamd: anything containing FAKE_VIRUS is infected
+pub struct FakeScanner;
+
+#[async_trait]
+impl Scanner for FakeScanner {
+    async fn scan(
+        &self,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> anyhow::Result<ScanVerdict> {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).await?;
+        let content = String::from_utf8_lossy(&content);
+        if content.contains(FAKE_SCAN_ERROR) {
+            anyhow::bail!("clamd is unreachable")
+        } else if content.contains(FAKE_VIRUS) {
+            Ok(ScanVerdict::Infected("Eicar-Signature".to_string()))
+        } else {
+            Ok(ScanVerdict::Clean)
+        }
+    }
+}
+
 pub async fn setup() -> (axum::Router, String) {
+    let (app_state, token) = setup_state().await;
+    (router(app_state), token)
+}
+
+// Like setup, for tests that also call the background jobs with the app's state
+pub async fn setup_state() -> (AppState, String) {
     dotenvy::dotenv().ok();
     let pool = setup_database_pool().await;
 
@@ -25,9 +96,18 @@ pub async fn setup() -> (axum::Router, String) {
 
     let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set for tests");
 
+    let storage = Arc::new(EncryptedStorage::new(
+        Arc::new(LocalStorage::new(storage_dir())),
+        Keyring::new(pool.clone(), MasterKey::from_base64(MASTER_KEY).unwrap()),
+    ));
     let app_state = AppState {
         db_pool: pool,
         jwt_secret: jwt_secret.clone(),
+        mailer: Arc::new(LogMailer),
+        storage,
+        scanner: Arc::new(FakeScanner),
+        classifier: Arc::new(RuleClassifier),
+        portal_url: "http://localhost:5173/portal".to_string(),
     };
 
     // Create a test user
@@ -61,5 +141,241 @@ pub async fn setup() -> (axum::Router, String) {
     )
     .unwrap();
 
-    (router(app_state), token)
+    (app_state, token)
+}
+
+// Sends a request to the app, returns its status, headers and body
+pub async fn send_raw(
+    app: &axum::Router,
+    request: Request<Body>,
+) -> (StatusCode, HeaderMap, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, headers, body.to_vec())
+}
+
+// Sends a request to the app, returns its status and JSON body (null when it has none)
+pub async fn send(app: &axum::Router, request: Request<Body>) -> (StatusCode, Value) {
+    let (status, _, body) = send_raw(app, request).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+// Sends a request to the app, returns its status and raw body
+pub async fn send_bytes(app: &axum::Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+    let (status, _, body) = send_raw(app, request).await;
+    (status, body)
+}
+
+pub fn get(uri: &str, token: &str) -> Request<Body> {
+    Request::builder()
+        .method(http::Method::GET)
+        .uri(uri)
+        .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap()
+}
+
+pub fn delete(uri: &str, token: &str) -> Request<Body> {
+    Request::builder()
+        .method(http::Method::DELETE)
+        .uri(uri)
+        .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap()
+}
+
+pub fn json_request(method: http::Method, uri: &str, token: &str, payload: Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+        .body(Body::from(serde_json::to_vec(&payload).unwrap()))
+        .unwrap()
+}
+
+pub fn post(uri: &str, token: &str, payload: Value) -> Request<Body> {
+    json_request(http::Method::POST, uri, token, payload)
+}
+
+pub fn put(uri: &str, token: &str, payload: Value) -> Request<Body> {
+    json_request(http::Method::PUT, uri, token, payload)
+}
+
+pub fn patch(uri: &str, token: &str, payload: Value) -> Request<Body> {
+    json_request(http::Method::PATCH, uri, token, payload)
+}
+
+// A request to the routes open without a token, /register and /login
+pub fn post_public(uri: &str, payload: Value) -> Request<Body> {
+    Request::builder()
+        .method(http::Method::POST)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(serde_json::to_vec(&payload).unwrap()))
+        .unwrap()
+}
+
+// A request of a client through the portal, authorized by its collection's access token
+pub fn portal_request(
+    method: http::Method,
+    uri: &str,
+    access_token: &str,
+    payload: Value,
+) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .header("X-Access-Token", access_token)
+        .body(Body::from(serde_json::to_vec(&payload).unwrap()))
+        .unwrap()
+}
+
+pub fn portal_get(uri: &str, access_token: &str) -> Request<Body> {
+    Request::builder()
+        .method(http::Method::GET)
+        .uri(uri)
+        .header("X-Access-Token", access_token)
+        .body(Body::empty())
+        .unwrap()
+}
+
+// POST /files with the given fields as (name, file name for files, content), in this order
+pub fn multipart(token: &str, fields: &[(&str, Option<&str>, &[u8])]) -> Request<Body> {
+    let mut body = Vec::new();
+    for (name, file_name, content) in fields {
+        body.extend_from_slice(b"--BOUNDARY\r\n");
+        match file_name {
+            Some(file_name) => body.extend_from_slice(
+                format!(
+                    "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                    name, file_name
+                )
+                .as_bytes(),
+            ),
+            None => body.extend_from_slice(
+                format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes(),
+            ),
+        }
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(b"--BOUNDARY--\r\n");
+
+    Request::builder()
+        .method(http::Method::POST)
+        .uri("/files")
+        .header(
+            http::header::CONTENT_TYPE,
+            "multipart/form-data; boundary=BOUNDARY",
+        )
+        .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+// POST /files of one file for a request
+pub fn multipart_upload(
+    token: &str,
+    request_id: &str,
+    file_name: &str,
+    content: &[u8],
+) -> Request<Body> {
+    multipart(
+        token,
+        &[
+            ("request_id", None, request_id.as_bytes()),
+            ("file", Some(file_name), content),
+        ],
+    )
+}
+
+// Creates a request in the seeded collection, unless the payload gives another collection_id
+pub async fn create_request(app: &axum::Router, token: &str, payload: Value) -> Value {
+    let mut body = json!({ "collection_id": SEED_COLLECTION_ID, "title": "Documents" });
+    body.as_object_mut()
+        .unwrap()
+        .extend(payload.as_object().unwrap().clone());
+    let (status, request) = send(app, post("/requests", token, body)).await;
+    assert_eq!(status, StatusCode::OK, "{}", request);
+    request
+}
+
+// Creates a collection of the seeded client, returns its id
+pub async fn create_collection(app: &axum::Router, token: &str, title: &str) -> String {
+    let (status, collection) = send(
+        app,
+        post(
+            "/collections",
+            token,
+            json!({ "client_id": SEED_CLIENT_ID, "user_id": SEED_USER_ID, "title": title }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", collection);
+    collection["id"].as_str().unwrap().to_string()
+}
+
+// Files are processed in the background after their upload: polls the file until `done`
+pub async fn wait_for_file(
+    app: &axum::Router,
+    token: &str,
+    file_id: &str,
+    done: impl Fn(&FileResponse) -> bool,
+) -> FileResponse {
+    for _ in 0..50 {
+        let (status, body) = send(app, get(&format!("/files/{}", file_id), token)).await;
+        assert_eq!(status, StatusCode::OK);
+        let file: FileResponse = serde_json::from_value(body).unwrap();
+        if done(&file) {
+            return file;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("file {} was not processed in time", file_id);
+}
+
+pub fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+// A one-page PDF with a text layer, like the documents produced by billing software
+pub fn text_pdf(lines: &[&str]) -> Vec<u8> {
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+    });
+    let mut operations = String::from("BT /F1 12 Tf 72 760 Td ");
+    for line in lines {
+        operations.push_str(&format!("({}) Tj 0 -16 Td ", line));
+    }
+    operations.push_str("ET");
+    let content_id = document.add_object(Stream::new(dictionary! {}, operations.into_bytes()));
+    let page_id = document.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        "Contents" => content_id,
+        "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+    });
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }),
+    );
+    let catalog_id = document.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    document.trailer.set("Root", catalog_id);
+
+    let mut content = Vec::new();
+    document.save_to(&mut content).unwrap();
+    content
 }
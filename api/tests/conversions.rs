@@ -0,0 +1,308 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use image::{DynamicImage, ImageFormat, RgbImage};
+use lopdf::{Document, Object};
+use serde_json::{json, Value};
+use std::io::Cursor;
+
+use trombone::handlers::file::{scan_pending_files, MAX_COMBINED_PHOTOS};
+use trombone::model::file::FileResponse;
+use trombone::router::router;
+
+mod common;
+
+use common::{
+    create_request, delete, get, multipart, post, send, send_bytes, FAKE_VIRUS, SEED_COLLECTION_ID,
+};
+
+// POST /files of several files at once
+fn upload_files(token: &str, request_id: &str, files: &[(&str, &[u8])]) -> Request<Body> {
+    let mut fields = vec![("request_id", None, request_id.as_bytes())];
+    fields.extend(
+        files
+            .iter()
+            .map(|(file_name, content)| ("file", Some(*file_name), *content)),
+    );
+    multipart(token, &fields)
+}
+
+fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+    let image = RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+    });
+    let mut content = Vec::new();
+    DynamicImage::ImageRgb8(image)
+        .write_to(&mut Cursor::new(&mut content), format)
+        .unwrap();
+    content
+}
+
+// A JPEG as taken by a phone held upright: stored sideways with EXIF orientation 6 (rotate 90°)
+fn sideways_jpeg(width: u32, height: u32) -> Vec<u8> {
+    let jpeg = encode(width, height, ImageFormat::Jpeg);
+    let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+    exif.extend_from_slice(b"\x01\x12\0\x03\0\0\0\x01\0\x06\0\0"); // Orientation, SHORT, 1, 6
+    exif.extend_from_slice(b"\0\0\0\0");
+
+    let mut content = jpeg[..2].to_vec(); // SOI
+    content.extend_from_slice(b"\xFF\xE1");
+    content.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+    content.extend_from_slice(&exif);
+    content.extend_from_slice(&jpeg[2..]);
+    content
+}
+
+// (page width, page height, image width, image height) of every page
+fn pdf_pages(pdf: &[u8]) -> Vec<(f32, f32, i64, i64)> {
+    let document = Document::load_mem(pdf).unwrap();
+    document
+        .get_pages()
+        .into_values()
+        .map(|page_id| {
+            let page = document.get_dictionary(page_id).unwrap();
+            let media_box = page.get(b"MediaBox").unwrap().as_array().unwrap();
+            let number = |object: &Object| object.as_float().unwrap();
+            let resources = page.get(b"Resources").unwrap().as_dict().unwrap();
+            let xobjects = resources.get(b"XObject").unwrap().as_dict().unwrap();
+            let image_id = xobjects.get(b"Im0").unwrap().as_reference().unwrap();
+            let image = &document
+                .get_object(image_id)
+                .unwrap()
+                .as_stream()
+                .unwrap()
+                .dict;
+            (
+                number(&media_box[2]),
+                number(&media_box[3]),
+                image.get(b"Width").unwrap().as_i64().unwrap(),
+                image.get(b"Height").unwrap().as_i64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_combine_photos_into_pdf() {
+    let (app, token) = common::setup().await;
+    let request = create_request(
+        &app,
+        &token,
+        json!({ "convert_images_to_pdf": true, "combine_images": true, "allowed_types": ["application/pdf"] }),
+    )
+    .await;
+    assert_eq!(request["convert_images_to_pdf"], true);
+    assert_eq!(request["combine_images"], true);
+    let request_id = request["id"].as_str().unwrap();
+
+    let sideways = sideways_jpeg(400, 300);
+    let landscape = encode(600, 400, ImageFormat::Png);
+    let (status, body) = send_bytes(
+        &app,
+        upload_files(
+            &token,
+            request_id,
+            &[("receipt.jpg", &sideways), ("ticket.png", &landscape)],
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    let pdf: FileResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(pdf.file_name, "receipt.pdf");
+    assert_eq!(pdf.mime_type, "application/pdf");
+    assert_eq!(pdf.converted_into, None);
+
+    // The photo is turned upright on a portrait page, the landscape one gets a landscape page
+    let (status, content) =
+        send_bytes(&app, get(&format!("/files/{}/download", pdf.id), &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        pdf_pages(&content),
+        vec![(595.0, 842.0, 300, 400), (842.0, 595.0, 600, 400)]
+    );
+
+    // The photos are kept as originals of the PDF, which is the only file listed
+    let (status, body) =
+        send_bytes(&app, get(&format!("/files/{}/originals", pdf.id), &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    let originals: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    let names: Vec<&str> = originals
+        .iter()
+        .map(|file| file["file_name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["receipt.jpg", "ticket.png"]);
+    assert!(originals
+        .iter()
+        .all(|file| file["converted_into"] == json!(pdf.id)));
+    assert_eq!(originals[0]["mime_type"], "image/jpeg");
+
+    let (_, body) = send_bytes(
+        &app,
+        get(&format!("/requests/{}/files", request_id), &token),
+    )
+    .await;
+    let files: Vec<FileResponse> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].id, pdf.id);
+
+    // Deleting the PDF deletes its originals
+    let (status, _) = send_bytes(&app, delete(&format!("/files/{}", pdf.id), &token)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let original_id = originals[0]["id"].as_str().unwrap();
+    let (status, _) = send_bytes(&app, get(&format!("/files/{}", original_id), &token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_convert_photos_one_at_a_time() {
+    let (app, token) = common::setup().await;
+    let request = create_request(&app, &token, json!({ "convert_images_to_pdf": true })).await;
+    let request_id = request["id"].as_str().unwrap();
+
+    let photo = encode(300, 400, ImageFormat::Jpeg);
+    let (status, body) = send_bytes(
+        &app,
+        upload_files(&token, request_id, &[("IMG_0001.JPG", &photo)]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let pdf: FileResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(pdf.file_name, "IMG_0001.pdf");
+    assert_eq!(pdf.mime_type, "application/pdf");
+
+    // Photos are not combined unless the request says so
+    let (status, _) = send_bytes(
+        &app,
+        upload_files(&token, request_id, &[("a.jpg", &photo), ("b.jpg", &photo)]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Other files are kept as they are
+    let (status, body) = send_bytes(
+        &app,
+        upload_files(&token, request_id, &[("notes.txt", b"paid in cash")]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(file.mime_type, "text/plain");
+
+    // Combining requires converting
+    let (status, _) = send_bytes(
+        &app,
+        post(
+            "/requests",
+            &token,
+            json!({ "collection_id": SEED_COLLECTION_ID, "title": "Receipts", "combine_images": true }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_combined_photos_are_scanned_first() {
+    let (app, token) = common::setup().await;
+    let request = create_request(
+        &app,
+        &token,
+        json!({ "convert_images_to_pdf": true, "combine_images": true }),
+    )
+    .await;
+    let request_id = request["id"].as_str().unwrap();
+    let photo = encode(300, 400, ImageFormat::Jpeg);
+
+    // Rejected before being decoded, and nothing is kept
+    let mut infected = photo.clone();
+    infected.extend_from_slice(FAKE_VIRUS.as_bytes());
+    let (status, message) = send_bytes(
+        &app,
+        upload_files(
+            &token,
+            request_id,
+            &[("a.jpg", &photo), ("b.jpg", &infected)],
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        String::from_utf8(message).unwrap(),
+        "The file was rejected: Eicar-Signature detected."
+    );
+    let (_, files) = send(
+        &app,
+        get(&format!("/requests/{}/files", request_id), &token),
+    )
+    .await;
+    assert_eq!(files, json!([]));
+
+    let photos: Vec<(&str, &[u8])> = vec![("photo.jpg", &photo); MAX_COMBINED_PHOTOS + 1];
+    let (status, message) = send_bytes(&app, upload_files(&token, request_id, &photos)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        String::from_utf8(message).unwrap(),
+        format!("At most {} photos can be combined.", MAX_COMBINED_PHOTOS)
+    );
+}
+
+#[tokio::test]
+async fn test_infected_photo_quarantines_its_pdf() {
+    let (app_state, token) = common::setup_state().await;
+    let app = router(app_state.clone());
+    let request = create_request(
+        &app,
+        &token,
+        json!({ "convert_images_to_pdf": true, "combine_images": true }),
+    )
+    .await;
+    let request_id = request["id"].as_str().unwrap();
+    let photo = encode(300, 400, ImageFormat::Jpeg);
+    let (status, pdf) = send(
+        &app,
+        upload_files(&token, request_id, &[("a.jpg", &photo), ("b.jpg", &photo)]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(pdf["scan_status"], "clean");
+    let pdf_id = pdf["id"].as_str().unwrap();
+    let (_, originals) = send(&app, get(&format!("/files/{}/originals", pdf_id), &token)).await;
+    assert!(originals
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|original| original["scan_status"] == "clean"));
+
+    // Found infected by a later scan, with newer signatures
+    let original = &originals[0];
+    app_state
+        .storage
+        .put(
+            original["storage_key"].as_str().unwrap(),
+            &mut FAKE_VIRUS.as_bytes(),
+        )
+        .await
+        .unwrap();
+    let original_id: uuid::Uuid = original["id"].as_str().unwrap().parse().unwrap();
+    sqlx::query!(
+        "UPDATE files SET scan_status = 'pending' WHERE id = $1",
+        original_id
+    )
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+    scan_pending_files(&app_state).await.unwrap();
+
+    let (_, pdf) = send(&app, get(&format!("/files/{}", pdf_id), &token)).await;
+    assert_eq!(pdf["scan_status"], "infected");
+    assert_eq!(pdf["scan_result"], "Eicar-Signature");
+    let (status, _) = send_bytes(&app, get(&format!("/files/{}/download", pdf_id), &token)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (_, originals) = send(&app, get(&format!("/files/{}/originals", pdf_id), &token)).await;
+    assert!(originals
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|original| original["scan_status"] == "infected"));
+}
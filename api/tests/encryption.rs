@@ -0,0 +1,431 @@
+use axum::http::StatusCode;
+use serde_json::json;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+use uuid::Uuid;
+
+use trombone::db::setup_database_pool;
+use trombone::encryption::{
+    decrypt, encrypt, generate_key, plaintext_size, EncryptedStorage, Keyring, MasterKey,
+};
+use trombone::preview::preview_key;
+use trombone::storage::{LocalStorage, Storage};
+
+mod common;
+
+use common::{create_request, get, multipart, post, send, send_bytes, SEED_USER_ID};
+
+// A payslip spanning several encrypted chunks
+fn payslip() -> Vec<u8> {
+    "Bulletin de paie - Mars 2026 - Salaire net : 2 345,67 EUR\n"
+        .repeat(3000)
+        .into_bytes()
+}
+
+async fn decrypted(key: &[u8; 32], encrypted: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    let mut reader = decrypt(key, Box::new(std::io::Cursor::new(encrypted)))
+        .await
+        .map_err(std::io::Error::other)?;
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content).await?;
+    Ok(content)
+}
+
+#[tokio::test]
+async fn test_encrypt_and_decrypt() {
+    let key = generate_key();
+    // Empty, exactly one chunk, a chunk and a byte
+    for size in [0, 64 * 1024, 64 * 1024 + 1, 200_000] {
+        let content: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        let mut encrypted = Vec::new();
+        encrypt(&key, Uuid::new_v4(), &mut content.as_slice())
+            .read_to_end(&mut encrypted)
+            .await
+            .unwrap();
+        assert_eq!(plaintext_size(encrypted.len() as u64), size as u64);
+        assert_eq!(decrypted(&key, encrypted.clone()).await.unwrap(), content);
+
+        // Truncated after a whole chunk, cut within one, or tampered with
+        if size > 64 * 1024 {
+            let truncated = encrypted[..5 + 16 + 64 * 1024 + 16].to_vec();
+            assert!(decrypted(&key, truncated).await.is_err());
+        }
+        let cut = encrypted[..encrypted.len() - 1].to_vec();
+        assert!(decrypted(&key, cut).await.is_err());
+        let mut tampered = encrypted.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(decrypted(&key, tampered).await.is_err());
+        assert!(decrypted(&generate_key(), encrypted).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_files_encrypted_at_rest() {
+    let (app, token) = common::setup().await;
+    let request = create_request(&app, &token, json!({ "title": "Bulletins de paie" })).await;
+    let request_id = request["id"].as_str().unwrap();
+
+    // Files sent before the request are encrypted once its firm is known
+    let (status, early) = send(
+        &app,
+        multipart(
+            &token,
+            &[
+                ("file", Some("paie.txt"), b"Salaire de mars"),
+                ("request_id", None, request_id.as_bytes()),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", early);
+    let stored =
+        std::fs::read(common::storage_dir().join(early["storage_key"].as_str().unwrap())).unwrap();
+    assert!(stored.starts_with(b"TRBE2"));
+    let (status, downloaded) = send_bytes(
+        &app,
+        get(
+            &format!("/files/{}/download", early["id"].as_str().unwrap()),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(downloaded, b"Salaire de mars");
+
+    let content = payslip();
+    let (status, file) = send(
+        &app,
+        multipart(
+            &token,
+            &[
+                ("request_id", None, request_id.as_bytes()),
+                ("file", Some("paie-mars.txt"), &content),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", file);
+    assert_eq!(file["file_size"], content.len());
+
+    let stored =
+        std::fs::read(common::storage_dir().join(file["storage_key"].as_str().unwrap())).unwrap();
+    assert!(stored.starts_with(b"TRBE2"));
+    assert!(!String::from_utf8_lossy(&stored).contains("Bulletin de paie"));
+    assert_eq!(plaintext_size(stored.len() as u64), content.len() as u64);
+
+    let (status, downloaded) = send_bytes(
+        &app,
+        get(
+            &format!("/files/{}/download", file["id"].as_str().unwrap()),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(downloaded, content);
+}
+
+#[tokio::test]
+async fn test_rotate_keys() {
+    let (app, token) = common::setup().await;
+
+    // A firm of its own, whose master key is replaced without affecting other tests
+    let (_, firm) = send(
+        &app,
+        post("/firms", &token, json!({ "name": "Rotations & Co" })),
+    )
+    .await;
+    let firm_id: Uuid = firm["id"].as_str().unwrap().parse().unwrap();
+    let (_, client) = send(
+        &app,
+        post(
+            "/clients",
+            &token,
+            json!({
+                "firm_id": firm_id,
+                "company_name": "Bakery",
+                "email": "bakery@example.com"
+            }),
+        ),
+    )
+    .await;
+    let (_, collection) = send(
+        &app,
+        post(
+            "/collections",
+            &token,
+            json!({
+                "client_id": client["id"],
+                "user_id": SEED_USER_ID,
+                "title": "Paie 2026"
+            }),
+        ),
+    )
+    .await;
+    let (_, request) = send(
+        &app,
+        post(
+            "/requests",
+            &token,
+            json!({ "collection_id": collection["id"], "title": "Bulletins de paie" }),
+        ),
+    )
+    .await;
+
+    let content = payslip();
+    let (status, file) = send(
+        &app,
+        multipart(
+            &token,
+            &[
+                (
+                    "request_id",
+                    None,
+                    request["id"].as_str().unwrap().as_bytes(),
+                ),
+                ("file", Some("paie-mars.txt"), &content),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", file);
+    let storage_key = file["storage_key"].as_str().unwrap().to_string();
+    let path = common::storage_dir().join(&storage_key);
+    let stored = std::fs::read(&path).unwrap();
+
+    let pool = setup_database_pool().await;
+    let wrapped_key = |pool| {
+        sqlx::query_scalar!(
+            "SELECT wrapped_key FROM data_keys WHERE storage_key = $1",
+            storage_key
+        )
+        .fetch_one(pool)
+    };
+    let before = wrapped_key(&pool).await.unwrap();
+
+    let old_master_key = MasterKey::from_base64(common::MASTER_KEY).unwrap();
+    let new_master_key =
+        MasterKey::from_base64("bmV3LXRyb21ib25lLW1hc3Rlci1rZXktMzItYnl0ZXM=").unwrap();
+    let keyring = Keyring::new(pool.clone(), new_master_key.clone());
+
+    // The firm key is still wrapped with the old master key
+    assert!(keyring.rotate_firm(firm_id, None).await.is_err());
+    let rotated = keyring
+        .rotate_firm(firm_id, Some(&old_master_key))
+        .await
+        .unwrap();
+    assert!(rotated >= 1);
+    assert_ne!(wrapped_key(&pool).await.unwrap(), before);
+
+    // Only the keys changed: the object reads the same with the new master key
+    assert_eq!(std::fs::read(&path).unwrap(), stored);
+    let storage = EncryptedStorage::new(
+        Arc::new(LocalStorage::new(common::storage_dir())),
+        keyring.clone(),
+    );
+    let mut decrypted = Vec::new();
+    storage
+        .get(&storage_key)
+        .await
+        .unwrap()
+        .read_to_end(&mut decrypted)
+        .await
+        .unwrap();
+    assert_eq!(decrypted, content);
+    assert!(Keyring::new(pool.clone(), old_master_key)
+        .rotate_firm(firm_id, None)
+        .await
+        .is_err());
+
+    // Rotated again with the new master key alone
+    keyring.rotate_firm(firm_id, None).await.unwrap();
+    let mut decrypted = Vec::new();
+    storage
+        .get(&storage_key)
+        .await
+        .unwrap()
+        .read_to_end(&mut decrypted)
+        .await
+        .unwrap();
+    assert_eq!(decrypted, content);
+}
+
+#[tokio::test]
+async fn test_objects_stored_before_encryption() {
+    let (app, token) = common::setup().await;
+    let request = create_request(&app, &token, json!({ "title": "Bulletins de paie" })).await;
+    let (status, file) = send(
+        &app,
+        multipart(
+            &token,
+            &[
+                (
+                    "request_id",
+                    None,
+                    request["id"].as_str().unwrap().as_bytes(),
+                ),
+                ("file", Some("paie-avril.txt"), b"Salaire d'avril"),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", file);
+
+    // A file stored in plain text, under a key without its firm, before encryption was enabled
+    let pool = setup_database_pool().await;
+    let legacy_key = Uuid::new_v4().to_string();
+    let plain = LocalStorage::new(common::storage_dir());
+    plain
+        .put(&legacy_key, &mut b"Salaire de janvier".as_slice())
+        .await
+        .unwrap();
+    sqlx::query!(
+        "UPDATE files SET storage_key = $1 WHERE id = $2",
+        legacy_key,
+        file["id"].as_str().unwrap().parse::<Uuid>().unwrap()
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let storage = EncryptedStorage::new(
+        Arc::new(LocalStorage::new(common::storage_dir())),
+        Keyring::new(
+            pool.clone(),
+            MasterKey::from_base64(common::MASTER_KEY).unwrap(),
+        ),
+    );
+    let read = |key: String| {
+        let storage = &storage;
+        async move {
+            let mut content = Vec::new();
+            storage.get(&key).await?.read_to_end(&mut content).await?;
+            anyhow::Ok(content)
+        }
+    };
+    assert_eq!(
+        read(legacy_key.clone()).await.unwrap(),
+        b"Salaire de janvier"
+    );
+
+    // Its preview is encrypted with the keys of the firm of its file
+    let preview = preview_key(&legacy_key);
+    storage
+        .put(&preview, &mut b"preview".as_slice())
+        .await
+        .unwrap();
+    assert!(std::fs::read(common::storage_dir().join(&preview))
+        .unwrap()
+        .starts_with(b"TRBE2"));
+    assert_eq!(read(preview.clone()).await.unwrap(), b"preview");
+    assert!(storage
+        .put(&Uuid::new_v4().to_string(), &mut b"orphan".as_slice())
+        .await
+        .is_err());
+
+    // An encrypted object whose key is lost is not served as it is
+    sqlx::query!("DELETE FROM data_keys WHERE storage_key = $1", preview)
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert!(read(preview).await.is_err());
+}
+
+// Fails after its first bytes, like a client disconnecting during an upload
+struct Interrupted(bool);
+
+impl AsyncRead for Interrupted {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.0 {
+            return Poll::Ready(Err(std::io::Error::other("connection reset")));
+        }
+        self.0 = true;
+        buf.put_slice(b"half a");
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn test_rewritten_objects() {
+    let (app, token) = common::setup().await;
+    let request = create_request(&app, &token, json!({ "title": "Bulletins de paie" })).await;
+    let (status, file) = send(
+        &app,
+        multipart(
+            &token,
+            &[
+                (
+                    "request_id",
+                    None,
+                    request["id"].as_str().unwrap().as_bytes(),
+                ),
+                ("file", Some("paie-mai.txt"), b"Salaire de mai"),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", file);
+
+    let pool = setup_database_pool().await;
+    let storage = EncryptedStorage::new(
+        Arc::new(LocalStorage::new(common::storage_dir())),
+        Keyring::new(
+            pool.clone(),
+            MasterKey::from_base64(common::MASTER_KEY).unwrap(),
+        ),
+    );
+    let read = |key: String| {
+        let storage = &storage;
+        async move {
+            let mut content = Vec::new();
+            storage.get(&key).await?.read_to_end(&mut content).await?;
+            anyhow::Ok(content)
+        }
+    };
+    let data_keys = |key: String| {
+        let pool = &pool;
+        async move {
+            sqlx::query_scalar!(
+                r#"SELECT COUNT(*) as "count!" FROM data_keys WHERE storage_key = $1"#,
+                key
+            )
+            .fetch_one(pool)
+            .await
+            .unwrap()
+        }
+    };
+
+    // A preview rendered again whose write fails leaves the previous one readable
+    let preview = preview_key(file["storage_key"].as_str().unwrap());
+    storage
+        .put(&preview, &mut b"first preview".as_slice())
+        .await
+        .unwrap();
+    assert!(storage
+        .put(&preview, &mut Interrupted(false))
+        .await
+        .is_err());
+    assert_eq!(read(preview.clone()).await.unwrap(), b"first preview");
+    assert_eq!(data_keys(preview.clone()).await, 1);
+
+    // Once written, only the key of the new content is kept
+    storage
+        .put(&preview, &mut b"second preview".as_slice())
+        .await
+        .unwrap();
+    assert_eq!(read(preview.clone()).await.unwrap(), b"second preview");
+    assert_eq!(data_keys(preview.clone()).await, 1);
+
+    // Objects encrypted before they named their key are read with the key of their storage key
+    let path = common::storage_dir().join(&preview);
+    let named = std::fs::read(&path).unwrap();
+    std::fs::write(&path, [b"TRBE1".as_slice(), &named[5 + 16..]].concat()).unwrap();
+    assert_eq!(read(preview).await.unwrap(), b"second preview");
+}
@@ -0,0 +1,317 @@
+use async_zip::base::read::mem::ZipFileReader;
+use axum::http::{self, StatusCode};
+use chrono::Utc;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde_json::json;
+use uuid::Uuid;
+
+use trombone::export::{export_entries, CsvExporter, Exporter, FecExporter};
+use trombone::fec::read_fec;
+use trombone::model::export::ExportDocument;
+use trombone::model::invoice::{ExtractedField, Invoice, InvoiceFields, InvoiceParty};
+
+mod common;
+
+use common::{
+    create_collection, create_request, date, get, multipart_upload, post, send, send_bytes,
+    send_raw, text_pdf, wait_for_file,
+};
+
+fn field<T>(value: T) -> Option<ExtractedField<T>> {
+    Some(ExtractedField {
+        value,
+        confidence: 0.9,
+        corrected: false,
+    })
+}
+
+fn document(file_name: &str, category: Option<&str>) -> ExportDocument {
+    ExportDocument {
+        file_id: Uuid::new_v4(),
+        request_title: "Invoices".to_string(),
+        file_name: file_name.to_string(),
+        storage_key: String::new(),
+        uploaded_at: Utc::now(),
+        category: category.map(str::to_string),
+        invoice: None,
+        invoice_fields: None,
+    }
+}
+
+fn einvoice(number: &str, type_code: &str, net: Decimal, vat: Decimal) -> Invoice {
+    Invoice {
+        format: "ubl".to_string(),
+        profile: None,
+        number: Some(number.to_string()),
+        type_code: Some(type_code.to_string()),
+        issue_date: Some(date(2026, 2, 10)),
+        due_date: Some(date(2026, 3, 10)),
+        currency: Some("EUR".to_string()),
+        seller: InvoiceParty {
+            name: Some("Metro Cash & Carry".to_string()),
+            siren: Some("552100554".to_string()),
+            vat_number: Some("FR96552100554".to_string()),
+        },
+        buyer: InvoiceParty {
+            name: Some("Default Client".to_string()),
+            ..InvoiceParty::default()
+        },
+        net_total: Some(net),
+        vat_total: Some(vat),
+        gross_total: Some(net + vat),
+        lines: vec![],
+        validation_errors: vec![],
+    }
+}
+
+// An e-invoice and a credit note from a supplier, a sales invoice read from a PDF and a file
+// which is not an invoice
+fn documents() -> Vec<ExportDocument> {
+    let purchase = ExportDocument {
+        invoice: Some(einvoice("F-101", "380", dec!(100.0), dec!(20.0))),
+        ..document("metro.xml", Some("purchase_invoice"))
+    };
+    let credit_note = ExportDocument {
+        invoice: Some(einvoice("AV-7", "381", dec!(10.0), dec!(2.0))),
+        ..document("avoir.xml", Some("purchase_invoice"))
+    };
+    let sale = ExportDocument {
+        invoice_fields: Some(InvoiceFields {
+            number: field("2026-118".to_string()),
+            issue_date: field(date(2026, 1, 5)),
+            supplier_name: field("Default Client".to_string()),
+            net_total: field(dec!(500.0)),
+            vat_total: field(dec!(100.0)),
+            gross_total: field(dec!(600.0)),
+            ..InvoiceFields::default()
+        }),
+        ..document("scan 12.pdf", Some("sales_invoice"))
+    };
+    vec![
+        purchase,
+        credit_note,
+        sale,
+        document("Kbis.pdf", Some("other")),
+    ]
+}
+
+#[test]
+fn test_export_entries_and_csv() {
+    let documents = documents();
+    let (paths, entries) = export_entries(&documents, false);
+    assert_eq!(
+        paths,
+        vec![
+            "documents/2026-02-10 Metro Cash & Carry F-101.xml",
+            "documents/2026-02-10 Metro Cash & Carry AV-7.xml",
+            "documents/2026-01-05 2026-118.pdf",
+            "documents/Kbis.pdf",
+        ]
+    );
+    assert_eq!(entries.len(), 3);
+    assert!(entries[2].sale);
+    assert_eq!(entries[2].counterparty, None); // The supplier of a sales invoice is the client
+
+    let csv = String::from_utf8(CsvExporter.write(&entries).unwrap()).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "document,file_id,request,type,source,number,issue_date,due_date,counterparty,siren,\
+vat_number,currency,net_total,vat_total,gross_total"
+    );
+    assert_eq!(
+        lines[2],
+        format!(
+            "documents/2026-02-10 Metro Cash & Carry AV-7.xml,{},Invoices,purchase,e-invoice,AV-7,\
+2026-02-10,2026-03-10,Metro Cash & Carry,552100554,FR96552100554,EUR,-10.00,-2.00,-12.00",
+            documents[1].file_id
+        )
+    );
+    assert_eq!(
+        lines[3],
+        format!(
+            "documents/2026-01-05 2026-118.pdf,{},Invoices,sale,extracted,2026-118,2026-01-05,,,,,,\
+500.00,100.00,600.00",
+            documents[2].file_id
+        )
+    );
+}
+
+#[test]
+fn test_fec_draft() {
+    let (_, entries) = export_entries(&documents(), false);
+    let draft = String::from_utf8(FecExporter.write(&entries).unwrap()).unwrap();
+    let lines: Vec<Vec<&str>> = draft
+        .lines()
+        .skip(1)
+        .map(|line| line.split('\t').collect())
+        .collect();
+    // Journal, number, account, auxiliary account, piece, debit and credit
+    let summary: Vec<[&str; 7]> = lines
+        .iter()
+        .map(|line| {
+            [
+                line[0], line[2], line[4], line[6], line[8], line[11], line[12],
+            ]
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ["AC", "1", "401000", "METROCASHC", "F-101", "0,00", "120,00"],
+            ["AC", "1", "607000", "", "F-101", "100,00", "0,00"],
+            ["AC", "1", "445660", "", "F-101", "20,00", "0,00"],
+            ["AC", "2", "401000", "METROCASHC", "AV-7", "12,00", "0,00"],
+            ["AC", "2", "607000", "", "AV-7", "0,00", "10,00"],
+            ["AC", "2", "445660", "", "AV-7", "0,00", "2,00"],
+            ["VE", "1", "411000", "", "2026-118", "600,00", "0,00"],
+            ["VE", "1", "706000", "", "2026-118", "0,00", "500,00"],
+            ["VE", "1", "445710", "", "2026-118", "0,00", "100,00"],
+        ]
+    );
+    assert_eq!(lines[0][3], "20260210");
+    assert_eq!(lines[0][10], "Facture Metro Cash & Carry F-101");
+
+    // The draft reads as an FEC whose entries balance, only waiting to be validated
+    let fec = read_fec("552100554FEC20261231.txt", &draft).unwrap();
+    assert_eq!(fec.report.entry_count, 3);
+    assert!(fec
+        .report
+        .errors
+        .iter()
+        .all(|issue| issue.message == "ValidDate is missing"));
+    assert_eq!(fec.summary.total_debit, 732.0);
+    assert_eq!(fec.summary.total_credit, 732.0);
+}
+
+// The entries of each file of a ZIP, as (name, content)
+async fn unzip(body: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let zip = ZipFileReader::new(body.to_vec()).await.unwrap();
+    let mut contents = Vec::new();
+    for index in 0..zip.file().entries().len() {
+        let name = zip.file().entries()[index]
+            .filename()
+            .as_str()
+            .unwrap()
+            .to_string();
+        let mut content = Vec::new();
+        zip.reader_with_entry(index)
+            .await
+            .unwrap()
+            .read_to_end_checked(&mut content)
+            .await
+            .unwrap();
+        contents.push((name, content));
+    }
+    contents
+}
+
+#[tokio::test]
+async fn test_collection_export() {
+    let (app, token) = common::setup().await;
+    let collection_id = create_collection(&app, &token, "Q1 2026").await;
+    let request = create_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Purchase invoices" }),
+    )
+    .await;
+    let request_id = request["id"].as_str().unwrap();
+
+    let invoice = text_pdf(&[
+        "Minoterie Dupuis SAS",
+        "Facture No F-2026-0042",
+        "Date de facture : 14/03/2026",
+        "Total HT 640,00",
+        "TVA 5,5 % 35,20",
+        "Total TTC 675,20",
+        "Minoterie Dupuis SAS - SIRET 552 100 554 00005 - TVA FR96552100554",
+    ]);
+    let (status, file) = send(
+        &app,
+        multipart_upload(&token, request_id, "scan.pdf", &invoice),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", file);
+    let file_id = file["id"].as_str().unwrap().to_string();
+    let (status, _) = send(
+        &app,
+        multipart_upload(&token, request_id, "notes.txt", b"paid by card"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Rejected documents are left out of the bundle
+    let (status, rejected) = send(
+        &app,
+        multipart_upload(&token, request_id, "wrong-month.txt", b"february"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        post(
+            &format!("/files/{}/reject", rejected["id"].as_str().unwrap()),
+            &token,
+            json!({ "reason": "This is February's statement." }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Fields are read in the background after the upload
+    wait_for_file(&app, &token, &file_id, |file| file.invoice_fields.is_some()).await;
+
+    let (status, headers, body) = send_raw(
+        &app,
+        get(&format!("/collections/{}/export", collection_id), &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers[http::header::CONTENT_DISPOSITION],
+        "attachment; filename=\"Q1 2026 - csv.zip\""
+    );
+    let contents = unzip(&body).await;
+    let names: Vec<&str> = contents.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "invoices.csv",
+            "documents/2026-03-14 Minoterie Dupuis SAS F-2026-0042.pdf",
+            "documents/notes.txt",
+        ]
+    );
+    assert_eq!(contents[1].1, invoice);
+    assert_eq!(contents[2].1, b"paid by card");
+    let csv = String::from_utf8(contents[0].1.clone()).unwrap();
+    assert!(csv.contains(&format!(
+        "{},Purchase invoices,purchase,extracted,F-2026-0042,2026-03-14,,Minoterie Dupuis SAS,\
+552100554,FR96552100554,,640.00,35.20,675.20",
+        file_id
+    )));
+
+    let (status, body) = send_bytes(
+        &app,
+        get(
+            &format!("/collections/{}/export?format=fec", collection_id),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let contents = unzip(&body).await;
+    assert_eq!(contents[0].0, "fec-draft.txt");
+    assert_eq!(String::from_utf8_lossy(&contents[0].1).lines().count(), 4);
+
+    let (status, _) = send(
+        &app,
+        get(
+            &format!("/collections/{}/export?format=sage", collection_id),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
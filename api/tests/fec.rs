@@ -0,0 +1,229 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use trombone::fec::read_fec;
+use trombone::model::fec::{FecAccountClass, FecJournal, FecReport, FecSummary};
+
+mod common;
+
+use common::{create_collection, create_request, date, get, multipart_upload, send, wait_for_file};
+
+// A request in a new collection of the seeded client
+async fn create_fec_request(app: &axum::Router, token: &str) -> String {
+    let collection_id = create_collection(app, token, "Year-end 2025").await;
+    let request = create_request(
+        app,
+        token,
+        json!({ "collection_id": collection_id, "title": "FEC" }),
+    )
+    .await;
+    request["id"].as_str().unwrap().to_string()
+}
+
+const HEADER: &str = "JournalCode|JournalLib|EcritureNum|EcritureDate|CompteNum|CompteLib|\
+CompAuxNum|CompAuxLib|PieceRef|PieceDate|EcritureLib|Debit|Credit|EcritureLet|DateLet|ValidDate|\
+Montantdevise|Idevise";
+
+// An FEC with a purchase, its payment and a sale, lines written with pipes
+const LINES: &[&str] = &[
+    "AC|Achats|1|20250110|607000|Achats de marchandises|||F-101|20250108|Facture Metro|100,00|0,00|||20250115||",
+    "AC|Achats|1|20250110|445660|TVA deductible|||F-101|20250108|Facture Metro|20,00|0,00|||20250115||",
+    "AC|Achats|1|20250110|401000|Fournisseurs|METRO|Metro|F-101|20250108|Facture Metro|0,00|120,00|A|20250205|20250115||",
+    "BQ|Banque|1|20250205|401000|Fournisseurs|METRO|Metro|VIR-7|20250205|Paiement Metro|120,00|0,00|A|20250205|20250210||",
+    "BQ|Banque|1|20250205|512000|Banque|||VIR-7|20250205|Paiement Metro|0,00|120,00|||20250210||",
+    "VE|Ventes|1|20250301|411000|Clients|DUPONT|Dupont|V-12|20250301|Facture Dupont|600,00|0,00|||20250305||",
+    "VE|Ventes|1|20250301|706000|Prestations de services|||V-12|20250301|Facture Dupont|0,00|500,00|||20250305||",
+    "VE|Ventes|1|20250301|445710|TVA collectee|||V-12|20250301|Facture Dupont|0,00|100,00|||20250305||",
+];
+
+fn fec_content(delimiter: &str, lines: &[&str]) -> String {
+    std::iter::once(HEADER)
+        .chain(lines.iter().copied())
+        .map(|line| line.replace('|', delimiter))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+fn journal(code: &str, label: &str, line_count: usize, amount: f64) -> FecJournal {
+    FecJournal {
+        code: code.to_string(),
+        label: label.to_string(),
+        entry_count: 1,
+        line_count,
+        debit: amount,
+        credit: amount,
+    }
+}
+
+fn rules(report: &FecReport) -> Vec<&str> {
+    report
+        .errors
+        .iter()
+        .map(|issue| issue.rule.as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_fec_upload_and_summary() {
+    let (app, token) = common::setup().await;
+    let request_id = create_fec_request(&app, &token).await;
+
+    let content = fec_content("\t", LINES);
+    let (status, file) = send(
+        &app,
+        multipart_upload(
+            &token,
+            &request_id,
+            "552100554FEC20251231.txt",
+            content.as_bytes(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", file);
+    let file_id = file["id"].as_str().unwrap();
+
+    // FECs are validated in the background after the upload
+    let file = wait_for_file(&app, &token, file_id, |file| file.fec_report.is_some()).await;
+    let report = file.fec_report.unwrap();
+    assert_eq!(
+        report,
+        FecReport {
+            valid: true,
+            delimiter: "tab".to_string(),
+            line_count: 8,
+            entry_count: 3,
+            error_count: 0,
+            errors: vec![],
+        }
+    );
+
+    let (status, summary) = send(&app, get(&format!("/files/{}/fec", file_id), &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    let summary: FecSummary = serde_json::from_value(summary).unwrap();
+    let class = |class: &str, debit: f64, credit: f64| FecAccountClass {
+        class: class.to_string(),
+        debit,
+        credit,
+    };
+    assert_eq!(
+        summary,
+        FecSummary {
+            siren: Some("552100554".to_string()),
+            closing_date: Some(date(2025, 12, 31)),
+            start_date: Some(date(2025, 1, 10)),
+            end_date: Some(date(2025, 3, 1)),
+            journals: vec![
+                journal("AC", "Achats", 3, 120.0),
+                journal("BQ", "Banque", 2, 120.0),
+                journal("VE", "Ventes", 3, 600.0),
+            ],
+            account_classes: vec![
+                class("4", 740.0, 220.0),
+                class("5", 0.0, 120.0),
+                class("6", 100.0, 0.0),
+                class("7", 0.0, 500.0),
+            ],
+            total_debit: 840.0,
+            total_credit: 840.0,
+        }
+    );
+
+    // Other files are not FECs
+    let (status, file) = send(
+        &app,
+        multipart_upload(&token, &request_id, "notes.txt", b"see the ledger"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        get(
+            &format!("/files/{}/fec", file["id"].as_str().unwrap()),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_pipe_fec_with_montant_and_sens() {
+    let header = HEADER.replace("|Debit|Credit|", "|Montant|Sens|");
+    let content = [
+        header.as_str(),
+        "OD|Operations diverses|12|20251231|681100|Dotations amortissements|||AM-25|20251231|Amortissements 2025|1500.00|D|||20260115||",
+        "OD|Operations diverses|12|20251231|281830|Amortissements materiel|||AM-25|20251231|Amortissements 2025|1500.00|C|||20260115||",
+    ]
+    .join("\n");
+
+    let fec = read_fec("552100554FEC20251231.txt", &content).unwrap();
+    assert!(fec.report.valid, "{:?}", fec.report.errors);
+    assert_eq!(fec.report.delimiter, "pipe");
+    assert_eq!(
+        fec.summary.journals,
+        vec![FecJournal {
+            code: "OD".to_string(),
+            label: "Operations diverses".to_string(),
+            entry_count: 1,
+            line_count: 2,
+            debit: 1500.0,
+            credit: 1500.0,
+        }]
+    );
+
+    // Other text files are not FECs
+    assert!(read_fec("notes.txt", "JournalCode is a column of the FEC").is_none());
+    assert!(read_fec("export.csv", "Date;Label;Amount\n2025-01-01;Coffee;-3.50").is_none());
+}
+
+#[test]
+fn test_fec_validation_errors() {
+    let lines = [
+        // Unbalanced, and dated after the closing date
+        "AC|Achats|1|20260110|607000|Achats|||F-1|20260108|Facture|100,00|0,00|||20260115||",
+        "AC|Achats|1|20260110|401000|Fournisseurs|||F-1|20260108|Facture|0,00|90,00|||20260115||",
+        // Dated before the previous entry of the journal, without PieceRef, with a bad date
+        "AC|Achats|2|20250105|607000|Achats||||2025-01-05|Facture|50,00|0,00|||20250110||",
+        "AC|Achats|2|20250105|401000|Fournisseurs|FOU1||F-2|20250105|Facture|0,00|50,00|||20250110||",
+        // Both a debit and a credit, and a missing field
+        "BQ|Banque|1|20250201|512000|Banque|||R-1|20250201|Remise|10,00|10,00|||20250202||",
+        "BQ|Banque|1|20250201|411000",
+    ];
+    let fec = read_fec("FEC.txt", &fec_content("|", &lines)).unwrap();
+    assert!(!fec.report.valid);
+    assert_eq!(fec.report.line_count, 6);
+    assert_eq!(fec.report.entry_count, 3);
+    assert_eq!(fec.report.error_count, fec.report.errors.len());
+    assert_eq!(
+        rules(&fec.report),
+        vec![
+            "required", // PieceRef of line 4
+            "date",     // PieceDate of line 4
+            "required", // CompAuxLib of line 5
+            "amount",   // Line 6
+            "columns",  // Line 7
+            "balance",  // Entry AC 1
+            "sequence", // Entry AC 2
+            "file_name",
+        ]
+    );
+    assert_eq!(fec.report.errors[0].line, Some(4));
+    assert_eq!(fec.report.errors[6].line, Some(4));
+    assert_eq!(
+        fec.report.errors[5].message,
+        "Entry 1 of journal AC is not balanced: 100.00 debit, 90.00 credit"
+    );
+
+    // Entries after the closing date of the file name
+    let fec = read_fec("552100554FEC20251231.txt", &fec_content("|", &lines[..2])).unwrap();
+    assert_eq!(rules(&fec.report), vec!["balance", "date"]);
+
+    // A wrong column order
+    let content = fec_content("\t", LINES).replacen("Debit\tCredit", "Credit\tDebit", 1);
+    let fec = read_fec("552100554FEC20251231.txt", &content).unwrap();
+    assert_eq!(rules(&fec.report), vec!["columns", "columns"]);
+    assert_eq!(
+        fec.report.errors[0].message,
+        "Column 12 should be Debit, not Credit"
+    );
+}
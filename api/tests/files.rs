@@ -5,10 +5,13 @@ use axum::{
 use http_body_util::BodyExt; 
 use tower::ServiceExt; 
 
+use trombone::file_type;
 use trombone::model::file::FileResponse;
 
 mod common;
 
+use common::{multipart_upload, post, send, send_bytes};
+
 #[tokio::test]
 async fn test_get_one_file() {
     let (app, token) = common::setup().await; // Destructure the tuple
@@ -95,4 +98,252 @@ async fn test_get_one_file_not_found() {
         .unwrap();
 
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_upload_and_delete_file() {
+    let (app, token) = common::setup().await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/requests")
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::from(
+                    r#"{"collection_id": "c1d2e3f4-5a6b-7c8d-9e0f-a1b2c3d4e5f6", "title": "Payslips"}"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let request_id = request["id"].as_str().unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(multipart_upload(&token, request_id, "march.txt", b"net pay: 2000"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(file.file_name, "march.txt");
+    assert_eq!(file.file_size, 13);
+    assert_eq!(file.mime_type, "text/plain");
+    assert_eq!(file.request.id.to_string(), request_id);
+
+    // Unknown requests are rejected
+    let response = app
+        .clone()
+        .oneshot(multipart_upload(
+            &token,
+            "00000000-0000-0000-0000-000000000000",
+            "march.txt",
+            b"net pay: 2000",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::DELETE)
+                .uri(format!("/files/{}", file.id))
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri(format!("/files/{}", file.id))
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+async fn post_json(app: &axum::Router, token: &str, uri: &str, body: serde_json::Value) -> serde_json::Value {
+    let (status, body) = send(app, post(uri, token, body)).await;
+    assert_eq!(status, StatusCode::OK);
+    body
+}
+
+#[tokio::test]
+async fn test_duplicate_uploads() {
+    let (app, token) = common::setup().await;
+
+    // A firm of its own, so that its settings don't affect other tests
+    let firm = post_json(&app, &token, "/firms", serde_json::json!({ "name": "Duplicates & Co" })).await;
+    let client = post_json(
+        &app,
+        &token,
+        "/clients",
+        serde_json::json!({ "firm_id": firm["id"], "company_name": "Bakery", "email": "bakery@example.com" }),
+    )
+    .await;
+    let mut request_ids = Vec::new();
+    for title in ["Q1 2026", "Q2 2026"] {
+        let collection = post_json(
+            &app,
+            &token,
+            "/collections",
+            serde_json::json!({
+                "client_id": client["id"],
+                "user_id": "b1c2d3e4-5f6a-7b8c-9d0e-f1a2b3c4d5e6",
+                "title": title
+            }),
+        )
+        .await;
+        let request = post_json(
+            &app,
+            &token,
+            "/requests",
+            serde_json::json!({ "collection_id": collection["id"], "title": "Bank statements" }),
+        )
+        .await;
+        request_ids.push(request["id"].as_str().unwrap().to_string());
+    }
+
+    let upload = |request_id: String, content: &'static [u8]| {
+        let app = app.clone();
+        let token = token.clone();
+        async move {
+            let (status, body) = send(&app, multipart_upload(&token, &request_id, "statement.txt", content)).await;
+            (status, serde_json::from_value::<FileResponse>(body).ok())
+        }
+    };
+
+    let (status, original) = upload(request_ids[0].clone(), b"balance: 100").await;
+    assert_eq!(status, StatusCode::OK);
+    let original = original.unwrap();
+    assert_eq!(
+        original.sha256.as_deref(),
+        Some("491d5fbc53bd55570eefe8911934c2e8f265e609f0b728b067fb64ac3766e0a0")
+    );
+    assert_eq!(original.duplicate_of, None);
+
+    // The same statement sent again for the next quarter is flagged
+    let (status, duplicate) = upload(request_ids[1].clone(), b"balance: 100").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(duplicate.unwrap().duplicate_of, Some(original.id));
+
+    // Once the firm rejects duplicates, it is refused
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::PUT)
+                .uri(format!("/firms/{}/settings", firm["id"].as_str().unwrap()))
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::from(r#"{"reject_duplicate_uploads": true}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (status, _) = upload(request_ids[1].clone(), b"balance: 100").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, other) = upload(request_ids[1].clone(), b"balance: 250").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(other.unwrap().duplicate_of, None);
+}
+
+#[tokio::test]
+async fn test_allowed_file_types() {
+    let (app, token) = common::setup().await;
+
+    let request = post_json(
+        &app,
+        &token,
+        "/requests",
+        serde_json::json!({
+            "collection_id": "c1d2e3f4-5a6b-7c8d-9e0f-a1b2c3d4e5f6",
+            "title": "Purchase invoices",
+            "allowed_types": ["application/pdf", "IMAGE/*"]
+        }),
+    )
+    .await;
+    assert_eq!(request["allowed_types"], serde_json::json!(["application/pdf", "image/*"]));
+    let request_id = request["id"].as_str().unwrap();
+
+    let upload = |file_name: &'static str, content: &'static [u8]| {
+        let app = app.clone();
+        let token = token.clone();
+        let request_id = request_id.to_string();
+        async move { send_bytes(&app, multipart_upload(&token, &request_id, file_name, content)).await }
+    };
+
+    // The browser sends application/octet-stream for every file in these tests, the content decides
+    let (status, body) = upload("invoice.pdf", b"%PDF-1.7\n1 0 obj\n").await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(file.mime_type, "application/pdf");
+
+    let (status, body) = upload("receipt.JPG", b"\xFF\xD8\xFF\xE0\x00\x10JFIF\x00").await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(file.mime_type, "image/jpeg");
+
+    let (status, body) = upload("notes.txt", b"to be paid in March").await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(
+        String::from_utf8(body).unwrap(),
+        "This request only accepts application/pdf, image/* files, this file is text/plain."
+    );
+
+    let (status, body) = upload("invoice.pdf", b"not really a PDF").await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert!(String::from_utf8(body).unwrap().contains("does not match its extension"));
+
+    // Invalid types are refused when creating the request
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/requests")
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::from(
+                    r#"{"collection_id": "c1d2e3f4-5a6b-7c8d-9e0f-a1b2c3d4e5f6", "title": "Receipts", "allowed_types": ["pdf"]}"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn test_file_type_detection() {
+    assert_eq!(file_type::detect("scan.PDF", b"%PDF-1.4"), Ok("application/pdf".to_string()));
+    assert_eq!(file_type::detect("export.csv", b"date;amount\n"), Ok("text/csv".to_string()));
+    // A Windows-1252 CSV is not UTF-8 but still accepted
+    assert_eq!(file_type::detect("export.csv", b"libell\xe9;montant\n"), Ok("text/csv".to_string()));
+    assert_eq!(file_type::detect("noextension", b"\x89PNG\r\n\x1a\n"), Ok("image/png".to_string()));
+    assert!(file_type::detect("photo.png", b"%PDF-1.4").is_err());
+    assert!(file_type::detect("photo.jpg", b"\x00\x01\x02").is_err());
+
+    let allowed = vec!["application/pdf".to_string(), "image/*".to_string()];
+    assert!(file_type::is_allowed("image/heif", &allowed));
+    assert!(!file_type::is_allowed("text/csv", &allowed));
+    assert!(!file_type::is_allowed("imagex/png", &allowed));
+}
@@ -0,0 +1,217 @@
+use axum::http::StatusCode;
+use rust_decimal_macros::dec;
+use serde_json::json;
+
+use trombone::invoice_fields::extract_invoice_fields;
+use trombone::model::file::FileResponse;
+use trombone::model::invoice::{ExtractedField, InvoiceFields};
+
+mod common;
+
+use common::{create_request, date, multipart_upload, patch, send, text_pdf, wait_for_file};
+
+fn field<T>(value: T, confidence: f32) -> Option<ExtractedField<T>> {
+    Some(ExtractedField {
+        value,
+        confidence,
+        corrected: false,
+    })
+}
+
+const SUPPLIER_INVOICE: &[&str] = &[
+    "Minoterie Dupuis SAS",
+    "3 rue des Moulins, 69003 Lyon",
+    "Facture No F-2026-0042",
+    "Date de facture : 14/03/2026",
+    "Facture a : Default Client",
+    "12 avenue Foch, 75008 Paris",
+    "Farine T65 - 20 sacs 640,00",
+    "Total HT 640,00",
+    "TVA 5,5 % 35,20",
+    "Total TTC 675,20",
+    "Echeance : 13/04/2026",
+    "Minoterie Dupuis SAS au capital de 50 000 EUR - SIRET 552 100 554 00005 - TVA FR96552100554",
+];
+
+#[test]
+fn test_extract_french_invoice() {
+    let text = SUPPLIER_INVOICE.join("\n");
+    assert_eq!(
+        extract_invoice_fields(&text).unwrap(),
+        InvoiceFields {
+            number: field("F-2026-0042".to_string(), 0.9),
+            issue_date: field(date(2026, 3, 14), 0.9),
+            supplier_name: field("Minoterie Dupuis SAS".to_string(), 0.7),
+            siret: field("55210055400005".to_string(), 0.95),
+            vat_number: field("FR96552100554".to_string(), 0.99),
+            net_total: field(dec!(640.0), 0.95),
+            vat_total: field(dec!(35.2), 0.95),
+            gross_total: field(dec!(675.2), 0.95),
+        }
+    );
+
+    // One VAT line per rate, thousands separated by spaces, the buyer's SIRET shown too
+    let text = "Boulangerie Martin\n\
+                FACTURE 2026-118\n\
+                Date : 2 avril 2026\n\
+                Client : Hôtel du Parc\n\
+                SIRET 732 829 320 00009\n\
+                Total HT 1 000,00\n\
+                TVA 5,5 % 27,50\n\
+                TVA 20 % 100,00\n\
+                Total TTC 1 127,50\n\
+                Échéance : 2 mai 2026";
+    let fields = extract_invoice_fields(text).unwrap();
+    assert_eq!(fields.number, field("2026-118".to_string(), 0.7));
+    assert_eq!(fields.issue_date, field(date(2026, 4, 2), 0.7));
+    assert_eq!(
+        fields.supplier_name,
+        field("Boulangerie Martin".to_string(), 0.4)
+    );
+    assert_eq!(fields.siret, field("73282932000009".to_string(), 0.95));
+    assert_eq!(fields.net_total, field(dec!(1000.0), 0.9));
+    assert_eq!(fields.vat_total, field(dec!(127.5), 0.9));
+    assert_eq!(fields.gross_total, field(dec!(1127.5), 0.9));
+}
+
+#[test]
+fn test_extract_other_layouts() {
+    // Small businesses charge no VAT
+    let text = "Jean Dupont - Graphiste\n\
+                Facture n° 12\n\
+                Date d'émission : 05/01/2026\n\
+                Création d'un logo 800,00 €\n\
+                Total : 800,00 €\n\
+                TVA non applicable, art. 293 B du CGI";
+    assert_eq!(
+        extract_invoice_fields(text).unwrap(),
+        InvoiceFields {
+            number: field("12".to_string(), 0.9),
+            issue_date: field(date(2026, 1, 5), 0.9),
+            supplier_name: field("Jean Dupont".to_string(), 0.4),
+            siret: None,
+            vat_number: None,
+            net_total: field(dec!(800.0), 0.95),
+            vat_total: field(dec!(0.0), 0.95),
+            gross_total: field(dec!(800.0), 0.95),
+        }
+    );
+
+    // Labels and values in separate cells, English number formats
+    let text = "Acme Software Ltd\n\
+                INVOICE\n\
+                Invoice number: INV-7781\n\
+                Invoice date\n\
+                2026-03-03\n\
+                Bill to: Default Client\n\
+                Subtotal\n\
+                1,250.00\n\
+                VAT 20%\n\
+                250.00\n\
+                Total due\n\
+                1,500.00\n\
+                VAT number: DE123456789";
+    let fields = extract_invoice_fields(text).unwrap();
+    assert_eq!(fields.number, field("INV-7781".to_string(), 0.9));
+    assert_eq!(fields.issue_date, field(date(2026, 3, 3), 0.9));
+    assert_eq!(
+        fields.supplier_name,
+        field("Acme Software Ltd".to_string(), 0.7)
+    );
+    assert_eq!(fields.vat_number, field("DE123456789".to_string(), 0.7));
+    assert_eq!(fields.net_total, field(dec!(1250.0), 0.95));
+    assert_eq!(fields.vat_total, field(dec!(250.0), 0.95));
+    assert_eq!(fields.gross_total, field(dec!(1500.0), 0.95));
+
+    // Only two totals: the third follows
+    let fields = extract_invoice_fields("Facture F12\nTotal HT 100,00\nTotal TTC 120,00").unwrap();
+    assert_eq!(fields.vat_total, field(dec!(20.0), 0.5));
+    let fields = extract_invoice_fields("Facture F13\nTotal HT 100,10\nTotal TTC 120,30").unwrap();
+    assert_eq!(fields.vat_total.unwrap().value.to_string(), "20.20");
+
+    assert_eq!(
+        extract_invoice_fields("Compte rendu de la réunion du 12/03/2026\nPrésents : 4"),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_correct_invoice_fields() {
+    let (app, token) = common::setup().await;
+    let request = create_request(&app, &token, json!({ "title": "Factures fournisseurs" })).await;
+
+    let (status, file) = send(
+        &app,
+        multipart_upload(
+            &token,
+            request["id"].as_str().unwrap(),
+            "facture-minoterie.pdf",
+            &text_pdf(SUPPLIER_INVOICE),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", file);
+    let file_id = file["id"].as_str().unwrap().to_string();
+
+    let file = wait_for_file(&app, &token, &file_id, |file| file.invoice_fields.is_some()).await;
+    let fields = file.invoice_fields.unwrap();
+    assert_eq!(fields.number, field("F-2026-0042".to_string(), 0.9));
+    assert_eq!(fields.gross_total, field(dec!(675.2), 0.95));
+
+    let uri = format!("/files/{}/invoice-fields", file_id);
+    let (status, _) = send(&app, patch(&uri, &token, json!({ "siret": "552 100" }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, file) = send(
+        &app,
+        patch(
+            &uri,
+            &token,
+            json!({ "number": "F-2026-0042-B", "gross_total": 675.25, "supplier_name": "" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_value(file).unwrap();
+    let corrected = file.invoice_fields.unwrap();
+    assert_eq!(
+        corrected.number,
+        Some(ExtractedField {
+            value: "F-2026-0042-B".to_string(),
+            confidence: 1.0,
+            corrected: true
+        })
+    );
+    assert_eq!(corrected.gross_total.unwrap().value, dec!(675.25));
+    assert_eq!(corrected.supplier_name, None);
+    assert_eq!(corrected.siret, fields.siret);
+
+    // null clears a date or an amount, absent fields are left as they are
+    let (status, file) = send(
+        &app,
+        patch(
+            &uri,
+            &token,
+            json!({ "issue_date": null, "vat_total": null }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_value(file).unwrap();
+    let cleared = file.invoice_fields.unwrap();
+    assert_eq!(cleared.issue_date, None);
+    assert_eq!(cleared.vat_total, None);
+    assert_eq!(cleared.gross_total.unwrap().value, dec!(675.25));
+    assert_eq!(cleared.number, corrected.number);
+
+    let (status, _) = send(
+        &app,
+        patch(
+            "/files/00000000-0000-0000-0000-000000000000/invoice-fields",
+            &token,
+            json!({ "number": "1" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
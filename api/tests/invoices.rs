@@ -0,0 +1,475 @@
+use axum::http::{self, StatusCode};
+use bytes::Bytes;
+use chrono::NaiveDate;
+use lopdf::{dictionary, Document, Object, Stream, StringFormat};
+use rust_decimal_macros::dec;
+use serde_json::{json, Value};
+
+use trombone::einvoice::{is_valid_french_vat, parse_cii, parse_invoice, siren_from_vat};
+use trombone::model::file::FileResponse;
+use trombone::model::invoice::{Invoice, InvoiceLine, InvoiceParty};
+
+mod common;
+
+use common::{create_request, get, multipart_upload, send_bytes, send_raw, wait_for_file};
+
+const FACTURX_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rsm:CrossIndustryInvoice xmlns:rsm="urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100" xmlns:ram="urn:un:unece:uncefact:data:standard:ReusableAggregateBusinessInformationEntity:100" xmlns:udt="urn:un:unece:uncefact:data:standard:UnqualifiedDataType:100">
+  <rsm:ExchangedDocumentContext>
+    <ram:GuidelineSpecifiedDocumentContextParameter>
+      <ram:ID>urn:cen.eu:en16931:2017</ram:ID>
+    </ram:GuidelineSpecifiedDocumentContextParameter>
+  </rsm:ExchangedDocumentContext>
+  <rsm:ExchangedDocument>
+    <ram:ID>FA-2026-0117</ram:ID>
+    <ram:TypeCode>380</ram:TypeCode>
+    <ram:IssueDateTime><udt:DateTimeString format="102">20260315</udt:DateTimeString></ram:IssueDateTime>
+  </rsm:ExchangedDocument>
+  <rsm:SupplyChainTradeTransaction>
+    <ram:IncludedSupplyChainTradeLineItem>
+      <ram:AssociatedDocumentLineDocument><ram:LineID>1</ram:LineID></ram:AssociatedDocumentLineDocument>
+      <ram:SpecifiedTradeProduct><ram:Name>Farine T65 (sac de 25 kg)</ram:Name></ram:SpecifiedTradeProduct>
+      <ram:SpecifiedLineTradeAgreement>
+        <ram:NetPriceProductTradePrice><ram:ChargeAmount>21.50</ram:ChargeAmount></ram:NetPriceProductTradePrice>
+      </ram:SpecifiedLineTradeAgreement>
+      <ram:SpecifiedLineTradeDelivery><ram:BilledQuantity unitCode="C62">10</ram:BilledQuantity></ram:SpecifiedLineTradeDelivery>
+      <ram:SpecifiedLineTradeSettlement>
+        <ram:ApplicableTradeTax><ram:TypeCode>VAT</ram:TypeCode><ram:CategoryCode>S</ram:CategoryCode><ram:RateApplicablePercent>5.5</ram:RateApplicablePercent></ram:ApplicableTradeTax>
+        <ram:SpecifiedTradeSettlementLineMonetarySummation><ram:LineTotalAmount>215.00</ram:LineTotalAmount></ram:SpecifiedTradeSettlementLineMonetarySummation>
+      </ram:SpecifiedLineTradeSettlement>
+    </ram:IncludedSupplyChainTradeLineItem>
+    <ram:IncludedSupplyChainTradeLineItem>
+      <ram:AssociatedDocumentLineDocument><ram:LineID>2</ram:LineID></ram:AssociatedDocumentLineDocument>
+      <ram:SpecifiedTradeProduct><ram:Name>Livraison</ram:Name></ram:SpecifiedTradeProduct>
+      <ram:SpecifiedLineTradeAgreement>
+        <ram:NetPriceProductTradePrice><ram:ChargeAmount>35.00</ram:ChargeAmount></ram:NetPriceProductTradePrice>
+      </ram:SpecifiedLineTradeAgreement>
+      <ram:SpecifiedLineTradeDelivery><ram:BilledQuantity unitCode="C62">1</ram:BilledQuantity></ram:SpecifiedLineTradeDelivery>
+      <ram:SpecifiedLineTradeSettlement>
+        <ram:ApplicableTradeTax><ram:TypeCode>VAT</ram:TypeCode><ram:CategoryCode>S</ram:CategoryCode><ram:RateApplicablePercent>20</ram:RateApplicablePercent></ram:ApplicableTradeTax>
+        <ram:SpecifiedTradeSettlementLineMonetarySummation><ram:LineTotalAmount>35.00</ram:LineTotalAmount></ram:SpecifiedTradeSettlementLineMonetarySummation>
+      </ram:SpecifiedLineTradeSettlement>
+    </ram:IncludedSupplyChainTradeLineItem>
+    <ram:ApplicableHeaderTradeAgreement>
+      <ram:SellerTradeParty>
+        <ram:Name>Moulins de Provence</ram:Name>
+        <ram:SpecifiedLegalOrganization><ram:ID schemeID="0002">552100554</ram:ID></ram:SpecifiedLegalOrganization>
+        <ram:SpecifiedTaxRegistration><ram:ID schemeID="VA">FR96552100554</ram:ID></ram:SpecifiedTaxRegistration>
+      </ram:SellerTradeParty>
+      <ram:BuyerTradeParty>
+        <ram:Name>Boulangerie Martin</ram:Name>
+        <ram:SpecifiedTaxRegistration><ram:ID schemeID="VA">FR83404833048</ram:ID></ram:SpecifiedTaxRegistration>
+      </ram:BuyerTradeParty>
+    </ram:ApplicableHeaderTradeAgreement>
+    <ram:ApplicableHeaderTradeDelivery/>
+    <ram:ApplicableHeaderTradeSettlement>
+      <ram:InvoiceCurrencyCode>EUR</ram:InvoiceCurrencyCode>
+      <ram:SpecifiedTradePaymentTerms>
+        <ram:DueDateDateTime><udt:DateTimeString format="102">20260414</udt:DateTimeString></ram:DueDateDateTime>
+      </ram:SpecifiedTradePaymentTerms>
+      <ram:SpecifiedTradeSettlementHeaderMonetarySummation>
+        <ram:LineTotalAmount>250.00</ram:LineTotalAmount>
+        <ram:TaxBasisTotalAmount>250.00</ram:TaxBasisTotalAmount>
+        <ram:TaxTotalAmount currencyID="EUR">18.83</ram:TaxTotalAmount>
+        <ram:GrandTotalAmount>268.83</ram:GrandTotalAmount>
+        <ram:DuePayableAmount>268.83</ram:DuePayableAmount>
+      </ram:SpecifiedTradeSettlementHeaderMonetarySummation>
+    </ram:ApplicableHeaderTradeSettlement>
+  </rsm:SupplyChainTradeTransaction>
+</rsm:CrossIndustryInvoice>
+"#;
+
+const UBL_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">
+  <cbc:CustomizationID>urn:cen.eu:en16931:2017#compliant#urn:fdc:peppol.eu:2017:poacc:billing:3.0</cbc:CustomizationID>
+  <cbc:ID>INV-8812</cbc:ID>
+  <cbc:IssueDate>2026-04-02</cbc:IssueDate>
+  <cbc:DueDate>2026-05-02</cbc:DueDate>
+  <cbc:InvoiceTypeCode>380</cbc:InvoiceTypeCode>
+  <cbc:DocumentCurrencyCode>EUR</cbc:DocumentCurrencyCode>
+  <cac:AccountingSupplierParty>
+    <cac:Party>
+      <cac:PartyName><cbc:Name>Électricité Générale Sud</cbc:Name></cac:PartyName>
+      <cac:PartyTaxScheme>
+        <cbc:CompanyID>FR44732829320</cbc:CompanyID>
+        <cac:TaxScheme><cbc:ID>VAT</cbc:ID></cac:TaxScheme>
+      </cac:PartyTaxScheme>
+      <cac:PartyLegalEntity>
+        <cbc:RegistrationName>EGS SARL</cbc:RegistrationName>
+        <cbc:CompanyID schemeID="0002">732829320</cbc:CompanyID>
+      </cac:PartyLegalEntity>
+    </cac:Party>
+  </cac:AccountingSupplierParty>
+  <cac:AccountingCustomerParty>
+    <cac:Party>
+      <cac:PartyLegalEntity><cbc:RegistrationName>Boulangerie Martin</cbc:RegistrationName></cac:PartyLegalEntity>
+    </cac:Party>
+  </cac:AccountingCustomerParty>
+  <cac:TaxTotal><cbc:TaxAmount currencyID="EUR">96.00</cbc:TaxAmount></cac:TaxTotal>
+  <cac:LegalMonetaryTotal>
+    <cbc:LineExtensionAmount currencyID="EUR">480.00</cbc:LineExtensionAmount>
+    <cbc:TaxExclusiveAmount currencyID="EUR">480.00</cbc:TaxExclusiveAmount>
+    <cbc:TaxInclusiveAmount currencyID="EUR">576.00</cbc:TaxInclusiveAmount>
+    <cbc:PayableAmount currencyID="EUR">576.00</cbc:PayableAmount>
+  </cac:LegalMonetaryTotal>
+  <cac:InvoiceLine>
+    <cbc:ID>1</cbc:ID>
+    <cbc:InvoicedQuantity unitCode="HUR">8</cbc:InvoicedQuantity>
+    <cbc:LineExtensionAmount currencyID="EUR">480.00</cbc:LineExtensionAmount>
+    <cac:Item>
+      <cbc:Name>Mise aux normes du tableau électrique</cbc:Name>
+      <cac:ClassifiedTaxCategory><cbc:ID>S</cbc:ID><cbc:Percent>20</cbc:Percent><cac:TaxScheme><cbc:ID>VAT</cbc:ID></cac:TaxScheme></cac:ClassifiedTaxCategory>
+    </cac:Item>
+    <cac:Price><cbc:PriceAmount currencyID="EUR">60.00</cbc:PriceAmount></cac:Price>
+  </cac:InvoiceLine>
+</Invoice>
+"#;
+
+async fn create_invoice_request(app: &axum::Router, token: &str) -> String {
+    let request = create_request(app, token, json!({ "title": "Supplier invoices" })).await;
+    request["id"].as_str().unwrap().to_string()
+}
+
+// A one-page PDF with the given files attached, as Factur-X invoices embed their XML
+fn pdf_with_attachments(attachments: &[(&str, &str)]) -> Vec<u8> {
+    let mut document = Document::with_version("1.7");
+    let pages_id = document.new_object_id();
+    let content_id = document.add_object(Stream::new(dictionary! {}, b"".to_vec()));
+    let page_id = document.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        "Contents" => content_id,
+    });
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }),
+    );
+
+    let mut names: Vec<Object> = Vec::new();
+    for (name, content) in attachments {
+        let mut stream = Stream::new(
+            dictionary! { "Type" => "EmbeddedFile", "Subtype" => "text/xml" },
+            content.as_bytes().to_vec(),
+        );
+        stream.compress().unwrap();
+        let file_id = document.add_object(stream);
+        let file_spec_id = document.add_object(dictionary! {
+            "Type" => "Filespec",
+            "F" => Object::String(name.as_bytes().to_vec(), StringFormat::Literal),
+            "UF" => Object::String(name.as_bytes().to_vec(), StringFormat::Literal),
+            "AFRelationship" => "Data",
+            "EF" => dictionary! { "F" => file_id, "UF" => file_id },
+        });
+        names.push(Object::String(
+            name.as_bytes().to_vec(),
+            StringFormat::Literal,
+        ));
+        names.push(file_spec_id.into());
+    }
+    let catalog_id = document.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+        "Names" => dictionary! { "EmbeddedFiles" => dictionary! { "Names" => names } },
+    });
+    document.trailer.set("Root", catalog_id);
+
+    let mut content = Vec::new();
+    document.save_to(&mut content).unwrap();
+    content
+}
+
+fn expected_invoice() -> Invoice {
+    Invoice {
+        format: "factur-x".to_string(),
+        profile: Some("urn:cen.eu:en16931:2017".to_string()),
+        number: Some("FA-2026-0117".to_string()),
+        type_code: Some("380".to_string()),
+        issue_date: NaiveDate::from_ymd_opt(2026, 3, 15),
+        due_date: NaiveDate::from_ymd_opt(2026, 4, 14),
+        currency: Some("EUR".to_string()),
+        seller: InvoiceParty {
+            name: Some("Moulins de Provence".to_string()),
+            siren: Some("552100554".to_string()),
+            vat_number: Some("FR96552100554".to_string()),
+        },
+        buyer: InvoiceParty {
+            name: Some("Boulangerie Martin".to_string()),
+            siren: Some("404833048".to_string()), // From the VAT number
+            vat_number: Some("FR83404833048".to_string()),
+        },
+        net_total: Some(dec!(250.0)),
+        vat_total: Some(dec!(18.83)),
+        gross_total: Some(dec!(268.83)),
+        lines: vec![
+            InvoiceLine {
+                id: Some("1".to_string()),
+                description: Some("Farine T65 (sac de 25 kg)".to_string()),
+                quantity: Some(dec!(10.0)),
+                unit_price: Some(dec!(21.5)),
+                net_amount: Some(dec!(215.0)),
+                vat_rate: Some(dec!(5.5)),
+            },
+            InvoiceLine {
+                id: Some("2".to_string()),
+                description: Some("Livraison".to_string()),
+                quantity: Some(dec!(1.0)),
+                unit_price: Some(dec!(35.0)),
+                net_amount: Some(dec!(35.0)),
+                vat_rate: Some(dec!(20.0)),
+            },
+        ],
+        validation_errors: vec![],
+    }
+}
+
+// Invoices are parsed in the background after the upload
+async fn wait_for_invoice(app: &axum::Router, token: &str, file_id: &str) -> Invoice {
+    let file = wait_for_file(app, token, file_id, |file| file.invoice.is_some()).await;
+    file.invoice.unwrap()
+}
+
+#[tokio::test]
+async fn test_facturx_invoice() {
+    let (app, token) = common::setup().await;
+    let request_id = create_invoice_request(&app, &token).await;
+
+    let pdf = pdf_with_attachments(&[
+        ("conditions.xml", "<conditions/>"),
+        ("factur-x.xml", FACTURX_XML),
+    ]);
+    let (status, body) = send_bytes(
+        &app,
+        multipart_upload(&token, &request_id, "FA-2026-0117.pdf", &pdf),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+
+    let invoice = wait_for_invoice(&app, &token, &file.id.to_string()).await;
+    assert_eq!(invoice, expected_invoice());
+
+    // Listed files carry their invoice too
+    let (_, body) = send_bytes(
+        &app,
+        get(&format!("/requests/{}/files", request_id), &token),
+    )
+    .await;
+    let files: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(files[0]["invoice"]["number"], "FA-2026-0117");
+    assert_eq!(files[0]["invoice"]["issue_date"], "2026-03-15");
+
+    // The PDF is the human-readable invoice already
+    assert!(!files[0]["rendition_available"].as_bool().unwrap());
+    let (status, _) = send_bytes(&app, get(&format!("/files/{}/rendition", file.id), &token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// Text of a rendered invoice, one line per text operation
+fn pdf_text(pdf: &[u8]) -> String {
+    let document = Document::load_mem(pdf).unwrap();
+    let pages: Vec<u32> = document.get_pages().into_keys().collect();
+    document.extract_text(&pages).unwrap()
+}
+
+#[tokio::test]
+async fn test_ubl_invoice() {
+    let (app, token) = common::setup().await;
+    let request_id = create_invoice_request(&app, &token).await;
+
+    let (status, body) = send_bytes(
+        &app,
+        multipart_upload(&token, &request_id, "INV-8812.xml", UBL_XML.as_bytes()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(file.mime_type, "application/xml");
+
+    let invoice = wait_for_invoice(&app, &token, &file.id.to_string()).await;
+    assert_eq!(
+        invoice,
+        Invoice {
+            format: "ubl".to_string(),
+            profile: Some(
+                "urn:cen.eu:en16931:2017#compliant#urn:fdc:peppol.eu:2017:poacc:billing:3.0"
+                    .to_string()
+            ),
+            number: Some("INV-8812".to_string()),
+            type_code: Some("380".to_string()),
+            issue_date: NaiveDate::from_ymd_opt(2026, 4, 2),
+            due_date: NaiveDate::from_ymd_opt(2026, 5, 2),
+            currency: Some("EUR".to_string()),
+            seller: InvoiceParty {
+                name: Some("Électricité Générale Sud".to_string()),
+                siren: Some("732829320".to_string()),
+                vat_number: Some("FR44732829320".to_string()),
+            },
+            buyer: InvoiceParty {
+                name: Some("Boulangerie Martin".to_string()),
+                siren: None,
+                vat_number: None,
+            },
+            net_total: Some(dec!(480.0)),
+            vat_total: Some(dec!(96.0)),
+            gross_total: Some(dec!(576.0)),
+            lines: vec![InvoiceLine {
+                id: Some("1".to_string()),
+                description: Some("Mise aux normes du tableau électrique".to_string()),
+                quantity: Some(dec!(8.0)),
+                unit_price: Some(dec!(60.0)),
+                net_amount: Some(dec!(480.0)),
+                vat_rate: Some(dec!(20.0)),
+            }],
+            validation_errors: vec![],
+        }
+    );
+
+    // Accountants read it as a PDF
+    let (_, body) = send_bytes(&app, get(&format!("/files/{}", file.id), &token)).await;
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+    assert!(file.rendition_available);
+    let (status, headers, pdf) =
+        send_raw(&app, get(&format!("/files/{}/rendition", file.id), &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[http::header::CONTENT_TYPE], "application/pdf");
+    let text = pdf_text(&pdf);
+    for expected in [
+        "Invoice INV-8812",
+        "Électricité Générale Sud",
+        "SIREN 732829320",
+        "Mise aux normes du tableau électrique",
+        "576.00 EUR",
+    ] {
+        assert!(text.contains(expected), "{} not in {}", expected, text);
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_cii_invoice() {
+    let (app, token) = common::setup().await;
+    let request_id = create_invoice_request(&app, &token).await;
+
+    let xml = FACTURX_XML
+        .replace("<ram:Name>Boulangerie Martin</ram:Name>", "")
+        .replace(
+            "<ram:GrandTotalAmount>268.83</ram:GrandTotalAmount>",
+            "<ram:GrandTotalAmount>286.83</ram:GrandTotalAmount>",
+        )
+        .replace(r#""0002">552100554<"#, r#""0002">552100555<"#);
+    let (status, body) = send_bytes(
+        &app,
+        multipart_upload(&token, &request_id, "facture.xml", xml.as_bytes()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+
+    // Invalid invoices are still parsed, with the rules they break
+    let invoice = wait_for_invoice(&app, &token, &file.id.to_string()).await;
+    assert_eq!(invoice.format, "cii");
+    assert_eq!(invoice.gross_total, Some(dec!(286.83)));
+    assert_eq!(
+        invoice.validation_errors,
+        vec![
+            "BR-07: An invoice shall contain the buyer name",
+            "BR-CO-15: The total with VAT (286.83) shall equal the total without VAT (250.00) plus the VAT (18.83)",
+            "The seller SIREN 552100555 is not valid",
+        ]
+    );
+
+    let (status, pdf) =
+        send_bytes(&app, get(&format!("/files/{}/rendition", file.id), &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    let text = pdf_text(&pdf);
+    assert!(text.contains("Validation errors"), "{}", text);
+    assert!(text.contains("BR-07"), "{}", text);
+}
+
+#[tokio::test]
+async fn test_other_xml_is_not_an_invoice() {
+    let invoice = parse_invoice(
+        "application/xml",
+        Bytes::from_static(b"<order><id>1</id></order>"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(invoice, None);
+
+    // An Invoice element outside of the UBL namespace is not UBL
+    let invoice = parse_invoice(
+        "application/xml",
+        Bytes::from_static(b"<Invoice><ID>1</ID></Invoice>"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(invoice, None);
+
+    assert!(
+        parse_invoice("application/xml", Bytes::from_static(b"<Invoice>"))
+            .await
+            .is_err()
+    );
+}
+
+#[test]
+fn test_parse_zugferd_invoice() {
+    // ZUGFeRD producers use other prefixes and SIRET numbers
+    let xml = FACTURX_XML
+        .replace("rsm:", "rsm2:")
+        .replace("xmlns:rsm=", "xmlns:rsm2=")
+        .replace(
+            r#"<ram:ID schemeID="0002">552100554</ram:ID>"#,
+            r#"<ram:ID schemeID="0009">55210055400013</ram:ID>"#,
+        );
+    let invoice = parse_cii(&xml, "factur-x").unwrap();
+    assert_eq!(invoice, expected_invoice());
+
+    assert!(parse_cii("<Invoice/>", "factur-x").is_err());
+}
+
+#[test]
+fn test_invoice_business_rules() {
+    let xml = FACTURX_XML
+        .replace(
+            "<ram:NetPriceProductTradePrice><ram:ChargeAmount>21.50</ram:ChargeAmount></ram:NetPriceProductTradePrice>",
+            "",
+        )
+        .replace(
+            "<ram:ChargeAmount>35.00</ram:ChargeAmount>",
+            "<ram:ChargeAmount>-35.00</ram:ChargeAmount>",
+        )
+        .replace(
+            "<ram:TaxBasisTotalAmount>250.00</ram:TaxBasisTotalAmount>",
+            "<ram:TaxBasisTotalAmount>250.000</ram:TaxBasisTotalAmount>",
+        );
+    let invoice = parse_cii(&xml, "factur-x").unwrap();
+    assert_eq!(
+        invoice.validation_errors,
+        vec![
+            "BR-26: Each invoice line shall contain the item net price (line 1)",
+            "BR-27: The item net price shall not be negative (line 2)",
+            "BR-DEC-12: The invoice total amount without VAT shall have at most 2 decimals",
+        ]
+    );
+}
+
+#[test]
+fn test_french_vat_numbers() {
+    assert!(is_valid_french_vat("FR96552100554"));
+    assert!(is_valid_french_vat(" fr 96 552 100 554"));
+    assert!(!is_valid_french_vat("FR97552100554"));
+    assert_eq!(
+        siren_from_vat("fr96 552100554"),
+        Some("552100554".to_string())
+    );
+
+    // Multibyte characters are rejected rather than sliced through
+    assert!(!is_valid_french_vat("FR3É12345678"));
+    assert!(!is_valid_french_vat("FRÉ3123456789"));
+    assert_eq!(siren_from_vat("FR3É12345678"), None);
+}
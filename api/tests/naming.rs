@@ -0,0 +1,236 @@
+use async_zip::base::read::mem::ZipFileReader;
+use axum::http::StatusCode;
+use chrono::{NaiveDate, Utc};
+use futures::future::join_all;
+use serde_json::{json, Value};
+
+use trombone::model::file::FileResponse;
+use trombone::naming::{apply_template, validate_template, NamingContext};
+
+mod common;
+
+use common::{
+    create_request, get, multipart_upload, post, put, send, send_bytes, wait_for_file, SEED_USER_ID,
+};
+
+// A one-entry FEC, its name carries the SIREN and the closing date of the company
+const FEC: &str = "JournalCode\tJournalLib\tEcritureNum\tEcritureDate\tCompteNum\tCompteLib\t\
+CompAuxNum\tCompAuxLib\tPieceRef\tPieceDate\tEcritureLib\tDebit\tCredit\tEcritureLet\tDateLet\t\
+ValidDate\tMontantdevise\tIdevise\r\n\
+VE\tVentes\t1\t20250301\t411000\tClients\t\t\tV-12\t20250301\tFacture\t100,00\t0,00\t\t\t20250305\t\t\r\n\
+VE\tVentes\t1\t20250301\t706000\tPrestations\t\t\tV-12\t20250301\tFacture\t0,00\t100,00\t\t\t20250305\t\t";
+
+fn context(original_file_name: &str) -> NamingContext<'_> {
+    NamingContext {
+        client: "Boulangerie Martin",
+        collection: "Year-end 2025",
+        request: "Bank statements: BNP",
+        date: NaiveDate::from_ymd_opt(2026, 3, 14).unwrap(),
+        seq: 3,
+        original_file_name,
+    }
+}
+
+#[test]
+fn test_apply_template() {
+    let template = "{client}_{collection}_{request}_{date}_{seq}.{ext}";
+    assert_eq!(
+        apply_template(template, &context("IMG_2034.JPG")),
+        "Boulangerie Martin_Year-end 2025_Bank statements_ BNP_2026-03-14_03.jpg"
+    );
+    // The extension is kept when the template leaves it out
+    assert_eq!(
+        apply_template("{date} {original}", &context("scan(3).pdf")),
+        "2026-03-14 scan(3).pdf"
+    );
+    assert_eq!(
+        apply_template("{request}-{seq}", &context("README")),
+        "Bank statements_ BNP-03"
+    );
+
+    assert!(validate_template(template).is_ok());
+    assert_eq!(
+        validate_template("{client}_{year}.{ext}").unwrap_err(),
+        "Unknown placeholder {year}, use {client}, {collection}, {request}, {date}, {seq}, \
+{original}, {ext}."
+    );
+    assert!(validate_template("{client").is_err());
+    assert!(validate_template("client}").is_err());
+    assert!(validate_template("  ").is_err());
+}
+
+// The names of the files of a ZIP
+async fn zip_names(app: &axum::Router, token: &str, uri: &str) -> Vec<String> {
+    let (status, body) = send_bytes(app, get(uri, token)).await;
+    assert_eq!(status, StatusCode::OK);
+    let zip = ZipFileReader::new(body).await.unwrap();
+    zip.file()
+        .entries()
+        .iter()
+        .map(|entry| entry.filename().as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_uploads_renamed_by_template() {
+    let (app, token) = common::setup().await;
+
+    // A firm of its own, so that its settings don't affect other tests
+    let (_, firm) = send(
+        &app,
+        post("/firms", &token, json!({ "name": "Templates & Co" })),
+    )
+    .await;
+    let (_, client) = send(
+        &app,
+        post(
+            "/clients",
+            &token,
+            json!({
+                "firm_id": firm["id"],
+                "company_name": "Bakery",
+                "email": "bakery@example.com"
+            }),
+        ),
+    )
+    .await;
+    let (_, collection) = send(
+        &app,
+        post(
+            "/collections",
+            &token,
+            json!({
+                "client_id": client["id"],
+                "user_id": SEED_USER_ID,
+                "title": "Q1 2026"
+            }),
+        ),
+    )
+    .await;
+    let collection_id = collection["id"].as_str().unwrap();
+    let request = create_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Receipts" }),
+    )
+    .await;
+    let request_id = request["id"].as_str().unwrap();
+    let settings_uri = format!("/firms/{}/settings", firm["id"].as_str().unwrap());
+
+    let (status, _) = send(
+        &app,
+        put(
+            &settings_uri,
+            &token,
+            json!({ "file_name_template": "{client}_{month}" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let template = "{client}_{request}_{date}_{seq}.{ext}";
+    let (status, settings) = send(
+        &app,
+        put(
+            &settings_uri,
+            &token,
+            json!({ "file_name_template": template }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(settings["file_name_template"], template);
+    assert_eq!(settings["reject_duplicate_uploads"], false);
+
+    let upload = |file_name: &'static str, content: &'static [u8]| {
+        let app = app.clone();
+        let token = token.clone();
+        async move {
+            let (status, file) = send(
+                &app,
+                multipart_upload(&token, request_id, file_name, content),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{}", file);
+            serde_json::from_value::<FileResponse>(file).unwrap()
+        }
+    };
+    let today = Utc::now().date_naive();
+    let first = upload("scan(3).TXT", b"coffee 3.50").await;
+    assert_eq!(first.file_name, format!("Bakery_Receipts_{}_01.txt", today));
+    assert_eq!(first.original_file_name, "scan(3).TXT");
+    let second = upload("IMG_2034.txt", b"taxi 18.00").await;
+    assert_eq!(
+        second.file_name,
+        format!("Bakery_Receipts_{}_02.txt", today)
+    );
+    assert_eq!(second.original_file_name, "IMG_2034.txt");
+
+    // Archives and exports use the names given by the template
+    assert_eq!(
+        zip_names(
+            &app,
+            &token,
+            &format!("/collections/{}/archive", collection_id)
+        )
+        .await,
+        vec![
+            format!("Receipts/{}", first.file_name),
+            format!("Receipts/{}", second.file_name),
+            "manifest.csv".to_string(),
+        ]
+    );
+    assert_eq!(
+        zip_names(
+            &app,
+            &token,
+            &format!("/collections/{}/export", collection_id)
+        )
+        .await,
+        vec![
+            "invoices.csv".to_string(),
+            format!("documents/{}", first.file_name),
+            format!("documents/{}", second.file_name),
+        ]
+    );
+
+    // Concurrent uploads are given their own numbers
+    let files = join_all([
+        upload("a.txt", b"bread 2.10"),
+        upload("b.txt", b"stamps 5.20"),
+        upload("c.txt", b"tolls 9.80"),
+    ])
+    .await;
+    let mut names: Vec<String> = files.into_iter().map(|file| file.file_name).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        (3..=5)
+            .map(|seq| format!("Bakery_Receipts_{}_{:02}.txt", today, seq))
+            .collect::<Vec<_>>()
+    );
+
+    // FECs are still read with the name they were sent with, which holds the SIREN and the
+    // closing date
+    let fec = upload("552100554FEC20251231.txt", FEC.as_bytes()).await;
+    assert_eq!(fec.file_name, format!("Bakery_Receipts_{}_06.txt", today));
+    wait_for_file(&app, &token, &fec.id.to_string(), |file| {
+        file.fec_report.is_some()
+    })
+    .await;
+    let (status, summary) = send(&app, get(&format!("/files/{}/fec", fec.id), &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["siren"], "552100554");
+    assert_eq!(summary["closing_date"], "2025-12-31");
+
+    // Without a template, uploads keep their names
+    let (status, settings) = send(
+        &app,
+        put(&settings_uri, &token, json!({ "file_name_template": "" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(settings["file_name_template"], Value::Null);
+    let third = upload("IMG_2035.txt", b"parking 4.00").await;
+    assert_eq!(third.file_name, "IMG_2035.txt");
+    assert_eq!(third.original_file_name, "IMG_2035.txt");
+}
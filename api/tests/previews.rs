@@ -0,0 +1,203 @@
+use axum::http::{self, StatusCode};
+use image::{DynamicImage, ImageFormat, RgbImage};
+use lopdf::{dictionary, Document, Object, Stream};
+use serde_json::json;
+use std::io::Cursor;
+
+use trombone::model::file::FileResponse;
+
+mod common;
+
+use common::{create_request, get, multipart_upload, send_bytes, send_raw, wait_for_file};
+
+async fn create_preview_request(app: &axum::Router, token: &str) -> String {
+    let request = create_request(app, token, json!({ "title": "Receipts" })).await;
+    request["id"].as_str().unwrap().to_string()
+}
+
+fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+    let image = RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+    });
+    let mut content = Vec::new();
+    DynamicImage::ImageRgb8(image)
+        .write_to(&mut Cursor::new(&mut content), format)
+        .unwrap();
+    content
+}
+
+// A one-page PDF like the ones produced by scanners: the page is a single JPEG
+fn scanned_pdf(jpeg: Vec<u8>, width: u32, height: u32) -> Vec<u8> {
+    image_pdf(jpeg, (width, height), (width, height))
+}
+
+// A one-page PDF drawing a JPEG of `size` at the bottom left of the page
+fn image_pdf(jpeg: Vec<u8>, (width, height): (u32, u32), page_size: (u32, u32)) -> Vec<u8> {
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+    let image_id = document.add_object(Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => width as i64,
+            "Height" => height as i64,
+            "ColorSpace" => "DeviceRGB",
+            "BitsPerComponent" => 8,
+            "Filter" => "DCTDecode",
+        },
+        jpeg,
+    ));
+    let content_id = document.add_object(Stream::new(
+        dictionary! {},
+        format!("q {} 0 0 {} 0 0 cm /Im0 Do Q", width, height).into_bytes(),
+    ));
+    let page_id = document.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![
+            0.into(),
+            0.into(),
+            (page_size.0 as i64).into(),
+            (page_size.1 as i64).into(),
+        ],
+        "Contents" => content_id,
+        "Resources" => dictionary! { "XObject" => dictionary! { "Im0" => image_id } },
+    });
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }),
+    );
+    let catalog_id = document.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    document.trailer.set("Root", catalog_id);
+
+    let mut content = Vec::new();
+    document.save_to(&mut content).unwrap();
+    content
+}
+
+async fn upload_and_preview(
+    app: &axum::Router,
+    token: &str,
+    file_name: &str,
+    content: &[u8],
+) -> DynamicImage {
+    let request_id = create_preview_request(app, token).await;
+    let (status, body) = send_bytes(
+        app,
+        multipart_upload(token, &request_id, file_name, content),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+
+    // Previews are rendered in the background after the upload
+    wait_for_file(app, token, &file.id.to_string(), |file| {
+        file.preview_available
+    })
+    .await;
+    let (status, headers, png) =
+        send_raw(app, get(&format!("/files/{}/preview", file.id), token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[http::header::CONTENT_TYPE], "image/png");
+    image::load_from_memory_with_format(&png, ImageFormat::Png).unwrap()
+}
+
+#[tokio::test]
+async fn test_photo_preview() {
+    let (app, token) = common::setup().await;
+
+    let preview = upload_and_preview(
+        &app,
+        &token,
+        "receipt.png",
+        &encode(1200, 800, ImageFormat::Png),
+    )
+    .await;
+    assert_eq!((preview.width(), preview.height()), (512, 341));
+
+    // Small photos are not enlarged
+    let preview = upload_and_preview(
+        &app,
+        &token,
+        "small.jpg",
+        &encode(300, 200, ImageFormat::Jpeg),
+    )
+    .await;
+    assert_eq!((preview.width(), preview.height()), (300, 200));
+}
+
+#[tokio::test]
+async fn test_scanned_pdf_preview() {
+    let (app, token) = common::setup().await;
+
+    let pdf = scanned_pdf(encode(620, 877, ImageFormat::Jpeg), 620, 877);
+    let preview = upload_and_preview(&app, &token, "scan.pdf", &pdf).await;
+    assert_eq!((preview.width(), preview.height()), (362, 512));
+}
+
+// pdftoppm is provisioned by the flake, without it PDF previews are reported as failed
+fn has_pdftoppm() -> bool {
+    std::process::Command::new("pdftoppm")
+        .arg("-v")
+        .output()
+        .is_ok()
+}
+
+#[tokio::test]
+async fn test_pdf_preview_renders_first_page() {
+    let (app, token) = common::setup().await;
+    let request_id = create_preview_request(&app, &token).await;
+
+    // A logo on an A4 invoice is not the page
+    let pdf = image_pdf(encode(100, 50, ImageFormat::Jpeg), (100, 50), (595, 842));
+    let (status, body) = send_bytes(
+        &app,
+        multipart_upload(&token, &request_id, "invoice.pdf", &pdf),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+    let file = wait_for_file(&app, &token, &file.id.to_string(), |file| {
+        file.preview_status != "pending"
+    })
+    .await;
+
+    if has_pdftoppm() {
+        assert_eq!(file.preview_status, "ready");
+        let (status, png) =
+            send_bytes(&app, get(&format!("/files/{}/preview", file.id), &token)).await;
+        assert_eq!(status, StatusCode::OK);
+        let preview = image::load_from_memory_with_format(&png, ImageFormat::Png).unwrap();
+        assert_eq!((preview.width(), preview.height()), (362, 512));
+    } else {
+        assert_eq!(file.preview_status, "failed");
+        assert!(!file.preview_available);
+    }
+}
+
+#[tokio::test]
+async fn test_no_preview_for_other_files() {
+    let (app, token) = common::setup().await;
+    let request_id = create_preview_request(&app, &token).await;
+
+    let (status, body) = send_bytes(
+        &app,
+        multipart_upload(&token, &request_id, "notes.txt", b"paid in cash"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file: FileResponse = serde_json::from_slice(&body).unwrap();
+    assert!(!file.preview_available);
+    let file = wait_for_file(&app, &token, &file.id.to_string(), |file| {
+        file.preview_status != "pending"
+    })
+    .await;
+    assert_eq!(file.preview_status, "unsupported");
+
+    let (status, _) = send_bytes(&app, get(&format!("/files/{}/preview", file.id), &token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
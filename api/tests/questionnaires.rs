@@ -0,0 +1,201 @@
+use axum::http::{self, StatusCode};
+use rust_decimal_macros::dec;
+use serde_json::{json, Value};
+
+use trombone::model::question::{is_valid_iban, parse_decimal};
+
+mod common;
+
+use common::{
+    get, json_request, portal_get, portal_request, send_bytes, SEED_CLIENT_ID, SEED_USER_ID,
+};
+
+// Creates a collection holding a questionnaire, returns (collection, request)
+async fn create_questionnaire(app: &axum::Router, token: &str) -> (Value, Value) {
+    let (status, collection) = send_bytes(
+        app,
+        json_request(
+            http::Method::POST,
+            "/collections",
+            token,
+            json!({
+                "client_id": SEED_CLIENT_ID,
+                "user_id": SEED_USER_ID,
+                "title": "Year-end 2025"
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let collection: Value = serde_json::from_slice(&collection).unwrap();
+
+    let (status, request) = send_bytes(
+        app,
+        json_request(
+            http::Method::POST,
+            "/requests",
+            token,
+            json!({
+                "collection_id": collection["id"],
+                "title": "Year-end questions",
+                "kind": "questionnaire",
+                "questions": [
+                    { "key": "vehicle", "label": "Did you buy a vehicle this year?", "type": "yes_no" },
+                    { "key": "iban", "label": "New bank account IBAN?", "type": "iban" },
+                    { "key": "amount", "label": "Vehicle price", "type": "amount" },
+                    { "key": "bought_on", "label": "Purchase date", "type": "date" },
+                    { "key": "usage", "label": "Usage", "type": "choice", "options": ["professional", "mixed"] },
+                    { "key": "notes", "label": "Anything else?", "type": "text", "required": false }
+                ]
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let request: Value = serde_json::from_slice(&request).unwrap();
+
+    (collection, request)
+}
+
+#[tokio::test]
+async fn test_create_questionnaire_request() {
+    let (app, token) = common::setup().await;
+    let (_, request) = create_questionnaire(&app, &token).await;
+
+    assert_eq!(request["kind"], "questionnaire");
+    assert_eq!(request["questions"].as_array().unwrap().len(), 6);
+    assert_eq!(
+        request["questions"][4]["options"],
+        json!(["professional", "mixed"])
+    );
+    assert!(request["answers"].is_null());
+
+    // Files requests have no questions
+    let (status, _) = send_bytes(
+        &app,
+        json_request(
+            http::Method::POST,
+            "/requests",
+            &token,
+            json!({
+                "collection_id": "c1d2e3f4-5a6b-7c8d-9e0f-a1b2c3d4e5f6",
+                "title": "Invoices",
+                "questions": [{ "key": "a", "label": "A", "type": "text" }]
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_submit_answers_through_portal() {
+    let (app, token) = common::setup().await;
+    let (collection, request) = create_questionnaire(&app, &token).await;
+    let access_token = collection["access_token"].as_str().unwrap();
+    let uri = format!(
+        "/portal/requests/{}/answers",
+        request["id"].as_str().unwrap()
+    );
+
+    let (status, body) = send_bytes(
+        &app,
+        portal_request(
+            http::Method::PUT,
+            &uri,
+            access_token,
+            json!({
+                "vehicle": "oui",
+                "iban": "FR76 3000 6000 0112 3456 7890 188",
+                "amount": "12 500,505",
+                "bought_on": "01/03/2025",
+                "usage": "personal"
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let message = String::from_utf8(body).unwrap();
+    assert!(message.contains("iban: invalid IBAN"));
+    assert!(message.contains("usage: expected one of"));
+    assert!(message.contains("amount: an amount has at most 2 decimals"));
+
+    let (status, body) = send_bytes(
+        &app,
+        portal_request(
+            http::Method::PUT,
+            &uri,
+            access_token,
+            json!({
+                "vehicle": "oui",
+                "iban": "fr76 3000 6000 0112 3456 7890 189",
+                "amount": "12 500,5",
+                "bought_on": "01/03/2025",
+                "usage": "mixed"
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let body: Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(body["status"], "fulfilled");
+    assert!(body["answered_at"].is_string());
+    assert_eq!(
+        body["answers"],
+        json!({
+            "vehicle": true,
+            "iban": "FR7630006000011234567890189",
+            "amount": "12500.50",
+            "bought_on": "2025-03-01",
+            "usage": "mixed"
+        })
+    );
+
+    // Clients see their request without the firm's details
+    let (status, body) = send_bytes(
+        &app,
+        portal_get(
+            &format!("/portal/requests/{}", request["id"].as_str().unwrap()),
+            access_token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["status"], "fulfilled");
+    assert_eq!(body["collection_title"], "Year-end 2025");
+    assert!(body.get("collection").is_none());
+    assert!(!String::from_utf8_lossy(&serde_json::to_vec(&body).unwrap()).contains(access_token));
+
+    // The answers are part of the collection's export
+    let (status, csv) = send_bytes(
+        &app,
+        get(
+            &format!(
+                "/collections/{}/answers",
+                collection["id"].as_str().unwrap()
+            ),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let csv = String::from_utf8(csv).unwrap();
+    assert!(csv.contains("iban,New bank account IBAN?,FR7630006000011234567890189"));
+    assert!(csv.contains("vehicle,Did you buy a vehicle this year?,yes"));
+}
+
+#[test]
+fn test_answer_parsing() {
+    assert!(is_valid_iban("FR7630006000011234567890189"));
+    assert!(is_valid_iban("DE89370400440532013000"));
+    assert!(!is_valid_iban("DE89370400440532013001"));
+    assert!(!is_valid_iban("FR76"));
+
+    assert_eq!(parse_decimal("1 234,56"), Some(dec!(1234.56)));
+    assert_eq!(parse_decimal("1.234,56 €"), Some(dec!(1234.56)));
+    assert_eq!(parse_decimal("1,234.56"), Some(dec!(1234.56)));
+    assert_eq!(parse_decimal("0.1"), Some(dec!(0.1)));
+    assert_eq!(parse_decimal("abc"), None);
+}
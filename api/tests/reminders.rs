@@ -0,0 +1,194 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use trombone::app_state::AppState;
+use trombone::handlers::reminder::send_due_reminders;
+use trombone::mailer::{Email, Mailer};
+use trombone::model::reminder::ReminderPolicy;
+use trombone::router::router;
+
+mod common;
+
+use common::{create_collection, get, post, put, send};
+
+// Keeps the emails instead of sending them
+#[derive(Default)]
+struct RecordingMailer {
+    sent: Mutex<Vec<Email>>,
+}
+
+#[async_trait]
+impl Mailer for RecordingMailer {
+    async fn send(&self, email: Email) -> anyhow::Result<()> {
+        self.sent.lock().unwrap().push(email);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_put_and_get_reminder_policy() {
+    let (app, token) = common::setup().await;
+    let collection_id = create_collection(&app, &token, "Year-end 2025").await;
+    let uri = format!("/collections/{}/reminder-policy", collection_id);
+
+    let (status, _) = send(&app, get(&uri, &token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = send(&app, put(&uri, &token, json!({ "days_before": [5, 1] }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["days_before"], json!([5, 1]));
+    assert_eq!(body["repeat_every_days"], 7);
+
+    let (status, body) = send(&app, put(&uri, &token, json!({ "enabled": false }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["days_before"], json!([5, 1]));
+    assert_eq!(body["enabled"], false);
+}
+
+#[tokio::test]
+async fn test_send_reminder_now() {
+    let (app, token) = common::setup().await;
+    let collection_id = create_collection(&app, &token, "Year-end 2025").await;
+
+    let (status, _) = send(
+        &app,
+        post(
+            "/requests",
+            &token,
+            json!({ "collection_id": collection_id, "title": "Bank statements" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let uri = format!("/collections/{}/reminders", collection_id);
+    let (status, body) = send(&app, post(&uri, &token, Value::Null)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["trigger"], "manual");
+    assert_eq!(body["pending_requests"], 1);
+    assert_eq!(body["recipient"], "default@client.com");
+
+    let (status, body) = send(&app, get(&uri, &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_send_reminder_without_pending_requests() {
+    let (app, token) = common::setup().await;
+    let collection_id = create_collection(&app, &token, "Year-end 2025").await;
+
+    let uri = format!("/collections/{}/reminders", collection_id);
+    let (status, _) = send(&app, post(&uri, &token, Value::Null)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_send_due_reminders_once() {
+    let (app_state, token) = common::setup_state().await;
+    let mailer = Arc::new(RecordingMailer::default());
+    let app_state = AppState {
+        mailer: mailer.clone(),
+        ..app_state
+    };
+    let app = router(app_state.clone());
+    let title = format!("Year-end {}", Uuid::new_v4());
+    let collection_id = create_collection(&app, &token, &title).await;
+    let (status, _) = send(
+        &app,
+        post(
+            "/requests",
+            &token,
+            json!({ "collection_id": collection_id, "title": "Bank statements" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let uri = format!("/collections/{}/reminder-policy", collection_id);
+    let (status, _) = send(&app, put(&uri, &token, json!({ "days_before": [2] }))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Due 2 days before the expiry, tomorrow, with a policy set up long before
+    let collection_id: Uuid = collection_id.parse().unwrap();
+    sqlx::query!(
+        "UPDATE collections SET expires_at = now() + interval '1 day' WHERE id = $1",
+        collection_id
+    )
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE reminder_policies SET created_at = now() - interval '30 days' WHERE collection_id = $1",
+        collection_id
+    )
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+
+    // Concurrent runs, then a later one, send the slot's reminder once
+    let (first, second) = tokio::join!(
+        send_due_reminders(&app_state),
+        send_due_reminders(&app_state)
+    );
+    first.unwrap();
+    second.unwrap();
+    send_due_reminders(&app_state).await.unwrap();
+
+    let sent = mailer
+        .sent
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|email| email.subject.contains(&title))
+        .count();
+    assert_eq!(sent, 1);
+    let (status, body) = send(
+        &app,
+        get(&format!("/collections/{}/reminders", collection_id), &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let reminders = body.as_array().unwrap();
+    assert_eq!(reminders.len(), 1);
+    assert_eq!(reminders[0]["trigger"], "scheduled");
+    assert!(reminders[0]["slot_at"].is_string());
+}
+
+#[test]
+fn test_reminder_policy_due_slot() {
+    let now = Utc::now();
+    let expires_at = now + Duration::days(5);
+    let policy = ReminderPolicy {
+        collection_id: Uuid::new_v4(),
+        days_before: vec![7, 2],
+        repeat_every_days: Some(7),
+        enabled: true,
+        created_at: now - Duration::days(30),
+        updated_at: now,
+    };
+
+    // 7 days before expiry has passed, 2 days before has not
+    assert_eq!(
+        policy.due_slot(expires_at, now),
+        Some(expires_at - Duration::days(7))
+    );
+
+    // Weekly after expiry
+    let later = expires_at + Duration::days(15);
+    assert_eq!(
+        policy.due_slot(expires_at, later),
+        Some(expires_at + Duration::days(14))
+    );
+
+    // Slots before the policy was created are ignored
+    let recent_policy = ReminderPolicy {
+        created_at: now,
+        ..policy
+    };
+    assert_eq!(recent_policy.due_slot(expires_at, now), None);
+}
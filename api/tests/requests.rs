@@ -163,3 +163,73 @@ async fn test_delete_request() {
         .unwrap();
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
+
+#[tokio::test]
+async fn test_overdue_requests() {
+    let (app, token) = common::setup().await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/requests")
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::from(
+                    serde_json::to_vec(&json!({
+                        "collection_id": "c1d2e3f4-5a6b-7c8d-9e0f-a1b2c3d4e5f6",
+                        "title": "Payroll for January",
+                        "due_date": "2020-01-31"
+                    }))
+                    .unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let request: Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(request["due_date"], "2020-01-31");
+    assert_eq!(request["overdue"], true);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/requests?overdue=true")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Vec<Value> = serde_json::from_slice(&body).unwrap();
+
+    assert!(body.iter().all(|r| r["overdue"] == true));
+    assert!(body.iter().any(|r| r["id"] == request["id"]));
+    // The due date is kept when not given, and removed with null
+    let uri = format!("/requests/{}", request["id"].as_str().unwrap());
+    let (status, request) = common::send(
+        &app,
+        common::patch(&uri, &token, json!({ "title": "Payroll" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(request["due_date"], "2020-01-31");
+    let (status, request) = common::send(
+        &app,
+        common::patch(&uri, &token, json!({ "due_date": null })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(request["due_date"], Value::Null);
+    assert_eq!(request["overdue"], false);
+}
@@ -0,0 +1,190 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use trombone::handlers::file::{scan_pending_files, MAX_SCAN_ATTEMPTS};
+use trombone::router::router;
+use trombone::scanner::{parse_clamd_reply, ClamdScanner, ScanVerdict, Scanner};
+
+mod common;
+
+use common::{create_request, get, multipart_upload, send, send_bytes};
+
+#[tokio::test]
+async fn test_uploads_are_scanned() {
+    let (app, token) = common::setup().await;
+    let request = create_request(&app, &token, json!({ "title": "Receipts" })).await;
+    let request_id = request["id"].as_str().unwrap();
+
+    let (status, file) = send_bytes(
+        &app,
+        multipart_upload(&token, request_id, "receipt.txt", "taxi: 23.50".as_bytes()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let file: Value = serde_json::from_slice(&file).unwrap();
+    assert_eq!(file["scan_status"], "clean");
+    assert!(file["scanned_at"].is_string());
+
+    let (status, content) = send_bytes(
+        &app,
+        get(
+            &format!("/files/{}/download", file["id"].as_str().unwrap()),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content, b"taxi: 23.50");
+
+    let (status, message) = send_bytes(
+        &app,
+        multipart_upload(
+            &token,
+            request_id,
+            "receipt.txt",
+            common::FAKE_VIRUS.as_bytes(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        String::from_utf8(message).unwrap(),
+        "The file was rejected: Eicar-Signature detected."
+    );
+
+    // The infected file is kept as a record but cannot be downloaded
+    let (status, files) = send_bytes(
+        &app,
+        get(&format!("/requests/{}/files", request_id), &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let files: Value = serde_json::from_slice(&files).unwrap();
+    let infected = files
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|file| file["scan_status"] == "infected")
+        .unwrap();
+    assert_eq!(infected["scan_result"], "Eicar-Signature");
+
+    let (status, _) = send_bytes(
+        &app,
+        get(
+            &format!("/files/{}/download", infected["id"].as_str().unwrap()),
+            &token,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_failed_scans_are_given_up() {
+    let (app_state, token) = common::setup_state().await;
+    let app = router(app_state.clone());
+    let request = create_request(&app, &token, json!({ "title": "Receipts" })).await;
+    let request_id = request["id"].as_str().unwrap();
+
+    // Quarantined while the scanner fails, and retried in the background
+    let (status, file) = send(
+        &app,
+        multipart_upload(
+            &token,
+            request_id,
+            "receipt.txt",
+            common::FAKE_SCAN_ERROR.as_bytes(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(file["scan_status"], "pending");
+    assert_eq!(file["scan_result"], "Scan failed: clamd is unreachable");
+    let file_uri = format!("/files/{}", file["id"].as_str().unwrap());
+
+    for attempt in 2..=MAX_SCAN_ATTEMPTS {
+        let (_, file) = send(&app, get(&file_uri, &token)).await;
+        assert_eq!(file["scan_status"], "pending", "before attempt {}", attempt);
+        scan_pending_files(&app_state).await.unwrap();
+    }
+
+    // No longer retried, and still not served
+    let (_, file) = send(&app, get(&file_uri, &token)).await;
+    assert_eq!(file["scan_status"], "failed");
+    let (status, message) = send_bytes(&app, get(&format!("{}/download", file_uri), &token)).await;
+    assert_eq!(status, StatusCode::LOCKED);
+    assert_eq!(
+        String::from_utf8(message).unwrap(),
+        "The file could not be scanned for malware and stays quarantined."
+    );
+}
+
+// Minimal clamd speaking INSTREAM, flagging streams that contain "EICAR"
+async fn fake_clamd() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut command = [0; 10];
+                socket.read_exact(&mut command).await.unwrap();
+                assert_eq!(&command, b"zINSTREAM\0");
+
+                let mut content = Vec::new();
+                loop {
+                    let length = socket.read_u32().await.unwrap() as usize;
+                    if length == 0 {
+                        break;
+                    }
+                    let mut chunk = vec![0; length];
+                    socket.read_exact(&mut chunk).await.unwrap();
+                    content.extend_from_slice(&chunk);
+                }
+
+                let reply: &[u8] = if String::from_utf8_lossy(&content).contains("EICAR") {
+                    b"stream: Eicar-Test-Signature FOUND\0"
+                } else {
+                    b"stream: OK\0"
+                };
+                socket.write_all(reply).await.unwrap();
+            });
+        }
+    });
+
+    address
+}
+
+#[tokio::test]
+async fn test_clamd_scanner() {
+    let scanner = ClamdScanner::new(fake_clamd().await);
+
+    let mut clean: &[u8] = &[b'a'; 200_000];
+    assert_eq!(scanner.scan(&mut clean).await.unwrap(), ScanVerdict::Clean);
+
+    let mut infected = common::FAKE_VIRUS.as_bytes();
+    assert_eq!(
+        scanner.scan(&mut infected).await.unwrap(),
+        ScanVerdict::Infected("Eicar-Test-Signature".to_string())
+    );
+
+    // Nothing listens there
+    let unreachable = ClamdScanner::new("127.0.0.1:1");
+    assert!(unreachable.scan(&mut &b"data"[..]).await.is_err());
+}
+
+#[test]
+fn test_parse_clamd_reply() {
+    assert_eq!(
+        parse_clamd_reply("stream: OK\0").unwrap(),
+        ScanVerdict::Clean
+    );
+    assert_eq!(
+        parse_clamd_reply("stream: Win.Test.EICAR_HDB-1 FOUND\0").unwrap(),
+        ScanVerdict::Infected("Win.Test.EICAR_HDB-1".to_string())
+    );
+    assert!(parse_clamd_reply("INSTREAM size limit exceeded. ERROR\0").is_err());
+}
@@ -0,0 +1,186 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use std::time::Duration;
+use uuid::Uuid;
+
+mod common;
+
+use common::{get, multipart_upload, post, post_public, send, text_pdf};
+
+// A firm with its own accountant, client, collection and request
+struct Firm {
+    token: String,
+    request_id: String,
+}
+
+async fn create_firm(app: &axum::Router, token: &str, name: &str) -> Firm {
+    let (status, firm) = send(app, post("/firms", token, json!({ "name": name }))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let email = format!("accountant+{}@example.com", Uuid::new_v4());
+    let (status, user) = send(
+        app,
+        post_public(
+            "/register",
+            json!({
+                "firm_id": firm["id"],
+                "email": email,
+                "password": "password123",
+                "first_name": "Ada",
+                "last_name": "Accountant"
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, login) = send(
+        app,
+        post_public(
+            "/login",
+            json!({ "email": email, "password": "password123" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let token = login["token"].as_str().unwrap().to_string();
+
+    let (status, client) = send(
+        app,
+        post(
+            "/clients",
+            &token,
+            json!({ "firm_id": firm["id"], "company_name": "Boulangerie Martin", "email": "contact@martin.example" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, collection) = send(
+        app,
+        post(
+            "/collections",
+            &token,
+            json!({ "client_id": client["id"], "user_id": user["id"], "title": "Year-end 2026" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, request) = send(
+        app,
+        post(
+            "/requests",
+            &token,
+            json!({ "collection_id": collection["id"], "title": "Phone invoices" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    Firm {
+        token,
+        request_id: request["id"].as_str().unwrap().to_string(),
+    }
+}
+
+// Text is extracted in the background after the upload
+async fn wait_for_results(app: &axum::Router, token: &str, query: &str) -> Vec<Value> {
+    for _ in 0..50 {
+        let (status, results) = send(app, get(&format!("/search?q={}", query), token)).await;
+        assert_eq!(status, StatusCode::OK);
+        let results = results.as_array().unwrap().clone();
+        if !results.is_empty() {
+            return results;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("no results for {}", query);
+}
+
+#[tokio::test]
+async fn test_search_file_contents() {
+    let (app, token) = common::setup().await;
+    let firm = create_firm(&app, &token, "Cabinet Dupont").await;
+
+    let pdf = text_pdf(&["Facture Orange", "Forfait mobile 5G", "Total TTC 39,99 EUR"]);
+    let (status, invoice) = send(
+        &app,
+        multipart_upload(&firm.token, &firm.request_id, "scan_0042.pdf", &pdf),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        multipart_upload(
+            &firm.token,
+            &firm.request_id,
+            "bank-export.csv",
+            b"date;label;amount\n2026-03-02;PRLV SEPA FREE MOBILE;-19.99\n",
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let results = wait_for_results(&app, &firm.token, "forfait%20mobile").await;
+    assert_eq!(results.len(), 1);
+    let result = &results[0];
+    assert_eq!(result["file_id"], invoice["id"]);
+    assert_eq!(result["file_name"], "scan_0042.pdf");
+    assert!(
+        result["snippet"]
+            .as_str()
+            .unwrap()
+            .contains("**Forfait** **mobile**"),
+        "{}",
+        result["snippet"]
+    );
+    assert_eq!(result["request"]["id"], firm.request_id.as_str());
+    assert_eq!(result["request"]["name"], "Phone invoices");
+    assert_eq!(result["collection"]["name"], "Year-end 2026");
+    assert_eq!(result["client"]["name"], "Boulangerie Martin");
+
+    // CSV exports and file names are searchable too
+    let results = wait_for_results(&app, &firm.token, "free").await;
+    assert_eq!(results[0]["file_name"], "bank-export.csv");
+    let results = wait_for_results(&app, &firm.token, "scan").await;
+    assert_eq!(results[0]["file_name"], "scan_0042.pdf");
+
+    // Both files mention mobile, the invoice ranks first as it also mentions forfait
+    let (_, results) = send(&app, get("/search?q=mobile%20OR%20forfait", &firm.token)).await;
+    let names: Vec<&str> = results
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["file_name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["scan_0042.pdf", "bank-export.csv"]);
+}
+
+#[tokio::test]
+async fn test_search_is_scoped_to_firm() {
+    let (app, token) = common::setup().await;
+    let firm = create_firm(&app, &token, "Cabinet Dupont").await;
+    let other_firm = create_firm(&app, &token, "Cabinet Durand").await;
+
+    let (status, _) = send(
+        &app,
+        multipart_upload(
+            &firm.token,
+            &firm.request_id,
+            "notes.txt",
+            b"Loyer du local commercial rue des Lilas",
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    wait_for_results(&app, &firm.token, "lilas").await;
+
+    let (status, results) = send(&app, get("/search?q=lilas", &other_firm.token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(results, json!([]));
+
+    // The test user belongs to no firm
+    let (status, _) = send(&app, get("/search?q=lilas", &token)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(&app, get("/search?q=%20", &firm.token)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,434 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    http::{self, HeaderMap, Request, StatusCode},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::json;
+use tokio::io::AsyncRead;
+
+use trombone::app_state::AppState;
+use trombone::router::router;
+use trombone::scanner::{ScanVerdict, Scanner};
+
+mod common;
+
+use common::{
+    create_collection, create_request, get, send, send_bytes, send_raw, FakeScanner, FAKE_VIRUS,
+    SEED_ACCESS_TOKEN,
+};
+
+// Authorizes a request as a firm user, or as a client when given an access token
+enum Auth<'a> {
+    User(&'a str),
+    Client(&'a str),
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers.get(name).unwrap().to_str().unwrap()
+}
+
+fn tus(method: http::Method, uri: &str, auth: &Auth) -> http::request::Builder {
+    let builder = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Tus-Resumable", "1.0.0");
+    match auth {
+        Auth::User(token) => {
+            builder.header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+        }
+        Auth::Client(access_token) => builder.header("X-Access-Token", *access_token),
+    }
+}
+
+fn create(
+    uri: &str,
+    auth: &Auth,
+    request_id: &str,
+    file_name: &str,
+    length: usize,
+) -> Request<Body> {
+    tus(http::Method::POST, uri, auth)
+        .header("Upload-Length", length)
+        .header(
+            "Upload-Metadata",
+            format!(
+                "request_id {},filename {},is_confidential",
+                STANDARD.encode(request_id),
+                STANDARD.encode(file_name)
+            ),
+        )
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn patch(uri: &str, auth: &Auth, offset: usize, chunk: &[u8]) -> Request<Body> {
+    tus(http::Method::PATCH, uri, auth)
+        .header(
+            http::header::CONTENT_TYPE,
+            "application/offset+octet-stream",
+        )
+        .header("Upload-Offset", offset)
+        .body(Body::from(chunk.to_vec()))
+        .unwrap()
+}
+
+fn head(uri: &str, auth: &Auth) -> Request<Body> {
+    tus(http::Method::HEAD, uri, auth)
+        .body(Body::empty())
+        .unwrap()
+}
+
+// Uploads are completed in the background after their last chunk: polls the upload until it
+// became a file, whose id is returned
+async fn wait_for_file_id(app: &axum::Router, uri: &str, auth: &Auth<'_>) -> String {
+    for _ in 0..50 {
+        let (status, headers, _) = send_raw(app, head(uri, auth)).await;
+        assert_eq!(status, StatusCode::OK);
+        if let Some(file_id) = headers.get("File-Id") {
+            return file_id.to_str().unwrap().to_string();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("upload {} was not completed in time", uri);
+}
+
+async fn create_upload_request(app: &axum::Router, token: &str) -> String {
+    let request = create_request(app, token, json!({ "title": "Archives scannées" })).await;
+    request["id"].as_str().unwrap().to_string()
+}
+
+// Scanned archives spanning several chunks
+fn archive() -> Vec<u8> {
+    "Grand livre 2025 - compte 401000 - Minoterie Dupuis - 640,00\n"
+        .repeat(2500)
+        .into_bytes()
+}
+
+#[tokio::test]
+async fn test_resumable_upload() {
+    let (app, token) = common::setup().await;
+    let user = Auth::User(&token);
+    let request_id = create_upload_request(&app, &token).await;
+
+    let (status, headers, _) = send_raw(
+        &app,
+        tus(http::Method::OPTIONS, "/uploads", &user)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(header(&headers, "Tus-Version"), "1.0.0");
+    assert_eq!(header(&headers, "Tus-Extension"), "creation,termination");
+    // Well above the 200 MB of scanned archives
+    assert_eq!(header(&headers, "Tus-Max-Size"), "2147483648");
+    let (status, _, _) = send_raw(
+        &app,
+        create("/uploads", &user, &request_id, "a.txt", 2_147_483_649),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    let (status, headers, _) = send_raw(
+        &app,
+        create("/uploads", &user, &request_id, "archives.zip", 300_000_000),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let large = header(&headers, "Location").to_string();
+    let (_, headers, _) = send_raw(&app, head(&large, &user)).await;
+    assert_eq!(header(&headers, "Upload-Length"), "300000000");
+    let (status, _, _) = send_raw(
+        &app,
+        tus(http::Method::DELETE, &large, &user)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // Other versions of the protocol are refused
+    let (status, headers, _) = send_raw(
+        &app,
+        Request::builder()
+            .method(http::Method::POST)
+            .uri("/uploads")
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+            .header("Tus-Resumable", "0.2.2")
+            .header("Upload-Length", 10)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    assert_eq!(header(&headers, "Tus-Version"), "1.0.0");
+
+    let content = archive();
+    let (status, headers, _) = send_raw(
+        &app,
+        create(
+            "/uploads",
+            &user,
+            &request_id,
+            "grand-livre.txt",
+            content.len(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(header(&headers, "Tus-Resumable"), "1.0.0");
+    let location = header(&headers, "Location").to_string();
+    assert!(location.starts_with("/uploads/"));
+
+    let (status, headers, _) = send_raw(&app, head(&location, &user)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(header(&headers, "Upload-Offset"), "0");
+    assert_eq!(header(&headers, "Upload-Length"), content.len().to_string());
+    assert_eq!(header(&headers, "Cache-Control"), "no-store");
+
+    let (status, headers, _) = send_raw(&app, patch(&location, &user, 0, &content[..70_000])).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(header(&headers, "Upload-Offset"), "70000");
+    assert!(headers.get("File-Id").is_none());
+
+    // Resumed from the offset the server has, not the one the client believes
+    let (status, _, _) = send_raw(&app, patch(&location, &user, 0, &content[..70_000])).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _, _) = send_raw(
+        &app,
+        tus(http::Method::PATCH, &location, &user)
+            .header(http::header::CONTENT_TYPE, "application/octet-stream")
+            .header("Upload-Offset", 70_000)
+            .body(Body::from(content[70_000..].to_vec()))
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let (_, headers, _) = send_raw(&app, head(&location, &user)).await;
+    assert_eq!(header(&headers, "Upload-Offset"), "70000");
+
+    let (status, headers, _) =
+        send_raw(&app, patch(&location, &user, 70_000, &content[70_000..])).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(header(&headers, "Upload-Offset"), content.len().to_string());
+    let file_id = wait_for_file_id(&app, &location, &user).await;
+
+    // Confirmed again to a client whose response was lost
+    let (status, headers, _) = send_raw(&app, patch(&location, &user, content.len(), b"")).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(header(&headers, "File-Id"), file_id);
+
+    let (status, file) = send(&app, get(&format!("/files/{}", file_id), &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(file["request"]["id"], request_id.as_str());
+    assert_eq!(file["file_name"], "grand-livre.txt");
+    assert_eq!(file["file_size"], content.len());
+    assert_eq!(file["scan_status"], "clean");
+
+    let (status, downloaded) =
+        send_bytes(&app, get(&format!("/files/{}/download", file_id), &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(downloaded, content);
+
+    // Completed uploads take no more chunks
+    let (status, _, _) = send_raw(&app, patch(&location, &user, content.len(), b"more")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _, _) = send_raw(&app, patch(&location, &user, 70_000, &content[70_000..])).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_portal_resumable_upload() {
+    let (app, token) = common::setup().await;
+    let client = Auth::Client(SEED_ACCESS_TOKEN);
+    let request_id = create_upload_request(&app, &token).await;
+
+    let (status, _, _) = send_raw(
+        &app,
+        create(
+            "/portal/uploads",
+            &Auth::Client("wrong"),
+            &request_id,
+            "a.txt",
+            10,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Clients only reach the requests of their collection
+    let collection_id = create_collection(&app, &token, "Autre dossier").await;
+    let other_request = create_request(
+        &app,
+        &token,
+        json!({ "collection_id": collection_id, "title": "Relevés" }),
+    )
+    .await;
+    let (status, _, _) = send_raw(
+        &app,
+        create(
+            "/portal/uploads",
+            &client,
+            other_request["id"].as_str().unwrap(),
+            "a.txt",
+            10,
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let content = archive();
+    let (status, headers, _) = send_raw(
+        &app,
+        create(
+            "/portal/uploads",
+            &client,
+            &request_id,
+            "archives.txt",
+            content.len(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let location = header(&headers, "Location").to_string();
+    assert!(location.starts_with("/portal/uploads/"));
+
+    // Abandoned, then removed
+    let (status, _, _) = send_raw(&app, patch(&location, &client, 0, &content[..1000])).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, _) = send_raw(
+        &app,
+        tus(http::Method::DELETE, &location, &client)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, _) = send_raw(&app, head(&location, &client)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, headers, _) = send_raw(
+        &app,
+        create(
+            "/portal/uploads",
+            &client,
+            &request_id,
+            "archives.txt",
+            content.len(),
+        ),
+    )
+    .await;
+    let location = header(&headers, "Location").to_string();
+    let (status, _, _) = send_raw(&app, patch(&location, &client, 0, &content)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let file_id = wait_for_file_id(&app, &location, &client).await;
+
+    // The file is listed with the request's others
+    let (status, files) = send(
+        &app,
+        get(&format!("/requests/{}/files", request_id), &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let files = files.as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["id"], file_id.as_str());
+}
+
+#[tokio::test]
+async fn test_refused_resumable_upload() {
+    let (app, token) = common::setup().await;
+    let user = Auth::User(&token);
+    let request_id = create_upload_request(&app, &token).await;
+
+    let mut content = archive();
+    content.extend_from_slice(FAKE_VIRUS.as_bytes());
+    let (_, headers, _) = send_raw(
+        &app,
+        create("/uploads", &user, &request_id, "virus.txt", content.len()),
+    )
+    .await;
+    let location = header(&headers, "Location").to_string();
+    let (status, _, _) = send_raw(&app, patch(&location, &user, 0, &content)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // Refused once scanned, with the reason, the file being kept in quarantine
+    let mut status = StatusCode::OK;
+    for _ in 0..50 {
+        (status, _, _) = send_raw(&app, head(&location, &user)).await;
+        if status != StatusCode::OK {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _, message) = send_raw(&app, patch(&location, &user, content.len(), b"")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        String::from_utf8(message).unwrap(),
+        "The file was rejected: Eicar-Signature detected."
+    );
+    let (_, files) = send(
+        &app,
+        get(&format!("/requests/{}/files", request_id), &token),
+    )
+    .await;
+    let files = files.as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["scan_status"], "infected");
+}
+
+// Scans no more than a few bytes, like clamd with a StreamMaxLength below the uploads
+struct SmallScanner;
+
+#[async_trait]
+impl Scanner for SmallScanner {
+    async fn scan(
+        &self,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+    ) -> anyhow::Result<ScanVerdict> {
+        FakeScanner.scan(reader).await
+    }
+
+    fn max_bytes(&self) -> u64 {
+        1024
+    }
+}
+
+#[tokio::test]
+async fn test_resumable_upload_larger_than_scanner() {
+    let (app_state, token) = common::setup_state().await;
+    let app = router(AppState {
+        scanner: Arc::new(SmallScanner),
+        ..app_state
+    });
+    let user = Auth::User(&token);
+    let request_id = create_upload_request(&app, &token).await;
+
+    let content = archive();
+    let (_, headers, _) = send_raw(
+        &app,
+        create(
+            "/uploads",
+            &user,
+            &request_id,
+            "grand-livre.txt",
+            content.len(),
+        ),
+    )
+    .await;
+    let location = header(&headers, "Location").to_string();
+    let (status, _, _) = send_raw(&app, patch(&location, &user, 0, &content)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let file_id = wait_for_file_id(&app, &location, &user).await;
+
+    // Recorded, but quarantined for good rather than sent to a scanner that refuses it
+    let (status, file) = send(&app, get(&format!("/files/{}", file_id), &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(file["scan_status"], "failed");
+    let (status, _) = send_bytes(&app, get(&format!("/files/{}/download", file_id), &token)).await;
+    assert_eq!(status, StatusCode::LOCKED);
+}
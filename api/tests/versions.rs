@@ -0,0 +1,269 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use trombone::model::file::{FileResponse, FileVersion};
+
+mod common;
+
+use common::{create_request, delete, get, multipart, post, put, send, FAKE_VIRUS};
+
+// Upload of a file, as a new version of `replaces` when given
+fn replacing_upload(
+    token: &str,
+    request_id: &str,
+    replaces: Option<Uuid>,
+    file_name: &str,
+    content: &[u8],
+) -> Request<Body> {
+    let replaces = replaces.map(|replaces| replaces.to_string());
+    let mut fields = vec![("request_id", None, request_id.as_bytes())];
+    if let Some(replaces) = &replaces {
+        fields.push(("replaces", None, replaces.as_bytes()));
+    }
+    fields.push(("file", Some(file_name), content));
+    multipart(token, &fields)
+}
+
+async fn create_titled_request(app: &axum::Router, token: &str, title: &str) -> String {
+    let request = create_request(app, token, json!({ "title": title })).await;
+    request["id"].as_str().unwrap().to_string()
+}
+
+async fn upload(
+    app: &axum::Router,
+    token: &str,
+    request_id: &str,
+    replaces: Option<Uuid>,
+    content: &'static [u8],
+) -> FileResponse {
+    let (status, file) = send(
+        app,
+        replacing_upload(token, request_id, replaces, "payslip.txt", content),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", file);
+    serde_json::from_value(file).unwrap()
+}
+
+async fn listed_ids(app: &axum::Router, token: &str, request_id: &str) -> Vec<Uuid> {
+    let (status, files) = send(app, get(&format!("/requests/{}/files", request_id), token)).await;
+    assert_eq!(status, StatusCode::OK);
+    let files: Vec<FileResponse> = serde_json::from_value(files).unwrap();
+    files.into_iter().map(|file| file.id).collect()
+}
+
+async fn versions(
+    app: &axum::Router,
+    token: &str,
+    file_id: Uuid,
+) -> Vec<(i32, bool, Option<String>)> {
+    let (status, versions) = send(app, get(&format!("/files/{}/versions", file_id), token)).await;
+    assert_eq!(status, StatusCode::OK);
+    let versions: Vec<FileVersion> = serde_json::from_value(versions).unwrap();
+    versions
+        .into_iter()
+        .map(|version| {
+            (
+                version.version,
+                version.is_current,
+                version.rejection_reason,
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_file_versions() {
+    let (app, token) = common::setup().await;
+    let request_id = create_titled_request(&app, &token, "Payslips").await;
+
+    let first = upload(&app, &token, &request_id, None, b"net pay: 2100").await;
+    assert_eq!(
+        (first.version, first.version_of, first.is_current),
+        (1, None, true)
+    );
+
+    // A corrected payslip replaces the first one in the request's files
+    let second = upload(&app, &token, &request_id, Some(first.id), b"net pay: 2150").await;
+    assert_eq!(
+        (second.version, second.version_of, second.is_current),
+        (2, Some(first.id), true)
+    );
+    assert_eq!(listed_ids(&app, &token, &request_id).await, vec![second.id]);
+    assert_eq!(
+        versions(&app, &token, first.id).await,
+        vec![(1, false, None), (2, true, None)]
+    );
+
+    // A rejected version stays visible with its reason
+    let (status, _) = send(
+        &app,
+        post(
+            &format!("/files/{}/reject", second.id),
+            &token,
+            json!({ "reason": " " }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, rejected) = send(
+        &app,
+        post(
+            &format!("/files/{}/reject", second.id),
+            &token,
+            json!({ "reason": "The month is wrong." }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let rejected: FileResponse = serde_json::from_value(rejected).unwrap();
+    assert_eq!(
+        rejected.rejection_reason.as_deref(),
+        Some("The month is wrong.")
+    );
+    assert!(rejected.rejected_at.is_some());
+    assert_eq!(listed_ids(&app, &token, &request_id).await, vec![second.id]);
+
+    // The accountant goes back to the first version, a rejected one can't be made current
+    let (status, _) = send(
+        &app,
+        put(&format!("/files/{}/current", second.id), &token, json!({})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, current) = send(
+        &app,
+        put(&format!("/files/{}/current", first.id), &token, json!({})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        serde_json::from_value::<FileResponse>(current)
+            .unwrap()
+            .is_current
+    );
+    assert_eq!(listed_ids(&app, &token, &request_id).await, vec![first.id]);
+    assert_eq!(
+        versions(&app, &token, second.id).await,
+        vec![
+            (1, true, None),
+            (2, false, Some("The month is wrong.".to_string()))
+        ]
+    );
+
+    // Replacing any version adds the latest one
+    let third = upload(
+        &app,
+        &token,
+        &request_id,
+        Some(second.id),
+        b"net pay: 2150 (march)",
+    )
+    .await;
+    assert_eq!((third.version, third.version_of), (3, Some(first.id)));
+    assert_eq!(listed_ids(&app, &token, &request_id).await, vec![third.id]);
+
+    // Deleting the first version keeps the others linked, deleting the current one lists the
+    // latest remaining
+    let (status, _) = send(&app, delete(&format!("/files/{}", first.id), &token)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(
+        versions(&app, &token, third.id).await,
+        vec![
+            (2, false, Some("The month is wrong.".to_string())),
+            (3, true, None)
+        ]
+    );
+    let (status, _) = send(&app, delete(&format!("/files/{}", third.id), &token)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    // Only the rejected version remains, it isn't listed again
+    assert!(listed_ids(&app, &token, &request_id).await.is_empty());
+    assert_eq!(
+        versions(&app, &token, second.id).await,
+        vec![(2, false, Some("The month is wrong.".to_string()))]
+    );
+}
+
+#[tokio::test]
+async fn test_infected_replacement() {
+    let (app, token) = common::setup().await;
+    let request_id = create_titled_request(&app, &token, "Payslips").await;
+    let first = upload(&app, &token, &request_id, None, b"net pay: 2100").await;
+
+    // The previous version stays listed when its replacement is infected
+    let (status, _) = send(
+        &app,
+        replacing_upload(
+            &token,
+            &request_id,
+            Some(first.id),
+            "payslip.txt",
+            FAKE_VIRUS.as_bytes(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(listed_ids(&app, &token, &request_id).await, vec![first.id]);
+    assert_eq!(
+        versions(&app, &token, first.id).await,
+        vec![(1, true, None), (2, false, None)]
+    );
+
+    // An infected version can't be made current, nor listed when the current one is deleted
+    let (status, versions) =
+        send(&app, get(&format!("/files/{}/versions", first.id), &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    let infected = serde_json::from_value::<Vec<FileVersion>>(versions).unwrap()[1].id;
+    let (status, _) = send(
+        &app,
+        put(&format!("/files/{}/current", infected), &token, json!({})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, delete(&format!("/files/{}", first.id), &token)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(listed_ids(&app, &token, &request_id).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_replace_checks() {
+    let (app, token) = common::setup().await;
+    let request_id = create_titled_request(&app, &token, "Payslips").await;
+    let other_request_id = create_titled_request(&app, &token, "Contracts").await;
+    let other = upload(&app, &token, &other_request_id, None, b"contract").await;
+
+    let (status, _) = send(
+        &app,
+        replacing_upload(
+            &token,
+            &request_id,
+            Some(other.id),
+            "payslip.txt",
+            b"net pay",
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        replacing_upload(
+            &token,
+            &request_id,
+            Some(Uuid::new_v4()),
+            "payslip.txt",
+            b"net pay",
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(
+        &app,
+        get(&format!("/files/{}/versions", Uuid::new_v4()), &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -36,6 +36,9 @@
             pkgs.cargo-watch
             pkgs.nodejs_22
             pkgs.pnpm
+            # Previews and PDF conversions of uploads: pdftoppm and heif-convert
+            pkgs.poppler_utils
+            pkgs.libheif
           ];
         };
       }